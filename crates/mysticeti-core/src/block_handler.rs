@@ -28,6 +28,7 @@ use crate::{
         BaseStatement,
         BlockReference,
         StatementBlock,
+        TimestampNs,
         Transaction,
         TransactionLocator,
     },
@@ -367,7 +368,7 @@ impl<H: ProcessedTransactionHandler<TransactionLocator>> TestCommitHandler<H> {
     ) -> Self {
         let consensus_only = env::var("CONSENSUS_ONLY").is_ok();
         Self {
-            commit_interpreter: Linearizer::new(),
+            commit_interpreter: Linearizer::new(committee.clone()),
             transaction_votes: TransactionAggregator::with_handler(handler),
             committee,
             committed_leaders: vec![],
@@ -474,7 +475,12 @@ impl<H: ProcessedTransactionHandler<TransactionLocator> + Send + Sync> CommitObs
         self.transaction_votes.state()
     }
 
-    fn recover_committed(&mut self, committed: HashSet<BlockReference>, state: Option<Bytes>) {
+    fn recover_committed(
+        &mut self,
+        committed: HashSet<BlockReference>,
+        state: Option<Bytes>,
+        last_timestamp_ns: TimestampNs,
+    ) {
         assert!(self.commit_interpreter.committed.is_empty());
         if let Some(state) = state {
             self.transaction_votes.with_state(&state);
@@ -482,5 +488,6 @@ impl<H: ProcessedTransactionHandler<TransactionLocator> + Send + Sync> CommitObs
             assert!(committed.is_empty());
         }
         self.commit_interpreter.committed = committed;
+        self.commit_interpreter.last_timestamp_ns = last_timestamp_ns;
     }
 }
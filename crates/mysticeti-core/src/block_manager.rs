@@ -24,6 +24,9 @@ pub struct BlockManager {
     /// Keeps all blocks that need to be synced in order to unblock the processing of other pending
     /// blocks. The indices of the vector correspond the authority indices.
     missing: Vec<HashSet<BlockReference>>,
+    /// Blocks rejected once their includes were known, and the blocks including them. Honest
+    /// authorities never include them, so blocks including them are dropped instead of pending.
+    rejected: HashSet<BlockReference>,
     block_store: BlockStore,
 }
 
@@ -33,6 +36,7 @@ impl BlockManager {
             blocks_pending: Default::default(),
             block_references_waiting: Default::default(),
             missing: (0..committee.len()).map(|_| HashSet::new()).collect(),
+            rejected: Default::default(),
             block_store,
         }
     }
@@ -51,9 +55,19 @@ impl BlockManager {
             let block_reference = block.reference();
             if self.block_store.block_exists(*block_reference)
                 || self.blocks_pending.contains_key(block_reference)
+                || self.rejected.contains(block_reference)
             {
                 continue;
             }
+            if let Some(rejected) = block
+                .includes()
+                .iter()
+                .find(|include| self.rejected.contains(*include))
+            {
+                tracing::warn!("Rejected block {block_reference} including rejected {rejected}");
+                self.reject(*block_reference);
+                continue;
+            }
 
             let mut processed = true;
             for included_reference in block.includes() {
@@ -76,6 +90,20 @@ impl BlockManager {
                 self.blocks_pending.insert(*block_reference, block);
             } else {
                 let block_reference = *block_reference;
+                let includes: Vec<_> = block
+                    .includes()
+                    .iter()
+                    .map(|include| {
+                        self.block_store
+                            .get_block(*include)
+                            .expect("All includes are processed")
+                    })
+                    .collect();
+                if let Err(e) = block.verify_creation_time_after(includes.iter().map(|b| &**b)) {
+                    tracing::warn!("Rejected block {block_reference}: {e}");
+                    self.reject(block_reference);
+                    continue;
+                }
 
                 // Block can be processed. So need to update indexes etc
                 let position = block_writer.insert_block(block.clone());
@@ -107,6 +135,39 @@ impl BlockManager {
         newly_blocks_processed
     }
 
+    /// Records a rejected block and drops the pending blocks including it, transitively.
+    fn reject(&mut self, block_reference: BlockReference) {
+        let mut rejected = vec![block_reference];
+        while let Some(rejected_reference) = rejected.pop() {
+            self.rejected.insert(rejected_reference);
+            self.missing[rejected_reference.authority as usize].remove(&rejected_reference);
+            let Some(waiting_references) =
+                self.block_references_waiting.remove(&rejected_reference)
+            else {
+                continue;
+            };
+            for waiting_block_reference in waiting_references {
+                let Some(block) = self.blocks_pending.remove(&waiting_block_reference) else {
+                    continue;
+                };
+                tracing::warn!(
+                    "Dropped block {waiting_block_reference} including rejected {rejected_reference}"
+                );
+                // Its other includes are no longer needed for it
+                for include in block.includes() {
+                    if let Some(waiting) = self.block_references_waiting.get_mut(include) {
+                        waiting.remove(&waiting_block_reference);
+                        if waiting.is_empty() {
+                            self.block_references_waiting.remove(include);
+                            self.missing[include.authority as usize].remove(include);
+                        }
+                    }
+                }
+                rejected.push(waiting_block_reference);
+            }
+        }
+    }
+
     pub fn missing_blocks(&self) -> &[HashSet<BlockReference>] {
         &self.missing
     }
@@ -149,6 +210,75 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_block_manager_rejects_block_created_before_includes() {
+        let dag = Dag::draw("A1:[A0, B0]; B1:[A0, B0]").add_genesis_blocks();
+        let committee = dag.committee();
+        let mut block_writer = TestBlockWriter::new(&committee);
+        let mut bm = BlockManager::new(block_writer.block_store(), &committee);
+        let round_1 = dag
+            .random_iter(&mut rng(0))
+            .map(|block| {
+                let time = if block.round() == 0 { 0 } else { 10 };
+                Data::new((**block).clone().with_creation_time(time))
+            })
+            .collect();
+        bm.add_blocks(round_1, &mut block_writer);
+
+        let early = Dag::draw_block("A2:[A1, B1]").with_creation_time(5);
+        let child = Dag::draw_block("B3:[A2, B1]").with_creation_time(20);
+        let processed = bm.add_blocks(vec![Data::new(early), Data::new(child)], &mut block_writer);
+        assert!(processed.is_empty());
+        assert!(!bm.block_store.block_exists(BlockReference::new_test(0, 2)));
+        assert!(bm.rejected.contains(&BlockReference::new_test(1, 3)));
+        assert!(bm.blocks_pending.is_empty());
+
+        let on_time = Dag::draw_block("B2:[A1, B1]").with_creation_time(10);
+        let processed = bm.add_blocks(vec![Data::new(on_time)], &mut block_writer);
+        assert_eq!(processed.len(), 1);
+    }
+
+    #[test]
+    fn test_block_manager_drops_children_of_rejected_block() {
+        let dag = Dag::draw("A1:[A0, B0]; B1:[A0, B0]").add_genesis_blocks();
+        let committee = dag.committee();
+        let mut block_writer = TestBlockWriter::new(&committee);
+        let mut bm = BlockManager::new(block_writer.block_store(), &committee);
+        let round_1 = dag
+            .random_iter(&mut rng(0))
+            .map(|block| {
+                let time = if block.round() == 0 { 0 } else { 10 };
+                Data::new((**block).clone().with_creation_time(time))
+            })
+            .collect();
+        bm.add_blocks(round_1, &mut block_writer);
+
+        // The descendants arrive before the rejected block and wait for it
+        let child = Dag::draw_block("B3:[A2, B1]").with_creation_time(20);
+        let grandchild = Dag::draw_block("A4:[A2, B3]").with_creation_time(30);
+        let processed = bm.add_blocks(
+            vec![Data::new(child), Data::new(grandchild)],
+            &mut block_writer,
+        );
+        assert!(processed.is_empty());
+        assert_eq!(bm.blocks_pending.len(), 2);
+        assert!(bm.missing[0].contains(&BlockReference::new_test(0, 2)));
+
+        let early = Dag::draw_block("A2:[A1, B1]").with_creation_time(5);
+        let processed = bm.add_blocks(vec![Data::new(early)], &mut block_writer);
+        assert!(processed.is_empty());
+        assert!(bm.blocks_pending.is_empty());
+        assert!(bm.block_references_waiting.is_empty());
+        assert!(bm.missing.iter().all(HashSet::is_empty));
+
+        // Blocks including a dropped block are dropped as they arrive
+        let late = Dag::draw_block("B5:[A4, B3]").with_creation_time(40);
+        let processed = bm.add_blocks(vec![Data::new(late)], &mut block_writer);
+        assert!(processed.is_empty());
+        assert!(bm.blocks_pending.is_empty());
+        assert!(bm.missing.iter().all(HashSet::is_empty));
+    }
+
     fn rng(s: u8) -> StdRng {
         let mut seed = [0; 32];
         seed[0] = s;
@@ -0,0 +1,527 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    crypto::{self, HashFunction, PublicKey, SignatureBytes, Signer},
+    sign_guard::{SignGuard, SignGuardError, SignedBlockRecord},
+    types::{
+        AuthorityIndex,
+        BaseStatement,
+        BlockReference,
+        Epoch,
+        EpochStatus,
+        RoundNumber,
+        TimestampNs,
+    },
+};
+
+/// Large enough for an ML-DSA public key or signature.
+const MAX_FRAME_SIZE: usize = 64 * 1024;
+const REMOTE_SIGNER_TIMEOUT: Duration = Duration::from_secs(5);
+pub(crate) const HANDSHAKE_SIGNATURE_DOMAIN: &[u8] = b"mysticeti-handshake";
+
+/// Signs the blocks proposed by this validator, and the handshakes of its connections.
+pub trait BlockSigner: Send + Sync {
+    fn public_key(&self) -> PublicKey;
+
+    #[allow(clippy::too_many_arguments)]
+    fn sign_block(
+        &self,
+        authority: AuthorityIndex,
+        round: RoundNumber,
+        includes: &[BlockReference],
+        statements: &[BaseStatement],
+        meta_creation_time_ns: TimestampNs,
+        epoch: Epoch,
+        epoch_marker: EpochStatus,
+        hash_function: HashFunction,
+    ) -> Result<SignatureBytes, SignError>;
+
+    /// Signs the challenge a peer sends when we connect, proving that we hold the key we announce.
+    fn sign_handshake(&self, challenge: &[u8]) -> Result<SignatureBytes, SignError>;
+
+    /// The last block signed according to the sign guard, if this signer keeps one.
+    fn last_signed(&self) -> Option<SignedBlockRecord> {
+        None
+    }
+}
+
+/// Shares a signer between the core, signing blocks, and the network, signing handshakes.
+impl<T: BlockSigner + ?Sized> BlockSigner for Arc<T> {
+    fn public_key(&self) -> PublicKey {
+        self.as_ref().public_key()
+    }
+
+    fn sign_block(
+        &self,
+        authority: AuthorityIndex,
+        round: RoundNumber,
+        includes: &[BlockReference],
+        statements: &[BaseStatement],
+        meta_creation_time_ns: TimestampNs,
+        epoch: Epoch,
+        epoch_marker: EpochStatus,
+        hash_function: HashFunction,
+    ) -> Result<SignatureBytes, SignError> {
+        self.as_ref().sign_block(
+            authority,
+            round,
+            includes,
+            statements,
+            meta_creation_time_ns,
+            epoch,
+            epoch_marker,
+            hash_function,
+        )
+    }
+
+    fn sign_handshake(&self, challenge: &[u8]) -> Result<SignatureBytes, SignError> {
+        self.as_ref().sign_handshake(challenge)
+    }
+
+    fn last_signed(&self) -> Option<SignedBlockRecord> {
+        self.as_ref().last_signed()
+    }
+}
+
+#[derive(Debug)]
+pub enum SignError {
+    /// The remote signer could not be reached.
+    Io(io::Error),
+    /// The signer refused to sign, for example because it already signed
+    /// a different block for the same round.
+    Refused(String),
+}
+
+/// Consults the sign guard before every signature handed out by the inner signer.
+pub struct GuardedSigner {
+    inner: Box<dyn BlockSigner>,
+    guard: Mutex<SignGuard>,
+}
+
+impl GuardedSigner {
+    pub fn new(inner: Box<dyn BlockSigner>, guard: SignGuard) -> Self {
+        Self {
+            inner,
+            guard: Mutex::new(guard),
+        }
+    }
+}
+
+impl BlockSigner for GuardedSigner {
+    fn public_key(&self) -> PublicKey {
+        self.inner.public_key()
+    }
+
+    fn sign_block(
+        &self,
+        authority: AuthorityIndex,
+        round: RoundNumber,
+        includes: &[BlockReference],
+        statements: &[BaseStatement],
+        meta_creation_time_ns: TimestampNs,
+        epoch: Epoch,
+        epoch_marker: EpochStatus,
+        hash_function: HashFunction,
+    ) -> Result<SignatureBytes, SignError> {
+        let digest = crypto::block_signing_digest(
+            authority,
+            round,
+            includes,
+            statements,
+            meta_creation_time_ns,
+            epoch,
+            epoch_marker,
+            hash_function,
+        );
+        self.guard.lock().record(SignedBlockRecord::new(
+            authority, epoch, round, includes, digest,
+        ))?;
+        self.inner.sign_block(
+            authority,
+            round,
+            includes,
+            statements,
+            meta_creation_time_ns,
+            epoch,
+            epoch_marker,
+            hash_function,
+        )
+    }
+
+    // Handshakes are not blocks, the guard does not apply
+    fn sign_handshake(&self, challenge: &[u8]) -> Result<SignatureBytes, SignError> {
+        self.inner.sign_handshake(challenge)
+    }
+
+    fn last_signed(&self) -> Option<SignedBlockRecord> {
+        self.guard.lock().last_signed().copied()
+    }
+}
+
+/// The key is held in the validator process.
+impl BlockSigner for Signer {
+    fn public_key(&self) -> PublicKey {
+        Signer::public_key(self)
+    }
+
+    fn sign_block(
+        &self,
+        authority: AuthorityIndex,
+        round: RoundNumber,
+        includes: &[BlockReference],
+        statements: &[BaseStatement],
+        meta_creation_time_ns: TimestampNs,
+        epoch: Epoch,
+        epoch_marker: EpochStatus,
+        hash_function: HashFunction,
+    ) -> Result<SignatureBytes, SignError> {
+        Ok(Signer::sign_block(
+            self,
+            authority,
+            round,
+            includes,
+            statements,
+            meta_creation_time_ns,
+            epoch,
+            epoch_marker,
+            hash_function,
+        ))
+    }
+
+    fn sign_handshake(&self, challenge: &[u8]) -> Result<SignatureBytes, SignError> {
+        Ok(self.sign_message(HANDSHAKE_SIGNATURE_DOMAIN, challenge))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+enum SignerRequest {
+    PublicKey,
+    SignBlock(SignedBlockRecord),
+    SignHandshake(Vec<u8>),
+}
+
+#[derive(Serialize, Deserialize)]
+enum SignerResponse {
+    PublicKey(PublicKey),
+    Signature(SignatureBytes),
+    Refused(String),
+}
+
+/// The key is held by a separate signer process (see serve_signer), reached over a Unix
+/// domain socket. Only block digests and handshake challenges cross the socket.
+pub struct RemoteSigner {
+    path: PathBuf,
+    public_key: PublicKey,
+    stream: Mutex<Option<UnixStream>>,
+}
+
+impl RemoteSigner {
+    pub fn connect(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let mut stream = Some(Self::open_stream(&path)?);
+        let public_key = match Self::exchange(&path, &mut stream, &SignerRequest::PublicKey)? {
+            SignerResponse::PublicKey(public_key) => public_key,
+            _ => return Err(unexpected_response()),
+        };
+        Ok(Self {
+            path,
+            public_key,
+            stream: Mutex::new(stream),
+        })
+    }
+
+    fn open_stream(path: &PathBuf) -> io::Result<UnixStream> {
+        let stream = UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(REMOTE_SIGNER_TIMEOUT))?;
+        stream.set_write_timeout(Some(REMOTE_SIGNER_TIMEOUT))?;
+        Ok(stream)
+    }
+
+    /// Sends the request, reconnecting once in case the signer process was restarted.
+    /// Retrying a signing request is safe because the signer accepts the same digest
+    /// for the same round again.
+    fn exchange(
+        path: &PathBuf,
+        stream: &mut Option<UnixStream>,
+        request: &SignerRequest,
+    ) -> io::Result<SignerResponse> {
+        let mut result = Err(io::ErrorKind::NotConnected.into());
+        for _ in 0..2 {
+            if stream.is_none() {
+                *stream = Some(Self::open_stream(path)?);
+            }
+            let connection = stream.as_mut().unwrap();
+            result = write_frame(connection, request).and_then(|_| read_frame(connection));
+            if result.is_ok() {
+                break;
+            }
+            *stream = None;
+        }
+        result
+    }
+}
+
+impl BlockSigner for RemoteSigner {
+    fn public_key(&self) -> PublicKey {
+        self.public_key.clone()
+    }
+
+    fn sign_block(
+        &self,
+        authority: AuthorityIndex,
+        round: RoundNumber,
+        includes: &[BlockReference],
+        statements: &[BaseStatement],
+        meta_creation_time_ns: TimestampNs,
+        epoch: Epoch,
+        epoch_marker: EpochStatus,
+        hash_function: HashFunction,
+    ) -> Result<SignatureBytes, SignError> {
+        let digest = crypto::block_signing_digest(
+            authority,
+            round,
+            includes,
+            statements,
+            meta_creation_time_ns,
+            epoch,
+            epoch_marker,
+            hash_function,
+        );
+        let request = SignerRequest::SignBlock(SignedBlockRecord::new(
+            authority, epoch, round, includes, digest,
+        ));
+        let mut stream = self.stream.lock();
+        match Self::exchange(&self.path, &mut stream, &request).map_err(SignError::Io)? {
+            SignerResponse::Signature(signature) => Ok(signature),
+            SignerResponse::Refused(reason) => Err(SignError::Refused(reason)),
+            SignerResponse::PublicKey(_) => Err(SignError::Io(unexpected_response())),
+        }
+    }
+
+    fn sign_handshake(&self, challenge: &[u8]) -> Result<SignatureBytes, SignError> {
+        let request = SignerRequest::SignHandshake(challenge.to_vec());
+        let mut stream = self.stream.lock();
+        match Self::exchange(&self.path, &mut stream, &request).map_err(SignError::Io)? {
+            SignerResponse::Signature(signature) => Ok(signature),
+            SignerResponse::Refused(reason) => Err(SignError::Refused(reason)),
+            SignerResponse::PublicKey(_) => Err(SignError::Io(unexpected_response())),
+        }
+    }
+}
+
+/// Runs the signer process: answers signing requests of validators connecting to `listener`
+/// with the given key, until the listener fails.
+///
+/// The signer keeps its own sign guard, so it never signs two different digests for the same
+/// round, nor goes back in rounds. Asking for the last signed digest again is fine, this is
+/// what happens when the validator retries after a lost response. Note that the signer can not
+/// tell which round a digest belongs to: this protects against a misbehaving validator (e.g.
+/// two instances sharing a key) rather than against a compromised one.
+pub fn serve_signer(listener: UnixListener, signer: Signer, guard: SignGuard) -> io::Result<()> {
+    let state = Arc::new((signer, Mutex::new(guard)));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let state = state.clone();
+        thread::spawn(move || {
+            let (signer, guard) = state.as_ref();
+            if let Err(e) = handle_signer_connection(stream, signer, guard) {
+                tracing::warn!("Signer connection failed: {e}");
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle_signer_connection(
+    mut stream: UnixStream,
+    signer: &Signer,
+    guard: &Mutex<SignGuard>,
+) -> io::Result<()> {
+    loop {
+        let request = match read_frame(&mut stream) {
+            Ok(request) => request,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let response = match request {
+            SignerRequest::PublicKey => SignerResponse::PublicKey(signer.public_key()),
+            SignerRequest::SignBlock(record) => {
+                // Keep the guard locked while signing, so that concurrent connections
+                // can not both pass the check for the same round
+                let mut guard = guard.lock();
+                match guard.record(record) {
+                    Ok(()) => SignerResponse::Signature(signer.sign_block_digest(&record.digest)),
+                    Err(e) => {
+                        tracing::warn!("Refused to sign block: {e}");
+                        SignerResponse::Refused(e.to_string())
+                    }
+                }
+            }
+            SignerRequest::SignHandshake(challenge) => SignerResponse::Signature(
+                signer.sign_message(HANDSHAKE_SIGNATURE_DOMAIN, &challenge),
+            ),
+        };
+        write_frame(&mut stream, &response)?;
+    }
+}
+
+fn write_frame(stream: &mut impl Write, message: &impl Serialize) -> io::Result<()> {
+    let bytes =
+        bincode::serialize(message).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    stream.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    stream.write_all(&bytes)
+}
+
+fn read_frame<T: DeserializeOwned>(stream: &mut impl Read) -> io::Result<T> {
+    let mut length = [0u8; 4];
+    stream.read_exact(&mut length)?;
+    let length = u32::from_le_bytes(length) as usize;
+    if length > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Signer frame too large: {length}"),
+        ));
+    }
+    let mut bytes = vec![0u8; length];
+    stream.read_exact(&mut bytes)?;
+    bincode::deserialize(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn unexpected_response() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Unexpected response from signer",
+    )
+}
+
+impl From<io::Error> for SignError {
+    fn from(e: io::Error) -> Self {
+        SignError::Io(e)
+    }
+}
+
+impl From<SignGuardError> for SignError {
+    fn from(e: SignGuardError) -> Self {
+        match e {
+            SignGuardError::Conflict(reason) => SignError::Refused(reason),
+            SignGuardError::Io(e) => SignError::Io(e),
+        }
+    }
+}
+
+impl fmt::Display for SignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignError::Io(e) => write!(f, "signer unavailable: {e}"),
+            SignError::Refused(reason) => write!(f, "signer refused: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for SignError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_signer() {
+        let dir = tempdir::TempDir::new("remote_signer").unwrap();
+        let path = dir.path().join("signer.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let guard = SignGuard::open(dir.path().join("sign-guard")).unwrap();
+        let signer = Signer::new_for_test(1).pop().unwrap();
+        let public_key = signer.public_key();
+        thread::spawn(move || serve_signer(listener, signer, guard));
+
+        let remote = RemoteSigner::connect(&path).unwrap();
+        assert_eq!(BlockSigner::public_key(&remote), public_key);
+        let reference = BlockReference::new_test(0, 0);
+        let signature = remote
+            .sign_block(
+                0,
+                1,
+                &[reference],
+                &[],
+                0,
+                0,
+                false,
+                HashFunction::default(),
+            )
+            .unwrap();
+        let digest = crypto::block_signing_digest(
+            0,
+            1,
+            &[reference],
+            &[],
+            0,
+            0,
+            false,
+            HashFunction::default(),
+        );
+        assert_eq!(
+            signature,
+            Signer::new_for_test(1)[0].sign_block_digest(&digest)
+        );
+        let signature = remote.sign_handshake(b"challenge").unwrap();
+        public_key
+            .verify_message(HANDSHAKE_SIGNATURE_DOMAIN, b"challenge", &signature)
+            .unwrap();
+
+        // A second connection shares the guard of the signer process
+        let other = RemoteSigner::connect(&path).unwrap();
+        assert!(matches!(
+            other.sign_block(
+                0,
+                0,
+                &[reference],
+                &[],
+                0,
+                0,
+                false,
+                HashFunction::default()
+            ),
+            Err(SignError::Refused(_))
+        ));
+    }
+
+    #[test]
+    fn guarded_signer() {
+        let dir = tempdir::TempDir::new("guarded_signer").unwrap();
+        let path = dir.path().join("sign-guard");
+        let open = || {
+            let signer = Signer::new_for_test(1).pop().unwrap();
+            GuardedSigner::new(Box::new(signer), SignGuard::open(&path).unwrap())
+        };
+        let signer = open();
+        assert!(signer.last_signed().is_none());
+        signer
+            .sign_block(0, 1, &[], &[], 0, 0, false, HashFunction::default())
+            .unwrap();
+        signer
+            .sign_block(0, 2, &[], &[], 0, 0, false, HashFunction::default())
+            .unwrap();
+        drop(signer);
+
+        let signer = open();
+        assert_eq!(signer.last_signed().unwrap().round, 2);
+        assert!(matches!(
+            signer.sign_block(0, 1, &[], &[], 0, 0, false, HashFunction::default()),
+            Err(SignError::Refused(_))
+        ));
+    }
+}
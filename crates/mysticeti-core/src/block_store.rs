@@ -11,13 +11,14 @@ use std::{
 
 use minibytes::Bytes;
 use parking_lot::RwLock;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::{
     committee::Committee,
     consensus::linearizer::CommittedSubDag,
     data::Data,
     metrics::{Metrics, UtilizationTimerExt},
+    snapshot::Snapshot,
     state::{RecoveredState, RecoveredStateBuilder},
     types::{
         AuthorityIndex,
@@ -26,10 +27,11 @@ use crate::{
         BlockReference,
         RoundNumber,
         StatementBlock,
+        TimestampNs,
         Transaction,
         TransactionLocator,
     },
-    wal::{Tag, WalPosition, WalReader, WalWriter},
+    wal::{Tag, WalError, WalIterator, WalPosition, WalReader, WalWriter},
 };
 
 #[derive(Clone)]
@@ -60,73 +62,118 @@ enum IndexEntry {
     Loaded(WalPosition, Data<StatementBlock>),
 }
 
+impl IndexEntry {
+    fn position(&self) -> WalPosition {
+        match self {
+            IndexEntry::WalPosition(position) | IndexEntry::Loaded(position, _) => *position,
+        }
+    }
+}
+
+/// The block index of a snapshot, without the blocks at or below its gc round.
+#[derive(Serialize, Deserialize, Default)]
+pub(crate) struct IndexSnapshot {
+    blocks: Vec<(BlockReference, WalPosition)>,
+    highest_round: RoundNumber,
+    last_seen_by_authority: Vec<RoundNumber>,
+}
+
+// Rebuilds the block index and the recovered state from the wal entries
+struct WalReplay {
+    inner: BlockStoreInner,
+    builder: RecoveredStateBuilder,
+    block_count: u64,
+}
+
 impl BlockStore {
+    /// Replays the wal to rebuild the block store and the state of the core. Fails if an entry
+    /// before the end of the wal is corrupted, the tail of a wal that was not fully written
+    /// is dropped when opening the wal.
     pub fn open(
         authority: AuthorityIndex,
         block_wal_reader: Arc<WalReader>,
         wal_writer: &WalWriter,
         metrics: Arc<Metrics>,
         committee: &Committee,
-    ) -> RecoveredState {
-        let last_seen_by_authority = committee.authorities().map(|_| 0).collect();
-        let mut inner = BlockStoreInner {
+    ) -> Result<RecoveredState, WalError> {
+        Self::open_from_snapshot(
             authority,
-            last_seen_by_authority,
-            ..Default::default()
-        };
-        let mut builder = RecoveredStateBuilder::new();
-        let mut replay_started: Option<Instant> = None;
-        let mut block_count = 0u64;
-        for (pos, (tag, data)) in block_wal_reader.iter_until(wal_writer) {
-            if replay_started.is_none() {
-                replay_started = Some(Instant::now());
-                tracing::info!("Wal is not empty, starting replay");
-            }
-            let block = match tag {
-                WAL_ENTRY_BLOCK => {
-                    let block = Data::<StatementBlock>::from_bytes(data)
-                        .expect("Failed to deserialize data from wal");
-                    builder.block(pos, &block);
-                    block
-                }
-                WAL_ENTRY_PAYLOAD => {
-                    builder.payload(pos, data);
-                    continue;
-                }
-                WAL_ENTRY_OWN_BLOCK => {
-                    let (own_block_data, own_block) = OwnBlockData::from_bytes(data)
-                        .expect("Failed to deserialized own block data from wal");
-                    builder.own_block(own_block_data);
-                    own_block
-                }
-                WAL_ENTRY_STATE => {
-                    builder.state(data);
-                    continue;
-                }
-                WAL_ENTRY_COMMIT => {
-                    let (commit_data, state) = bincode::deserialize(&data)
-                        .expect("Failed to deserialized commit data from wal");
-                    builder.commit_data(commit_data, state);
-                    continue;
-                }
-                _ => panic!("Unknown wal tag {tag} at position {pos}"),
-            };
-            // todo - we want to keep some last blocks in the cache
-            block_count += 1;
-            inner.add_unloaded(block.reference(), pos);
+            block_wal_reader,
+            wal_writer,
+            metrics,
+            committee,
+            None,
+        )
+    }
+
+    /// Works as BlockStore::open, but starts from the snapshot and only replays the wal entries
+    /// written after it. The blocks and committed blocks the snapshot left out, at or below its
+    /// gc round, are not recovered.
+    pub fn open_from_snapshot(
+        authority: AuthorityIndex,
+        block_wal_reader: Arc<WalReader>,
+        wal_writer: &WalWriter,
+        metrics: Arc<Metrics>,
+        committee: &Committee,
+        snapshot: Option<Snapshot>,
+    ) -> Result<RecoveredState, WalError> {
+        if let Some(dropped_tail) = wal_writer.dropped_tail() {
+            tracing::warn!(
+                "Dropped {} bytes at the end of the wal from position {}, they were not fully written",
+                dropped_tail.bytes,
+                dropped_tail.position
+            );
         }
-        metrics.block_store_entries.inc_by(block_count);
-        if let Some(replay_started) = replay_started {
-            tracing::info!("Wal replay completed in {:?}", replay_started.elapsed());
-        } else {
+        let (mut replay, position) = match snapshot {
+            Some(snapshot) => {
+                tracing::info!(
+                    "Recovering from the snapshot at wal position {}",
+                    snapshot.position
+                );
+                let position = snapshot.position;
+                (WalReplay::from_snapshot(authority, snapshot), position)
+            }
+            None => (WalReplay::new(authority, committee), WalPosition::default()),
+        };
+        let replay_started = Instant::now();
+        let entries = replay.replay(block_wal_reader.iter_from(position, wal_writer))?;
+        if entries > 0 {
+            tracing::info!(
+                "Wal replay of {entries} entries from position {position} completed in {:?}",
+                replay_started.elapsed()
+            );
+        } else if position == WalPosition::default() {
             tracing::info!("Wal is empty, will start from genesis");
         }
+        metrics.block_store_entries.inc_by(replay.block_count);
         let this = Self {
             block_wal_reader,
-            inner: Arc::new(RwLock::new(inner)),
+            inner: Arc::new(RwLock::new(replay.inner)),
             metrics,
         };
-        builder.build(this)
+        Ok(replay.builder.build(this))
+    }
+
+    /// Snapshot of the state recovered from the wal up to the end position. Only the entries
+    /// written since the previous snapshot are replayed. The blocks and committed
+    /// blocks at or below the gc round are left out.
+    pub fn snapshot(
+        &self,
+        previous: Option<Snapshot>,
+        end: WalPosition,
+        committee: &Committee,
+        gc_round: RoundNumber,
+    ) -> Result<Snapshot, WalError> {
+        let authority = self.inner.read().authority;
+        let (mut replay, position) = match previous {
+            Some(previous) => {
+                let position = previous.position;
+                (WalReplay::from_snapshot(authority, previous), position)
+            }
+            None => (WalReplay::new(authority, committee), WalPosition::default()),
+        };
+        replay.replay(self.block_wal_reader.iter_between(position, end))?;
+        Ok(replay.into_snapshot(end, gc_round))
     }
 
     pub fn insert_block(&self, block: Data<StatementBlock>, position: WalPosition) {
@@ -231,6 +278,33 @@ impl BlockStore {
         self.metrics.wal_mappings.set(retained_maps as i64);
     }
 
+    /// The wal position of a block in the store.
+    pub fn block_position(&self, reference: BlockReference) -> Option<WalPosition> {
+        self.inner
+            .read()
+            .get_block(reference)
+            .map(|entry| entry.position())
+    }
+
+    /// The lowest wal position of the blocks above the specified round, if any.
+    pub fn first_position_above_round(&self, round: RoundNumber) -> Option<WalPosition> {
+        let inner = self.inner.read();
+        inner
+            .index
+            .range((round + 1)..)
+            .flat_map(|(_, map)| map.values().map(IndexEntry::position))
+            .min()
+    }
+
+    /// Forget the blocks at or below the threshold round that were written before the position,
+    /// once the wal segments holding them are deleted.
+    pub fn remove_before(&self, threshold_round: RoundNumber, position: WalPosition) {
+        let removed = self.inner.write().remove_before(threshold_round, position);
+        if removed > 0 {
+            tracing::debug!("Removed {removed} blocks before wal position {position}");
+        }
+    }
+
     pub fn get_own_blocks(
         &self,
         from_excluded: RoundNumber,
@@ -257,6 +331,11 @@ impl BlockStore {
         self.inner.read().last_seen_by_authority(authority)
     }
 
+    /// The authority whose own blocks this store holds.
+    pub fn authority(&self) -> AuthorityIndex {
+        self.inner.read().authority
+    }
+
     pub fn last_own_block_ref(&self) -> Option<BlockReference> {
         self.inner.read().last_own_block()
     }
@@ -384,6 +463,24 @@ impl BlockStoreInner {
         unloaded
     }
 
+    pub fn remove_before(&mut self, threshold_round: RoundNumber, position: WalPosition) -> usize {
+        let mut removed = 0usize;
+        for (round, map) in self.index.range_mut(..=threshold_round) {
+            map.retain(|(authority, _), entry| {
+                if entry.position() >= position {
+                    return true;
+                }
+                if *authority == self.authority {
+                    self.own_blocks.remove(round);
+                }
+                removed += 1;
+                false
+            });
+        }
+        self.index.retain(|_, map| !map.is_empty());
+        removed
+    }
+
     pub fn add_unloaded(&mut self, reference: &BlockReference, position: WalPosition) {
         self.highest_round = max(self.highest_round, reference.round());
         let map = self.index.entry(reference.round()).or_default();
@@ -489,6 +586,139 @@ pub const WAL_ENTRY_STATE: Tag = 4;
 // Commit entry includes both commit interpreter incremental state and committed transactions aggregator
 // todo - They could be separated for better performance, but this will require catching up for committed transactions aggregator state
 pub const WAL_ENTRY_COMMIT: Tag = 5;
+pub const WAL_ENTRY_EQUIVOCATION: Tag = 6;
+// The first byte of a commit entry, bumped whenever the layout of the entry changes
+pub const WAL_COMMIT_VERSION: u8 = 1;
+// Entries compressed in the wal when compression is on, the others are small or rarely written
+pub const WAL_COMPRESSED_ENTRIES: [Tag; 3] =
+    [WAL_ENTRY_BLOCK, WAL_ENTRY_PAYLOAD, WAL_ENTRY_OWN_BLOCK];
+
+impl WalReplay {
+    fn new(authority: AuthorityIndex, committee: &Committee) -> Self {
+        let inner = BlockStoreInner {
+            authority,
+            last_seen_by_authority: committee.authorities().map(|_| 0).collect(),
+            ..Default::default()
+        };
+        Self {
+            inner,
+            builder: RecoveredStateBuilder::new(),
+            block_count: 0,
+        }
+    }
+
+    fn from_snapshot(authority: AuthorityIndex, snapshot: Snapshot) -> Self {
+        let IndexSnapshot {
+            blocks,
+            highest_round,
+            last_seen_by_authority,
+        } = snapshot.index;
+        let mut inner = BlockStoreInner {
+            authority,
+            highest_round,
+            last_seen_by_authority,
+            ..Default::default()
+        };
+        for (reference, position) in &blocks {
+            inner.add_unloaded(reference, *position);
+        }
+        Self {
+            inner,
+            builder: snapshot.state,
+            block_count: blocks.len() as u64,
+        }
+    }
+
+    // Returns the number of replayed entries
+    fn replay(&mut self, entries: WalIterator) -> Result<u64, WalError> {
+        let mut count = 0;
+        for entry in entries {
+            let (pos, (tag, data)) = entry?;
+            count += 1;
+            let block = match tag {
+                WAL_ENTRY_BLOCK => {
+                    let block = Data::<StatementBlock>::from_bytes(data)
+                        .expect("Failed to deserialize data from wal");
+                    self.builder.block(pos, &block);
+                    block
+                }
+                WAL_ENTRY_PAYLOAD => {
+                    self.builder.payload(pos, data);
+                    continue;
+                }
+                WAL_ENTRY_OWN_BLOCK => {
+                    let (own_block_data, own_block) = OwnBlockData::from_bytes(data)
+                        .expect("Failed to deserialized own block data from wal");
+                    self.builder.own_block(own_block_data);
+                    own_block
+                }
+                WAL_ENTRY_STATE => {
+                    self.builder.state(data);
+                    continue;
+                }
+                WAL_ENTRY_COMMIT => {
+                    let (commit_data, state, leader_reputation, epoch_change) =
+                        Self::commit_entry(pos, &data)?;
+                    self.builder
+                        .commit_data(commit_data, state, leader_reputation, epoch_change);
+                    continue;
+                }
+                WAL_ENTRY_EQUIVOCATION => {
+                    self.builder.equivocation(pos, data);
+                    continue;
+                }
+                _ => panic!("Unknown wal tag {tag} at position {pos}"),
+            };
+            // todo - we want to keep some last blocks in the cache
+            self.block_count += 1;
+            self.inner.add_unloaded(block.reference(), pos);
+        }
+        Ok(count)
+    }
+
+    fn commit_entry<T: DeserializeOwned>(
+        position: WalPosition,
+        data: &[u8],
+    ) -> Result<T, WalError> {
+        let reason = match data.split_first() {
+            Some((&WAL_COMMIT_VERSION, entry)) => match bincode::deserialize(entry) {
+                Ok(entry) => return Ok(entry),
+                Err(err) => format!("failed to deserialize commit entry: {err}"),
+            },
+            Some((version, _)) => format!("unsupported commit entry version {version}"),
+            None => "empty commit entry".to_string(),
+        };
+        Err(WalError::InvalidEntry { position, reason })
+    }
+
+    fn into_snapshot(mut self, position: WalPosition, gc_round: RoundNumber) -> Snapshot {
+        let blocks = self
+            .inner
+            .index
+            .range((gc_round + 1)..)
+            .flat_map(|(round, map)| {
+                map.iter().map(|((authority, digest), entry)| {
+                    let reference = BlockReference {
+                        authority: *authority,
+                        round: *round,
+                        digest: *digest,
+                    };
+                    (reference, entry.position())
+                })
+            })
+            .collect();
+        self.builder.forget_committed_at_or_below(gc_round);
+        Snapshot {
+            position,
+            index: IndexSnapshot {
+                blocks,
+                highest_round: self.inner.highest_round,
+                last_seen_by_authority: self.inner.last_seen_by_authority,
+            },
+            state: self.builder,
+        }
+    }
+}
 
 impl BlockWriter for (&mut WalWriter, &BlockStore) {
     fn insert_block(&mut self, block: Data<StatementBlock>) -> WalPosition {
@@ -506,7 +736,9 @@ impl BlockWriter for (&mut WalWriter, &BlockStore) {
     }
 }
 
-// This data structure has a special serialization in/from Bytes, see OwnBlockData::from_bytes/write_to_wal
+// This data structure has a special serialization in/from Bytes, see OwnBlockData::from_bytes/write_to_wal.
+// Serde is only used for snapshots.
+#[derive(Serialize, Deserialize)]
 pub struct OwnBlockData {
     pub next_entry: WalPosition,
     pub block: Data<StatementBlock>,
@@ -543,6 +775,7 @@ pub struct CommitData {
     pub leader: BlockReference,
     // All committed blocks, including the leader
     pub sub_dag: Vec<BlockReference>,
+    pub timestamp_ns: TimestampNs,
 }
 
 impl From<&CommittedSubDag> for CommitData {
@@ -551,13 +784,22 @@ impl From<&CommittedSubDag> for CommitData {
         Self {
             leader: value.anchor,
             sub_dag,
+            timestamp_ns: value.timestamp_ns,
         }
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::{fs, fs::OpenOptions, io, os::unix::fs::FileExt, path::Path};
+
+    use rand::Rng;
+
     use super::*;
+    use crate::{
+        test_util::{committee, rng_at_seed, test_metrics},
+        wal::{segmented_wal, HEADER_LEN_BYTES},
+    };
 
     #[test]
     fn own_block_serialization_test() {
@@ -565,4 +807,145 @@ mod test {
         let serialized = bincode::serialize(&next_entry).unwrap();
         assert_eq!(serialized.len(), OWN_BLOCK_HEADER_SIZE);
     }
+
+    #[test]
+    fn truncated_wal_recovery_test() {
+        let committee = committee(4);
+        for seed in 0..16 {
+            let mut rng = rng_at_seed(seed);
+            let dir = tempdir::TempDir::new("truncated_wal_recovery_test").unwrap();
+            let blocks = write_blocks(dir.path(), &committee, &mut rng);
+
+            // The end of the wal is lost, from a random offset
+            let segment = dir.path().join("0000000000.wal");
+            let offset = rng.gen_range(0..fs::metadata(&segment).unwrap().len());
+            OpenOptions::new()
+                .write(true)
+                .open(&segment)
+                .unwrap()
+                .set_len(offset)
+                .unwrap();
+
+            let (wal_writer, wal_reader) = segmented_wal(dir.path()).unwrap();
+            let block_store = BlockStore::open(
+                0,
+                Arc::new(wal_reader),
+                &wal_writer,
+                test_metrics(),
+                &committee,
+            )
+            .expect("The end of the wal should be dropped")
+            .block_store;
+
+            // The recovered blocks are a prefix of the written ones, with all the blocks written
+            // before the damaged offset
+            let kept = blocks
+                .iter()
+                .take_while(|(_, block)| block_store.block_exists(*block.reference()))
+                .count();
+            for (_, block) in &blocks[kept..] {
+                assert!(!block_store.block_exists(*block.reference()));
+            }
+            for (_, block) in &blocks[..kept] {
+                assert_eq!(&block_store.get_block(*block.reference()).unwrap(), block);
+            }
+            let damaged = WalPosition::default().add(offset);
+            for window in blocks.windows(2) {
+                if window[1].0 <= damaged {
+                    assert!(block_store.block_exists(*window[0].1.reference()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_wal_recovery_test() {
+        let committee = committee(4);
+        for seed in 0..16 {
+            let mut rng = rng_at_seed(seed);
+            let dir = tempdir::TempDir::new("corrupted_wal_recovery_test").unwrap();
+            let blocks = write_blocks(dir.path(), &committee, &mut rng);
+
+            // A byte of a synced block is corrupted, with blocks written after it
+            let (position, block) = &blocks[rng.gen_range(0..blocks.len() - 1)];
+            let entry_len = HEADER_LEN_BYTES + block.serialized_bytes().len() as u64;
+            let offset = position.bytes_since(WalPosition::default()) + rng.gen_range(0..entry_len);
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(dir.path().join("0000000000.wal"))
+                .unwrap();
+            let mut byte = [0u8];
+            file.read_exact_at(&mut byte, offset).unwrap();
+            file.write_all_at(&[byte[0] ^ 0xff], offset).unwrap();
+            drop(file);
+
+            let error = segmented_wal(dir.path())
+                .err()
+                .expect("Corruption in the middle of the wal should not be dropped");
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn unknown_commit_version_fails_recovery() {
+        let committee = committee(4);
+        let dir = tempdir::TempDir::new("unknown_commit_version_fails_recovery").unwrap();
+        let (mut wal_writer, _) = segmented_wal(dir.path()).unwrap();
+        wal_writer
+            .write(WAL_ENTRY_COMMIT, &[WAL_COMMIT_VERSION + 1])
+            .unwrap();
+        wal_writer.sync().unwrap();
+        drop(wal_writer);
+
+        let (wal_writer, wal_reader) = segmented_wal(dir.path()).unwrap();
+        let result = BlockStore::open(
+            0,
+            Arc::new(wal_reader),
+            &wal_writer,
+            test_metrics(),
+            &committee,
+        );
+        assert!(matches!(result, Err(WalError::InvalidEntry { .. })));
+    }
+
+    fn write_blocks(
+        path: &Path,
+        committee: &Committee,
+        rng: &mut impl Rng,
+    ) -> Vec<(WalPosition, Data<StatementBlock>)> {
+        let (mut wal_writer, wal_reader) = segmented_wal(path).unwrap();
+        let recovered = BlockStore::open(
+            0,
+            Arc::new(wal_reader),
+            &wal_writer,
+            test_metrics(),
+            committee,
+        )
+        .unwrap();
+        let mut blocks = vec![];
+        for round in 1..=40 {
+            for authority in committee.authorities() {
+                // Blocks of varying size, some of them do not fit at the end of a mapping. They
+                // all fit in the first segment, so that the damage is always at the end of the wal.
+                let transaction = Transaction::new(vec![round as u8; rng.gen_range(0..1024)]);
+                let block = Data::new(StatementBlock::new(
+                    authority,
+                    round,
+                    vec![],
+                    vec![BaseStatement::Share(transaction)],
+                    0,
+                    committee.epoch(),
+                    false,
+                    Default::default(),
+                    committee.hash_function(),
+                ));
+                let position =
+                    (&mut wal_writer, &recovered.block_store).insert_block(block.clone());
+                blocks.push((position, block));
+            }
+        }
+        wal_writer.sync().unwrap();
+        blocks
+    }
 }
@@ -0,0 +1,315 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::mpsc;
+
+use crate::{
+    block_handler::BlockHandler,
+    committee::Committee,
+    config::{node_defaults, NodeParameters},
+    crypto,
+    data::Data,
+    metrics::{Metrics, UtilizationTimerVecExt},
+    net_sync::NetworkSyncerInner,
+    runtime::{self, sleep, Handle, JoinHandle},
+    syncer::CommitObserver,
+    types::{BlockLimits, BlockReference, StatementBlock},
+};
+
+pub struct BlockVerifierParameters {
+    /// How long to wait for more blocks after the first block of a batch arrived.
+    pub batch_window: Duration,
+    /// The maximum number of blocks verified in a single batch.
+    pub max_batch_size: usize,
+    /// How far ahead of the local clock the creation time of a block may be.
+    pub max_clock_skew: Duration,
+    pub block_limits: BlockLimits,
+}
+
+impl Default for BlockVerifierParameters {
+    fn default() -> Self {
+        Self {
+            batch_window: Duration::from_millis(1),
+            max_batch_size: 256,
+            max_clock_skew: node_defaults::default_max_clock_skew(),
+            block_limits: NodeParameters::default().block_limits(),
+        }
+    }
+}
+
+/// A block that failed verification, reported to the connection it was received from.
+pub struct Rejection {
+    pub reference: BlockReference,
+    pub error: eyre::Report,
+}
+
+struct VerifyRequest {
+    block: Data<StatementBlock>,
+    rejections: mpsc::UnboundedSender<Rejection>,
+}
+
+/// Collects blocks received from all connections, verifies their signatures in batches
+/// and hands the valid blocks to the core thread.
+pub struct BlockVerifier {
+    sender: mpsc::Sender<VerifyRequest>,
+    handle: JoinHandle<Option<()>>,
+}
+
+impl BlockVerifier {
+    pub fn start<H, C>(
+        inner: Arc<NetworkSyncerInner<H, C>>,
+        committee: Arc<Committee>,
+        parameters: BlockVerifierParameters,
+        metrics: Arc<Metrics>,
+    ) -> Self
+    where
+        H: BlockHandler + 'static,
+        C: CommitObserver + 'static,
+    {
+        let (sender, receiver) = mpsc::channel(1000);
+        let worker = BlockVerifierWorker {
+            inner,
+            committee,
+            receiver,
+            parameters,
+            metrics,
+        };
+        let handle = Handle::current().spawn(worker.run());
+        Self { sender, handle }
+    }
+
+    /// Queues the block to be verified and, if it is valid, added to the core, without waiting for
+    /// either. An invalid block is reported on the rejections channel of its connection.
+    pub async fn submit(
+        &self,
+        block: Data<StatementBlock>,
+        rejections: &mpsc::UnboundedSender<Rejection>,
+    ) -> eyre::Result<()> {
+        let request = VerifyRequest {
+            block,
+            rejections: rejections.clone(),
+        };
+        if self.sender.send(request).await.is_err() {
+            eyre::bail!("Block verifier has stopped");
+        }
+        Ok(())
+    }
+
+    pub async fn shutdown(self) {
+        self.handle.abort();
+        self.handle.await.ok();
+    }
+}
+
+struct BlockVerifierWorker<H: BlockHandler, C: CommitObserver> {
+    inner: Arc<NetworkSyncerInner<H, C>>,
+    committee: Arc<Committee>,
+    receiver: mpsc::Receiver<VerifyRequest>,
+    parameters: BlockVerifierParameters,
+    metrics: Arc<Metrics>,
+}
+
+impl<H, C> BlockVerifierWorker<H, C>
+where
+    H: BlockHandler + 'static,
+    C: CommitObserver + 'static,
+{
+    async fn run(mut self) -> Option<()> {
+        loop {
+            let batch = self.next_batch().await?;
+            let results = verify_batch(
+                &self.committee,
+                batch.iter().map(|request| &request.block),
+                &self.parameters,
+                runtime::timestamp_utc(),
+                &self.metrics,
+            );
+            let verified: Vec<_> = batch
+                .iter()
+                .zip(results.iter())
+                .filter(|(_, result)| result.is_ok())
+                .map(|(request, _)| request.block.clone())
+                .collect();
+            if !verified.is_empty() {
+                self.inner.syncer.add_blocks(verified).await;
+            }
+            for (request, result) in batch.into_iter().zip(results) {
+                if let Err(error) = result {
+                    let rejection = Rejection {
+                        reference: *request.block.reference(),
+                        error,
+                    };
+                    // The connection might have been closed in the meantime
+                    request.rejections.send(rejection).ok();
+                }
+            }
+        }
+    }
+
+    async fn next_batch(&mut self) -> Option<Vec<VerifyRequest>> {
+        let mut batch = vec![self.receiver.recv().await?];
+        let window = sleep(self.parameters.batch_window);
+        tokio::pin!(window);
+        while batch.len() < self.parameters.max_batch_size {
+            tokio::select! {
+                _ = &mut window => break,
+                request = self.receiver.recv() => match request {
+                    Some(request) => batch.push(request),
+                    None => break,
+                },
+            }
+        }
+        Some(batch)
+    }
+}
+
+/// Verifies a batch of blocks, returning the verification result of each block in order.
+///
+/// Blocks created more than the allowed clock skew after `now` are rejected before their signature is
+/// checked. Signatures are checked with a single batch call. Only if that fails the signatures are
+/// re-checked one by one to find out which blocks are invalid.
+fn verify_batch<'a>(
+    committee: &Committee,
+    blocks: impl Iterator<Item = &'a Data<StatementBlock>>,
+    parameters: &BlockVerifierParameters,
+    now: Duration,
+    metrics: &Metrics,
+) -> Vec<eyre::Result<()>> {
+    let _timer = metrics
+        .utilization_timer
+        .utilization_timer("BlockVerifier::verify_batch");
+    let blocks: Vec<_> = blocks.collect();
+    let mut results: Vec<_> = blocks
+        .iter()
+        .map(|block| {
+            block.verify_without_signature(committee, &parameters.block_limits)?;
+            block.verify_creation_time_skew(now, parameters.max_clock_skew)
+        })
+        .collect();
+    let signed = blocks
+        .iter()
+        .zip(results.iter())
+        .filter(|(_, result)| result.is_ok())
+        .map(|(block, _)| {
+            let block: &StatementBlock = block;
+            let public_key = committee
+                .get_public_key(block.author())
+                .expect("Author is checked by verify_without_signature");
+            (public_key, block)
+        });
+    if crypto::verify_blocks_batch(signed, committee.hash_function()).is_ok() {
+        metrics
+            .block_verification_batches_total
+            .with_label_values(&["batch"])
+            .inc();
+        return results;
+    }
+    metrics
+        .block_verification_batches_total
+        .with_label_values(&["fallback"])
+        .inc();
+    for (block, result) in blocks.iter().zip(results.iter_mut()) {
+        if result.is_ok() {
+            *result = block.verify_signature(committee);
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto::{block_signing_digest, Signer},
+        test_util::{committee, test_metrics},
+        types::AuthorityIndex,
+    };
+
+    // A round one block of the authority, signed by the signer
+    fn signed_block(
+        committee: &Committee,
+        authority: AuthorityIndex,
+        signer: &Signer,
+    ) -> Data<StatementBlock> {
+        let mut includes = vec![BlockReference::new_test(authority, 0)];
+        includes.extend(
+            committee
+                .authorities()
+                .filter(|other| *other != authority)
+                .map(|other| BlockReference::new_test(other, 0)),
+        );
+        let hash_function = committee.hash_function();
+        let digest = block_signing_digest(authority, 1, &includes, &[], 0, 0, false, hash_function);
+        Data::new(StatementBlock::new(
+            authority,
+            1,
+            includes,
+            vec![],
+            0,
+            0,
+            false,
+            signer.sign_block_digest(&digest),
+            hash_function,
+        ))
+    }
+
+    fn verify(
+        committee: &Committee,
+        blocks: &[Data<StatementBlock>],
+        metrics: &Metrics,
+    ) -> Vec<bool> {
+        let parameters = BlockVerifierParameters::default();
+        verify_batch(
+            committee,
+            blocks.iter(),
+            &parameters,
+            runtime::timestamp_utc(),
+            metrics,
+        )
+        .iter()
+        .map(Result::is_ok)
+        .collect()
+    }
+
+    fn batches(metrics: &Metrics, kind: &str) -> u64 {
+        metrics
+            .block_verification_batches_total
+            .with_label_values(&[kind])
+            .get()
+    }
+
+    #[test]
+    fn verify_batch_accepts_signed_blocks() {
+        let committee = committee(4);
+        let signers = Signer::new_for_test(4);
+        let blocks: Vec<_> = committee
+            .authorities()
+            .map(|authority| signed_block(&committee, authority, &signers[authority as usize]))
+            .collect();
+        let metrics = test_metrics();
+        assert_eq!(verify(&committee, &blocks, &metrics), vec![true; 4]);
+        assert_eq!(batches(&metrics, "batch"), 1);
+        assert_eq!(batches(&metrics, "fallback"), 0);
+    }
+
+    #[test]
+    fn verify_batch_falls_back_to_find_bad_signature() {
+        let committee = committee(4);
+        let signers = Signer::new_for_test(4);
+        let mut blocks: Vec<_> = committee
+            .authorities()
+            .map(|authority| signed_block(&committee, authority, &signers[authority as usize]))
+            .collect();
+        // Signed with the key of another authority
+        blocks[2] = signed_block(&committee, 2, &signers[3]);
+        let metrics = test_metrics();
+        assert_eq!(
+            verify(&committee, &blocks, &metrics),
+            vec![true, true, false, true]
+        );
+        assert_eq!(batches(&metrics, "batch"), 0);
+        assert_eq!(batches(&metrics, "fallback"), 1);
+    }
+}
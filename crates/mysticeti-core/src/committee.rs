@@ -17,7 +17,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     config::ImportExport,
-    crypto::{dummy_public_key, PublicKey, Signer},
+    crypto::{HashFunction, PublicKey, SignatureScheme, Signer},
     data::Data,
     range_map::RangeMap,
     types::{
@@ -25,6 +25,7 @@ use crate::{
         AuthoritySet,
         BaseStatement,
         BlockReference,
+        Epoch,
         Stake,
         StatementBlock,
         TransactionLocator,
@@ -34,41 +35,128 @@ use crate::{
 };
 
 #[derive(Serialize, Deserialize)]
+#[serde(try_from = "CommitteeFile")]
 pub struct Committee {
     authorities: Vec<Authority>,
     validity_threshold: Stake, // The minimum stake required for validity
     quorum_threshold: Stake,   // The minimum stake required for quorum
+    #[serde(default)]
+    signature_scheme: SignatureScheme,
+    #[serde(default)]
+    hash_function: HashFunction,
+    #[serde(default)]
+    epoch: Epoch,
+    #[serde(skip)]
+    index: HashMap<PublicKey, AuthorityIndex>,
+}
+
+// Only the authorities, the hash function and the epoch are read from the committee file,
+// everything else is derived from them
+#[derive(Deserialize)]
+struct CommitteeFile {
+    authorities: Vec<Authority>,
+    #[serde(default)]
+    hash_function: HashFunction,
+    #[serde(default)]
+    epoch: Epoch,
+}
+
+impl TryFrom<CommitteeFile> for Committee {
+    type Error = String;
+
+    fn try_from(file: CommitteeFile) -> Result<Self, Self::Error> {
+        Self::from_authorities(file.authorities, file.hash_function, file.epoch)
+    }
 }
 
 impl Committee {
     pub const DEFAULT_FILENAME: &'static str = "committee.yaml";
 
     pub fn new_test(stake: Vec<Stake>) -> Arc<Self> {
-        let authorities = stake.into_iter().map(Authority::test_from_stake).collect();
+        // Test keys are generated in the canonical order, so authority i gets stake[i]
+        let authorities = Signer::new_for_test(stake.len())
+            .into_iter()
+            .zip(stake)
+            .map(|(signer, stake)| Authority::new(stake, signer.public_key()))
+            .collect();
         Self::new(authorities)
     }
 
     pub fn new(authorities: Vec<Authority>) -> Arc<Self> {
-        // todo - check duplicate public keys
-        // Ensure the list is not empty
-        assert!(!authorities.is_empty());
-
-        // Ensure all stakes are positive
-        assert!(authorities.iter().all(|a| a.stake() > 0));
-        assert!(authorities.len() <= 128); // For now AuthoritySet only supports up to 128 authorities
-
-        let mut total_stake: Stake = 0;
-        for a in authorities.iter() {
-            total_stake = total_stake
-                .checked_add(a.stake())
-                .expect("Total stake overflow");
+        Self::new_with_hash_function(authorities, HashFunction::default())
+    }
+
+    pub fn new_with_hash_function(
+        authorities: Vec<Authority>,
+        hash_function: HashFunction,
+    ) -> Arc<Self> {
+        match Self::from_authorities(authorities, hash_function, 0) {
+            Ok(committee) => Arc::new(committee),
+            Err(e) => panic!("Invalid committee: {e}"),
+        }
+    }
+
+    /// The committee of the next epoch, signing blocks with the same scheme and hash function.
+    pub fn next_epoch(&self, authorities: Vec<Authority>) -> Result<Arc<Self>, String> {
+        let committee = Self::from_authorities(authorities, self.hash_function, self.epoch + 1)?;
+        if committee.signature_scheme != self.signature_scheme {
+            return Err(format!(
+                "the next committee uses {} instead of {}",
+                committee.signature_scheme, self.signature_scheme
+            ));
         }
+        Ok(Arc::new(committee))
+    }
+
+    /// Authorities are ordered by public key: the index of an authority is derived from its key
+    /// alone, reordering the committee file does not change identities.
+    fn from_authorities(
+        mut authorities: Vec<Authority>,
+        hash_function: HashFunction,
+        epoch: Epoch,
+    ) -> Result<Self, String> {
+        if authorities.is_empty() {
+            return Err("committee is empty".to_string());
+        }
+        if authorities.iter().any(|a| a.stake() == 0) {
+            return Err("all authorities must have a positive stake".to_string());
+        }
+        // All authorities have to sign with the same scheme
+        let signature_scheme = authorities[0].public_key().scheme();
+        if authorities
+            .iter()
+            .any(|a| a.public_key().scheme() != signature_scheme)
+        {
+            return Err("all authorities must use the same signature scheme".to_string());
+        }
+
+        authorities.sort_by(|a, b| a.public_key().as_ref().cmp(b.public_key().as_ref()));
+        let mut index = HashMap::with_capacity(authorities.len());
+        for (i, a) in authorities.iter().enumerate() {
+            if index
+                .insert(a.public_key().clone(), i as AuthorityIndex)
+                .is_some()
+            {
+                return Err(format!(
+                    "public key of authority {i} is used more than once"
+                ));
+            }
+        }
+
+        let total_stake = authorities
+            .iter()
+            .try_fold(0 as Stake, |total, a| total.checked_add(a.stake()))
+            .ok_or("total stake overflows")?;
         let validity_threshold = total_stake / 3;
         let quorum_threshold = 2 * total_stake / 3;
-        Arc::new(Committee {
+        Ok(Committee {
             authorities,
             validity_threshold,
             quorum_threshold,
+            signature_scheme,
+            hash_function,
+            epoch,
+            index,
         })
     }
 
@@ -86,12 +174,30 @@ impl Committee {
         self.quorum_threshold + 1
     }
 
+    pub fn signature_scheme(&self) -> SignatureScheme {
+        self.signature_scheme
+    }
+
+    /// Hash function of the block digests produced and accepted by this committee.
+    pub fn hash_function(&self) -> HashFunction {
+        self.hash_function
+    }
+
+    /// Committees change from one epoch to the next, the genesis committee runs epoch 0.
+    pub fn epoch(&self) -> Epoch {
+        self.epoch
+    }
+
     pub fn get_public_key(&self, authority: AuthorityIndex) -> Option<&PublicKey> {
         self.authorities
             .get(authority as usize)
             .map(Authority::public_key)
     }
 
+    pub fn authority_index(&self, public_key: &PublicKey) -> Option<AuthorityIndex> {
+        self.index.get(public_key).copied()
+    }
+
     pub fn known_authority(&self, authority: AuthorityIndex) -> bool {
         authority < self.len() as AuthorityIndex
     }
@@ -111,11 +217,16 @@ impl Committee {
                 if a == for_authority {
                     None
                 } else {
-                    Some(StatementBlock::new_genesis(a))
+                    Some(StatementBlock::new_genesis(
+                        a,
+                        self.epoch,
+                        self.hash_function,
+                    ))
                 }
             })
             .collect();
-        let own_genesis_block = StatementBlock::new_genesis(for_authority);
+        let own_genesis_block =
+            StatementBlock::new_genesis(for_authority, self.epoch, self.hash_function);
         (own_genesis_block, other_blocks)
     }
 
@@ -127,6 +238,10 @@ impl Committee {
         amount > self.quorum_threshold
     }
 
+    pub fn total_stake(&self) -> Stake {
+        self.authorities.iter().map(Authority::stake).sum()
+    }
+
     pub fn get_total_stake<A: Borrow<AuthorityIndex>>(&self, authorities: &HashSet<A>) -> Stake {
         let mut total_stake = 0;
         for authority in authorities {
@@ -135,11 +250,6 @@ impl Committee {
         total_stake
     }
 
-    // TODO: fix to select by stake
-    pub fn elect_leader(&self, r: u64) -> AuthorityIndex {
-        (r % self.authorities.len() as u64) as AuthorityIndex
-    }
-
     pub fn random_authority(&self, rng: &mut impl Rng) -> AuthorityIndex {
         rng.gen_range(self.authorities())
     }
@@ -149,14 +259,27 @@ impl Committee {
     }
 
     pub fn new_for_benchmarks(committee_size: usize) -> Arc<Self> {
-        Self::new(
-            Signer::new_for_test(committee_size)
+        Self::new_for_benchmarks_with_scheme(
+            committee_size,
+            SignatureScheme::default(),
+            HashFunction::default(),
+        )
+    }
+
+    pub fn new_for_benchmarks_with_scheme(
+        committee_size: usize,
+        signature_scheme: SignatureScheme,
+        hash_function: HashFunction,
+    ) -> Arc<Self> {
+        Self::new_with_hash_function(
+            Signer::new_for_test_with_scheme(committee_size, signature_scheme)
                 .into_iter()
                 .map(|keypair| Authority {
                     stake: 1,
                     public_key: keypair.public_key(),
                 })
                 .collect(),
+            hash_function,
         )
     }
 }
@@ -168,11 +291,8 @@ pub struct Authority {
 }
 
 impl Authority {
-    pub fn test_from_stake(stake: Stake) -> Self {
-        Self {
-            stake,
-            public_key: dummy_public_key(),
-        }
+    pub fn new(stake: Stake, public_key: PublicKey) -> Self {
+        Self { stake, public_key }
     }
 
     pub fn stake(&self) -> Stake {
@@ -431,6 +551,7 @@ impl<TH: CommitteeThreshold, H: ProcessedTransactionHandler<TransactionLocator>>
                 BaseStatement::VoteRange(range) => {
                     self.vote(*range, block.author(), committee, &mut processed);
                 }
+                BaseStatement::Reconfigure(_) => {}
             }
         }
         processed
@@ -492,4 +613,58 @@ mod test {
         assert_eq!(Some(4..5), b.add(6));
         assert_eq!(Some(6..7), b.finish());
     }
+    #[test]
+    fn authority_index_follows_public_key() {
+        let signers = Signer::new_for_test(4);
+        let authorities: Vec<_> = signers
+            .iter()
+            .enumerate()
+            .map(|(i, signer)| Authority::new(i as Stake + 1, signer.public_key()))
+            .collect();
+        let committee = Committee::new(authorities.clone());
+
+        let mut reordered = authorities;
+        reordered.reverse();
+        let yaml = serde_yaml::to_string(&Committee::new(reordered)).unwrap();
+        let loaded: Committee = serde_yaml::from_str(&yaml).unwrap();
+        for (i, signer) in signers.iter().enumerate() {
+            let authority = loaded.authority_index(&signer.public_key()).unwrap();
+            assert_eq!(authority, i as AuthorityIndex);
+            assert_eq!(loaded.get_stake(authority), committee.get_stake(authority));
+        }
+        assert_eq!(loaded.quorum_threshold(), committee.quorum_threshold());
+
+        let duplicate = vec![Authority::new(1, signers[0].public_key()); 2];
+        assert!(Committee::from_authorities(duplicate, HashFunction::default(), 0).is_err());
+    }
+
+    #[test]
+    fn hash_function_in_committee_file() {
+        let committee = Committee::new_for_benchmarks_with_scheme(
+            4,
+            SignatureScheme::default(),
+            HashFunction::Sha256,
+        );
+        let yaml = serde_yaml::to_string(&committee).unwrap();
+        assert!(yaml.contains("hash_function: sha256"));
+        let loaded: Committee = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(loaded.hash_function(), HashFunction::Sha256);
+
+        // Committee files written before the hash function was configurable use blake2b
+        let yaml = yaml.replace("hash_function: sha256\n", "");
+        let loaded: Committee = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(loaded.hash_function(), HashFunction::Blake2b);
+    }
+
+    #[test]
+    fn stake_aggregator_large_committee() {
+        let committee = Committee::new_test(vec![1; 1000]);
+        let mut aggregator = StakeAggregator::<QuorumThreshold>::new();
+        for authority in 0..666 {
+            assert!(!aggregator.add(authority, &committee));
+        }
+        assert!(!aggregator.add(0, &committee));
+        assert!(aggregator.add(999, &committee));
+        assert_eq!(aggregator.voters().count(), 667);
+    }
 }
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::{
+    collections::BTreeMap,
     fs,
     io,
     net::{IpAddr, Ipv4Addr, SocketAddr},
@@ -9,11 +10,16 @@ use std::{
     time::Duration,
 };
 
+use digest::Digest;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::{
-    crypto::{dummy_signer, Signer},
-    types::{AuthorityIndex, PublicKey, RoundNumber},
+    block_signer::{BlockSigner, RemoteSigner},
+    consensus::MINIMUM_WAVE_LENGTH,
+    crypto::{dummy_signer, SignatureScheme, Signer},
+    equivocation::EquivocationPolicyKind,
+    keystore::Keystore,
+    types::{AuthorityIndex, BlockLimits, Epoch, PublicKey, RoundNumber},
 };
 
 pub trait ImportExport: Serialize + DeserializeOwned {
@@ -37,8 +43,23 @@ pub struct NodeParameters {
     pub wave_length: RoundNumber,
     #[serde(default = "node_defaults::default_leader_timeout")]
     pub leader_timeout: Duration,
+    /// Adapt the leader timeout to the latency of the leaders of each round and back off after
+    /// consecutive timeouts. The leader timeout then bounds the timeout derived from latencies.
+    #[serde(default = "node_defaults::default_adaptive_leader_timeout")]
+    pub adaptive_leader_timeout: bool,
+    /// Lower bound of the adaptive leader timeout.
+    #[serde(default = "node_defaults::default_min_leader_timeout")]
+    pub min_leader_timeout: Duration,
+    /// Upper bound of the adaptive leader timeout when backing off.
+    #[serde(default = "node_defaults::default_max_leader_timeout")]
+    pub max_leader_timeout: Duration,
+    /// The maximum size of a serialized block, larger blocks are rejected.
     #[serde(default = "node_defaults::default_max_block_size")]
     pub max_block_size: usize,
+    /// Blocks of other authorities more than this many rounds older than a block can not be
+    /// included in it.
+    #[serde(default = "node_defaults::default_max_include_round_gap")]
+    pub max_include_round_gap: RoundNumber,
     #[serde(default = "node_defaults::default_rounds_in_epoch")]
     pub rounds_in_epoch: RoundNumber,
     #[serde(default = "node_defaults::default_shutdown_grace_period")]
@@ -51,6 +72,78 @@ pub struct NodeParameters {
     pub consensus_only: bool,
     #[serde(default = "node_defaults::default_enable_synchronizer")]
     pub enable_synchronizer: bool,
+    /// Number of commits between two updates of the leader schedule from reputation scores.
+    /// Zero keeps the base schedule.
+    #[serde(default = "node_defaults::default_commits_per_schedule")]
+    pub commits_per_schedule: u64,
+    /// Percentage of the total stake whose leader slots may be handed over to better performing
+    /// authorities at each schedule update.
+    #[serde(default = "node_defaults::default_bad_nodes_stake_threshold")]
+    pub bad_nodes_stake_threshold: u64,
+    /// How far ahead of the local clock the creation time of a received block may be.
+    #[serde(default = "node_defaults::default_max_clock_skew")]
+    pub max_clock_skew: Duration,
+    /// How long the wal syncer batches the sync requests of own blocks and commits into a single
+    /// sync. Own blocks are sent to peers once synced.
+    #[serde(default = "node_defaults::default_wal_sync_interval")]
+    pub wal_sync_interval: Duration,
+    /// Compress the blocks and payloads written to the wal. Compressed entries are flagged, so
+    /// that it can be changed across restarts.
+    #[serde(default = "node_defaults::default_wal_compression")]
+    pub wal_compression: bool,
+    /// Bytes written to the wal between two snapshots of the recovered state, a restart only
+    /// replays the wal written after the latest snapshot.
+    #[serde(default = "node_defaults::default_wal_bytes_between_snapshots")]
+    pub wal_bytes_between_snapshots: u64,
+    /// Whether to keep accepting blocks from an authority once it was caught equivocating.
+    #[serde(default = "node_defaults::default_equivocation_policy")]
+    pub equivocation_policy: EquivocationPolicyKind,
+    /// Consensus parameters taking effect at the start of the given epoch, replacing the wave
+    /// length, number of leaders, pipelining and leader schedule parameters above from that
+    /// epoch onwards. Validators exchange a digest of the parameters of the epoch when connecting
+    /// and refuse peers whose parameters differ.
+    #[serde(default)]
+    pub epoch_consensus_parameters: BTreeMap<Epoch, ConsensusParameters>,
+}
+
+/// The parameters all nodes must agree on to commit the same leaders. They can only change at an
+/// epoch boundary.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConsensusParameters {
+    pub wave_length: RoundNumber,
+    pub number_of_leaders: usize,
+    pub enable_pipelining: bool,
+    #[serde(default = "node_defaults::default_commits_per_schedule")]
+    pub commits_per_schedule: u64,
+    #[serde(default = "node_defaults::default_bad_nodes_stake_threshold")]
+    pub bad_nodes_stake_threshold: u64,
+}
+
+impl ConsensusParameters {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.wave_length < MINIMUM_WAVE_LENGTH {
+            return Err(format!(
+                "wave length {} is below the minimum of {MINIMUM_WAVE_LENGTH}",
+                self.wave_length
+            ));
+        }
+        if self.number_of_leaders == 0 {
+            return Err("number of leaders must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Digest exchanged by validators when connecting, to refuse peers that would commit
+    /// different leaders.
+    pub fn digest(&self) -> [u8; 32] {
+        let mut hasher = blake2::Blake2b::<digest::consts::U32>::new();
+        hasher.update(self.wave_length.to_be_bytes());
+        hasher.update((self.number_of_leaders as u64).to_be_bytes());
+        hasher.update([self.enable_pipelining as u8]);
+        hasher.update(self.commits_per_schedule.to_be_bytes());
+        hasher.update(self.bad_nodes_stake_threshold.to_be_bytes());
+        hasher.finalize().into()
+    }
 }
 
 pub mod node_defaults {
@@ -62,10 +155,26 @@ pub mod node_defaults {
         std::time::Duration::from_secs(2)
     }
 
+    pub fn default_adaptive_leader_timeout() -> bool {
+        true
+    }
+
+    pub fn default_min_leader_timeout() -> std::time::Duration {
+        std::time::Duration::from_millis(250)
+    }
+
+    pub fn default_max_leader_timeout() -> std::time::Duration {
+        std::time::Duration::from_secs(10)
+    }
+
     pub fn default_max_block_size() -> usize {
         4 * 1024 * 1024
     }
 
+    pub fn default_max_include_round_gap() -> super::RoundNumber {
+        100
+    }
+
     pub fn default_rounds_in_epoch() -> super::RoundNumber {
         super::RoundNumber::MAX
     }
@@ -89,6 +198,34 @@ pub mod node_defaults {
     pub fn default_enable_synchronizer() -> bool {
         false
     }
+
+    pub fn default_commits_per_schedule() -> u64 {
+        300
+    }
+
+    pub fn default_bad_nodes_stake_threshold() -> u64 {
+        20
+    }
+
+    pub fn default_max_clock_skew() -> std::time::Duration {
+        std::time::Duration::from_secs(5)
+    }
+
+    pub fn default_wal_sync_interval() -> std::time::Duration {
+        std::time::Duration::from_millis(5)
+    }
+
+    pub fn default_wal_compression() -> bool {
+        false
+    }
+
+    pub fn default_wal_bytes_between_snapshots() -> u64 {
+        256 * 1024 * 1024
+    }
+
+    pub fn default_equivocation_policy() -> super::EquivocationPolicyKind {
+        super::EquivocationPolicyKind::Accept
+    }
 }
 
 impl Default for NodeParameters {
@@ -96,17 +233,65 @@ impl Default for NodeParameters {
         Self {
             wave_length: node_defaults::default_wave_length(),
             leader_timeout: node_defaults::default_leader_timeout(),
+            adaptive_leader_timeout: node_defaults::default_adaptive_leader_timeout(),
+            min_leader_timeout: node_defaults::default_min_leader_timeout(),
+            max_leader_timeout: node_defaults::default_max_leader_timeout(),
             max_block_size: node_defaults::default_max_block_size(),
+            max_include_round_gap: node_defaults::default_max_include_round_gap(),
             rounds_in_epoch: node_defaults::default_rounds_in_epoch(),
             shutdown_grace_period: node_defaults::default_shutdown_grace_period(),
             number_of_leaders: node_defaults::default_number_of_leaders(),
             enable_pipelining: node_defaults::default_enable_pipelining(),
             consensus_only: node_defaults::default_consensus_only(),
             enable_synchronizer: node_defaults::default_enable_synchronizer(),
+            commits_per_schedule: node_defaults::default_commits_per_schedule(),
+            bad_nodes_stake_threshold: node_defaults::default_bad_nodes_stake_threshold(),
+            max_clock_skew: node_defaults::default_max_clock_skew(),
+            wal_sync_interval: node_defaults::default_wal_sync_interval(),
+            wal_compression: node_defaults::default_wal_compression(),
+            wal_bytes_between_snapshots: node_defaults::default_wal_bytes_between_snapshots(),
+            equivocation_policy: node_defaults::default_equivocation_policy(),
+            epoch_consensus_parameters: BTreeMap::new(),
         }
     }
 }
 
+impl NodeParameters {
+    /// The consensus parameters in force during an epoch.
+    pub fn consensus_parameters(&self, epoch: Epoch) -> ConsensusParameters {
+        match self.epoch_consensus_parameters.range(..=epoch).next_back() {
+            Some((_, parameters)) => *parameters,
+            None => ConsensusParameters {
+                wave_length: self.wave_length,
+                number_of_leaders: self.number_of_leaders,
+                enable_pipelining: self.enable_pipelining,
+                commits_per_schedule: self.commits_per_schedule,
+                bad_nodes_stake_threshold: self.bad_nodes_stake_threshold,
+            },
+        }
+    }
+
+    pub fn block_limits(&self) -> BlockLimits {
+        BlockLimits {
+            max_block_size: self.max_block_size,
+            max_include_round_gap: self.max_include_round_gap,
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.max_include_round_gap == 0 {
+            return Err("max include round gap must be positive".to_string());
+        }
+        self.consensus_parameters(0).validate()?;
+        for (epoch, parameters) in &self.epoch_consensus_parameters {
+            parameters
+                .validate()
+                .map_err(|e| format!("consensus parameters of epoch {epoch}: {e}"))?;
+        }
+        Ok(())
+    }
+}
+
 impl ImportExport for NodeParameters {}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -117,17 +302,52 @@ pub struct NodeIdentifier {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "NodePublicConfigFile")]
 pub struct NodePublicConfig {
+    /// Ordered by public key, like the authorities of the committee.
     pub identifiers: Vec<NodeIdentifier>,
     pub parameters: NodeParameters,
 }
 
+#[derive(Deserialize)]
+struct NodePublicConfigFile {
+    identifiers: Vec<NodeIdentifier>,
+    parameters: NodeParameters,
+}
+
+impl TryFrom<NodePublicConfigFile> for NodePublicConfig {
+    type Error = String;
+
+    fn try_from(file: NodePublicConfigFile) -> Result<Self, Self::Error> {
+        let mut identifiers = file.identifiers;
+        identifiers.sort_by(|a, b| a.public_key.as_ref().cmp(b.public_key.as_ref()));
+        if identifiers
+            .windows(2)
+            .any(|pair| pair[0].public_key == pair[1].public_key)
+        {
+            return Err("public config lists the same public key more than once".to_string());
+        }
+        file.parameters.validate()?;
+        Ok(Self {
+            identifiers,
+            parameters: file.parameters,
+        })
+    }
+}
+
 impl NodePublicConfig {
     pub const DEFAULT_FILENAME: &'static str = "public-config.yaml";
     pub const PORT_OFFSET_FOR_TESTS: u16 = 1500;
 
     pub fn new_for_tests(committee_size: usize) -> Self {
-        let keys = Signer::new_for_test(committee_size);
+        Self::new_for_tests_with_scheme(committee_size, SignatureScheme::default())
+    }
+
+    pub fn new_for_tests_with_scheme(
+        committee_size: usize,
+        signature_scheme: SignatureScheme,
+    ) -> Self {
+        let keys = Signer::new_for_test_with_scheme(committee_size, signature_scheme);
         let ips = vec![IpAddr::V4(Ipv4Addr::LOCALHOST); committee_size];
         let benchmark_port_offset = ips.len() as u16;
         let mut identifiers = Vec::new();
@@ -151,7 +371,16 @@ impl NodePublicConfig {
     }
 
     pub fn new_for_benchmarks(ips: Vec<IpAddr>, node_parameters: Option<NodeParameters>) -> Self {
-        let default_with_ips = Self::new_for_tests(ips.len()).with_ips(ips);
+        Self::new_for_benchmarks_with_scheme(ips, node_parameters, SignatureScheme::default())
+    }
+
+    pub fn new_for_benchmarks_with_scheme(
+        ips: Vec<IpAddr>,
+        node_parameters: Option<NodeParameters>,
+        signature_scheme: SignatureScheme,
+    ) -> Self {
+        let default_with_ips =
+            Self::new_for_tests_with_scheme(ips.len(), signature_scheme).with_ips(ips);
         Self {
             identifiers: default_with_ips.identifiers,
             parameters: node_parameters.unwrap_or_default(),
@@ -186,6 +415,12 @@ impl NodePublicConfig {
         self.identifiers.iter().map(|id| id.metrics_address)
     }
 
+    pub fn identifier(&self, public_key: &PublicKey) -> Option<&NodeIdentifier> {
+        self.identifiers
+            .iter()
+            .find(|id| &id.public_key == public_key)
+    }
+
     pub fn network_address(&self, authority: AuthorityIndex) -> Option<SocketAddr> {
         self.identifiers
             .get(authority as usize)
@@ -201,38 +436,109 @@ impl NodePublicConfig {
 
 impl ImportExport for NodePublicConfig {}
 
+/// The private config does not name an authority: the node finds its index in the committee
+/// from its public key.
 #[derive(Serialize, Deserialize)]
 pub struct NodePrivateConfig {
-    authority: AuthorityIndex,
-    pub keypair: Signer,
+    /// Plaintext private key. Only benchmark configs carry it, otherwise it stays empty
+    /// until the keystore is unlocked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keypair: Option<Signer>,
+    /// Passphrase-encrypted keystore holding the private key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keystore_path: Option<PathBuf>,
+    /// Unix socket of a `mysticeti signer` process holding the private key,
+    /// used instead of the keypair and keystore.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_signer_path: Option<PathBuf>,
     pub storage_path: PathBuf,
 }
 
 impl NodePrivateConfig {
-    pub fn new_for_tests(index: AuthorityIndex) -> Self {
+    pub fn new_for_tests() -> Self {
         Self {
-            authority: index,
-            keypair: dummy_signer(),
+            keypair: Some(dummy_signer()),
+            keystore_path: None,
+            remote_signer_path: None,
             storage_path: PathBuf::from("storage"),
         }
     }
 
     pub fn new_for_benchmarks(working_dir: &Path, committee_size: usize) -> Vec<Self> {
-        Signer::new_for_test(committee_size)
+        Self::new_for_benchmarks_with_scheme(
+            working_dir,
+            committee_size,
+            SignatureScheme::default(),
+        )
+    }
+
+    pub fn new_for_benchmarks_with_scheme(
+        working_dir: &Path,
+        committee_size: usize,
+        signature_scheme: SignatureScheme,
+    ) -> Vec<Self> {
+        Signer::new_for_test_with_scheme(committee_size, signature_scheme)
             .into_iter()
             .enumerate()
             .map(|(i, keypair)| {
                 let authority = i as AuthorityIndex;
                 let path = working_dir.join(NodePrivateConfig::default_storage_path(authority));
                 Self {
-                    authority,
-                    keypair,
+                    keypair: Some(keypair),
+                    keystore_path: None,
+                    remote_signer_path: None,
                     storage_path: path,
                 }
             })
             .collect()
     }
 
+    pub fn new_with_keystore(keystore_path: PathBuf, storage_path: PathBuf) -> Self {
+        Self {
+            keypair: None,
+            keystore_path: Some(keystore_path),
+            remote_signer_path: None,
+            storage_path,
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.keypair.is_none() && self.remote_signer_path.is_none()
+    }
+
+    /// Decrypts the private key from the keystore. Does nothing if the key is already available.
+    pub fn unlock(&mut self, passphrase: &str) -> Result<(), io::Error> {
+        if !self.is_locked() {
+            return Ok(());
+        }
+        let Some(keystore_path) = &self.keystore_path else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Private config has neither a keypair nor a keystore",
+            ));
+        };
+        let keystore = Keystore::load(keystore_path)?;
+        let keypair = keystore
+            .decrypt(passphrase)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.keypair = Some(keypair);
+        Ok(())
+    }
+
+    /// Takes the unlocked private key out of the config, or connects to the remote signer.
+    pub fn block_signer(&mut self) -> Result<Box<dyn BlockSigner>, io::Error> {
+        if let Some(keypair) = self.keypair.take() {
+            return Ok(Box::new(keypair));
+        }
+        match &self.remote_signer_path {
+            Some(path) => Ok(Box::new(RemoteSigner::connect(path)?)),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Private key is locked",
+            )),
+        }
+    }
+
     pub fn default_filename(authority: AuthorityIndex) -> PathBuf {
         format!("private-config-{authority}.yaml").into()
     }
@@ -241,16 +547,36 @@ impl NodePrivateConfig {
         format!("storage-{authority}").into()
     }
 
-    pub fn certified_transactions_log(&self) -> PathBuf {
-        self.storage_path.join("certified.txt")
+    /// Every epoch starts a new dag, stored apart from the others. The genesis epoch is stored at
+    /// the root of the storage path, the next ones in `epoch-<n>` along with their committee and
+    /// public config.
+    pub fn epoch_storage_path(&self, epoch: Epoch) -> PathBuf {
+        match epoch {
+            0 => self.storage_path.clone(),
+            epoch => self.storage_path.join(format!("epoch-{epoch}")),
+        }
+    }
+
+    pub fn certified_transactions_log(&self, epoch: Epoch) -> PathBuf {
+        self.epoch_storage_path(epoch).join("certified.txt")
+    }
+
+    pub fn committed_transactions_log(&self, epoch: Epoch) -> PathBuf {
+        self.epoch_storage_path(epoch).join("committed.txt")
+    }
+
+    /// The directory holding the wal segments of the epoch.
+    pub fn wal(&self, epoch: Epoch) -> PathBuf {
+        self.epoch_storage_path(epoch).join("wal")
     }
 
-    pub fn committed_transactions_log(&self) -> PathBuf {
-        self.storage_path.join("committed.txt")
+    /// The directory holding the snapshots of the wal of the epoch.
+    pub fn snapshots(&self, epoch: Epoch) -> PathBuf {
+        self.epoch_storage_path(epoch).join("snapshots")
     }
 
-    pub fn wal(&self) -> PathBuf {
-        self.storage_path.join("wal")
+    pub fn sign_guard(&self) -> PathBuf {
+        self.storage_path.join("sign-guard")
     }
 }
 
@@ -3,7 +3,12 @@
 
 use std::{fmt::Display, sync::Arc};
 
-use super::{LeaderStatus, DEFAULT_WAVE_LENGTH};
+use super::{
+    decision_trace::{DecisionRule, DecisionTrace, LeaderBlockTrace, StakeTally},
+    leader_schedule::LeaderSchedule,
+    LeaderStatus,
+    DEFAULT_WAVE_LENGTH,
+};
 use crate::{
     block_store::BlockStore,
     committee::{Committee, QuorumThreshold, StakeAggregator},
@@ -45,15 +50,22 @@ pub struct BaseCommitter {
     committee: Arc<Committee>,
     /// Keep all block data
     block_store: BlockStore,
+    /// The leader schedule, shared with the other committers
+    leader_schedule: Arc<dyn LeaderSchedule>,
     /// The options used by this committer
     options: BaseCommitterOptions,
 }
 
 impl BaseCommitter {
-    pub fn new(committee: Arc<Committee>, block_store: BlockStore) -> Self {
+    pub fn new(
+        committee: Arc<Committee>,
+        block_store: BlockStore,
+        leader_schedule: Arc<dyn LeaderSchedule>,
+    ) -> Self {
         Self {
             committee,
             block_store,
+            leader_schedule,
             options: BaseCommitterOptions::default(),
         }
     }
@@ -91,8 +103,10 @@ impl BaseCommitter {
             return None;
         }
 
-        let offset = self.options.leader_offset as RoundNumber;
-        Some(self.committee.elect_leader(round + offset))
+        Some(
+            self.leader_schedule
+                .elect_leader(round, self.options.leader_offset),
+        )
     }
 
     /// Find which block is supported at (author, round) by the given block.
@@ -259,34 +273,43 @@ impl BaseCommitter {
         false
     }
 
-    /// Apply the indirect decision rule to the specified leader to see whether we can indirect-commit
-    /// or indirect-skip it.
-    #[tracing::instrument(skip_all, fields(leader = %format_authority_round(leader, leader_round)))]
-    pub fn try_indirect_decide<'a>(
+    /// The anchor of a leader is the first committed leader with round higher than the decision
+    /// round of the leader. The search stops upon encountering an undecided leader.
+    fn find_anchor<'a>(
         &self,
-        leader: AuthorityIndex,
         leader_round: RoundNumber,
         leaders: impl Iterator<Item = &'a LeaderStatus>,
-    ) -> LeaderStatus {
-        // The anchor is the first committed leader with round higher than the decision round of the
-        // target leader. We must stop the iteration upon encountering an undecided leader.
+    ) -> Option<&'a Data<StatementBlock>> {
         let anchors = leaders.filter(|x| leader_round + self.options.wave_length <= x.round());
-
         for anchor in anchors {
-            tracing::trace!(
-                "[{self}] Trying to indirect-decide {} using anchor {anchor}",
-                format_authority_round(leader, leader_round),
-            );
             match anchor {
-                LeaderStatus::Commit(anchor) => {
-                    return self.decide_leader_from_anchor(anchor, leader, leader_round);
-                }
+                LeaderStatus::Commit(anchor) => return Some(anchor),
                 LeaderStatus::Skip(..) => (),
                 LeaderStatus::Undecided(..) => break,
             }
         }
+        None
+    }
 
-        LeaderStatus::Undecided(leader, leader_round)
+    /// Apply the indirect decision rule to the specified leader to see whether we can indirect-commit
+    /// or indirect-skip it.
+    #[tracing::instrument(skip_all, fields(leader = %format_authority_round(leader, leader_round)))]
+    pub fn try_indirect_decide<'a>(
+        &self,
+        leader: AuthorityIndex,
+        leader_round: RoundNumber,
+        leaders: impl Iterator<Item = &'a LeaderStatus>,
+    ) -> LeaderStatus {
+        match self.find_anchor(leader_round, leaders) {
+            Some(anchor) => {
+                tracing::trace!(
+                    "[{self}] Trying to indirect-decide {} using anchor {anchor}",
+                    format_authority_round(leader, leader_round),
+                );
+                self.decide_leader_from_anchor(anchor, leader, leader_round)
+            }
+            None => LeaderStatus::Undecided(leader, leader_round),
+        }
     }
 
     /// Apply the direct decision rule to the specified leader to see whether we can direct-commit or
@@ -333,6 +356,92 @@ impl BaseCommitter {
     }
 }
 
+impl BaseCommitter {
+    /// Explain the decision taken for a leader: the rule that decided it, and every vote,
+    /// certificate and blame it counted. The `leaders` are the leaders decided after it, as
+    /// passed to `try_indirect_decide`.
+    pub fn trace_decision<'a>(
+        &self,
+        status: &LeaderStatus,
+        direct: bool,
+        leaders: impl Iterator<Item = &'a LeaderStatus>,
+    ) -> DecisionTrace {
+        let (leader, leader_round) = (status.authority(), status.round());
+        let rule = match (status, direct) {
+            (LeaderStatus::Commit(..), true) => DecisionRule::DirectCommit,
+            (LeaderStatus::Skip(..), true) => DecisionRule::DirectSkip,
+            (LeaderStatus::Commit(..), false) => DecisionRule::IndirectCommit,
+            (LeaderStatus::Skip(..), false) => DecisionRule::IndirectSkip,
+            (LeaderStatus::Undecided(..), _) => DecisionRule::Undecided,
+        };
+        let anchor = match rule {
+            DecisionRule::IndirectCommit | DecisionRule::IndirectSkip => {
+                self.find_anchor(leader_round, leaders)
+            }
+            _ => None,
+        };
+
+        let voting_round = leader_round + 1;
+        let mut blames = StakeTally::default();
+        for voting_block in self.block_store.get_blocks_by_round(voting_round) {
+            if voting_block
+                .includes()
+                .iter()
+                .all(|include| include.authority != leader)
+            {
+                blames.add(voting_block.author(), &self.committee);
+            }
+        }
+
+        let decision_round = self.decision_round(self.wave_number(leader_round));
+        let vote_blocks = self.block_store.get_blocks_by_round(decision_round - 1);
+        let decision_blocks = self.block_store.get_blocks_by_round(decision_round);
+        let leader_blocks = self
+            .block_store
+            .get_blocks_at_authority_round(leader, leader_round)
+            .into_iter()
+            .map(|leader_block| {
+                let mut votes = StakeTally::default();
+                for block in &vote_blocks {
+                    if self.is_vote(block, &leader_block) {
+                        votes.add(block.author(), &self.committee);
+                    }
+                }
+                let mut certificates = StakeTally::default();
+                let mut anchor_certificates = anchor.map(|_| StakeTally::default());
+                for block in &decision_blocks {
+                    if self.is_certificate(block, &leader_block) {
+                        certificates.add(block.author(), &self.committee);
+                        if let (Some(anchor), Some(tally)) = (anchor, anchor_certificates.as_mut())
+                        {
+                            if self.block_store.linked(anchor, block) {
+                                tally.add(block.author(), &self.committee);
+                            }
+                        }
+                    }
+                }
+                LeaderBlockTrace {
+                    block: *leader_block.reference(),
+                    votes,
+                    certificates,
+                    anchor_certificates,
+                }
+            })
+            .collect();
+
+        DecisionTrace {
+            leader,
+            round: leader_round,
+            committer: self.to_string(),
+            rule,
+            quorum_threshold: self.committee.quorum_threshold(),
+            blames,
+            leader_blocks,
+            anchor: anchor.map(|anchor| *anchor.reference()),
+        }
+    }
+}
+
 impl Display for BaseCommitter {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
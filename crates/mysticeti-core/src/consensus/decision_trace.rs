@@ -0,0 +1,316 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    fmt::Display,
+    fs,
+    io,
+    path::Path,
+    sync::Arc,
+};
+
+use prometheus::Registry;
+use serde::Serialize;
+
+use super::universal_committer::UniversalCommitterBuilder;
+use crate::{
+    block_store::{BlockStore, BlockWriter},
+    committee::Committee,
+    config::NodeParameters,
+    data::Data,
+    metrics::Metrics,
+    state::RecoveredState,
+    types::{
+        format_authority_index,
+        format_authority_round,
+        AuthorityIndex,
+        BlockReference,
+        RoundNumber,
+        Stake,
+        StatementBlock,
+    },
+    wal::{open_wal_for_reading, walf},
+};
+
+/// The rule that decided a leader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DecisionRule {
+    /// 2f+1 certificates for the leader block in the decision round.
+    DirectCommit,
+    /// 2f+1 blocks of the voting round that do not include the leader.
+    DirectSkip,
+    /// The anchor is linked to a certificate for the leader block.
+    IndirectCommit,
+    /// The anchor is not linked to any certificate for the leader.
+    IndirectSkip,
+    /// Neither rule applied yet.
+    Undecided,
+}
+
+impl Display for DecisionRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rule = match self {
+            Self::DirectCommit => "direct-commit",
+            Self::DirectSkip => "direct-skip",
+            Self::IndirectCommit => "indirect-commit",
+            Self::IndirectSkip => "indirect-skip",
+            Self::Undecided => "undecided",
+        };
+        write!(f, "{rule}")
+    }
+}
+
+/// The authorities counted towards a decision and their total stake. Each authority is counted
+/// once, even if it equivocated.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StakeTally {
+    pub authorities: Vec<AuthorityIndex>,
+    pub stake: Stake,
+}
+
+impl StakeTally {
+    pub fn add(&mut self, authority: AuthorityIndex, committee: &Committee) {
+        if self.authorities.contains(&authority) {
+            return;
+        }
+        self.authorities.push(authority);
+        self.stake += committee.get_stake(authority).unwrap_or_default();
+    }
+}
+
+impl Display for StakeTally {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let authorities: Vec<_> = self
+            .authorities
+            .iter()
+            .map(|a| format_authority_index(*a))
+            .collect();
+        write!(f, "[{}] stake {}", authorities.join(","), self.stake)
+    }
+}
+
+/// Support for one block of the leader (a Byzantine leader may propose several).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LeaderBlockTrace {
+    pub block: BlockReference,
+    /// Blocks of the round before the decision round that support the leader block.
+    pub votes: StakeTally,
+    /// Blocks of the decision round that include a quorum of votes for the leader block.
+    pub certificates: StakeTally,
+    /// The certificates linked to the anchor, for indirect decisions.
+    pub anchor_certificates: Option<StakeTally>,
+}
+
+/// Why a leader was committed, skipped or left undecided by a committer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DecisionTrace {
+    pub leader: AuthorityIndex,
+    pub round: RoundNumber,
+    /// The base committer that elected the leader.
+    pub committer: String,
+    pub rule: DecisionRule,
+    /// The stake of a quorum, needed for direct decisions and certificates.
+    pub quorum_threshold: Stake,
+    /// Blocks of the voting round that do not include any block of the leader.
+    pub blames: StakeTally,
+    pub leader_blocks: Vec<LeaderBlockTrace>,
+    /// The committed leader used to indirectly decide this leader.
+    pub anchor: Option<BlockReference>,
+}
+
+impl Display for DecisionTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} ({}): {}, quorum {}",
+            format_authority_round(self.leader, self.round),
+            self.committer,
+            self.rule,
+            self.quorum_threshold
+        )?;
+        writeln!(f, "  blames {}", self.blames)?;
+        if self.leader_blocks.is_empty() {
+            writeln!(f, "  no leader block")?;
+        }
+        for leader_block in &self.leader_blocks {
+            writeln!(f, "  block {}", leader_block.block)?;
+            writeln!(f, "    votes {}", leader_block.votes)?;
+            writeln!(f, "    certificates {}", leader_block.certificates)?;
+            if let Some(anchor_certificates) = &leader_block.anchor_certificates {
+                writeln!(f, "    certificates linked to anchor {anchor_certificates}")?;
+            }
+        }
+        if let Some(anchor) = self.anchor {
+            writeln!(f, "  anchor {anchor}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Explain the decisions on the leaders of the dag stored in the wal of a validator, starting from
+/// the specified round. The validator's reputation-based leader swaps are applied, but only the
+/// latest two swap tables are kept in the wal, so older leaders follow the base schedule.
+pub fn explain_wal(
+    path: impl AsRef<Path>,
+    committee: Arc<Committee>,
+    parameters: &NodeParameters,
+    from_round: RoundNumber,
+) -> io::Result<Vec<DecisionTrace>> {
+    let recovered = open_wal(path, &committee)?;
+    Ok(explain(recovered, committee, parameters, from_round))
+}
+
+/// Explain the decisions on the leaders of a dag dump written by [`write_dag_dump`], starting
+/// from the specified round. Dumps carry no leader reputation, leaders follow the base schedule.
+pub fn explain_dag_dump(
+    path: impl AsRef<Path>,
+    committee: Arc<Committee>,
+    parameters: &NodeParameters,
+    from_round: RoundNumber,
+) -> io::Result<Vec<DecisionTrace>> {
+    let recovered = open_dag_dump(path, &committee)?;
+    Ok(explain(recovered, committee, parameters, from_round))
+}
+
+/// Load the blocks of a validator wal, without modifying it. The wal is either a directory of
+/// segments or, for wals written before segments, a single file.
+pub(crate) fn open_wal(
+    path: impl AsRef<Path>,
+    committee: &Committee,
+) -> io::Result<RecoveredState> {
+    let (wal_writer, wal_reader) = open_wal_for_reading(path)?;
+    let metrics = Metrics::new(&Registry::new(), Some(committee)).0;
+    Ok(BlockStore::open(
+        0,
+        Arc::new(wal_reader),
+        &wal_writer,
+        metrics,
+        committee,
+    )?)
+}
+
+/// Load the blocks of a dag dump into a temporary wal.
+pub(crate) fn open_dag_dump(
+    path: impl AsRef<Path>,
+    committee: &Committee,
+) -> io::Result<RecoveredState> {
+    let blocks: Vec<Data<StatementBlock>> = bincode::deserialize(&fs::read(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let (mut wal_writer, wal_reader) = walf(tempfile::tempfile()?)?;
+    let metrics = Metrics::new(&Registry::new(), Some(committee)).0;
+    let recovered = BlockStore::open(0, Arc::new(wal_reader), &wal_writer, metrics, committee)?;
+    for block in blocks {
+        (&mut wal_writer, &recovered.block_store).insert_block(block);
+    }
+    Ok(recovered)
+}
+
+/// Write a dag dump: the bincode encoding of the list of blocks.
+pub fn write_dag_dump(blocks: &[Data<StatementBlock>], path: impl AsRef<Path>) -> io::Result<()> {
+    let bytes =
+        bincode::serialize(blocks).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    fs::write(path, bytes)
+}
+
+fn explain(
+    recovered: RecoveredState,
+    committee: Arc<Committee>,
+    parameters: &NodeParameters,
+    from_round: RoundNumber,
+) -> Vec<DecisionTrace> {
+    // Configured as in the core
+    let metrics = Metrics::new(&Registry::new(), Some(&committee)).0;
+    let consensus_parameters = parameters.consensus_parameters(committee.epoch());
+    let committer = UniversalCommitterBuilder::new(committee, recovered.block_store, metrics)
+        .with_consensus_parameters(&consensus_parameters)
+        .build();
+    if let Some(leader_reputation) = recovered.leader_reputation {
+        for swap_table in leader_reputation.swap_tables().iter() {
+            committer.leader_schedule().update(swap_table.clone());
+        }
+    }
+    committer.explain(from_round)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        consensus::{universal_committer::UniversalCommitterBuilder, LeaderStatus},
+        test_util::{build_dag, build_dag_layer, committee, test_metrics, TestBlockWriter},
+    };
+
+    #[test]
+    fn explain_direct_decisions() {
+        let committee = committee(4);
+        let mut block_writer = TestBlockWriter::new(&committee);
+        build_dag(&committee, &mut block_writer, None, 7);
+        let committer = UniversalCommitterBuilder::new(
+            committee.clone(),
+            block_writer.into_block_store(),
+            test_metrics(),
+        )
+        .build();
+
+        let traces = committer.explain(1);
+        let sequence = committer.try_commit(BlockReference::default());
+        let LeaderStatus::Commit(leader_block) = &sequence[0] else {
+            panic!("Expected a commit: {sequence:?}");
+        };
+        let trace = traces
+            .iter()
+            .find(|trace| trace.round == leader_block.round())
+            .unwrap();
+        assert_eq!(trace.rule, DecisionRule::DirectCommit);
+        assert_eq!(trace.leader, leader_block.author());
+        assert_eq!(trace.blames.stake, 0);
+        assert_eq!(trace.leader_blocks.len(), 1);
+        assert_eq!(trace.leader_blocks[0].votes.stake, 4);
+        assert_eq!(trace.leader_blocks[0].certificates.stake, 4);
+        assert!(trace.anchor.is_none());
+        // The leader of the last wave misses its decision round
+        let last = traces.last().unwrap();
+        assert_eq!((last.round, last.rule), (6, DecisionRule::Undecided));
+    }
+
+    #[test]
+    fn explain_skip_from_dag_dump() {
+        let committee = committee(4);
+        let leader_round = 3;
+        let leader = crate::test_util::elect_leader(&committee, leader_round, 0);
+        let mut block_writer = TestBlockWriter::new(&committee);
+        let references = build_dag(&committee, &mut block_writer, None, leader_round);
+
+        // Nobody votes for the leader
+        let connections: Vec<_> = committee
+            .authorities()
+            .map(|authority| {
+                let includes = references
+                    .iter()
+                    .filter(|reference| reference.authority != leader)
+                    .cloned()
+                    .collect();
+                (authority, includes)
+            })
+            .collect();
+        let references = build_dag_layer(connections, &mut block_writer);
+        build_dag(&committee, &mut block_writer, Some(references), 6);
+
+        let dir = tempdir::TempDir::new("explain_skip_from_dag_dump").unwrap();
+        let path = dir.path().join("dag");
+        let blocks: Vec<_> = (0..=6)
+            .flat_map(|round| block_writer.block_store().get_blocks_by_round(round))
+            .collect();
+        write_dag_dump(&blocks, &path).unwrap();
+
+        let traces =
+            explain_dag_dump(&path, committee, &NodeParameters::default(), leader_round).unwrap();
+        let trace = traces
+            .iter()
+            .find(|trace| trace.round == leader_round && trace.leader == leader)
+            .unwrap();
+        assert_eq!(trace.rule, DecisionRule::DirectSkip);
+        assert_eq!(trace.blames.stake, 4);
+        assert_eq!(trace.leader_blocks[0].votes.stake, 0);
+    }
+}
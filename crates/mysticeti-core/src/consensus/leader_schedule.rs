@@ -0,0 +1,566 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashSet},
+    fmt::Display,
+    sync::Arc,
+};
+
+use digest::Digest;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use super::linearizer::CommittedSubDag;
+use crate::{
+    committee::Committee,
+    types::{format_authority_index, AuthorityIndex, BlockReference, RoundNumber, Stake},
+};
+
+/// Committed leaders stop collecting votes once the latest committed leader is this many rounds
+/// ahead of them. Votes are usually committed within a couple of waves of their leader.
+const VOTE_RETENTION_ROUNDS: RoundNumber = 100;
+
+/// Decides which authorities lead each round. Every node must derive the same leaders from the
+/// same inputs, so implementations have to be deterministic.
+pub trait LeaderSchedule: Send + Sync {
+    /// Elect the leader of the specified round for the given leader offset. Different offsets
+    /// must elect different leaders within a round, as long as the committee is large enough.
+    fn elect_leader(&self, round: RoundNumber, leader_offset: u64) -> AuthorityIndex;
+}
+
+/// The default schedule. The leaders of a round are drawn one after the other without
+/// replacement, each with probability proportional to its stake, so the offsets of a round
+/// elect distinct authorities. Draws are derived from a seed fixed for the epoch.
+pub struct StakeWeightedSchedule {
+    /// The running total of stakes in authority order, `cumulative_stakes[i]` being the stake of
+    /// authorities `0..=i`. Computed once for the epoch so draws only need a binary search.
+    cumulative_stakes: Vec<Stake>,
+    seed: [u8; 32],
+}
+
+impl StakeWeightedSchedule {
+    pub fn new(committee: Arc<Committee>, epoch: u64) -> Self {
+        // Different committees get different schedules even within the same epoch
+        let mut hasher = ScheduleHasher::default();
+        hasher.update(epoch.to_be_bytes());
+        for authority in committee.authorities() {
+            let public_key = committee.get_public_key(authority).unwrap();
+            hasher.update(public_key.as_ref());
+            hasher.update(committee.get_stake(authority).unwrap().to_be_bytes());
+        }
+        let cumulative_stakes = committee
+            .authorities()
+            .scan(0, |total, authority| {
+                *total += committee.get_stake(authority).unwrap();
+                Some(*total)
+            })
+            .collect();
+        Self {
+            cumulative_stakes,
+            seed: hasher.finalize().into(),
+        }
+    }
+
+    fn stake(&self, authority: AuthorityIndex) -> Stake {
+        let index = authority as usize;
+        let below = index
+            .checked_sub(1)
+            .map_or(0, |i| self.cumulative_stakes[i]);
+        self.cumulative_stakes[index] - below
+    }
+
+    fn draw(&self, round: RoundNumber, draw: u64) -> u64 {
+        let mut hasher = ScheduleHasher::default();
+        hasher.update(self.seed);
+        hasher.update(round.to_be_bytes());
+        hasher.update(draw.to_be_bytes());
+        let digest = hasher.finalize();
+        u64::from_be_bytes(digest[..8].try_into().unwrap())
+    }
+}
+
+type ScheduleHasher = blake2::Blake2b<digest::consts::U32>;
+
+impl LeaderSchedule for StakeWeightedSchedule {
+    fn elect_leader(&self, round: RoundNumber, leader_offset: u64) -> AuthorityIndex {
+        let leader_offset = leader_offset % self.cumulative_stakes.len() as u64;
+        // Authorities drawn earlier in the round, in authority order
+        let mut drawn: Vec<AuthorityIndex> = Vec::new();
+        let mut total_stake = *self
+            .cumulative_stakes
+            .last()
+            .expect("Committee is not empty");
+        for draw in 0..=leader_offset {
+            // The target indexes the stake of the authorities not drawn yet. Skipping over the
+            // stake of the drawn ones maps it back onto the cumulative stakes of the committee.
+            let mut target = self.draw(round, draw) % total_stake;
+            for &authority in &drawn {
+                let start = self.cumulative_stakes[authority as usize] - self.stake(authority);
+                if target < start {
+                    break;
+                }
+                target += self.stake(authority);
+            }
+            let authority =
+                self.cumulative_stakes
+                    .partition_point(|&stake| stake <= target) as AuthorityIndex;
+            if draw == leader_offset {
+                return authority;
+            }
+            let position = drawn.partition_point(|&a| a < authority);
+            drawn.insert(position, authority);
+            total_stake -= self.stake(authority);
+        }
+        unreachable!("The last draw returns")
+    }
+}
+
+/// Elects leaders in turn, regardless of stake.
+pub struct RoundRobinSchedule {
+    committee_size: u64,
+}
+
+impl RoundRobinSchedule {
+    pub fn new(committee: &Committee) -> Self {
+        Self {
+            committee_size: committee.len() as u64,
+        }
+    }
+}
+
+impl LeaderSchedule for RoundRobinSchedule {
+    fn elect_leader(&self, round: RoundNumber, leader_offset: u64) -> AuthorityIndex {
+        (round + leader_offset) % self.committee_size
+    }
+}
+
+/// Follows a base schedule, except that leaders in the `bad` set of the swap table hand their
+/// slot over to one of the `good` authorities (as in HammerHead). It is shared by all committers
+/// of a node; the core installs a new swap table at fixed commit intervals.
+#[derive(Clone)]
+pub struct ReputationSchedule {
+    base: Arc<dyn LeaderSchedule>,
+    committee: Arc<Committee>,
+    leaders_per_round: usize,
+    swap_tables: Arc<RwLock<SwapTables>>,
+}
+
+impl ReputationSchedule {
+    pub fn new(
+        base: Arc<dyn LeaderSchedule>,
+        committee: Arc<Committee>,
+        leaders_per_round: usize,
+    ) -> Self {
+        Self {
+            base,
+            committee,
+            leaders_per_round,
+            swap_tables: Default::default(),
+        }
+    }
+
+    /// The swap table in use at the specified round, if any.
+    pub fn swap_table(&self, round: RoundNumber) -> Option<LeaderSwapTable> {
+        self.swap_tables.read().at_round(round).cloned()
+    }
+
+    /// Whether reputation affects the leaders of the specified round, that is, some authorities
+    /// are swapped out.
+    pub fn is_reputation_based(&self, round: RoundNumber) -> bool {
+        self.swap_tables
+            .read()
+            .at_round(round)
+            .is_some_and(|table| !table.bad.is_empty())
+    }
+
+    pub fn update(&self, swap_table: LeaderSwapTable) {
+        self.swap_tables.write().push(swap_table);
+    }
+}
+
+impl LeaderSchedule for ReputationSchedule {
+    fn elect_leader(&self, round: RoundNumber, leader_offset: u64) -> AuthorityIndex {
+        let leader = self.base.elect_leader(round, leader_offset);
+        let swap_tables = self.swap_tables.read();
+        let Some(table) = swap_tables.at_round(round) else {
+            return leader;
+        };
+        if !table.bad.contains(&leader) {
+            return leader;
+        }
+
+        // Replacements must not collide with the other leaders of the round, so the whole round
+        // is resolved in offset order.
+        let leaders_per_round = self.leaders_per_round.max(leader_offset as usize + 1) as u64;
+        let base_leaders: Vec<_> = (0..leaders_per_round)
+            .map(|offset| self.base.elect_leader(round, offset))
+            .collect();
+        let mut taken: HashSet<_> = base_leaders
+            .iter()
+            .filter(|leader| !table.bad.contains(leader))
+            .copied()
+            .collect();
+        for (offset, &leader) in base_leaders.iter().enumerate() {
+            if !table.bad.contains(&leader) {
+                continue;
+            }
+            let replacement = table
+                .candidates(round + offset as u64, &self.committee)
+                .find(|candidate| !taken.contains(candidate))
+                .unwrap_or(leader);
+            if offset as u64 == leader_offset {
+                return replacement;
+            }
+            taken.insert(replacement);
+        }
+        unreachable!("Leader offset {leader_offset} is resolved above")
+    }
+}
+
+/// The latest swap table, and the one it replaced. The committer still needs the previous table
+/// to elect the leaders up to the round of the commit that closed the reputation window.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct SwapTables(Vec<LeaderSwapTable>);
+
+impl SwapTables {
+    fn push(&mut self, swap_table: LeaderSwapTable) {
+        // A table that never took effect is replaced rather than kept as the previous one
+        if self
+            .0
+            .last()
+            .is_some_and(|last| last.first_round >= swap_table.first_round)
+        {
+            self.0.pop();
+        }
+        self.0.push(swap_table);
+        if self.0.len() > 2 {
+            self.0.remove(0);
+        }
+    }
+
+    fn at_round(&self, round: RoundNumber) -> Option<&LeaderSwapTable> {
+        self.0.iter().rev().find(|table| table.first_round <= round)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &LeaderSwapTable> {
+        self.0.iter()
+    }
+}
+
+/// The authorities swapped out of their leader slots (`bad`) and those taking them over (`good`),
+/// from `first_round` on.
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq, Eq)]
+pub struct LeaderSwapTable {
+    first_round: RoundNumber,
+    /// Best scores first.
+    good: Vec<AuthorityIndex>,
+    /// Worst scores first.
+    bad: Vec<AuthorityIndex>,
+}
+
+impl LeaderSwapTable {
+    /// Swap the authorities with the lowest scores for those with the highest ones. Each side
+    /// holds at most `stake_threshold` percent of the total stake, and never more than the
+    /// stake of `f` authorities. Ties are broken by authority index.
+    pub fn from_scores(
+        scores: &[u64],
+        committee: &Committee,
+        stake_threshold: u64,
+        first_round: RoundNumber,
+    ) -> Self {
+        let max_stake = (committee.total_stake() * stake_threshold / 100)
+            .min(committee.validity_threshold() - 1);
+        let mut ranked: Vec<_> = committee.authorities().collect();
+        ranked.sort_by_key(|authority| (Reverse(scores[*authority as usize]), *authority));
+
+        let good = Self::take_stake(ranked.iter().copied(), committee, max_stake);
+        // Authorities doing as well as the good ones are not swapped out
+        let lowest_good_score = good.last().map_or(0, |a| scores[*a as usize]);
+        let bad = Self::take_stake(
+            ranked
+                .iter()
+                .rev()
+                .copied()
+                .take_while(|a| scores[*a as usize] < lowest_good_score),
+            committee,
+            max_stake,
+        );
+        if bad.is_empty() {
+            return Self {
+                first_round,
+                ..Default::default()
+            };
+        }
+        Self {
+            first_round,
+            good,
+            bad,
+        }
+    }
+
+    fn take_stake(
+        authorities: impl Iterator<Item = AuthorityIndex>,
+        committee: &Committee,
+        max_stake: Stake,
+    ) -> Vec<AuthorityIndex> {
+        let mut stake = 0;
+        authorities
+            .take_while(|authority| {
+                stake += committee.get_stake(*authority).unwrap();
+                stake <= max_stake
+            })
+            .collect()
+    }
+
+    /// Replacements for a bad leader, in order of preference: the good authorities first, then
+    /// any authority that is not swapped out. Both lists are rotated by `seed`.
+    fn candidates<'a>(
+        &'a self,
+        seed: u64,
+        committee: &'a Committee,
+    ) -> impl Iterator<Item = AuthorityIndex> + 'a {
+        let good =
+            (0..self.good.len()).map(move |i| self.good[(seed as usize + i) % self.good.len()]);
+        let others = committee
+            .authorities()
+            .map(move |i| (seed + i) % committee.len() as u64)
+            .filter(|authority| !self.bad.contains(authority));
+        good.chain(others)
+    }
+
+    pub fn first_round(&self) -> RoundNumber {
+        self.first_round
+    }
+
+    pub fn good(&self) -> &[AuthorityIndex] {
+        &self.good
+    }
+
+    pub fn bad(&self) -> &[AuthorityIndex] {
+        &self.bad
+    }
+}
+
+impl Display for LeaderSwapTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format = |authorities: &[AuthorityIndex]| {
+            authorities
+                .iter()
+                .map(|a| format_authority_index(*a))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        write!(
+            f,
+            "from round {}: good [{}] bad [{}]",
+            self.first_round,
+            format(&self.good),
+            format(&self.bad)
+        )
+    }
+}
+
+/// Reputation of the authorities, accumulated over a window of committed sub-dags. An authority
+/// scores a point for each of its committed leader blocks, and for each of its committed blocks
+/// that directly includes a committed leader of the previous round. Since it is computed from
+/// the commit sequence only, all honest nodes derive the same swap table at the end of a window.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct LeaderReputation {
+    scores: Vec<u64>,
+    commits: u64,
+    recent_leaders: BTreeSet<BlockReference>,
+    swap_tables: SwapTables,
+}
+
+impl LeaderReputation {
+    pub fn new(committee: &Committee) -> Self {
+        Self {
+            scores: vec![0; committee.len()],
+            ..Default::default()
+        }
+    }
+
+    /// The swap tables the leader schedule needs after a restart.
+    pub fn swap_tables(&self) -> &SwapTables {
+        &self.swap_tables
+    }
+
+    /// The number of commits left in the current window. The schedule must not change in the
+    /// middle of a batch of commits, so the core commits at most this many leaders at once.
+    pub fn commits_until_update(&self, commits_per_schedule: u64) -> u64 {
+        commits_per_schedule.saturating_sub(self.commits)
+    }
+
+    /// Account for a committed sub-dag. If this commit closes the window, returns the new swap
+    /// table, which applies to the rounds after the committed leader.
+    pub fn observe(
+        &mut self,
+        sub_dag: &CommittedSubDag,
+        committee: &Committee,
+        commits_per_schedule: u64,
+        stake_threshold: u64,
+    ) -> Option<LeaderSwapTable> {
+        let leader = sub_dag.anchor;
+        self.scores[leader.authority as usize] += 1;
+        for block in &sub_dag.blocks {
+            let votes = block.includes().iter().any(|include| {
+                include.round + 1 == block.round() && self.recent_leaders.contains(include)
+            });
+            if votes {
+                self.scores[block.author() as usize] += 1;
+            }
+        }
+        self.recent_leaders.insert(leader);
+        self.recent_leaders
+            .retain(|reference| reference.round + VOTE_RETENTION_ROUNDS > leader.round);
+
+        self.commits += 1;
+        if self.commits < commits_per_schedule {
+            return None;
+        }
+        let swap_table = LeaderSwapTable::from_scores(
+            &self.scores,
+            committee,
+            stake_threshold,
+            leader.round + 1,
+        );
+        self.swap_tables.push(swap_table.clone());
+        self.scores.iter_mut().for_each(|score| *score = 0);
+        self.commits = 0;
+        Some(swap_table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::committee;
+
+    #[test]
+    fn swap_table_from_scores() {
+        let committee = committee(4);
+        let table = LeaderSwapTable::from_scores(&[5, 0, 5, 5], &committee, 33, 0);
+        assert_eq!(table.good(), &[0]);
+        assert_eq!(table.bad(), &[1]);
+
+        // Nothing to swap when everyone does equally well
+        let table = LeaderSwapTable::from_scores(&[5, 5, 5, 5], &committee, 33, 0);
+        assert!(table.bad().is_empty());
+
+        // Never swap out more than f
+        let table = LeaderSwapTable::from_scores(&[5, 0, 0, 5], &committee, 100, 0);
+        assert_eq!(table.bad(), &[2]);
+
+        let committee = Committee::new_test(vec![1; 10]);
+        let scores = [9, 8, 7, 6, 5, 4, 3, 2, 1, 0];
+        let table = LeaderSwapTable::from_scores(&scores, &committee, 20, 0);
+        assert_eq!(table.good(), &[0, 1]);
+        assert_eq!(table.bad(), &[9, 8]);
+    }
+
+    #[test]
+    fn swapped_leaders_are_distinct() {
+        let committee = Committee::new_test(vec![1; 10]);
+        let base = Arc::new(RoundRobinSchedule::new(&committee));
+        let schedule = ReputationSchedule::new(base.clone(), committee.clone(), 3);
+        schedule.update(LeaderSwapTable {
+            first_round: 5,
+            good: vec![0, 1],
+            bad: vec![8, 9],
+        });
+        assert!(!schedule.is_reputation_based(4));
+        assert!(schedule.is_reputation_based(5));
+        assert_eq!(schedule.elect_leader(4, 0), base.elect_leader(4, 0));
+        for round in 5..25 {
+            let leaders: HashSet<_> = (0..3)
+                .map(|offset| schedule.elect_leader(round, offset))
+                .collect();
+            assert_eq!(leaders.len(), 3, "round {round}: {leaders:?}");
+            assert!(!leaders.contains(&8) && !leaders.contains(&9));
+        }
+        // Only the slots of bad leaders change
+        assert_eq!(schedule.elect_leader(14, 0), base.elect_leader(14, 0));
+        assert_ne!(schedule.elect_leader(18, 0), base.elect_leader(18, 0));
+
+        // The previous table stays in use up to the first round of the new one
+        schedule.update(LeaderSwapTable {
+            first_round: 10,
+            ..Default::default()
+        });
+        assert!(schedule.is_reputation_based(9));
+        assert_ne!(schedule.elect_leader(8, 0), base.elect_leader(8, 0));
+        assert_eq!(schedule.elect_leader(18, 0), base.elect_leader(18, 0));
+    }
+
+    #[test]
+    fn stake_weighted_leaders_are_distinct() {
+        let committee = Committee::new_test(vec![1, 2, 3, 4, 5]);
+        let schedule = StakeWeightedSchedule::new(committee.clone(), 0);
+        for round in 0..100 {
+            let leaders: HashSet<_> = (0..5)
+                .map(|offset| schedule.elect_leader(round, offset))
+                .collect();
+            assert_eq!(leaders.len(), 5, "round {round}: {leaders:?}");
+        }
+    }
+
+    #[test]
+    fn stake_weighted_schedule_matches_linear_scan() {
+        let committee = Committee::new_test(vec![3, 1, 4, 1, 5, 9, 2, 6]);
+        let schedule = StakeWeightedSchedule::new(committee.clone(), 0);
+        for round in 0..200 {
+            // Reference: draw without replacement by scanning the remaining authorities
+            let mut remaining: Vec<_> = committee
+                .authorities()
+                .map(|authority| (authority, committee.get_stake(authority).unwrap()))
+                .collect();
+            let mut total_stake = committee.total_stake();
+            for offset in 0..committee.len() as u64 {
+                let target = schedule.draw(round, offset) % total_stake;
+                let mut stake = 0;
+                let position = remaining
+                    .iter()
+                    .position(|(_, s)| {
+                        stake += s;
+                        target < stake
+                    })
+                    .unwrap();
+                let (expected, stake) = remaining.remove(position);
+                total_stake -= stake;
+                assert_eq!(schedule.elect_leader(round, offset), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn stake_weighted_schedule_is_seeded_per_epoch() {
+        let committee = Committee::new_test(vec![1; 10]);
+        let leaders = |schedule: &StakeWeightedSchedule| {
+            (0..50)
+                .map(|round| schedule.elect_leader(round, 0))
+                .collect::<Vec<_>>()
+        };
+        let first = leaders(&StakeWeightedSchedule::new(committee.clone(), 0));
+        let again = leaders(&StakeWeightedSchedule::new(committee.clone(), 0));
+        let next_epoch = leaders(&StakeWeightedSchedule::new(committee, 1));
+        assert_eq!(first, again);
+        assert_ne!(first, next_epoch);
+    }
+
+    #[test]
+    fn stake_weighted_schedule_follows_stake() {
+        let committee = Committee::new_test(vec![1, 1, 1, 7]);
+        let schedule = StakeWeightedSchedule::new(committee, 0);
+        let mut elected = [0usize; 4];
+        for round in 0..10_000 {
+            elected[schedule.elect_leader(round, 0) as usize] += 1;
+        }
+        // Expected about 1000 elections for each small authority and 7000 for the large one
+        for count in &elected[..3] {
+            assert!((800..1200).contains(count), "{elected:?}");
+        }
+        assert!((6600..7400).contains(&elected[3]), "{elected:?}");
+    }
+}
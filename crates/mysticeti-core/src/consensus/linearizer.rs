@@ -1,12 +1,13 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{collections::HashSet, fmt};
+use std::{collections::HashSet, fmt, sync::Arc};
 
 use crate::{
     block_store::BlockStore,
+    committee::Committee,
     data::Data,
-    types::{BlockReference, StatementBlock},
+    types::{AuthorityIndex, BlockReference, StatementBlock, TimestampNs},
 };
 
 /// The output of consensus is an ordered list of [`CommittedSubDag`]. The application can arbitrarily
@@ -16,12 +17,23 @@ pub struct CommittedSubDag {
     pub anchor: BlockReference,
     /// All the committed blocks that are part of this sub-dag
     pub blocks: Vec<Data<StatementBlock>>,
+    /// The consensus time of the sub-dag, the same on all validators. It never decreases from one
+    /// sub-dag to the next.
+    pub timestamp_ns: TimestampNs,
 }
 
 impl CommittedSubDag {
     /// Create new (empty) sub-dag.
-    pub fn new(anchor: BlockReference, blocks: Vec<Data<StatementBlock>>) -> Self {
-        Self { anchor, blocks }
+    pub fn new(
+        anchor: BlockReference,
+        blocks: Vec<Data<StatementBlock>>,
+        timestamp_ns: TimestampNs,
+    ) -> Self {
+        Self {
+            anchor,
+            blocks,
+            timestamp_ns,
+        }
     }
 
     /// Sort the blocks of the sub-dag by round number. Any deterministic algorithm works.
@@ -31,15 +43,56 @@ impl CommittedSubDag {
 }
 
 /// Expand a committed sequence of leader into a sequence of sub-dags.
-#[derive(Default)]
 pub struct Linearizer {
+    committee: Arc<Committee>,
     /// Keep track of all committed blocks to avoid committing the same block twice.
     pub committed: HashSet<BlockReference>,
+    /// The consensus time of the last committed sub-dag.
+    pub last_timestamp_ns: TimestampNs,
 }
 
 impl Linearizer {
-    pub fn new() -> Self {
-        Self::default()
+    pub fn new(committee: Arc<Committee>) -> Self {
+        Self {
+            committee,
+            committed: HashSet::new(),
+            last_timestamp_ns: 0,
+        }
+    }
+
+    /// The stake-weighted median of the creation times of the leader's parents, that is the blocks
+    /// of the previous round it includes. The parents of a committed leader hold a quorum of stake,
+    /// so the median is bounded by the clocks of honest authorities. Only the first parent of an
+    /// equivocating authority counts.
+    fn consensus_timestamp(
+        &self,
+        block_store: &BlockStore,
+        leader_block: &StatementBlock,
+    ) -> Option<TimestampNs> {
+        let mut authorities: HashSet<AuthorityIndex> = HashSet::new();
+        let mut times: Vec<_> = leader_block
+            .includes()
+            .iter()
+            .filter(|include| include.round + 1 == leader_block.round())
+            .filter(|include| authorities.insert(include.authority))
+            .map(|include| {
+                let parent = block_store
+                    .get_block(*include)
+                    .expect("We should have the whole sub-dag by now");
+                let stake = self.committee.get_stake(include.authority).unwrap_or(0);
+                (parent.meta_creation_time_ns(), stake)
+            })
+            .collect();
+        times.sort();
+        let total_stake: u64 = times.iter().map(|(_, stake)| stake).sum();
+        let mut stake = 0;
+        for (time, parent_stake) in times {
+            stake += parent_stake;
+            if 2 * stake >= total_stake {
+                return Some(time);
+            }
+        }
+        None
     }
 
     /// Collect the sub-dag from a specific anchor excluding any duplicates or blocks that
@@ -51,6 +104,11 @@ impl Linearizer {
     ) -> CommittedSubDag {
         let mut to_commit = Vec::new();
 
+        let timestamp_ns = self
+            .consensus_timestamp(block_store, &leader_block)
+            .unwrap_or_default()
+            .max(self.last_timestamp_ns);
+        self.last_timestamp_ns = timestamp_ns;
         let leader_block_ref = *leader_block.reference();
         let mut buffer = vec![leader_block];
         assert!(self.committed.insert(leader_block_ref));
@@ -69,7 +127,7 @@ impl Linearizer {
                 }
             }
         }
-        CommittedSubDag::new(leader_block_ref, to_commit)
+        CommittedSubDag::new(leader_block_ref, to_commit, timestamp_ns)
     }
 
     pub fn handle_commit(
@@ -99,3 +157,49 @@ impl fmt::Debug for CommittedSubDag {
         write!(f, ")")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::{build_dag, committee, TestBlockWriter},
+        types::Dag,
+    };
+
+    #[test]
+    fn consensus_timestamp_is_median_of_parents() {
+        let committee = committee(4);
+        let mut block_writer = TestBlockWriter::new(&committee);
+        build_dag(&committee, &mut block_writer, None, 0);
+        let blocks = [
+            ("A1:[A0, B0, C0, D0]", 10),
+            ("B1:[A0, B0, C0, D0]", 30),
+            ("C1:[A0, B0, C0, D0]", 20),
+            // A clock far ahead does not move the median
+            ("D1:[A0, B0, C0, D0]", 1_000),
+            ("A2:[A1, B1, C1, D1]", 1_000),
+            ("C2:[A1]", 12),
+            ("B3:[C2]", 1_000),
+        ];
+        for (block, time) in blocks {
+            let block = Dag::draw_block(block).with_creation_time(time);
+            block_writer.add_block(Data::new(block));
+        }
+        let block_store = block_writer.into_block_store();
+        let leaders = [(0, 2), (1, 3)]
+            .into_iter()
+            .map(|(authority, round)| {
+                block_store
+                    .get_block(BlockReference::new_test(authority, round))
+                    .unwrap()
+            })
+            .collect();
+
+        let mut linearizer = Linearizer::new(committee);
+        let committed = linearizer.handle_commit(&block_store, leaders);
+        assert_eq!(committed[0].timestamp_ns, 20);
+        // The only parent of the second leader is older, the consensus time does not go back
+        assert_eq!(committed[1].timestamp_ns, 20);
+        assert_eq!(linearizer.last_timestamp_ns, 20);
+    }
+}
@@ -9,7 +9,10 @@ use crate::{
 };
 
 pub mod base_committer;
+pub mod decision_trace;
+pub mod leader_schedule;
 pub mod linearizer;
+pub mod replay;
 pub mod universal_committer;
 
 #[cfg(test)]
@@ -0,0 +1,308 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{fmt::Display, io, path::Path, str::FromStr, sync::Arc};
+
+use prometheus::Registry;
+use tabled::{Table, Tabled};
+
+use super::{
+    decision_trace::{open_dag_dump, open_wal},
+    universal_committer::UniversalCommitterBuilder,
+    LeaderStatus,
+    DEFAULT_WAVE_LENGTH,
+    MINIMUM_WAVE_LENGTH,
+};
+use crate::{
+    block_store::{BlockStore, BlockWriter},
+    committee::Committee,
+    metrics::Metrics,
+    types::{BlockReference, RoundNumber},
+    wal::walf,
+};
+
+/// A committer configuration to replay a dag with, written as `wave=3,leaders=2,pipeline=true`.
+/// Omitted keys take the default of [`UniversalCommitterBuilder`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommitterConfig {
+    pub wave_length: RoundNumber,
+    pub number_of_leaders: usize,
+    pub pipeline: bool,
+}
+
+impl Default for CommitterConfig {
+    fn default() -> Self {
+        Self {
+            wave_length: DEFAULT_WAVE_LENGTH,
+            number_of_leaders: 1,
+            pipeline: false,
+        }
+    }
+}
+
+impl Display for CommitterConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "wave={},leaders={},pipeline={}",
+            self.wave_length, self.number_of_leaders, self.pipeline
+        )
+    }
+}
+
+impl FromStr for CommitterConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Self::default();
+        for entry in s.split(',').filter(|entry| !entry.is_empty()) {
+            let Some((key, value)) = entry.split_once('=') else {
+                return Err(format!("Expected key=value, got '{entry}'"));
+            };
+            let invalid = || format!("Invalid value for {key}: '{value}'");
+            match key.trim() {
+                "wave" => config.wave_length = value.trim().parse().map_err(|_| invalid())?,
+                "leaders" => {
+                    config.number_of_leaders = value.trim().parse().map_err(|_| invalid())?
+                }
+                "pipeline" => config.pipeline = value.trim().parse().map_err(|_| invalid())?,
+                _ => {
+                    return Err(format!(
+                        "Unknown key '{key}' (expected wave, leaders or pipeline)"
+                    ))
+                }
+            }
+        }
+        if config.wave_length < MINIMUM_WAVE_LENGTH {
+            return Err(format!(
+                "The wave length must be at least {MINIMUM_WAVE_LENGTH}"
+            ));
+        }
+        if config.number_of_leaders == 0 {
+            return Err("There must be at least one leader".to_string());
+        }
+        Ok(config)
+    }
+}
+
+/// The outcome of replaying a dag with one committer configuration.
+#[derive(Clone, Debug)]
+pub struct ReplayReport {
+    pub config: CommitterConfig,
+    /// The decided leaders, in commit order.
+    pub sequence: Vec<LeaderStatus>,
+    /// For each committed leader, the number of rounds the dag grew past the leader round before
+    /// the leader was committed.
+    pub commit_latencies: Vec<RoundNumber>,
+    pub direct_commits: usize,
+    pub indirect_commits: usize,
+    pub direct_skips: usize,
+    pub indirect_skips: usize,
+}
+
+impl ReplayReport {
+    fn new(config: CommitterConfig) -> Self {
+        Self {
+            config,
+            sequence: Vec::new(),
+            commit_latencies: Vec::new(),
+            direct_commits: 0,
+            indirect_commits: 0,
+            direct_skips: 0,
+            indirect_skips: 0,
+        }
+    }
+
+    pub fn average_latency(&self) -> Option<f64> {
+        if self.commit_latencies.is_empty() {
+            return None;
+        }
+        let total: RoundNumber = self.commit_latencies.iter().sum();
+        Some(total as f64 / self.commit_latencies.len() as f64)
+    }
+}
+
+/// Replay the dag stored in the wal of a validator through each committer configuration. The
+/// leaders follow the base schedule, without reputation-based swaps.
+pub fn replay_wal(
+    path: impl AsRef<Path>,
+    committee: Arc<Committee>,
+    configs: &[CommitterConfig],
+) -> io::Result<Vec<ReplayReport>> {
+    let recovered = open_wal(path, &committee)?;
+    replay_all(&recovered.block_store, committee, configs)
+}
+
+/// Replay a dag dump through each committer configuration.
+pub fn replay_dag_dump(
+    path: impl AsRef<Path>,
+    committee: Arc<Committee>,
+    configs: &[CommitterConfig],
+) -> io::Result<Vec<ReplayReport>> {
+    let recovered = open_dag_dump(path, &committee)?;
+    replay_all(&recovered.block_store, committee, configs)
+}
+
+fn replay_all(
+    block_store: &BlockStore,
+    committee: Arc<Committee>,
+    configs: &[CommitterConfig],
+) -> io::Result<Vec<ReplayReport>> {
+    configs
+        .iter()
+        .map(|config| replay(block_store, committee.clone(), *config))
+        .collect()
+}
+
+/// Feed the dag to a committer one round at a time, as a validator receiving the blocks would,
+/// and record every leader as soon as it is decided.
+pub(crate) fn replay(
+    source: &BlockStore,
+    committee: Arc<Committee>,
+    config: CommitterConfig,
+) -> io::Result<ReplayReport> {
+    let (mut wal_writer, wal_reader) = walf(tempfile::tempfile()?)?;
+    let metrics = Metrics::new(&Registry::new(), Some(&committee)).0;
+    let recovered = BlockStore::open(
+        0,
+        Arc::new(wal_reader),
+        &wal_writer,
+        metrics.clone(),
+        &committee,
+    )?;
+    let block_store = recovered.block_store;
+    let committer = UniversalCommitterBuilder::new(committee, block_store.clone(), metrics)
+        .with_wave_length(config.wave_length)
+        .with_number_of_leaders(config.number_of_leaders)
+        .with_pipeline(config.pipeline)
+        .build();
+
+    let mut report = ReplayReport::new(config);
+    let mut last_decided = BlockReference::default();
+    for round in 0..=source.highest_round() {
+        for block in source.get_blocks_by_round(round) {
+            (&mut wal_writer, &block_store).insert_block(block);
+        }
+        for decision in committer.try_decide(last_decided) {
+            let status = decision.status;
+            match (&status, decision.direct) {
+                (LeaderStatus::Commit(..), true) => report.direct_commits += 1,
+                (LeaderStatus::Commit(..), false) => report.indirect_commits += 1,
+                (LeaderStatus::Skip(..), true) => report.direct_skips += 1,
+                (LeaderStatus::Skip(..), false) => report.indirect_skips += 1,
+                (LeaderStatus::Undecided(..), _) => {
+                    unreachable!("Only decided leaders are returned")
+                }
+            }
+            if let LeaderStatus::Commit(..) = status {
+                report.commit_latencies.push(round - status.round());
+            }
+            // Decisions are final, replaying from the last decided leader is enough
+            last_decided = BlockReference {
+                authority: status.authority(),
+                round: status.round(),
+                digest: Default::default(),
+            };
+            report.sequence.push(status);
+        }
+    }
+    Ok(report)
+}
+
+/// Summarize the reports in a table, one configuration per row.
+pub fn format_reports(reports: &[ReplayReport]) -> String {
+    let rows = reports.iter().map(|report| ReplayRow {
+        config: report.config.to_string(),
+        commits: report.direct_commits + report.indirect_commits,
+        direct_commits: report.direct_commits,
+        indirect_commits: report.indirect_commits,
+        direct_skips: report.direct_skips,
+        indirect_skips: report.indirect_skips,
+        average_latency: report
+            .average_latency()
+            .map(|latency| format!("{latency:.2}"))
+            .unwrap_or_else(|| "-".to_string()),
+        max_latency: report
+            .commit_latencies
+            .iter()
+            .max()
+            .map(|latency| latency.to_string())
+            .unwrap_or_else(|| "-".to_string()),
+    });
+    Table::new(rows).to_string()
+}
+
+#[derive(Tabled)]
+struct ReplayRow {
+    config: String,
+    commits: usize,
+    #[tabled(rename = "direct commits")]
+    direct_commits: usize,
+    #[tabled(rename = "indirect commits")]
+    indirect_commits: usize,
+    #[tabled(rename = "direct skips")]
+    direct_skips: usize,
+    #[tabled(rename = "indirect skips")]
+    indirect_skips: usize,
+    #[tabled(rename = "avg latency (rounds)")]
+    average_latency: String,
+    #[tabled(rename = "max latency (rounds)")]
+    max_latency: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{build_dag, committee, elect_leader, TestBlockWriter};
+
+    #[test]
+    fn parse_committer_config() {
+        let config: CommitterConfig = "wave=4,leaders=2,pipeline=true".parse().unwrap();
+        assert_eq!(
+            config,
+            CommitterConfig {
+                wave_length: 4,
+                number_of_leaders: 2,
+                pipeline: true,
+            }
+        );
+        assert_eq!(config.to_string().parse(), Ok(config));
+        assert_eq!(
+            "leaders=3".parse::<CommitterConfig>().unwrap().wave_length,
+            3
+        );
+        assert!("wave=2".parse::<CommitterConfig>().is_err());
+        assert!("waves=3".parse::<CommitterConfig>().is_err());
+    }
+
+    #[test]
+    fn replay_configurations() {
+        let committee = committee(4);
+        let mut block_writer = TestBlockWriter::new(&committee);
+        build_dag(&committee, &mut block_writer, None, 12);
+        let block_store = block_writer.into_block_store();
+
+        let configs = [
+            CommitterConfig::default(),
+            "leaders=2,pipeline=true".parse().unwrap(),
+        ];
+        let reports = replay_all(&block_store, committee.clone(), &configs).unwrap();
+
+        // One leader every wave, decided as soon as its decision round is in
+        let single = &reports[0];
+        assert_eq!(single.direct_commits, 3);
+        assert_eq!(single.indirect_commits + single.direct_skips, 0);
+        assert!(single.commit_latencies.iter().all(|latency| *latency == 2));
+        let leaders: Vec<_> = single.sequence.iter().map(|x| x.round()).collect();
+        assert_eq!(leaders, vec![3, 6, 9]);
+        assert_eq!(
+            single.sequence[0].authority(),
+            elect_leader(&committee, 3, 0)
+        );
+
+        // Two leaders in every round from round 1
+        let pipelined = &reports[1];
+        assert_eq!(pipelined.direct_commits, 2 * 10);
+        assert!(format_reports(&reports).contains("wave=3,leaders=2,pipeline=true"));
+    }
+}
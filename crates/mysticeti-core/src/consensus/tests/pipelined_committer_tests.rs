@@ -7,7 +7,14 @@ use crate::{
         LeaderStatus,
         DEFAULT_WAVE_LENGTH,
     },
-    test_util::{build_dag, build_dag_layer, committee, test_metrics, TestBlockWriter},
+    test_util::{
+        build_dag,
+        build_dag_layer,
+        committee,
+        elect_leader,
+        test_metrics,
+        TestBlockWriter,
+    },
     types::{BlockReference, StatementBlock},
 };
 
@@ -36,7 +43,7 @@ fn direct_commit() {
 
     assert_eq!(sequence.len(), 1);
     if let LeaderStatus::Commit(ref block) = sequence[0] {
-        assert_eq!(block.author(), committee.elect_leader(1));
+        assert_eq!(block.author(), elect_leader(&committee, 1, 0));
     } else {
         panic!("Expected a committed leader")
     };
@@ -101,7 +108,7 @@ fn multiple_direct_commit() {
         assert_eq!(sequence.len(), 1);
         let leader_round = n as u64;
         if let LeaderStatus::Commit(ref block) = sequence[0] {
-            assert_eq!(block.author(), committee.elect_leader(leader_round));
+            assert_eq!(block.author(), elect_leader(&committee, leader_round, 0));
         } else {
             panic!("Expected a committed leader")
         }
@@ -140,7 +147,7 @@ fn direct_commit_late_call() {
     for (i, leader_block) in sequence.iter().enumerate() {
         let leader_round = 1 + i as u64;
         if let LeaderStatus::Commit(ref block) = leader_block {
-            assert_eq!(block.author(), committee.elect_leader(leader_round));
+            assert_eq!(block.author(), elect_leader(&committee, leader_round, 0));
         } else {
             panic!("Expected a committed leader")
         };
@@ -186,11 +193,14 @@ fn no_leader() {
 
     // Add enough blocks to reach the decision round of the first leader (but without the leader).
     let leader_round_1 = 1;
-    let leader_1 = committee.elect_leader(leader_round_1);
+    let leader_1 = elect_leader(&committee, leader_round_1, 0);
 
     let genesis: Vec<_> = committee
         .authorities()
-        .map(|authority| *StatementBlock::new_genesis(authority).reference())
+        .map(|authority| {
+            *StatementBlock::new_genesis(authority, committee.epoch(), committee.hash_function())
+                .reference()
+        })
         .collect();
     let connections = committee
         .authorities()
@@ -245,7 +255,7 @@ fn direct_skip() {
     // Filter out that leader.
     let references_without_leader_1: Vec<_> = references_1
         .into_iter()
-        .filter(|x| x.authority != committee.elect_leader(leader_round_1))
+        .filter(|x| x.authority != elect_leader(&committee, leader_round_1, 0))
         .collect();
 
     // Add enough blocks to reach the decision round of the first leader.
@@ -273,7 +283,7 @@ fn direct_skip() {
 
     assert_eq!(sequence.len(), 1);
     if let LeaderStatus::Skip(leader, round) = sequence[0] {
-        assert_eq!(leader, committee.elect_leader(leader_round_1));
+        assert_eq!(leader, elect_leader(&committee, leader_round_1, 0));
         assert_eq!(round, leader_round_1);
     } else {
         panic!("Expected to directly skip the leader");
@@ -297,7 +307,7 @@ fn indirect_commit() {
     let references_without_leader_1: Vec<_> = references_1
         .iter()
         .cloned()
-        .filter(|x| x.authority != committee.elect_leader(leader_round_1))
+        .filter(|x| x.authority != elect_leader(&committee, leader_round_1, 0))
         .collect();
 
     // Only 2f+1 validators vote for the 1st leader.
@@ -372,7 +382,7 @@ fn indirect_commit() {
     assert_eq!(sequence.len(), 5);
 
     let leader_round = 1;
-    let leader = committee.elect_leader(leader_round);
+    let leader = elect_leader(&committee, leader_round, 0);
     if let LeaderStatus::Commit(ref block) = sequence[0] {
         assert_eq!(block.author(), leader);
     } else {
@@ -397,7 +407,7 @@ fn indirect_skip() {
     let references_without_leader_4: Vec<_> = references_4
         .iter()
         .cloned()
-        .filter(|x| x.authority != committee.elect_leader(leader_round_4))
+        .filter(|x| x.authority != elect_leader(&committee, leader_round_4, 0))
         .collect();
 
     // Only f+1 validators connect to the 4th leader.
@@ -450,7 +460,7 @@ fn indirect_skip() {
     // Ensure we commit the first 3 leaders.
     for i in 0..=2 {
         let leader_round = i + 1;
-        let leader = committee.elect_leader(leader_round);
+        let leader = elect_leader(&committee, leader_round, 0);
         if let LeaderStatus::Commit(ref block) = sequence[i as usize] {
             assert_eq!(block.author(), leader);
         } else {
@@ -460,7 +470,7 @@ fn indirect_skip() {
 
     // Ensure we skip the leader of wave 1 (first pipeline) but commit the others.
     if let LeaderStatus::Skip(leader, round) = sequence[3] {
-        assert_eq!(leader, committee.elect_leader(leader_round_4));
+        assert_eq!(leader, elect_leader(&committee, leader_round_4, 0));
         assert_eq!(round, leader_round_4);
     } else {
         panic!("Expected a skipped leader")
@@ -468,7 +478,7 @@ fn indirect_skip() {
 
     for i in 4..=6 {
         let leader_round = i + 1;
-        let leader = committee.elect_leader(leader_round);
+        let leader = elect_leader(&committee, leader_round, 0);
         if let LeaderStatus::Commit(ref block) = sequence[i as usize] {
             assert_eq!(block.author(), leader);
         } else {
@@ -494,7 +504,7 @@ fn undecided() {
     let references_1_without_leader: Vec<_> = references_1
         .iter()
         .cloned()
-        .filter(|x| x.authority != committee.elect_leader(leader_round_1))
+        .filter(|x| x.authority != elect_leader(&committee, leader_round_1, 0))
         .collect();
 
     // Create a dag layer where only one authority votes for the first leader.
@@ -3,10 +3,17 @@
 
 use std::{collections::VecDeque, sync::Arc};
 
-use super::{base_committer::BaseCommitter, LeaderStatus, DEFAULT_WAVE_LENGTH};
+use super::{
+    base_committer::BaseCommitter,
+    decision_trace::DecisionTrace,
+    leader_schedule::{LeaderSchedule, ReputationSchedule, StakeWeightedSchedule},
+    LeaderStatus,
+    DEFAULT_WAVE_LENGTH,
+};
 use crate::{
     block_store::BlockStore,
     committee::Committee,
+    config::ConsensusParameters,
     consensus::base_committer::BaseCommitterOptions,
     metrics::Metrics,
     types::{format_authority_round, AuthorityIndex, BlockReference, RoundNumber},
@@ -18,21 +25,79 @@ use crate::{
 pub struct UniversalCommitter {
     block_store: BlockStore,
     committers: Vec<BaseCommitter>,
+    leader_schedule: Arc<ReputationSchedule>,
     metrics: Arc<Metrics>,
 }
 
+/// The outcome of the decision rules on a leader.
+pub(crate) struct Decision<'a> {
+    pub(crate) status: LeaderStatus,
+    pub(crate) committer: &'a BaseCommitter,
+    pub(crate) direct: bool,
+}
+
 impl UniversalCommitter {
     /// Try to commit part of the dag. This function is idempotent and returns a list of
     /// ordered decided leaders.
     #[tracing::instrument(skip_all, fields(last_decided = %last_decided))]
     pub fn try_commit(&self, last_decided: BlockReference) -> Vec<LeaderStatus> {
-        let highest_known_round = self.block_store.highest_round();
+        self.try_decide(last_decided)
+            .into_iter()
+            .map(|decision| decision.status)
+            .collect()
+    }
+
+    /// Same as `try_commit`, but also tells which rule decided each leader.
+    pub(crate) fn try_decide(&self, last_decided: BlockReference) -> Vec<Decision<'_>> {
         let last_decided_round = last_decided.round();
         let last_decided_round_authority = (last_decided.round(), last_decided.authority);
 
-        // Try to decide as many leaders as possible, starting with the highest round.
-        let mut leaders = VecDeque::new();
-        for round in (last_decided_round..=highest_known_round).rev() {
+        let decisions = self.decide_leaders(last_decided_round);
+        for decision in &decisions {
+            self.update_metrics(&decision.status, decision.direct);
+        }
+
+        // The decided sequence is the longest prefix of decided leaders.
+        let mut decisions = decisions.into_iter();
+        // Skip all leaders up to and including the last decided leader. Genesis leaders are
+        // never committed, so everything is undecided while the last decided round is 0.
+        if last_decided_round > 0 {
+            decisions
+                .find(|x| (x.status.round(), x.status.authority()) == last_decided_round_authority);
+        }
+        decisions
+            // Filter out all the genesis.
+            .filter(|x| x.status.round() > 0)
+            // Stop the sequence upon encountering an undecided leader.
+            .take_while(|x| x.status.is_decided())
+            .inspect(|x| tracing::debug!("Decided {}", x.status))
+            .collect()
+    }
+
+    /// Explain the decisions on all leaders from the specified round, whether decided or not.
+    /// Unlike `try_commit`, the traces do not stop at the first undecided leader.
+    pub fn explain(&self, from_round: RoundNumber) -> Vec<DecisionTrace> {
+        let decisions = self.decide_leaders(from_round);
+        decisions
+            .iter()
+            .enumerate()
+            .filter(|(_, decision)| decision.status.round() > 0)
+            .map(|(i, decision)| {
+                let later_leaders = decisions.iter().skip(i + 1).map(|x| &x.status);
+                decision
+                    .committer
+                    .trace_decision(&decision.status, decision.direct, later_leaders)
+            })
+            .collect()
+    }
+
+    /// Try to decide as many leaders as possible from the specified round, ordered by round.
+    fn decide_leaders(&self, from_round: RoundNumber) -> VecDeque<Decision<'_>> {
+        let highest_known_round = self.block_store.highest_round();
+
+        // Start with the highest round, the indirect rule relies on the later leaders.
+        let mut decisions: VecDeque<Decision> = VecDeque::new();
+        for round in (from_round..=highest_known_round).rev() {
             for committer in self.committers.iter().rev() {
                 // Skip committers that don't have a leader for this round.
                 let Some(leader) = committer.elect_leader(round) else {
@@ -45,33 +110,24 @@ impl UniversalCommitter {
 
                 // Try to directly decide the leader.
                 let mut status = committer.try_direct_decide(leader, round);
-                self.update_metrics(&status, true);
+                let direct = status.is_decided();
                 tracing::debug!("Outcome of direct rule: {status}");
 
                 // If we can't directly decide the leader, try to indirectly decide it.
-                if !status.is_decided() {
-                    status = committer.try_indirect_decide(leader, round, leaders.iter());
-                    self.update_metrics(&status, false);
+                if !direct {
+                    let leaders = decisions.iter().map(|decision| &decision.status);
+                    status = committer.try_indirect_decide(leader, round, leaders);
                     tracing::debug!("Outcome of indirect rule: {status}");
                 }
 
-                leaders.push_front(status);
+                decisions.push_front(Decision {
+                    status,
+                    committer,
+                    direct,
+                });
             }
         }
-
-        // The decided sequence is the longest prefix of decided leaders.
-        leaders
-            .into_iter()
-            // Skip all leaders before the last decided round.
-            .skip_while(|x| (x.round(), x.authority()) != last_decided_round_authority)
-            // Skip the last decided leader.
-            .skip(1)
-            // Filter out all the genesis.
-            .filter(|x| x.round() > 0)
-            // Stop the sequence upon encountering an undecided leader.
-            .take_while(|x| x.is_decided())
-            .inspect(|x| tracing::debug!("Decided {x}"))
-            .collect()
+        decisions
     }
 
     /// Return list of leaders for the round. Syncer may give those leaders some extra time.
@@ -84,9 +140,19 @@ impl UniversalCommitter {
             .collect()
     }
 
+    /// The leader schedule used by all committers.
+    pub fn leader_schedule(&self) -> &ReputationSchedule {
+        &self.leader_schedule
+    }
+
     /// Update metrics.
     fn update_metrics(&self, leader: &LeaderStatus, direct_decide: bool) {
         let authority = leader.authority().to_string();
+        let schedule = if self.leader_schedule.is_reputation_based(leader.round()) {
+            "reputation"
+        } else {
+            "base"
+        };
         let direct_or_indirect = if direct_decide { "direct" } else { "indirect" };
         let status = match leader {
             LeaderStatus::Commit(..) => format!("{direct_or_indirect}-commit"),
@@ -95,7 +161,7 @@ impl UniversalCommitter {
         };
         self.metrics
             .committed_leaders_total
-            .with_label_values(&[&authority, &status])
+            .with_label_values(&[&authority, &status, schedule])
             .inc();
     }
 }
@@ -106,6 +172,7 @@ pub struct UniversalCommitterBuilder {
     committee: Arc<Committee>,
     block_store: BlockStore,
     metrics: Arc<Metrics>,
+    leader_schedule: Option<Arc<dyn LeaderSchedule>>,
     wave_length: RoundNumber,
     number_of_leaders: usize,
     pipeline: bool,
@@ -117,12 +184,20 @@ impl UniversalCommitterBuilder {
             committee,
             block_store,
             metrics,
+            leader_schedule: None,
             wave_length: DEFAULT_WAVE_LENGTH,
             number_of_leaders: 1,
             pipeline: false,
         }
     }
 
+    /// Replace the default stake-weighted leader schedule. Reputation-based swaps still apply on
+    /// top of it.
+    pub fn with_leader_schedule(mut self, leader_schedule: Arc<dyn LeaderSchedule>) -> Self {
+        self.leader_schedule = Some(leader_schedule);
+        self
+    }
+
     pub fn with_wave_length(mut self, wave_length: RoundNumber) -> Self {
         self.wave_length = wave_length;
         self
@@ -138,7 +213,23 @@ impl UniversalCommitterBuilder {
         self
     }
 
+    /// Configure the wave length, number of leaders and pipelining of an epoch at once.
+    pub fn with_consensus_parameters(self, parameters: &ConsensusParameters) -> Self {
+        self.with_wave_length(parameters.wave_length)
+            .with_number_of_leaders(parameters.number_of_leaders)
+            .with_pipeline(parameters.enable_pipelining)
+    }
+
     pub fn build(self) -> UniversalCommitter {
+        let base_schedule = self.leader_schedule.unwrap_or_else(|| {
+            let epoch = self.committee.epoch();
+            Arc::new(StakeWeightedSchedule::new(self.committee.clone(), epoch))
+        });
+        let leader_schedule = Arc::new(ReputationSchedule::new(
+            base_schedule,
+            self.committee.clone(),
+            self.number_of_leaders,
+        ));
         let mut committers = Vec::new();
         let pipeline_stages = if self.pipeline { self.wave_length } else { 1 };
         for round_offset in 0..pipeline_stages {
@@ -148,9 +239,12 @@ impl UniversalCommitterBuilder {
                     round_offset,
                     leader_offset: leader_offset as RoundNumber,
                 };
-                let committer =
-                    BaseCommitter::new(self.committee.clone(), self.block_store.clone())
-                        .with_options(options);
+                let committer = BaseCommitter::new(
+                    self.committee.clone(),
+                    self.block_store.clone(),
+                    leader_schedule.clone(),
+                )
+                .with_options(options);
                 committers.push(committer);
             }
         }
@@ -158,6 +252,7 @@ impl UniversalCommitterBuilder {
         UniversalCommitter {
             block_store: self.block_store,
             committers,
+            leader_schedule,
             metrics: self.metrics,
         }
     }
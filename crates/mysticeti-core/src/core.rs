@@ -2,42 +2,75 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::{
-    collections::{HashSet, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    iter,
     mem,
     sync::{atomic::AtomicU64, Arc},
+    thread,
 };
 
+use eyre::ensure;
 use minibytes::Bytes;
 
 use crate::{
     block_handler::BlockHandler,
     block_manager::BlockManager,
+    block_signer::BlockSigner,
     block_store::{
         BlockStore,
         BlockWriter,
         CommitData,
         OwnBlockData,
+        WAL_COMMIT_VERSION,
         WAL_ENTRY_COMMIT,
+        WAL_ENTRY_EQUIVOCATION,
         WAL_ENTRY_PAYLOAD,
         WAL_ENTRY_STATE,
     },
     committee::Committee,
-    config::{NodePrivateConfig, NodePublicConfig},
+    config::{ConsensusParameters, NodePublicConfig},
     consensus::{
+        leader_schedule::LeaderReputation,
         linearizer::CommittedSubDag,
         universal_committer::{UniversalCommitter, UniversalCommitterBuilder},
     },
-    crypto::Signer,
+    crypto::{self, HashFunction},
     data::Data,
     epoch_close::EpochManager,
+    equivocation::{
+        EquivocationPolicy,
+        EquivocationProof,
+        EquivocationProofs,
+        EquivocationTracker,
+    },
     metrics::{Metrics, UtilizationTimerVecExt},
+    reconfiguration::{self, CommitteeChange},
     runtime::timestamp_utc,
+    sign_guard::SignedBlockRecord,
+    snapshot::Snapshots,
     state::RecoveredState,
     threshold_clock::ThresholdClockAggregator,
-    types::{AuthorityIndex, BaseStatement, BlockReference, RoundNumber, StatementBlock},
-    wal::{WalPosition, WalSyncer, WalWriter},
+    types::{
+        AuthorityIndex,
+        BaseStatement,
+        BlockLimits,
+        BlockReference,
+        Epoch,
+        RoundNumber,
+        StatementBlock,
+        TimestampNs,
+    },
+    wal::{WalDurability, WalPosition, WalSyncer, WalWriter},
 };
 
+/// Room kept in a block for all but its includes and statements. Most of it is taken by the
+/// signature, an ML-DSA-65 signature is 3309 bytes.
+const BLOCK_HEADER_RESERVE: usize = 4 * 1024;
+
+/// The blocks this many rounds below the last committed leader are cleaned up.
+const RETAIN_BELOW_COMMIT_ROUNDS: RoundNumber = 100;
+
 pub struct Core<H: BlockHandler> {
     block_manager: BlockManager,
     pending: VecDeque<(WalPosition, MetaStatement)>,
@@ -51,15 +84,34 @@ pub struct Core<H: BlockHandler> {
     block_store: BlockStore,
     pub(crate) metrics: Arc<Metrics>,
     options: CoreOptions,
-    signer: Signer,
+    signer: Box<dyn BlockSigner>,
     // todo - ugly, probably need to merge syncer and core
-    recovered_committed_blocks: Option<(HashSet<BlockReference>, Option<Bytes>)>,
+    recovered_committed_blocks: Option<(HashSet<BlockReference>, Option<Bytes>, TimestampNs)>,
     epoch_manager: EpochManager,
     rounds_in_epoch: RoundNumber,
     committer: UniversalCommitter,
+    consensus_parameters: ConsensusParameters,
+    leader_reputation: LeaderReputation,
+    block_limits: BlockLimits,
+    equivocations: EquivocationTracker,
+    // Where the last copy of each equivocation proof is in the wal
+    equivocation_positions: HashMap<(AuthorityIndex, RoundNumber), WalPosition>,
+    // Positions of the state and commit entries written since the core was opened
+    last_state_position: Option<WalPosition>,
+    last_commit_position: Option<WalPosition>,
+    snapshots: Option<Snapshots>,
+    wal_bytes_between_snapshots: u64,
+    last_snapshot_position: WalPosition,
+    // Reads the wal to write a snapshot, off the core thread
+    snapshot_task: Option<thread::JoinHandle<()>>,
+    // Last round signed in this epoch according to the sign guard. It is ahead of the wal when
+    // the node crashed after signing a block but before writing it, no block is proposed up to it
+    signed_round: RoundNumber,
 }
 
 pub struct CoreOptions {
+    /// Whether own blocks and commits are synced to disk before being relied upon. Without it, the
+    /// wal is reported durable as soon as it is written, which is only safe in tests.
     fsync: bool,
 }
 
@@ -75,7 +127,7 @@ impl<H: BlockHandler> Core<H> {
         mut block_handler: H,
         authority: AuthorityIndex,
         committee: Arc<Committee>,
-        private_config: NodePrivateConfig,
+        signer: Box<dyn BlockSigner>,
         public_config: &NodePublicConfig,
         metrics: Arc<Metrics>,
         recovered: RecoveredState,
@@ -90,8 +142,21 @@ impl<H: BlockHandler> Core<H> {
             unprocessed_blocks,
             last_committed_leader,
             committed_blocks,
+            last_commit_timestamp_ns,
             committed_state,
+            leader_reputation,
+            epoch_change,
+            equivocations,
         } = recovered;
+        let signed_round = signer.last_signed().map_or(0, |last_signed| {
+            check_sign_guard(
+                authority,
+                committee.epoch(),
+                &last_signed,
+                last_own_block.as_ref().map(|own_block| &*own_block.block),
+                committee.hash_function(),
+            )
+        });
         let mut threshold_clock = ThresholdClockAggregator::new(0);
         let last_own_block = if let Some(own_block) = last_own_block {
             for (_, pending_block) in pending.iter() {
@@ -101,8 +166,6 @@ impl<H: BlockHandler> Core<H> {
             }
             own_block
         } else {
-            // todo(fix) - this technically has a race condition if node crashes after genesis
-            assert!(pending.is_empty());
             // Initialize empty block store
             // A lot of this code is shared with Self::add_blocks, this is not great and some code reuse would be great
             let (own_genesis_block, other_genesis_blocks) = committee.genesis_blocks(authority);
@@ -111,6 +174,11 @@ impl<H: BlockHandler> Core<H> {
             for block in other_genesis_blocks {
                 let reference = *block.reference();
                 threshold_clock.add_block(reference, &committee);
+                // The node crashed while writing the genesis blocks, the ones in the wal are
+                // already pending
+                if block_store.block_exists(reference) {
+                    continue;
+                }
                 let position = block_writer.insert_block(block);
                 pending.push_back((position, MetaStatement::Include(reference)));
             }
@@ -128,21 +196,37 @@ impl<H: BlockHandler> Core<H> {
             block_handler.recover_state(&state);
         }
 
-        let epoch_manager = EpochManager::new();
+        let epoch_manager = epoch_change
+            .map(EpochManager::recover)
+            .unwrap_or_else(EpochManager::new);
 
+        for (_, proof) in &equivocations {
+            metrics
+                .equivocations_total
+                .with_label_values(&[&proof.authority.to_string()])
+                .inc();
+        }
+        let equivocation_positions = equivocations
+            .iter()
+            .map(|(position, proof)| ((proof.authority, proof.round), *position))
+            .collect();
+        let mut equivocations =
+            EquivocationTracker::new(equivocations.into_iter().map(|(_, proof)| proof).collect());
+        equivocations.set_policy(public_config.parameters.equivocation_policy.policy());
+
+        let consensus_parameters = public_config
+            .parameters
+            .consensus_parameters(committee.epoch());
         let committer =
             UniversalCommitterBuilder::new(committee.clone(), block_store.clone(), metrics.clone())
-                .with_number_of_leaders(public_config.parameters.number_of_leaders)
-                .with_pipeline(public_config.parameters.enable_pipelining)
+                .with_consensus_parameters(&consensus_parameters)
                 .build();
-        tracing::info!(
-            "Pipeline enabled: {}",
-            public_config.parameters.enable_pipelining
-        );
-        tracing::info!(
-            "Number of leaders: {}",
-            public_config.parameters.number_of_leaders
-        );
+        let leader_reputation =
+            leader_reputation.unwrap_or_else(|| LeaderReputation::new(&committee));
+        for swap_table in leader_reputation.swap_tables().iter() {
+            committer.leader_schedule().update(swap_table.clone());
+        }
+        tracing::info!("Consensus parameters: {consensus_parameters:?}");
 
         let mut this = Self {
             block_manager,
@@ -157,11 +241,27 @@ impl<H: BlockHandler> Core<H> {
             block_store,
             metrics,
             options,
-            signer: private_config.keypair,
-            recovered_committed_blocks: Some((committed_blocks, committed_state)),
+            signer,
+            recovered_committed_blocks: Some((
+                committed_blocks,
+                committed_state,
+                last_commit_timestamp_ns,
+            )),
             epoch_manager,
             rounds_in_epoch: public_config.parameters.rounds_in_epoch,
             committer,
+            consensus_parameters,
+            leader_reputation,
+            block_limits: public_config.parameters.block_limits(),
+            equivocations,
+            equivocation_positions,
+            last_state_position: None,
+            last_commit_position: None,
+            snapshots: None,
+            wal_bytes_between_snapshots: public_config.parameters.wal_bytes_between_snapshots,
+            last_snapshot_position: WalPosition::default(),
+            snapshot_task: None,
+            signed_round,
         };
 
         if !unprocessed_blocks.is_empty() {
@@ -171,6 +271,8 @@ impl<H: BlockHandler> Core<H> {
             );
             this.run_block_handler(&unprocessed_blocks);
         }
+        // The recovered own blocks are only sent again once they are durable
+        this.request_sync();
 
         this
     }
@@ -180,17 +282,37 @@ impl<H: BlockHandler> Core<H> {
         self
     }
 
+    /// Write snapshots of the state recovered from the wal when cleaning up.
+    pub fn with_snapshots(mut self, snapshots: Snapshots) -> Self {
+        self.snapshots = Some(snapshots);
+        self
+    }
+
+    pub fn with_equivocation_policy(mut self, policy: Box<dyn EquivocationPolicy>) -> Self {
+        self.equivocations.set_policy(policy);
+        self
+    }
+
+    pub fn equivocation_proofs(&self) -> EquivocationProofs {
+        self.equivocations.proofs()
+    }
+
     // Note that generally when you update this function you also want to change genesis initialization above
-    pub fn add_blocks(&mut self, blocks: Vec<Data<StatementBlock>>) -> Vec<Data<StatementBlock>> {
+    pub fn add_blocks(
+        &mut self,
+        mut blocks: Vec<Data<StatementBlock>>,
+    ) -> Vec<Data<StatementBlock>> {
         let _timer = self
             .metrics
             .utilization_timer
             .utilization_timer("Core::add_blocks");
+        blocks.retain(|block| !self.equivocations.is_rejected(block.author()));
         let processed = self
             .block_manager
             .add_blocks(blocks, &mut (&mut self.wal_writer, &self.block_store));
         let mut result = Vec::with_capacity(processed.len());
         for (position, processed) in processed.into_iter() {
+            self.check_equivocation(&processed);
             self.threshold_clock
                 .add_block(*processed.reference(), &self.committee);
             self.pending
@@ -201,6 +323,27 @@ impl<H: BlockHandler> Core<H> {
         result
     }
 
+    fn check_equivocation(&mut self, block: &Data<StatementBlock>) {
+        let Some(proof) = self.equivocations.check(block, &self.block_store) else {
+            return;
+        };
+        self.metrics
+            .equivocations_total
+            .with_label_values(&[&proof.authority.to_string()])
+            .inc();
+        self.write_equivocation_proof(&proof);
+    }
+
+    fn write_equivocation_proof(&mut self, proof: &EquivocationProof) {
+        let serialized = bincode::serialize(proof).expect("Serialization should not fail");
+        let position = self
+            .wal_writer
+            .write(WAL_ENTRY_EQUIVOCATION, &serialized)
+            .expect("Failed to write equivocation proof to wal");
+        self.equivocation_positions
+            .insert((proof.authority, proof.round), position);
+    }
+
     fn run_block_handler(&mut self, processed: &[Data<StatementBlock>]) {
         let _timer = self
             .metrics
@@ -219,18 +362,49 @@ impl<H: BlockHandler> Core<H> {
             .push_back((position, MetaStatement::Payload(statements)));
     }
 
+    /// Propose a change to the committee of the next epoch in the next own block. Changes
+    /// submitted once the epoch is changing would not be committed in time and are refused.
+    pub fn submit_committee_change(&mut self, change: CommitteeChange) -> eyre::Result<()> {
+        ensure!(
+            change.epoch == self.committee.epoch(),
+            "Committee change of epoch {} submitted in epoch {}",
+            change.epoch,
+            self.committee.epoch()
+        );
+        change.verify(&self.committee)?;
+        ensure!(!self.epoch_changing(), "Epoch {} is closing", change.epoch);
+        let statements = vec![BaseStatement::Reconfigure(Box::new(change))];
+        let serialized_statements =
+            bincode::serialize(&statements).expect("Payload serialization failed");
+        let position = self
+            .wal_writer
+            .write(WAL_ENTRY_PAYLOAD, &serialized_statements)
+            .expect("Failed to write statements to wal");
+        self.pending
+            .push_back((position, MetaStatement::Payload(statements)));
+        Ok(())
+    }
+
     pub fn try_new_block(&mut self) -> Option<Data<StatementBlock>> {
         let _timer = self
             .metrics
             .utilization_timer
             .utilization_timer("Core::try_new_block");
         let clock_round = self.threshold_clock.get_round();
-        if clock_round <= self.last_proposed() {
+        if clock_round <= self.last_proposed().max(self.signed_round) {
+            return None;
+        }
+        // A single own block is signed ahead of the wal, a crash loses at most that one. The
+        // syncer tries again once the last own block is durable
+        let own_block_position = self
+            .block_store
+            .block_position(*self.last_own_block.block.reference())
+            .expect("Last own block is not in the block store");
+        if !self.wal_writer.durability().is_durable(own_block_position) {
             return None;
         }
 
         let mut includes = vec![];
-        let mut statements = vec![];
 
         let first_include_index = self
             .pending
@@ -244,44 +418,79 @@ impl<H: BlockHandler> Core<H> {
         let mut taken = self.pending.split_off(first_include_index);
         // Split off returns the "tail", what we want is keep the tail in "pending" and get the head
         mem::swap(&mut taken, &mut self.pending);
+        // Where the taken statements go back if the block can not be signed
+        let taken_position = taken.front().map(|(position, _)| *position);
         // Compress the references in the block
         // Iterate through all the include statements in the block, and make a set of all the references in their includes.
         let mut references_in_block: HashSet<BlockReference> = HashSet::new();
         references_in_block.extend(self.last_own_block.block.includes());
+        // The block can not be created before the blocks it includes, even if our clock lags
+        let mut min_time_ns = self.last_own_block.block.meta_creation_time_ns();
         for (_, statement) in &taken {
             if let MetaStatement::Include(block_ref) = statement {
                 // for all the includes in the block, add the references in the block to the set
                 if let Some(block) = self.block_store.get_block(*block_ref) {
                     references_in_block.extend(block.includes());
+                    min_time_ns = min_time_ns.max(block.meta_creation_time_ns());
                 }
             }
         }
-        includes.push(*self.last_own_block.block.reference());
-        for (_, statement) in taken.into_iter() {
+        let own_reference = *self.last_own_block.block.reference();
+        includes.push(own_reference);
+        references_in_block.insert(own_reference);
+        let mut payloads = vec![];
+        for (position, statement) in taken.into_iter() {
             match statement {
                 MetaStatement::Include(include) => {
-                    if !references_in_block.contains(&include) {
+                    if clock_round - include.round > self.block_limits.max_include_round_gap {
+                        tracing::debug!("Not including {include}, too old for round {clock_round}");
+                        continue;
+                    }
+                    if references_in_block.insert(include) {
                         includes.push(include);
                     }
                 }
                 MetaStatement::Payload(payload) => {
                     if !self.epoch_changing() {
-                        statements.extend(payload);
+                        payloads.push((position, payload));
                     }
                 }
             }
         }
+        let statements = self.take_statements(payloads, &includes);
 
         assert!(!includes.is_empty());
-        let time_ns = timestamp_utc().as_nanos();
-        let block = StatementBlock::new_with_signer(
+        let time_ns = timestamp_utc().as_nanos().max(min_time_ns);
+        let signature = match self.signer.sign_block(
+            self.authority,
+            clock_round,
+            &includes,
+            &statements,
+            time_ns,
+            self.committee.epoch(),
+            self.epoch_changing(),
+            self.committee.hash_function(),
+        ) {
+            Ok(signature) => signature,
+            Err(e) => {
+                // Skip the round, the next attempt proposes the same includes and statements
+                tracing::warn!("Failed to sign block at round {clock_round}: {e}");
+                if let Some(position) = taken_position {
+                    self.restore_pending(position, &includes[1..], statements);
+                }
+                return None;
+            }
+        };
+        let block = StatementBlock::new(
             self.authority,
             clock_round,
             includes,
             statements,
             time_ns,
+            self.committee.epoch(),
             self.epoch_changing(),
-            &self.signer,
+            signature,
+            self.committee.hash_function(),
         );
         assert_eq!(
             block.includes().get(0).unwrap().authority,
@@ -314,21 +523,95 @@ impl<H: BlockHandler> Core<H> {
             block: block.clone(),
         };
         (&mut self.wal_writer, &self.block_store).insert_own_block(&self.last_own_block);
-
-        if self.options.fsync {
-            self.wal_writer.sync().expect("Wal sync failed");
-        }
+        // The block is sent to peers once durable, otherwise a crash could make us sign another
+        // block for this round
+        self.request_sync();
 
         tracing::debug!("Created block {block:?}");
         Some(block)
     }
 
+    /// Puts the includes and statements of a block that could not be signed back in front of the
+    /// pending statements.
+    fn restore_pending(
+        &mut self,
+        position: WalPosition,
+        includes: &[BlockReference],
+        statements: Vec<BaseStatement>,
+    ) {
+        if !statements.is_empty() {
+            self.pending
+                .push_front((position, MetaStatement::Payload(statements)));
+        }
+        for include in includes.iter().rev() {
+            self.pending
+                .push_front((position, MetaStatement::Include(*include)));
+        }
+    }
+
+    /// Take the statements of the payloads that fit in a block with the given includes. The others
+    /// go back to the front of the pending statements, for the next blocks. After a crash, the
+    /// statements of a payload that was only partly proposed are proposed again.
+    fn take_statements(
+        &mut self,
+        payloads: Vec<(WalPosition, Vec<BaseStatement>)>,
+        includes: &[BlockReference],
+    ) -> Vec<BaseStatement> {
+        let max_block_size = self.block_limits.max_block_size;
+        let mut size = bincode::serialized_size(includes).expect("Serialization failed") as usize
+            + BLOCK_HEADER_RESERVE;
+        let mut statements = vec![];
+        let mut deferred = vec![];
+        for (position, payload) in payloads {
+            if !deferred.is_empty() {
+                deferred.push((position, payload));
+                continue;
+            }
+            let mut payload = payload.into_iter();
+            while let Some(statement) = payload.next() {
+                let statement_size =
+                    bincode::serialized_size(&statement).expect("Serialization failed") as usize;
+                if size + statement_size <= max_block_size {
+                    size += statement_size;
+                    statements.push(statement);
+                } else if statements.is_empty() {
+                    tracing::warn!(
+                        "Dropping a statement of {statement_size} bytes, too large for a block"
+                    );
+                } else {
+                    let rest = iter::once(statement).chain(payload).collect();
+                    deferred.push((position, rest));
+                    break;
+                }
+            }
+        }
+        for (position, payload) in deferred.into_iter().rev() {
+            self.pending
+                .push_front((position, MetaStatement::Payload(payload)));
+        }
+        statements
+    }
+
     pub fn wal_syncer(&self) -> WalSyncer {
         self.wal_writer
             .syncer()
             .expect("Failed to create wal syncer")
     }
 
+    pub fn wal_durability(&self) -> Arc<WalDurability> {
+        self.wal_writer.durability()
+    }
+
+    /// Makes everything written to the wal so far durable. With fsync, the entries are synced by the
+    /// wal syncer along with the other requests of its interval, the callers do not wait for it.
+    fn request_sync(&self) {
+        if self.options.fsync {
+            self.wal_writer.request_sync();
+        } else {
+            self.wal_writer.assume_durable();
+        }
+    }
+
     fn proposed_block_stats(&self, block: &Data<StatementBlock>) {
         self.metrics
             .proposed_block_size_bytes
@@ -340,6 +623,7 @@ impl<H: BlockHandler> Core<H> {
                 BaseStatement::Share(_) => transactions += 1,
                 BaseStatement::Vote(_, _) => votes += 1,
                 BaseStatement::VoteRange(range) => votes += range.len(),
+                BaseStatement::Reconfigure(_) => {}
             }
         }
         self.metrics
@@ -349,11 +633,20 @@ impl<H: BlockHandler> Core<H> {
     }
 
     pub fn try_commit(&mut self) -> Vec<Data<StatementBlock>> {
+        // Leaders past the end of the reputation window are decided with the next schedule
+        let max_commits = match self.consensus_parameters.commits_per_schedule {
+            0 => usize::MAX,
+            commits_per_schedule => {
+                self.leader_reputation
+                    .commits_until_update(commits_per_schedule) as usize
+            }
+        };
         let sequence: Vec<_> = self
             .committer
             .try_commit(self.last_commit_leader)
             .into_iter()
             .filter_map(|leader| leader.into_decided_block())
+            .take(max_commits)
             .collect();
 
         if let Some(last) = sequence.last() {
@@ -368,28 +661,77 @@ impl<H: BlockHandler> Core<H> {
         sequence
     }
 
-    pub fn cleanup(&self) {
-        const RETAIN_BELOW_COMMIT_ROUNDS: RoundNumber = 100;
-
-        self.block_store.cleanup(
-            self.last_commit_leader
-                .round()
-                .saturating_sub(RETAIN_BELOW_COMMIT_ROUNDS),
-        );
+    pub fn cleanup(&mut self) {
+        let threshold_round = self
+            .last_commit_leader
+            .round()
+            .saturating_sub(RETAIN_BELOW_COMMIT_ROUNDS);
+        self.block_store.cleanup(threshold_round);
+        if threshold_round > 0 {
+            self.compact_wal(threshold_round);
+        }
+        self.write_snapshot(threshold_round);
 
         self.block_handler.cleanup();
     }
 
+    /// Delete the wal segments that recovery no longer needs: all their blocks are at or below the
+    /// threshold round, and the state and commit entries written since supersede theirs.
+    fn compact_wal(&mut self, threshold_round: RoundNumber) {
+        let (Some(state_position), Some(commit_position)) =
+            (self.last_state_position, self.last_commit_position)
+        else {
+            return;
+        };
+        let own_block_position = self
+            .block_store
+            .block_position(*self.last_own_block.block.reference())
+            .expect("Last own block is not in the block store");
+        let mut retained = [
+            state_position,
+            commit_position,
+            own_block_position,
+            self.last_own_block.next_entry,
+        ]
+        .into_iter()
+        .min()
+        .unwrap();
+        if let Some((position, _)) = self.pending.front() {
+            retained = retained.min(*position);
+        }
+        if let Some(position) = self.block_store.first_position_above_round(threshold_round) {
+            retained = retained.min(position);
+        }
+        if !self.wal_writer.can_truncate_before(retained) || self.snapshot_in_progress() {
+            return;
+        }
+        // Equivocation proofs are never superseded, the ones in the deleted segments are written
+        // again first
+        let first_retained = self.wal_writer.first_retained_position(retained);
+        for proof in self.equivocations.proofs().all() {
+            if self.equivocation_positions[&(proof.authority, proof.round)] < first_retained {
+                self.write_equivocation_proof(&proof);
+            }
+        }
+        let deleted = self
+            .wal_writer
+            .truncate_before(retained)
+            .expect("Failed to delete wal segments");
+        self.block_store
+            .remove_before(threshold_round, self.wal_writer.first_position());
+        self.metrics
+            .wal_segments
+            .set(self.wal_writer.segments() as i64);
+        tracing::info!("Deleted {deleted} wal segments below round {threshold_round}");
+    }
+
     /// This only checks readiness in terms of helping liveness for commit rule,
     /// try_new_block might still return None if threshold clock is not ready
     ///
     /// The algorithm to calling is roughly: if timeout || commit_ready_new_block then try_new_block(..)
-    pub fn ready_new_block(
-        &self,
-        period: u64,
-        connected_authorities: &HashSet<AuthorityIndex>,
-    ) -> bool {
+    pub fn ready_new_block(&self, connected_authorities: &HashSet<AuthorityIndex>) -> bool {
         let quorum_round = self.threshold_clock.get_round();
+        let period = self.consensus_parameters.wave_length;
 
         // Leader round we check if we have a leader block
         if quorum_round > self.last_commit_leader.round().max(period - 1) {
@@ -414,15 +756,58 @@ impl<H: BlockHandler> Core<H> {
                 self.epoch_manager
                     .observe_committed_block(block, &self.committee);
             }
+            if self.consensus_parameters.commits_per_schedule > 0 {
+                self.update_leader_reputation(commit);
+            }
             commit_data.push(CommitData::from(commit));
         }
         self.write_state(); // todo - this can be done less frequently to reduce IO
         self.write_commits(&commit_data, state);
-        // todo - We should also persist state of the epoch manager, otherwise if validator
-        // restarts during epoch change it will fork on the epoch change state.
         commit_data
     }
 
+    /// Snapshot the state recovered from the wal once enough was written since the last snapshot.
+    /// The blocks at or below the gc round are left out, the wal compaction deletes them. The wal
+    /// is read by a separate thread, a snapshot is skipped while the previous one is in progress.
+    fn write_snapshot(&mut self, gc_round: RoundNumber) {
+        let Some(snapshots) = &self.snapshots else {
+            return;
+        };
+        if self.snapshot_in_progress() {
+            return;
+        }
+        // The snapshot must not capture entries that a crash could still lose
+        let position = self.wal_writer.durability().durable_position();
+        if position.bytes_since(self.last_snapshot_position) < self.wal_bytes_between_snapshots {
+            return;
+        }
+        let snapshots = snapshots.clone();
+        let block_store = self.block_store.clone();
+        let committee = self.committee.clone();
+        let wal_start = self.wal_writer.first_position();
+        let write = move || -> io::Result<()> {
+            let previous = snapshots.latest_within(wal_start, position)?;
+            let snapshot = block_store.snapshot(previous, position, &committee, gc_round)?;
+            snapshots.write(&snapshot)
+        };
+        let task = thread::Builder::new()
+            .name("wal-snapshot".to_string())
+            .spawn(move || match write() {
+                Ok(()) => tracing::info!("Wrote wal snapshot at position {position}"),
+                Err(err) => tracing::warn!("Failed to write wal snapshot at {position}: {err}"),
+            })
+            .expect("Failed to spawn wal-snapshot");
+        self.snapshot_task = Some(task);
+        self.last_snapshot_position = position;
+    }
+
+    // The wal compaction waits for it, the snapshot reads the segments it would delete
+    fn snapshot_in_progress(&self) -> bool {
+        self.snapshot_task
+            .as_ref()
+            .is_some_and(|task| !task.is_finished())
+    }
+
     pub fn write_state(&mut self) {
         #[cfg(feature = "simulator")]
         if self.block_handler().state().len() >= crate::wal::MAX_ENTRY_SIZE {
@@ -431,19 +816,54 @@ impl<H: BlockHandler> Core<H> {
             // We need to put some limit/backpressure on the accumulator state
             return;
         }
-        self.wal_writer
+        let position = self
+            .wal_writer
             .write(WAL_ENTRY_STATE, &self.block_handler().state())
             .expect("Write to wal has failed");
+        self.last_state_position = Some(position);
+    }
+
+    fn update_leader_reputation(&mut self, commit: &CommittedSubDag) {
+        let Some(swap_table) = self.leader_reputation.observe(
+            commit,
+            &self.committee,
+            self.consensus_parameters.commits_per_schedule,
+            self.consensus_parameters.bad_nodes_stake_threshold,
+        ) else {
+            return;
+        };
+        tracing::info!(
+            "Leader schedule updated after {}: {swap_table}",
+            commit.anchor
+        );
+        self.committer.leader_schedule().update(swap_table);
     }
 
     pub fn write_commits(&mut self, commits: &[CommitData], state: &Bytes) {
-        let commits = bincode::serialize(&(commits, state)).expect("Commits serialization failed");
-        self.wal_writer
-            .write(WAL_ENTRY_COMMIT, &commits)
+        // The reputation and the epoch change are written along with the commits, so that the
+        // schedule and the epoch status recovered after a crash match the last recovered commit
+        let mut entry = vec![WAL_COMMIT_VERSION];
+        bincode::serialize_into(
+            &mut entry,
+            &(
+                commits,
+                state,
+                &self.leader_reputation,
+                self.epoch_manager.state(),
+            ),
+        )
+        .expect("Commits serialization failed");
+        let position = self
+            .wal_writer
+            .write(WAL_ENTRY_COMMIT, &entry)
             .expect("Write to wal has failed");
+        self.last_commit_position = Some(position);
+        self.request_sync();
     }
 
-    pub fn take_recovered_committed_blocks(&mut self) -> (HashSet<BlockReference>, Option<Bytes>) {
+    pub fn take_recovered_committed_blocks(
+        &mut self,
+    ) -> (HashSet<BlockReference>, Option<Bytes>, TimestampNs) {
         self.recovered_committed_blocks
             .take()
             .expect("take_recovered_committed_blocks called twice")
@@ -465,6 +885,11 @@ impl<H: BlockHandler> Core<H> {
         self.authority
     }
 
+    /// The leaders the node waits for before proposing after the specified round.
+    pub fn leaders(&self, round: RoundNumber) -> Vec<AuthorityIndex> {
+        self.committer.get_leaders(round)
+    }
+
     pub fn block_handler(&self) -> &H {
         &self.block_handler
     }
@@ -481,6 +906,10 @@ impl<H: BlockHandler> Core<H> {
         &self.committee
     }
 
+    pub fn consensus_parameters(&self) -> &ConsensusParameters {
+        &self.consensus_parameters
+    }
+
     pub fn epoch_closed(&self) -> bool {
         self.epoch_manager.closed()
     }
@@ -492,6 +921,80 @@ impl<H: BlockHandler> Core<H> {
     pub fn epoch_closing_time(&self) -> Arc<AtomicU64> {
         self.epoch_manager.closing_time()
     }
+
+    /// The committee and public config of the next epoch, derived from the committee changes
+    /// committed in this one. Only known once the epoch is safe to close.
+    pub fn next_epoch(
+        &self,
+        public_config: &NodePublicConfig,
+    ) -> Option<(Arc<Committee>, NodePublicConfig)> {
+        if !self.epoch_closed() {
+            return None;
+        }
+        Some(reconfiguration::next_committee(
+            &self.committee,
+            public_config,
+            self.epoch_manager.committee_changes(),
+        ))
+    }
+
+    /// Stop the core and take back its signer, to sign the blocks of the next epoch.
+    pub fn into_signer(self) -> Box<dyn BlockSigner> {
+        self.signer
+    }
+}
+
+/// Returns the last round signed in the epoch, the node must not propose blocks up to it. The
+/// sign guard is one block ahead of the wal when the node crashed after signing the block that
+/// follows the last durable own block, the block was never sent. Refuses to start from a wal that
+/// disagrees with the sign guard otherwise: it was lost or replaced, and the node would lose the
+/// own blocks it already sent or propose blocks again for rounds it already signed.
+fn check_sign_guard(
+    authority: AuthorityIndex,
+    epoch: Epoch,
+    last_signed: &SignedBlockRecord,
+    last_own_block: Option<&StatementBlock>,
+    hash_function: HashFunction,
+) -> RoundNumber {
+    assert!(
+        last_signed.epoch <= epoch,
+        "Sign guard holds a block of epoch {}, the node cannot go back to epoch {epoch}",
+        last_signed.epoch
+    );
+    if last_signed.epoch < epoch {
+        // Every epoch has its own wal, nothing was signed in this one yet
+        return 0;
+    }
+    assert_eq!(
+        last_signed.authority, authority,
+        "Sign guard belongs to authority {}",
+        last_signed.authority
+    );
+    let wal_round = last_own_block.map_or(0, StatementBlock::round);
+    assert!(
+        last_own_block.is_some() || last_signed.round == 0,
+        "Wal is empty, but round {} was already signed. Was the wal lost?",
+        last_signed.round
+    );
+    if last_signed.round > wal_round {
+        assert_eq!(
+            last_signed.parent_round, wal_round,
+            "Wal is older than the sign guard: last own block in the wal is at round {wal_round}, \
+            but round {} was already signed. Was the wal lost or is another instance running?",
+            last_signed.round
+        );
+        tracing::warn!(
+            "Round {} was signed but is missing from the wal, skipping it",
+            last_signed.round
+        );
+    } else if let Some(block) = last_own_block {
+        assert!(
+            last_signed.round < wal_round
+                || last_signed.digest == crypto::signed_digest(block, hash_function),
+            "Sign guard holds a different block for round {wal_round} than the wal"
+        );
+    }
+    last_signed.round
 }
 
 impl Default for CoreOptions {
@@ -512,14 +1015,33 @@ impl CoreOptions {
 
 #[cfg(test)]
 mod test {
-    use std::fmt::Write;
+    use std::{
+        fmt::Write,
+        sync::atomic::{AtomicBool, Ordering},
+    };
 
     use rand::{prelude::StdRng, Rng, SeedableRng};
 
     use super::*;
     use crate::{
-        test_util::{committee_and_cores, committee_and_cores_persisted},
+        block_handler::TestBlockHandler,
+        block_signer::{GuardedSigner, SignError},
+        consensus::linearizer::Linearizer,
+        crypto::{dummy_signer, SignatureBytes, Signer},
+        equivocation::EquivocationPolicyKind,
+        reconfiguration::CommitteeChangeKind,
+        sign_guard::SignGuard,
+        test_util::{
+            self,
+            committee_and_cores,
+            committee_and_cores_persisted,
+            committee_and_cores_persisted_epoch_duration,
+            cores_for_committee,
+            test_metrics,
+        },
         threshold_clock,
+        types::{EpochStatus, PublicKey, Transaction},
+        wal::segmented_wal,
     };
 
     #[test]
@@ -574,6 +1096,46 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_statements_fit_block_size() {
+        let mut config = NodePublicConfig::new_for_tests(4);
+        config.parameters.max_block_size = BLOCK_HEADER_RESERVE + 1024;
+        let (_committee, mut cores, _) =
+            committee_and_cores_persisted_epoch_duration(4, None, &config);
+        let core = &mut cores[0];
+        core.pending.clear();
+        // Each share takes a bit over 400 bytes, two fit in a block next to its include
+        let share = |byte| BaseStatement::Share(Transaction::new(vec![byte; 400]));
+        let first = WalPosition::default();
+        let second = first.add(1);
+        let payloads = vec![
+            (first, vec![share(1), share(2), share(3)]),
+            (second, vec![share(4)]),
+        ];
+        let includes = [BlockReference::new_test(0, 0)];
+
+        let statements = core.take_statements(payloads, &includes);
+        assert_eq!(statements, vec![share(1), share(2)]);
+        let deferred: Vec<_> = core
+            .pending
+            .drain(..)
+            .map(|(position, statement)| match statement {
+                MetaStatement::Payload(payload) => (position, payload),
+                MetaStatement::Include(_) => panic!("Unexpected include"),
+            })
+            .collect();
+        assert_eq!(
+            deferred,
+            vec![(first, vec![share(3)]), (second, vec![share(4)])]
+        );
+
+        // A statement that never fits is dropped rather than holding back the others
+        let too_large = BaseStatement::Share(Transaction::new(vec![0; 2048]));
+        let statements = core.take_statements(vec![(first, vec![too_large, share(5)])], &includes);
+        assert_eq!(statements, vec![share(5)]);
+        assert!(core.pending.is_empty());
+    }
+
     #[test]
     fn test_randomized_simple_exchange() {
         'l: for seed in 0..100 {
@@ -724,6 +1286,823 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_equivocation_recovery() {
+        let tmp = tempdir::TempDir::new("test_equivocation_recovery").unwrap();
+        let (_committee, mut cores, _) = committee_and_cores_persisted(4, Some(tmp.path()));
+
+        let mut blocks = vec![];
+        for core in &mut cores {
+            core.run_block_handler(&[]);
+            blocks.push(core.try_new_block().unwrap());
+        }
+        let conflicting = Data::new((*blocks[1]).clone().with_test_digest(1));
+        let core = &mut cores[0];
+        core.add_blocks(blocks.clone());
+        assert!(core.equivocation_proofs().all().is_empty());
+        core.add_blocks(vec![conflicting.clone()]);
+        let proofs = core.equivocation_proofs().by_authority(1);
+        assert_eq!(proofs.len(), 1);
+        assert_eq!(proofs[0].round, 1);
+        drop(cores);
+
+        let (_committee, cores, _) = committee_and_cores_persisted(4, Some(tmp.path()));
+        let mut core = cores
+            .into_iter()
+            .next()
+            .unwrap()
+            .with_equivocation_policy(Box::new(crate::equivocation::RejectEquivocators));
+        assert_eq!(core.equivocation_proofs().by_authority(1).len(), 1);
+        let later = Data::new((*blocks[1]).clone().with_test_digest(2));
+        assert!(core.add_blocks(vec![later]).is_empty());
+    }
+
+    #[test]
+    fn test_equivocation_policy_parameter() {
+        let mut config = NodePublicConfig::new_for_tests(4);
+        config.parameters.equivocation_policy = EquivocationPolicyKind::Reject;
+        let (_committee, mut cores, _) =
+            committee_and_cores_persisted_epoch_duration(4, None, &config);
+
+        let mut blocks = vec![];
+        for core in &mut cores {
+            core.run_block_handler(&[]);
+            blocks.push(core.try_new_block().unwrap());
+        }
+        let core = &mut cores[0];
+        core.add_blocks(blocks.clone());
+        core.add_blocks(vec![Data::new((*blocks[1]).clone().with_test_digest(1))]);
+        assert_eq!(core.equivocation_proofs().by_authority(1).len(), 1);
+        let later = Data::new((*blocks[1]).clone().with_test_digest(2));
+        assert!(core.add_blocks(vec![later]).is_empty());
+    }
+
+    #[test]
+    fn test_equivocation_proof_survives_compaction() {
+        let tmp = tempdir::TempDir::new("test_equivocation_proof_survives_compaction").unwrap();
+        let (committee, mut cores, _) = committee_and_cores_persisted(4, Some(tmp.path()));
+        // Blocks that are not part of the dag, other cores never need them
+        let conflicting = |byte| {
+            let includes = committee
+                .authorities()
+                .map(|authority| BlockReference::new_test(authority, 0))
+                .collect();
+            let block = StatementBlock::new(
+                1,
+                1,
+                includes,
+                vec![],
+                0,
+                0,
+                false,
+                SignatureBytes::default(),
+                HashFunction::default(),
+            );
+            Data::new(block.with_test_digest(byte))
+        };
+        let mut linearizers: Vec<_> = cores
+            .iter()
+            .map(|core| Linearizer::new(core.committee().clone()))
+            .collect();
+        for _ in 0..10 {
+            run_round_with_cleanup(&mut cores, &mut linearizers);
+        }
+        let proof = EquivocationProof::new(conflicting(1), conflicting(2)).unwrap();
+        cores[0].write_equivocation_proof(&proof);
+        drop(cores);
+
+        // Recovers the proof along with its position
+        let (_committee, mut cores, _) = committee_and_cores_persisted(4, Some(tmp.path()));
+        assert_eq!(cores[0].equivocation_proofs().by_authority(1).len(), 1);
+        let mut linearizers = vec![];
+        for core in &mut cores {
+            let (committed, _, last_timestamp_ns) = core.take_recovered_committed_blocks();
+            let mut linearizer = Linearizer::new(core.committee().clone());
+            linearizer.committed = committed;
+            linearizer.last_timestamp_ns = last_timestamp_ns;
+            linearizers.push(linearizer);
+        }
+        for _ in 0..300 {
+            run_round_with_cleanup(&mut cores, &mut linearizers);
+        }
+        assert!(cores[0].wal_writer.first_position() > WalPosition::default());
+        drop(cores);
+
+        // Written again only when the segment holding it was deleted
+        let (wal_writer, wal_reader) = segmented_wal(tmp.path().join("000.wal")).unwrap();
+        let copies = wal_reader
+            .iter_until(&wal_writer)
+            .filter(|entry| entry.as_ref().unwrap().1 .0 == WAL_ENTRY_EQUIVOCATION)
+            .count();
+        assert_eq!(copies, 1);
+        drop((wal_writer, wal_reader));
+        let (_committee, cores, _) = committee_and_cores_persisted(4, Some(tmp.path()));
+        assert_eq!(cores[0].equivocation_proofs().by_authority(1).len(), 1);
+    }
+
+    #[test]
+    fn test_own_block_durable_after_sync() {
+        let tmp = tempdir::TempDir::new("test_own_block_durable_after_sync").unwrap();
+        let (_committee, cores, _) = committee_and_cores_persisted(4, Some(tmp.path()));
+        let mut core = cores
+            .into_iter()
+            .next()
+            .unwrap()
+            .with_options(CoreOptions::production());
+        let syncer = core.wal_syncer();
+        let durability = core.wal_durability();
+
+        core.run_block_handler(&[]);
+        let block = core.try_new_block().unwrap();
+        let position = core.block_store.block_position(*block.reference()).unwrap();
+        assert!(!durability.is_durable(position));
+        assert_eq!(syncer.sync().unwrap(), 1);
+        assert!(durability.is_durable(position));
+    }
+
+    #[test]
+    fn test_wal_compaction() {
+        let tmp = tempdir::TempDir::new("test_wal_compaction").unwrap();
+        let (_committee, mut cores, _) = committee_and_cores_persisted(4, Some(tmp.path()));
+        let mut linearizers: Vec<_> = cores
+            .iter()
+            .map(|core| Linearizer::new(core.committee().clone()))
+            .collect();
+        for _ in 0..300 {
+            run_round_with_cleanup(&mut cores, &mut linearizers);
+        }
+        for core in &cores {
+            assert!(core.wal_writer.first_position() > WalPosition::default());
+            assert!(!core
+                .block_store
+                .block_exists(*core.committee.genesis_blocks(core.authority).0.reference()));
+        }
+        let last_proposed: Vec<_> = cores.iter().map(Core::last_proposed).collect();
+        drop(cores);
+
+        // Recovery only replays the retained segments
+        let (_committee, mut cores, _) = committee_and_cores_persisted(4, Some(tmp.path()));
+        let mut linearizers = vec![];
+        for (core, last_proposed) in cores.iter_mut().zip(last_proposed) {
+            assert_eq!(core.last_proposed(), last_proposed);
+            let (committed, _, last_timestamp_ns) = core.take_recovered_committed_blocks();
+            let mut linearizer = Linearizer::new(core.committee().clone());
+            linearizer.committed = committed;
+            linearizer.last_timestamp_ns = last_timestamp_ns;
+            linearizers.push(linearizer);
+        }
+        let last_commit = cores[0].last_commit_leader;
+        for _ in 0..10 {
+            run_round_with_cleanup(&mut cores, &mut linearizers);
+        }
+        assert!(cores[0].last_commit_leader.round() > last_commit.round());
+    }
+
+    fn run_round_with_cleanup(
+        cores: &mut [Core<TestBlockHandler>],
+        linearizers: &mut [Linearizer],
+    ) {
+        let blocks: Vec<_> = cores
+            .iter_mut()
+            .map(|core| core.try_new_block().unwrap())
+            .collect();
+        for (core, linearizer) in cores.iter_mut().zip(linearizers.iter_mut()) {
+            let others = blocks.iter().filter(|b| b.author() != core.authority);
+            core.add_blocks(others.cloned().collect());
+            let committed = core.try_commit();
+            let sub_dags = linearizer.handle_commit(core.block_store(), committed);
+            core.handle_committed_subdag(sub_dags, &Bytes::default());
+            core.cleanup();
+            wait_snapshot(core);
+        }
+    }
+
+    fn wait_snapshot(core: &mut Core<TestBlockHandler>) {
+        if let Some(task) = core.snapshot_task.take() {
+            task.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_snapshot_recovery() {
+        let tmp = tempdir::TempDir::new("test_snapshot_recovery").unwrap();
+        let mut config = NodePublicConfig::new_for_tests(4);
+        // A snapshot on every cleanup, each one built from the previous
+        config.parameters.wal_bytes_between_snapshots = 1;
+        let (committee, cores, _) =
+            committee_and_cores_persisted_epoch_duration(4, Some(tmp.path()), &config);
+        let snapshots_path =
+            |authority: AuthorityIndex| tmp.path().join(format!("{authority:03}.snapshots"));
+        let mut cores: Vec<_> = cores
+            .into_iter()
+            .map(|core| {
+                let snapshots = Snapshots::open(snapshots_path(core.authority)).unwrap();
+                core.with_snapshots(snapshots)
+            })
+            .collect();
+        let mut linearizers: Vec<_> = cores
+            .iter()
+            .map(|core| Linearizer::new(core.committee().clone()))
+            .collect();
+        // Past the retained rounds, so that the wal compaction deletes segments
+        for _ in 0..300 {
+            run_round_with_cleanup(&mut cores, &mut linearizers);
+        }
+        // The gc round of the last snapshot, no commit happens after it
+        let gc_rounds: Vec<_> = cores
+            .iter()
+            .map(|core| {
+                core.last_commit_leader
+                    .round()
+                    .saturating_sub(RETAIN_BELOW_COMMIT_ROUNDS)
+            })
+            .collect();
+        // Entries written after the last snapshot
+        let blocks: Vec<_> = cores
+            .iter_mut()
+            .map(|core| core.try_new_block().unwrap())
+            .collect();
+        for core in &mut cores {
+            let others = blocks.iter().filter(|b| b.author() != core.authority);
+            core.add_blocks(others.cloned().collect());
+        }
+        let block_store = &cores[0].block_store;
+        let references: Vec<_> = (0..=block_store.highest_round())
+            .flat_map(|round| block_store.get_blocks_by_round(round))
+            .map(|block| *block.reference())
+            .collect();
+        drop(cores);
+
+        for authority in committee.authorities() {
+            let recover = |from_snapshot: bool| {
+                let (wal_writer, wal_reader) =
+                    segmented_wal(tmp.path().join(format!("{authority:03}.wal"))).unwrap();
+                assert!(wal_writer.first_position() > WalPosition::default());
+                let snapshot = from_snapshot.then(|| {
+                    Snapshots::open(snapshots_path(authority))
+                        .unwrap()
+                        .latest(&wal_writer)
+                        .unwrap()
+                        .expect("No snapshot was written")
+                });
+                BlockStore::open_from_snapshot(
+                    authority,
+                    Arc::new(wal_reader),
+                    &wal_writer,
+                    test_metrics(),
+                    &committee,
+                    snapshot,
+                )
+                .unwrap()
+            };
+            let replayed = recover(false);
+            let from_snapshot = recover(true);
+            let gc_round = gc_rounds[authority as usize];
+            assert!(gc_round > 0);
+            // The full replay also recovers the blocks at or below the gc round that are left in
+            // the retained segments, the snapshot leaves them out
+            let references: Vec<_> = references
+                .iter()
+                .filter(|reference| reference.round > gc_round)
+                .copied()
+                .collect();
+            assert!(!references.is_empty());
+            assert_same_recovered_state(
+                &replayed,
+                &from_snapshot,
+                &committee,
+                &references,
+                gc_round,
+            );
+        }
+    }
+
+    fn assert_same_recovered_state(
+        a: &RecoveredState,
+        b: &RecoveredState,
+        committee: &Committee,
+        references: &[BlockReference],
+        gc_round: RoundNumber,
+    ) {
+        let serialized = |state: &RecoveredState| {
+            bincode::serialize(&(
+                &state.last_own_block,
+                &state.state,
+                &state.unprocessed_blocks,
+                &state.last_committed_leader,
+                &state.last_commit_timestamp_ns,
+                &state.committed_state,
+                &state.leader_reputation,
+                &state.epoch_change,
+                &state.equivocations,
+            ))
+            .unwrap()
+        };
+        assert_eq!(serialized(a), serialized(b));
+        let committed_above = |state: &RecoveredState| {
+            state
+                .committed_blocks
+                .iter()
+                .filter(|reference| reference.round > gc_round)
+                .copied()
+                .collect::<HashSet<_>>()
+        };
+        assert_eq!(committed_above(a), committed_above(b));
+        assert_eq!(format!("{:?}", a.pending), format!("{:?}", b.pending));
+        assert_eq!(a.block_store.highest_round(), b.block_store.highest_round());
+        assert_eq!(
+            a.block_store.last_own_block_ref(),
+            b.block_store.last_own_block_ref()
+        );
+        for authority in committee.authorities() {
+            assert_eq!(
+                a.block_store.last_seen_by_authority(authority),
+                b.block_store.last_seen_by_authority(authority)
+            );
+        }
+        for reference in references {
+            assert!(a.block_store.block_exists(*reference));
+            assert_eq!(
+                a.block_store.block_position(*reference),
+                b.block_store.block_position(*reference)
+            );
+        }
+    }
+
+    #[test]
+    fn test_leader_reputation() {
+        let tmp = tempdir::TempDir::new("test_leader_reputation").unwrap();
+        let mut config = NodePublicConfig::new_for_tests(4);
+        config.parameters.commits_per_schedule = 5;
+        config.parameters.bad_nodes_stake_threshold = 33;
+        let (_committee, cores, _) =
+            committee_and_cores_persisted_epoch_duration(4, Some(tmp.path()), &config);
+        // Authority 3 is down
+        let mut cores: Vec<_> = cores.into_iter().take(3).collect();
+        let mut linearizers: Vec<_> = cores
+            .iter()
+            .map(|core| Linearizer::new(core.committee().clone()))
+            .collect();
+        let mut sequences = vec![vec![]; cores.len()];
+
+        for _ in 0..30 {
+            let blocks: Vec<_> = cores
+                .iter_mut()
+                .map(|core| core.try_new_block().unwrap())
+                .collect();
+            for (i, core) in cores.iter_mut().enumerate() {
+                let others = blocks.iter().filter(|b| b.author() != core.authority);
+                core.add_blocks(others.cloned().collect());
+                let committed = core.try_commit();
+                sequences[i].extend(committed.iter().map(|block| *block.reference()));
+                let sub_dags = linearizers[i].handle_commit(core.block_store(), committed);
+                core.handle_committed_subdag(sub_dags, &Bytes::default());
+            }
+        }
+
+        // All nodes switch to the same schedule at the same commit
+        assert!(sequences[0].len() > 10);
+        assert!(sequences.iter().all(|sequence| sequence == &sequences[0]));
+        let latest_swap_table = |core: &Core<_>| {
+            core.committer
+                .leader_schedule()
+                .swap_table(RoundNumber::MAX)
+                .unwrap()
+        };
+        let swap_table = latest_swap_table(&cores[0]);
+        assert_eq!(swap_table.bad(), &[3]);
+        for core in &cores {
+            assert_eq!(latest_swap_table(core), swap_table);
+            for round in 100..110 {
+                assert!(!core.committer.get_leaders(round).contains(&3));
+            }
+        }
+        let decisions = |authority: &str, status, schedule| {
+            cores[0]
+                .metrics
+                .committed_leaders_total
+                .with_label_values(&[authority, status, schedule])
+                .get()
+        };
+        assert!(decisions("0", "direct-commit", "base") > 0);
+        assert!(decisions("0", "direct-commit", "reputation") > 0);
+        assert_eq!(decisions("3", "direct-skip", "reputation"), 0);
+        drop(cores);
+
+        let (_committee, cores, _) =
+            committee_and_cores_persisted_epoch_duration(4, Some(tmp.path()), &config);
+        for core in &cores[..3] {
+            assert_eq!(latest_swap_table(core), swap_table);
+        }
+    }
+
+    #[test]
+    fn test_epoch_consensus_parameters() {
+        let mut config = NodePublicConfig::new_for_tests(4);
+        let parameters = ConsensusParameters {
+            wave_length: 4,
+            number_of_leaders: 1,
+            enable_pipelining: false,
+            ..config.parameters.consensus_parameters(0)
+        };
+        config
+            .parameters
+            .epoch_consensus_parameters
+            .insert(0, parameters);
+        let (_committee, mut cores, _) =
+            committee_and_cores_persisted_epoch_duration(4, None, &config);
+        assert_eq!(cores[0].consensus_parameters(), &parameters);
+
+        let mut sequence = vec![];
+        for _ in 0..20 {
+            let blocks: Vec<_> = cores
+                .iter_mut()
+                .map(|core| core.try_new_block().unwrap())
+                .collect();
+            for core in cores.iter_mut() {
+                let others = blocks.iter().filter(|b| b.author() != core.authority);
+                core.add_blocks(others.cloned().collect());
+            }
+            sequence.extend(cores[0].try_commit());
+        }
+
+        // One leader every four rounds
+        let rounds: Vec<_> = sequence.iter().map(|block| block.round()).collect();
+        assert_eq!(rounds, vec![4, 8, 12, 16]);
+    }
+
+    #[test]
+    fn test_committee_reconfiguration() {
+        let mut config = NodePublicConfig::new_for_tests(4);
+        config.parameters.rounds_in_epoch = 10;
+        let next_parameters = ConsensusParameters {
+            wave_length: 4,
+            number_of_leaders: 1,
+            enable_pipelining: false,
+            ..config.parameters.consensus_parameters(0)
+        };
+        config
+            .parameters
+            .epoch_consensus_parameters
+            .insert(1, next_parameters);
+        let (committee, mut cores, _) =
+            committee_and_cores_persisted_epoch_duration(4, None, &config);
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let joiner = crypto::Signer::generate(crypto::SignatureScheme::Ed25519, &mut rng);
+        let join = |epoch| {
+            let kind = CommitteeChangeKind::Join {
+                network_address: "127.0.0.1:9000".parse().unwrap(),
+                metrics_address: "127.0.0.1:9001".parse().unwrap(),
+                stake: 1,
+            };
+            let mut change = CommitteeChange::new(&joiner, epoch, kind);
+            if epoch == committee.epoch() {
+                for approver in &crypto::Signer::new_for_test(3) {
+                    change.approve(approver, &committee).unwrap();
+                }
+            }
+            change
+        };
+        assert!(cores[0].submit_committee_change(join(1)).is_err());
+        cores[0].submit_committee_change(join(0)).unwrap();
+
+        let mut linearizers: Vec<_> = cores
+            .iter()
+            .map(|core| Linearizer::new(core.committee().clone()))
+            .collect();
+        let mut last_blocks = vec![];
+        for _ in 0..40 {
+            last_blocks = cores
+                .iter_mut()
+                .map(|core| core.try_new_block().unwrap())
+                .collect();
+            for (i, core) in cores.iter_mut().enumerate() {
+                let others = last_blocks.iter().filter(|b| b.author() != core.authority);
+                core.add_blocks(others.cloned().collect());
+                // As the syncer, stop committing once the epoch is safe to close
+                if core.epoch_closed() {
+                    continue;
+                }
+                let committed = core.try_commit();
+                let sub_dags = linearizers[i].handle_commit(core.block_store(), committed);
+                core.handle_committed_subdag(sub_dags, &Bytes::default());
+            }
+            if cores.iter().all(Core::epoch_closed) {
+                break;
+            }
+        }
+        assert!(cores.iter().all(Core::epoch_closed));
+
+        // All nodes derive the same committee for the next epoch
+        let next: Vec<_> = cores
+            .iter()
+            .map(|core| core.next_epoch(&config).unwrap())
+            .collect();
+        let (next_committee, next_config) = next[0].clone();
+        assert_eq!(next_committee.epoch(), committee.epoch() + 1);
+        assert_eq!(next_committee.len(), 5);
+        let joined = next_committee
+            .authority_index(&joiner.public_key())
+            .unwrap();
+        assert_eq!(next_committee.get_stake(joined), Some(1));
+        for (other_committee, other_config) in &next[1..] {
+            for authority in next_committee.authorities() {
+                assert_eq!(
+                    other_committee.get_public_key(authority),
+                    next_committee.get_public_key(authority)
+                );
+                assert_eq!(
+                    other_config.network_address(authority),
+                    next_config.network_address(authority)
+                );
+            }
+        }
+        // Blocks of the previous epoch are not accepted any more
+        assert!(last_blocks[0]
+            .verify(&next_committee, &config.parameters.block_limits())
+            .is_err());
+
+        // The next epoch starts from its own genesis, with its own consensus parameters
+        let (mut cores, _) = cores_for_committee(&next_committee, None, &next_config);
+        assert_eq!(cores[0].consensus_parameters(), &next_parameters);
+        let mut sequence = vec![];
+        for _ in 0..12 {
+            let blocks: Vec<_> = cores
+                .iter_mut()
+                .map(|core| core.try_new_block().unwrap())
+                .collect();
+            assert!(blocks.iter().all(|block| block.epoch() == 1));
+            for core in cores.iter_mut() {
+                let others = blocks.iter().filter(|b| b.author() != core.authority);
+                core.add_blocks(others.cloned().collect());
+            }
+            sequence.extend(cores[joined as usize].try_commit());
+        }
+        let rounds: Vec<_> = sequence.iter().map(|block| block.round()).collect();
+        assert_eq!(rounds, vec![4, 8]);
+    }
+
+    #[test]
+    fn test_epoch_change_recovery() {
+        let tmp = tempdir::TempDir::new("test_epoch_change_recovery").unwrap();
+        let mut config = NodePublicConfig::new_for_tests(4);
+        config.parameters.rounds_in_epoch = 10;
+        let open = || committee_and_cores_persisted_epoch_duration(4, Some(tmp.path()), &config);
+        let (_committee, mut cores, _) = open();
+        let leaving = &crypto::Signer::new_for_test(4)[3];
+        let leave = CommitteeChange::new(leaving, 0, CommitteeChangeKind::Leave);
+        cores[0].submit_committee_change(leave).unwrap();
+
+        // Crash all nodes once in every phase of the epoch change: open with the committee
+        // change committed, changing, and safe to close
+        let mut linearizers: Vec<_> = cores
+            .iter()
+            .map(|core| Linearizer::new(core.committee().clone()))
+            .collect();
+        let mut crashed = HashSet::new();
+        for _ in 0..40 {
+            let blocks: Vec<_> = cores
+                .iter_mut()
+                .map(|core| core.try_new_block().unwrap())
+                .collect();
+            for (i, core) in cores.iter_mut().enumerate() {
+                let others = blocks.iter().filter(|b| b.author() != core.authority);
+                core.add_blocks(others.cloned().collect());
+                if core.epoch_closed() {
+                    continue;
+                }
+                let committed = core.try_commit();
+                let sub_dags = linearizers[i].handle_commit(core.block_store(), committed);
+                core.handle_committed_subdag(sub_dags, &Bytes::default());
+            }
+
+            let phase = (cores[0].epoch_changing(), cores[0].epoch_closed());
+            if cores[0].epoch_manager.committee_changes().is_empty() || !crashed.insert(phase) {
+                continue;
+            }
+            let states: Vec<_> = cores
+                .iter()
+                .map(|core| bincode::serialize(core.epoch_manager.state()).unwrap())
+                .collect();
+            let closing_times: Vec<_> = cores
+                .iter()
+                .map(|core| core.epoch_closing_time().load(Ordering::Relaxed))
+                .collect();
+            drop(cores);
+
+            (_, cores, _) = open();
+            for (i, core) in cores.iter_mut().enumerate() {
+                let state = bincode::serialize(core.epoch_manager.state()).unwrap();
+                assert_eq!(
+                    state, states[i],
+                    "Epoch change of core {i} is not recovered"
+                );
+                assert_eq!(
+                    core.epoch_closing_time().load(Ordering::Relaxed),
+                    closing_times[i]
+                );
+                linearizers[i].committed = core.take_recovered_committed_blocks().0;
+            }
+            if cores.iter().all(Core::epoch_closed) {
+                break;
+            }
+        }
+        assert_eq!(crashed.len(), 3);
+        assert!(cores.iter().all(Core::epoch_closed));
+
+        // Committee changes committed before the crashes still shape the next committee
+        for core in &cores {
+            let (next_committee, _) = core.next_epoch(&config).unwrap();
+            assert_eq!(next_committee.len(), 3);
+            assert!(next_committee
+                .authority_index(&leaving.public_key())
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn test_sign_guard_check() {
+        let hash_function = HashFunction::default();
+        let block = StatementBlock::new(
+            1,
+            5,
+            vec![],
+            vec![],
+            0,
+            1,
+            false,
+            Default::default(),
+            hash_function,
+        );
+        let record = |epoch, round| SignedBlockRecord {
+            authority: 1,
+            epoch,
+            round,
+            parent_round: round - 1,
+            digest: crypto::signed_digest(&block, hash_function),
+        };
+        assert_eq!(
+            check_sign_guard(1, 1, &record(1, 5), Some(&block), hash_function),
+            5
+        );
+        assert_eq!(
+            check_sign_guard(1, 1, &record(1, 4), Some(&block), hash_function),
+            4
+        );
+        // The block signed after the last own block of the wal is skipped
+        assert_eq!(
+            check_sign_guard(1, 1, &record(1, 6), Some(&block), hash_function),
+            6
+        );
+        let result = std::panic::catch_unwind(|| {
+            check_sign_guard(1, 1, &record(1, 7), Some(&block), hash_function)
+        });
+        assert!(
+            result.is_err(),
+            "Wal behind the sign guard by more than a block must be rejected"
+        );
+        let result =
+            std::panic::catch_unwind(|| check_sign_guard(1, 1, &record(1, 1), None, hash_function));
+        assert!(result.is_err(), "Lost wal must be rejected");
+        let mut other = record(1, 5);
+        other.digest = [1; crypto::BLOCK_DIGEST_SIZE];
+        let result = std::panic::catch_unwind(|| {
+            check_sign_guard(1, 1, &other, Some(&block), hash_function)
+        });
+        assert!(
+            result.is_err(),
+            "A different block signed for the round of the wal must be rejected"
+        );
+
+        // A new epoch starts from an empty wal, possibly with another authority index
+        assert_eq!(
+            check_sign_guard(2, 2, &record(1, 6), None, hash_function),
+            0
+        );
+        let result =
+            std::panic::catch_unwind(|| check_sign_guard(1, 0, &record(1, 1), None, hash_function));
+        assert!(
+            result.is_err(),
+            "Going back to an older epoch must be rejected"
+        );
+    }
+
+    #[test]
+    fn test_crash_between_sign_guard_and_wal() {
+        let tmp = tempdir::TempDir::new("test_crash_between_sign_guard_and_wal").unwrap();
+        let config = NodePublicConfig::new_for_tests(4);
+        let committee = test_util::committee(4);
+        let guard_path = tmp.path().join("sign-guard");
+        let guarded_signer = || -> Box<dyn BlockSigner> {
+            let guard = SignGuard::open(&guard_path).unwrap();
+            Box::new(GuardedSigner::new(Box::new(dummy_signer()), guard))
+        };
+        let (mut others, _) = cores_for_committee(&committee, None, &config);
+        others.remove(0);
+
+        let (mut core, _) =
+            test_util::open_core(&committee, 0, Some(tmp.path()), &config, guarded_signer());
+        let own_block = core.try_new_block().unwrap();
+        assert_eq!(own_block.round(), 1);
+        let others_r1: Vec<_> = others
+            .iter_mut()
+            .map(|core| core.try_new_block().unwrap())
+            .collect();
+
+        // The block of round 2 is signed, but the node crashes before writing it to the wal
+        let signer = core.into_signer();
+        signer
+            .sign_block(
+                0,
+                2,
+                &[*own_block.reference()],
+                &[],
+                0,
+                committee.epoch(),
+                false,
+                committee.hash_function(),
+            )
+            .unwrap();
+        drop(signer);
+
+        let (mut core, _) =
+            test_util::open_core(&committee, 0, Some(tmp.path()), &config, guarded_signer());
+        assert_eq!(core.last_proposed(), 1);
+        core.add_blocks(others_r1.clone());
+        assert!(core.try_new_block().is_none(), "Round 2 was already signed");
+
+        let mut others_r2 = vec![];
+        for other in &mut others {
+            let blocks = others_r1.iter().filter(|b| b.author() != other.authority);
+            other.add_blocks(blocks.cloned().chain([own_block.clone()]).collect());
+            others_r2.push(other.try_new_block().unwrap());
+        }
+        core.add_blocks(others_r2);
+        let block = core.try_new_block().unwrap();
+        assert_eq!(block.round(), 3);
+        assert_eq!(block.includes()[0], *own_block.reference());
+    }
+
+    /// Fails to sign the first block, like a remote signer that times out.
+    struct FlakySigner {
+        inner: Signer,
+        failed: AtomicBool,
+    }
+
+    impl BlockSigner for FlakySigner {
+        fn public_key(&self) -> PublicKey {
+            self.inner.public_key()
+        }
+
+        fn sign_block(
+            &self,
+            authority: AuthorityIndex,
+            round: RoundNumber,
+            includes: &[BlockReference],
+            statements: &[BaseStatement],
+            meta_creation_time_ns: TimestampNs,
+            epoch: Epoch,
+            epoch_marker: EpochStatus,
+            hash_function: HashFunction,
+        ) -> Result<SignatureBytes, SignError> {
+            if !self.failed.swap(true, Ordering::SeqCst) {
+                return Err(SignError::Io(io::ErrorKind::TimedOut.into()));
+            }
+            BlockSigner::sign_block(
+                &self.inner,
+                authority,
+                round,
+                includes,
+                statements,
+                meta_creation_time_ns,
+                epoch,
+                epoch_marker,
+                hash_function,
+            )
+        }
+
+        fn sign_handshake(&self, challenge: &[u8]) -> Result<SignatureBytes, SignError> {
+            self.inner.sign_handshake(challenge)
+        }
+    }
+
+    #[test]
+    fn test_sign_failure_skips_round() {
+        let config = NodePublicConfig::new_for_tests(4);
+        let committee = test_util::committee(4);
+        let signer = FlakySigner {
+            inner: dummy_signer(),
+            failed: AtomicBool::new(false),
+        };
+        let (mut core, _) = test_util::open_core(&committee, 0, None, &config, Box::new(signer));
+        assert!(core.try_new_block().is_none());
+        // The next attempt proposes the block with the same includes
+        let block = core.try_new_block().unwrap();
+        assert_eq!(block.round(), 1);
+        assert_eq!(block.includes().len(), 4);
+    }
+
     fn push_all(
         p: &mut Vec<Vec<Data<StatementBlock>>>,
         except: AuthorityIndex,
@@ -1,13 +1,14 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::HashSet;
+use std::{collections::HashSet, time::Duration};
 
 use parking_lot::Mutex;
 
 use crate::{
     block_handler::BlockHandler,
     data::Data,
+    reconfiguration::CommitteeChange,
     syncer::{CommitObserver, Syncer, SyncerSignals},
     types::{AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
 };
@@ -37,8 +38,20 @@ impl<H: BlockHandler + 'static, S: SyncerSignals + 'static, C: CommitObserver +
         self.syncer.lock().force_new_block(round);
     }
 
+    pub async fn own_block_durable(&self) {
+        self.syncer.lock().own_block_durable();
+    }
+
+    pub async fn leader_timeout(&self, round: RoundNumber) -> Duration {
+        self.syncer.lock().leader_timeout(round)
+    }
+
+    pub async fn submit_committee_change(&self, change: CommitteeChange) -> eyre::Result<()> {
+        self.syncer.lock().submit_committee_change(change)
+    }
+
     pub async fn cleanup(&self) {
-        self.syncer.lock().core().cleanup();
+        self.syncer.lock().cleanup();
     }
 
     pub async fn get_missing_blocks(&self) -> Vec<HashSet<BlockReference>> {
@@ -1,7 +1,7 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{collections::HashSet, sync::Arc, thread};
+use std::{collections::HashSet, sync::Arc, thread, time::Duration};
 
 use tokio::sync::{mpsc, oneshot};
 
@@ -9,6 +9,7 @@ use crate::{
     block_handler::BlockHandler,
     data::Data,
     metrics::{Metrics, UtilizationTimerExt},
+    reconfiguration::CommitteeChange,
     syncer::{CommitObserver, Syncer, SyncerSignals},
     types::{AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
 };
@@ -27,7 +28,13 @@ pub struct CoreThread<H: BlockHandler, S: SyncerSignals, C: CommitObserver> {
 enum CoreThreadCommand {
     AddBlocks(Vec<Data<StatementBlock>>, oneshot::Sender<()>),
     ForceNewBlock(RoundNumber, oneshot::Sender<()>),
+    /// Indicate that more of the wal is durable.
+    OwnBlockDurable(oneshot::Sender<()>),
+    /// Request the time to wait for the leaders of a round.
+    LeaderTimeout(RoundNumber, oneshot::Sender<Duration>),
     Cleanup(oneshot::Sender<()>),
+    /// Propose a change to the committee of the next epoch.
+    SubmitCommitteeChange(Box<CommitteeChange>, oneshot::Sender<eyre::Result<()>>),
     /// Request missing blocks that need to be synched.
     GetMissing(oneshot::Sender<Vec<HashSet<BlockReference>>>),
     /// Indicate that a connection to an authority was established.
@@ -73,12 +80,35 @@ impl<H: BlockHandler + 'static, S: SyncerSignals + 'static, C: CommitObserver +
         receiver.await.expect("core thread is not expected to stop");
     }
 
+    pub async fn own_block_durable(&self) {
+        let (sender, receiver) = oneshot::channel();
+        self.send(CoreThreadCommand::OwnBlockDurable(sender)).await;
+        receiver.await.expect("core thread is not expected to stop");
+    }
+
+    pub async fn leader_timeout(&self, round: RoundNumber) -> Duration {
+        let (sender, receiver) = oneshot::channel();
+        self.send(CoreThreadCommand::LeaderTimeout(round, sender))
+            .await;
+        receiver.await.expect("core thread is not expected to stop")
+    }
+
     pub async fn cleanup(&self) {
         let (sender, receiver) = oneshot::channel();
         self.send(CoreThreadCommand::Cleanup(sender)).await;
         receiver.await.expect("core thread is not expected to stop");
     }
 
+    pub async fn submit_committee_change(&self, change: CommitteeChange) -> eyre::Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.send(CoreThreadCommand::SubmitCommitteeChange(
+            Box::new(change),
+            sender,
+        ))
+        .await;
+        receiver.await.expect("core thread is not expected to stop")
+    }
+
     pub async fn get_missing_blocks(&self) -> Vec<HashSet<BlockReference>> {
         let (sender, receiver) = oneshot::channel();
         self.send(CoreThreadCommand::GetMissing(sender)).await;
@@ -122,10 +152,22 @@ impl<H: BlockHandler, S: SyncerSignals, C: CommitObserver> CoreThread<H, S, C> {
                     self.syncer.force_new_block(round);
                     sender.send(()).ok();
                 }
+                CoreThreadCommand::OwnBlockDurable(sender) => {
+                    self.syncer.own_block_durable();
+                    sender.send(()).ok();
+                }
+                CoreThreadCommand::LeaderTimeout(round, sender) => {
+                    sender.send(self.syncer.leader_timeout(round)).ok();
+                }
                 CoreThreadCommand::Cleanup(sender) => {
-                    self.syncer.core().cleanup();
+                    self.syncer.cleanup();
                     sender.send(()).ok();
                 }
+                CoreThreadCommand::SubmitCommitteeChange(change, sender) => {
+                    sender
+                        .send(self.syncer.submit_committee_change(*change))
+                        .ok();
+                }
                 CoreThreadCommand::GetMissing(sender) => {
                     let missing = self.syncer.core().block_manager().missing_blocks();
                     sender.send(missing.to_vec()).ok();
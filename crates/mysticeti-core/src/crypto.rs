@@ -1,87 +1,251 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::fmt;
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
 
 use digest::Digest;
-#[cfg(not(test))]
-use ed25519_consensus::Signature;
-use rand::{rngs::StdRng, SeedableRng};
+use ed25519_consensus::{Signature, VerificationKeyBytes};
+use mysten_mldsa_native_rs as mldsa;
+use rand::{rngs::StdRng, CryptoRng, RngCore, SeedableRng};
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use zeroize::Zeroize;
 
-#[cfg(not(test))]
-use crate::types::Vote;
 use crate::{
     serde::{ByteRepr, BytesVisitor},
     types::{
         AuthorityIndex,
         BaseStatement,
         BlockReference,
+        Epoch,
         EpochStatus,
         RoundNumber,
         StatementBlock,
         TimestampNs,
+        Vote,
     },
 };
 
-pub const SIGNATURE_SIZE: usize = 64;
+pub const ED25519_SIGNATURE_SIZE: usize = 64;
+pub const MLDSA65_SIGNATURE_SIZE: usize = mldsa::SIGNATURE_LENGTH;
+/// Largest signature produced by any of the supported schemes.
+pub const MAX_SIGNATURE_SIZE: usize = MLDSA65_SIGNATURE_SIZE;
 pub const BLOCK_DIGEST_SIZE: usize = 32;
 
+/// Hash function of block digests, including the digest covered by block signatures.
+/// It is chosen per committee and recorded in the committee file.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Default, Debug, Serialize, Deserialize)]
+pub enum HashFunction {
+    #[default]
+    #[serde(rename = "blake2b")]
+    Blake2b,
+    /// For verifiers that can only check SHA-256, such as on-chain light clients.
+    #[serde(rename = "sha256")]
+    Sha256,
+    #[serde(rename = "blake3")]
+    Blake3,
+}
+
+/// Signature scheme used by all authorities of a committee.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Default, Debug, Serialize, Deserialize)]
+pub enum SignatureScheme {
+    #[default]
+    #[serde(rename = "ed25519")]
+    Ed25519,
+    /// Post-quantum ML-DSA-65 (FIPS 204, formerly Dilithium3).
+    #[serde(rename = "ml-dsa-65")]
+    MlDsa65,
+}
+
 #[derive(Clone, Copy, Eq, Ord, PartialOrd, PartialEq, Default, Hash)]
 pub struct BlockDigest([u8; BLOCK_DIGEST_SIZE]);
 
 #[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Debug)]
-pub struct PublicKey(ed25519_consensus::VerificationKey);
+pub enum PublicKey {
+    #[serde(rename = "ed25519")]
+    Ed25519(ed25519_consensus::VerificationKey),
+    #[serde(rename = "ml-dsa-65")]
+    MlDsa65(MlDsaPublicKey),
+}
+
+// ML-DSA public keys are ~2KB, box them to keep PublicKey (and Committee entries) small
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct MlDsaPublicKey(Box<mldsa::VerifyingKey>);
 
-#[derive(Clone, Copy, Eq, Ord, PartialOrd, PartialEq, Hash)]
-pub struct SignatureBytes([u8; SIGNATURE_SIZE]);
+/// Encoded signature, its length depends on the signature scheme of the committee.
+#[derive(Clone, Eq, Ord, PartialOrd, PartialEq, Hash, Default)]
+pub struct SignatureBytes(Vec<u8>);
 
 // Box ensures value is not copied in memory when Signer itself is moved around for better security
 #[derive(Serialize, Deserialize)]
-pub struct Signer(Box<ed25519_consensus::SigningKey>);
+pub enum Signer {
+    #[serde(rename = "ed25519")]
+    Ed25519(Box<ed25519_consensus::SigningKey>),
+    #[serde(rename = "ml-dsa-65")]
+    MlDsa65(Box<MlDsaSigner>),
+}
 
-#[cfg(not(test))]
-type BlockHasher = blake2::Blake2b<digest::consts::U32>;
+/// ML-DSA key pair. Only the seed is serialized, the expanded keys are derived from it on load.
+pub struct MlDsaSigner {
+    seed: mldsa::SigningKeySeed,
+    signing_key: mldsa::SigningKey,
+    public_key: mldsa::VerifyingKey,
+}
+
+#[derive(Debug)]
+pub enum SignatureError {
+    Ed25519(ed25519_consensus::Error),
+    MlDsa(mldsa::Error),
+}
+
+type Blake2bHasher = blake2::Blake2b<digest::consts::U32>;
+type MessageHasher = blake2::Blake2b<digest::consts::U32>;
+
+impl HashFunction {
+    /// Digest of the block content, followed by the signature if there is one.
+    #[allow(clippy::too_many_arguments)]
+    fn digest_block(
+        self,
+        authority: AuthorityIndex,
+        round: RoundNumber,
+        includes: &[BlockReference],
+        statements: &[BaseStatement],
+        meta_creation_time_ns: TimestampNs,
+        epoch: Epoch,
+        epoch_marker: EpochStatus,
+        signature: Option<&SignatureBytes>,
+    ) -> [u8; BLOCK_DIGEST_SIZE] {
+        match self {
+            HashFunction::Blake2b => digest_block::<Blake2bHasher>(
+                authority,
+                round,
+                includes,
+                statements,
+                meta_creation_time_ns,
+                epoch,
+                epoch_marker,
+                signature,
+            ),
+            HashFunction::Sha256 => digest_block::<sha2::Sha256>(
+                authority,
+                round,
+                includes,
+                statements,
+                meta_creation_time_ns,
+                epoch,
+                epoch_marker,
+                signature,
+            ),
+            HashFunction::Blake3 => digest_block::<blake3::Hasher>(
+                authority,
+                round,
+                includes,
+                statements,
+                meta_creation_time_ns,
+                epoch,
+                epoch_marker,
+                signature,
+            ),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn digest_block<D: Digest>(
+    authority: AuthorityIndex,
+    round: RoundNumber,
+    includes: &[BlockReference],
+    statements: &[BaseStatement],
+    meta_creation_time_ns: TimestampNs,
+    epoch: Epoch,
+    epoch_marker: EpochStatus,
+    signature: Option<&SignatureBytes>,
+) -> [u8; BLOCK_DIGEST_SIZE] {
+    let mut hasher = D::new();
+    BlockDigest::digest_without_signature(
+        &mut hasher,
+        authority,
+        round,
+        includes,
+        statements,
+        meta_creation_time_ns,
+        epoch,
+        epoch_marker,
+    );
+    if let Some(signature) = signature {
+        Digest::update(&mut hasher, signature);
+    }
+    let mut digest = [0u8; BLOCK_DIGEST_SIZE];
+    digest.copy_from_slice(&hasher.finalize());
+    digest
+}
 
 impl BlockDigest {
     #[cfg(not(test))]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         authority: AuthorityIndex,
         round: RoundNumber,
         includes: &[BlockReference],
         statements: &[BaseStatement],
         meta_creation_time_ns: TimestampNs,
+        epoch: Epoch,
         epoch_marker: EpochStatus,
         signature: &SignatureBytes,
+        hash_function: HashFunction,
     ) -> Self {
-        let mut hasher = BlockHasher::default();
-        Self::digest_without_signature(
-            &mut hasher,
+        Self(hash_function.digest_block(
             authority,
             round,
             includes,
             statements,
             meta_creation_time_ns,
+            epoch,
             epoch_marker,
-        );
-        hasher.update(signature);
-        Self(hasher.finalize().into())
+            Some(signature),
+        ))
     }
 
     #[cfg(test)]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         _authority: AuthorityIndex,
         _round: RoundNumber,
         _includes: &[BlockReference],
         _statements: &[BaseStatement],
         _meta_creation_time_ns: TimestampNs,
+        _epoch: Epoch,
         _epoch_marker: EpochStatus,
         _signature: &SignatureBytes,
+        _hash_function: HashFunction,
     ) -> Self {
         Default::default()
     }
 
+    /// Digest of the block content and signature, computed even in tests where Self::new returns
+    /// default digests. Used where the evidence is checked rather than the blocks synchronized.
+    pub fn of_block(block: &StatementBlock, hash_function: HashFunction) -> Self {
+        Self(hash_function.digest_block(
+            block.author(),
+            block.round(),
+            block.includes(),
+            block.statements(),
+            block.meta_creation_time_ns(),
+            block.epoch(),
+            block.epoch_changed(),
+            Some(block.signature()),
+        ))
+    }
+
+    /// Tests use default digests, this tells apart blocks of the same authority and round.
+    #[cfg(test)]
+    pub fn new_test(byte: u8) -> Self {
+        Self([byte; BLOCK_DIGEST_SIZE])
+    }
+
     /// There is a bit of a complexity around what is considered block digest and what is being signed
     ///
     /// * Block signature covers all the fields in the block, except for signature and reference.digest
@@ -89,14 +253,15 @@ impl BlockDigest {
     ///
     /// This is not very beautiful, but it allows to optimize block synchronization,
     /// by skipping signature verification for all the descendants of the certified block.
-    #[cfg(not(test))]
+    #[allow(clippy::too_many_arguments)]
     fn digest_without_signature(
-        hasher: &mut BlockHasher,
+        hasher: &mut impl Digest,
         authority: AuthorityIndex,
         round: RoundNumber,
         includes: &[BlockReference],
         statements: &[BaseStatement],
         meta_creation_time_ns: TimestampNs,
+        epoch: Epoch,
         epoch_marker: EpochStatus,
     ) {
         authority.crypto_hash(hasher);
@@ -127,9 +292,14 @@ impl BlockDigest {
                     [4].crypto_hash(hasher);
                     range.crypto_hash(hasher);
                 }
+                BaseStatement::Reconfigure(change) => {
+                    [5].crypto_hash(hasher);
+                    change.crypto_hash(hasher);
+                }
             }
         }
         meta_creation_time_ns.crypto_hash(hasher);
+        epoch.crypto_hash(hasher);
         epoch_marker.crypto_hash(hasher);
     }
 }
@@ -175,39 +345,237 @@ impl<T: AsBytes> CryptoHash for T {
     }
 }
 
+impl SignatureScheme {
+    pub fn signature_size(&self) -> usize {
+        match self {
+            SignatureScheme::Ed25519 => ED25519_SIGNATURE_SIZE,
+            SignatureScheme::MlDsa65 => MLDSA65_SIGNATURE_SIZE,
+        }
+    }
+}
+
 impl PublicKey {
+    pub fn scheme(&self) -> SignatureScheme {
+        match self {
+            PublicKey::Ed25519(_) => SignatureScheme::Ed25519,
+            PublicKey::MlDsa65(_) => SignatureScheme::MlDsa65,
+        }
+    }
+
     #[cfg(not(test))]
-    pub fn verify_block(&self, block: &StatementBlock) -> Result<(), ed25519_consensus::Error> {
-        let signature = Signature::from(block.signature().0);
-        let mut hasher = BlockHasher::default();
-        BlockDigest::digest_without_signature(
-            &mut hasher,
-            block.author(),
-            block.round(),
-            block.includes(),
-            block.statements(),
-            block.meta_creation_time_ns(),
-            block.epoch_changed(),
-        );
-        let digest: [u8; BLOCK_DIGEST_SIZE] = hasher.finalize().into();
-        self.0.verify(&signature, digest.as_ref())
+    pub fn verify_block(
+        &self,
+        block: &StatementBlock,
+        hash_function: HashFunction,
+    ) -> Result<(), SignatureError> {
+        self.verify_digest(&signed_digest(block, hash_function), block.signature())
     }
 
+    /// Blocks are left unsigned in tests, only the signatures that blocks carry are checked.
     #[cfg(test)]
-    pub fn verify_block(&self, _block: &StatementBlock) -> Result<(), ed25519_consensus::Error> {
-        Ok(())
+    pub fn verify_block(
+        &self,
+        block: &StatementBlock,
+        hash_function: HashFunction,
+    ) -> Result<(), SignatureError> {
+        if block.signature().as_ref().is_empty() {
+            return Ok(());
+        }
+        self.verify_digest(&signed_digest(block, hash_function), block.signature())
+    }
+
+    /// Verifies a signature produced by Signer::sign_message.
+    pub fn verify_message(
+        &self,
+        domain: &[u8],
+        message: &[u8],
+        signature: &SignatureBytes,
+    ) -> Result<(), SignatureError> {
+        self.verify_digest(&message_digest(domain, message), signature)
+    }
+
+    fn verify_digest(
+        &self,
+        digest: &[u8],
+        signature: &SignatureBytes,
+    ) -> Result<(), SignatureError> {
+        match self {
+            PublicKey::Ed25519(key) => {
+                let signature =
+                    Signature::try_from(signature.as_ref()).map_err(SignatureError::Ed25519)?;
+                key.verify(&signature, digest)
+                    .map_err(SignatureError::Ed25519)
+            }
+            PublicKey::MlDsa65(key) => {
+                let signature = mldsa::Signature::from_bytes(signature.as_ref())
+                    .map_err(SignatureError::MlDsa)?;
+                key.0
+                    .verify(digest, b"", &signature)
+                    .map_err(SignatureError::MlDsa)
+            }
+        }
+    }
+}
+
+/// Verifies signatures of multiple blocks at once.
+///
+/// All ed25519 signatures are checked with a single batch verification, other schemes fall back
+/// to individual checks. On failure this does not tell which signature was invalid,
+/// callers are expected to re-check blocks one by one with PublicKey::verify_block.
+#[cfg(not(test))]
+pub fn verify_blocks_batch<'a>(
+    blocks: impl IntoIterator<Item = (&'a PublicKey, &'a StatementBlock)>,
+    hash_function: HashFunction,
+) -> Result<(), SignatureError> {
+    let items: Vec<_> = blocks
+        .into_iter()
+        .map(|(public_key, block)| {
+            let digest = signed_digest(block, hash_function);
+            (public_key, digest, block.signature())
+        })
+        .collect();
+    verify_digests_batch(items.iter().map(|(k, d, s)| (*k, d.as_ref(), *s)))
+}
+
+/// Blocks are left unsigned in tests, only the signatures that blocks carry are checked.
+#[cfg(test)]
+pub fn verify_blocks_batch<'a>(
+    blocks: impl IntoIterator<Item = (&'a PublicKey, &'a StatementBlock)>,
+    hash_function: HashFunction,
+) -> Result<(), SignatureError> {
+    let items: Vec<_> = blocks
+        .into_iter()
+        .filter(|(_, block)| !block.signature().as_ref().is_empty())
+        .map(|(public_key, block)| {
+            let digest = signed_digest(block, hash_function);
+            (public_key, digest, block.signature())
+        })
+        .collect();
+    verify_digests_batch(items.iter().map(|(k, d, s)| (*k, d.as_ref(), *s)))
+}
+
+fn verify_digests_batch<'a>(
+    items: impl IntoIterator<Item = (&'a PublicKey, &'a [u8], &'a SignatureBytes)>,
+) -> Result<(), SignatureError> {
+    let mut batch = ed25519_consensus::batch::Verifier::new();
+    for (public_key, digest, signature) in items {
+        match public_key {
+            PublicKey::Ed25519(key) => {
+                let signature =
+                    Signature::try_from(signature.as_ref()).map_err(SignatureError::Ed25519)?;
+                batch.queue((VerificationKeyBytes::from(*key), signature, digest));
+            }
+            PublicKey::MlDsa65(_) => public_key.verify_digest(digest, signature)?,
+        }
     }
+    batch
+        .verify(rand::thread_rng())
+        .map_err(SignatureError::Ed25519)
+}
+
+fn message_digest(domain: &[u8], message: &[u8]) -> [u8; BLOCK_DIGEST_SIZE] {
+    let mut hasher = MessageHasher::default();
+    (domain.len() as u64).crypto_hash(&mut hasher);
+    hasher.update(domain);
+    hasher.update(message);
+    hasher.finalize().into()
+}
+
+/// Digest covered by the block signature, see BlockDigest::digest_without_signature
+pub fn signed_digest(
+    block: &StatementBlock,
+    hash_function: HashFunction,
+) -> [u8; BLOCK_DIGEST_SIZE] {
+    block_signing_digest(
+        block.author(),
+        block.round(),
+        block.includes(),
+        block.statements(),
+        block.meta_creation_time_ns(),
+        block.epoch(),
+        block.epoch_changed(),
+        hash_function,
+    )
+}
+
+/// Digest that is signed for a block with the given content.
+/// This is all a remote signer gets to see of the block.
+#[cfg(not(test))]
+#[allow(clippy::too_many_arguments)]
+pub fn block_signing_digest(
+    authority: AuthorityIndex,
+    round: RoundNumber,
+    includes: &[BlockReference],
+    statements: &[BaseStatement],
+    meta_creation_time_ns: TimestampNs,
+    epoch: Epoch,
+    epoch_marker: EpochStatus,
+    hash_function: HashFunction,
+) -> [u8; BLOCK_DIGEST_SIZE] {
+    hash_function.digest_block(
+        authority,
+        round,
+        includes,
+        statements,
+        meta_creation_time_ns,
+        epoch,
+        epoch_marker,
+        None,
+    )
+}
+
+#[cfg(test)]
+#[allow(clippy::too_many_arguments)]
+pub fn block_signing_digest(
+    _authority: AuthorityIndex,
+    _round: RoundNumber,
+    _includes: &[BlockReference],
+    _statements: &[BaseStatement],
+    _meta_creation_time_ns: TimestampNs,
+    _epoch: Epoch,
+    _epoch_marker: EpochStatus,
+    _hash_function: HashFunction,
+) -> [u8; BLOCK_DIGEST_SIZE] {
+    Default::default()
 }
 
 impl Signer {
     pub fn new_for_test(n: usize) -> Vec<Self> {
+        Self::new_for_test_with_scheme(n, SignatureScheme::Ed25519)
+    }
+
+    /// Deterministic test keys, listed in the order the committee assigns authority indices.
+    pub fn new_for_test_with_scheme(n: usize, scheme: SignatureScheme) -> Vec<Self> {
         let mut rng = StdRng::seed_from_u64(0);
-        (0..n)
-            .map(|_| Self(Box::new(ed25519_consensus::SigningKey::new(&mut rng))))
-            .collect()
+        let mut signers: Vec<_> = (0..n).map(|_| Self::generate(scheme, &mut rng)).collect();
+        signers.sort_by_cached_key(|signer| signer.public_key().as_ref().to_vec());
+        signers
+    }
+
+    pub fn generate(scheme: SignatureScheme, rng: &mut (impl RngCore + CryptoRng)) -> Self {
+        match scheme {
+            SignatureScheme::Ed25519 => {
+                Self::Ed25519(Box::new(ed25519_consensus::SigningKey::new(rng)))
+            }
+            SignatureScheme::MlDsa65 => {
+                let mut seed = [0u8; mldsa::SEED_LENGTH];
+                rng.fill_bytes(&mut seed);
+                let signer = MlDsaSigner::from_seed(seed.into());
+                seed.zeroize();
+                Self::MlDsa65(Box::new(signer))
+            }
+        }
+    }
+
+    pub fn scheme(&self) -> SignatureScheme {
+        match self {
+            Signer::Ed25519(_) => SignatureScheme::Ed25519,
+            Signer::MlDsa65(_) => SignatureScheme::MlDsa65,
+        }
     }
 
     #[cfg(not(test))]
+    #[allow(clippy::too_many_arguments)]
     pub fn sign_block(
         &self,
         authority: AuthorityIndex,
@@ -215,24 +583,24 @@ impl Signer {
         includes: &[BlockReference],
         statements: &[BaseStatement],
         meta_creation_time_ns: TimestampNs,
+        epoch: Epoch,
         epoch_marker: EpochStatus,
+        hash_function: HashFunction,
     ) -> SignatureBytes {
-        let mut hasher = BlockHasher::default();
-        BlockDigest::digest_without_signature(
-            &mut hasher,
+        self.sign_block_digest(&block_signing_digest(
             authority,
             round,
             includes,
             statements,
             meta_creation_time_ns,
+            epoch,
             epoch_marker,
-        );
-        let digest: [u8; BLOCK_DIGEST_SIZE] = hasher.finalize().into();
-        let signature = self.0.sign(digest.as_ref());
-        SignatureBytes(signature.to_bytes())
+            hash_function,
+        ))
     }
 
     #[cfg(test)]
+    #[allow(clippy::too_many_arguments)]
     pub fn sign_block(
         &self,
         _authority: AuthorityIndex,
@@ -240,13 +608,59 @@ impl Signer {
         _includes: &[BlockReference],
         _statements: &[BaseStatement],
         _meta_creation_time_ns: TimestampNs,
+        _epoch: Epoch,
         _epoch_marker: EpochStatus,
+        _hash_function: HashFunction,
     ) -> SignatureBytes {
         Default::default()
     }
 
+    /// Signs a digest produced by block_signing_digest.
+    pub fn sign_block_digest(&self, digest: &[u8; BLOCK_DIGEST_SIZE]) -> SignatureBytes {
+        self.sign_digest(digest)
+    }
+
+    /// Signs a message that is not a block.
+    ///
+    /// The message is hashed together with the domain, so that these signatures
+    /// can not be confused with block signatures or signatures from another domain.
+    pub fn sign_message(&self, domain: &[u8], message: &[u8]) -> SignatureBytes {
+        self.sign_digest(&message_digest(domain, message))
+    }
+
+    fn sign_digest(&self, digest: &[u8]) -> SignatureBytes {
+        match self {
+            Signer::Ed25519(key) => SignatureBytes(key.sign(digest).to_bytes().to_vec()),
+            Signer::MlDsa65(key) => {
+                // Use the deterministic variant of ML-DSA (all-zero randomness), so that
+                // re-signing the same block after a restart produces the same block digest.
+                let signature = key
+                    .signing_key
+                    .sign(digest, b"", &[0u8; mldsa::RND_LENGTH])
+                    .expect("Empty context can not be too long");
+                SignatureBytes(signature.as_bytes().to_vec())
+            }
+        }
+    }
+
     pub fn public_key(&self) -> PublicKey {
-        PublicKey(self.0.verification_key())
+        match self {
+            Signer::Ed25519(key) => PublicKey::Ed25519(key.verification_key()),
+            Signer::MlDsa65(key) => {
+                PublicKey::MlDsa65(MlDsaPublicKey(Box::new(key.public_key.clone())))
+            }
+        }
+    }
+}
+
+impl MlDsaSigner {
+    fn from_seed(seed: mldsa::SigningKeySeed) -> Self {
+        let (signing_key, public_key) = seed.expand();
+        Self {
+            seed,
+            signing_key,
+            public_key,
+        }
     }
 }
 
@@ -256,6 +670,21 @@ impl AsRef<[u8]> for BlockDigest {
     }
 }
 
+impl Hash for PublicKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_ref().hash(state)
+    }
+}
+
+impl AsRef<[u8]> for PublicKey {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            PublicKey::Ed25519(key) => key.as_ref(),
+            PublicKey::MlDsa65(key) => key.0.as_bytes(),
+        }
+    }
+}
+
 impl AsRef<[u8]> for SignatureBytes {
     fn as_ref(&self) -> &[u8] {
         &self.0
@@ -286,6 +715,12 @@ impl fmt::Display for BlockDigest {
     }
 }
 
+impl fmt::Debug for SignatureBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(&self.0))
+    }
+}
+
 impl fmt::Debug for Signer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "Signer(public_key={:?})", self.public_key())
@@ -298,33 +733,95 @@ impl fmt::Display for Signer {
     }
 }
 
-impl Default for SignatureBytes {
-    fn default() -> Self {
-        Self([0u8; 64])
+impl fmt::Display for SignatureScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureScheme::Ed25519 => write!(f, "ed25519"),
+            SignatureScheme::MlDsa65 => write!(f, "ml-dsa-65"),
+        }
+    }
+}
+
+impl FromStr for SignatureScheme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ed25519" => Ok(SignatureScheme::Ed25519),
+            "ml-dsa-65" => Ok(SignatureScheme::MlDsa65),
+            _ => Err(format!("Unknown signature scheme: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for HashFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashFunction::Blake2b => write!(f, "blake2b"),
+            HashFunction::Sha256 => write!(f, "sha256"),
+            HashFunction::Blake3 => write!(f, "blake3"),
+        }
+    }
+}
+
+impl FromStr for HashFunction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blake2b" => Ok(HashFunction::Blake2b),
+            "sha256" => Ok(HashFunction::Sha256),
+            "blake3" => Ok(HashFunction::Blake3),
+            _ => Err(format!("Unknown hash function: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Ed25519(e) => write!(f, "ed25519: {e}"),
+            SignatureError::MlDsa(e) => write!(f, "ml-dsa: {e}"),
+        }
     }
 }
 
+impl std::error::Error for SignatureError {}
+
 impl ByteRepr for SignatureBytes {
     fn try_copy_from_slice<E: de::Error>(v: &[u8]) -> Result<Self, E> {
-        if v.len() != SIGNATURE_SIZE {
+        Self::try_from_vec(v.to_vec())
+    }
+
+    fn try_from_vec<E: de::Error>(v: Vec<u8>) -> Result<Self, E> {
+        if v.len() > MAX_SIGNATURE_SIZE {
             return Err(E::custom(format!("Invalid signature length: {}", v.len())));
         }
-        let mut inner = [0u8; SIGNATURE_SIZE];
-        inner.copy_from_slice(v);
-        Ok(Self(inner))
+        Ok(Self(v))
     }
 }
 
+// Signed genesis submissions are yaml files, which can not hold raw bytes
 impl Serialize for SignatureBytes {
     #[inline]
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        serializer.serialize_bytes(&self.0)
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&hex::encode(&self.0))
+        } else {
+            serializer.serialize_bytes(&self.0)
+        }
     }
 }
 
 impl<'de> Deserialize<'de> for SignatureBytes {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
-        deserializer.deserialize_bytes(BytesVisitor::new())
+        if deserializer.is_human_readable() {
+            let encoded = String::deserialize(deserializer)?;
+            let bytes = hex::decode(encoded).map_err(de::Error::custom)?;
+            Self::try_from_vec(bytes)
+        } else {
+            deserializer.deserialize_bytes(BytesVisitor::new())
+        }
     }
 }
 
@@ -355,16 +852,215 @@ impl<'de> Deserialize<'de> for BlockDigest {
     }
 }
 
+impl ByteRepr for MlDsaPublicKey {
+    fn try_copy_from_slice<E: de::Error>(v: &[u8]) -> Result<Self, E> {
+        let key = mldsa::VerifyingKey::from_bytes(v)
+            .map_err(|_| E::custom(format!("Invalid ml-dsa public key length: {}", v.len())))?;
+        Ok(Self(Box::new(key)))
+    }
+}
+
+// Keys end up in yaml config files, use hex there rather than a list of a few thousand integers
+impl Serialize for MlDsaPublicKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&hex::encode(self.0.as_bytes()))
+        } else {
+            serializer.serialize_bytes(self.0.as_bytes())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MlDsaPublicKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let encoded = String::deserialize(deserializer)?;
+            let bytes = hex::decode(encoded).map_err(de::Error::custom)?;
+            Self::try_from_vec(bytes)
+        } else {
+            deserializer.deserialize_bytes(BytesVisitor::new())
+        }
+    }
+}
+
+impl Serialize for MlDsaSigner {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&hex::encode(self.seed.as_bytes()))
+        } else {
+            serializer.serialize_bytes(self.seed.as_bytes())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MlDsaSigner {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut bytes = if deserializer.is_human_readable() {
+            let encoded = String::deserialize(deserializer)?;
+            hex::decode(encoded).map_err(de::Error::custom)?
+        } else {
+            Vec::<u8>::deserialize(deserializer)?
+        };
+        let seed = mldsa::SigningKeySeed::from_bytes(&bytes);
+        bytes.zeroize();
+        let seed = seed.map_err(|_| de::Error::custom("Invalid ml-dsa seed length"))?;
+        Ok(Self::from_seed(seed))
+    }
+}
+
 impl Drop for Signer {
     fn drop(&mut self) {
-        self.0.zeroize()
+        // ML-DSA keys zeroize themselves on drop
+        if let Signer::Ed25519(key) = self {
+            key.zeroize()
+        }
     }
 }
 
 pub fn dummy_signer() -> Signer {
-    Signer(Box::new(ed25519_consensus::SigningKey::from([0u8; 32])))
+    Signer::Ed25519(Box::new(ed25519_consensus::SigningKey::from([0u8; 32])))
 }
 
 pub fn dummy_public_key() -> PublicKey {
     dummy_signer().public_key()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::Transaction;
+
+    #[test]
+    fn block_digest_test_vectors() {
+        let includes = [BlockReference {
+            authority: 0,
+            round: 1,
+            digest: BlockDigest([0xab; BLOCK_DIGEST_SIZE]),
+        }];
+        let statements = [BaseStatement::Share(Transaction::new(vec![1, 2, 3]))];
+        for (hash_function, expected) in [
+            (
+                HashFunction::Blake2b,
+                "77c24663a0291d64ac9296b59c8a2b850f50c094e4b62d26f55d7e410a907d6f",
+            ),
+            (
+                HashFunction::Sha256,
+                "35f0ae2a4d8ccc48402e5f94c89126e76fa1d194f290edd9a48c97c21b65c3a0",
+            ),
+            (
+                HashFunction::Blake3,
+                "616e955024747781625c419286ea56e2f5b96eb6fb22c9acf40312a03c089cc0",
+            ),
+        ] {
+            let digest =
+                hash_function.digest_block(1, 2, &includes, &statements, 1000, 0, false, None);
+            assert_eq!(hex::encode(digest), expected, "{hash_function}");
+            assert_eq!(hash_function.to_string().parse(), Ok(hash_function));
+        }
+    }
+
+    #[test]
+    fn sign_verify_all_schemes() {
+        let digest = [7u8; BLOCK_DIGEST_SIZE];
+        for scheme in [SignatureScheme::Ed25519, SignatureScheme::MlDsa65] {
+            let signers = Signer::new_for_test_with_scheme(2, scheme);
+            let signature = signers[0].sign_digest(&digest);
+            assert_eq!(signature.as_ref().len(), scheme.signature_size());
+
+            let public_key = signers[0].public_key();
+            assert_eq!(public_key.scheme(), scheme);
+            public_key.verify_digest(&digest, &signature).unwrap();
+            assert!(public_key.verify_digest(&[8u8; 32], &signature).is_err());
+            assert!(signers[1]
+                .public_key()
+                .verify_digest(&digest, &signature)
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn batch_verify_mixed_schemes() {
+        let digests: Vec<_> = (0..4u8).map(|i| [i; BLOCK_DIGEST_SIZE]).collect();
+        let mut signers = Signer::new_for_test(3);
+        signers.extend(Signer::new_for_test_with_scheme(
+            1,
+            SignatureScheme::MlDsa65,
+        ));
+        let public_keys: Vec<_> = signers.iter().map(Signer::public_key).collect();
+        let mut signatures: Vec<_> = signers
+            .iter()
+            .zip(digests.iter())
+            .map(|(signer, digest)| signer.sign_digest(digest))
+            .collect();
+
+        let items = |signatures: &[SignatureBytes]| {
+            let items: Vec<_> = public_keys
+                .iter()
+                .zip(digests.iter())
+                .zip(signatures.iter())
+                .map(|((k, d), s)| (k, d.as_ref(), s))
+                .collect();
+            verify_digests_batch(items)
+        };
+        items(&signatures).unwrap();
+
+        // A single bad ed25519 signature fails the whole batch
+        signatures.swap(0, 1);
+        assert!(items(&signatures).is_err());
+        signatures.swap(0, 1);
+
+        // As does a bad signature of a scheme that is not batched
+        signatures[3] = signers[3].sign_digest(&[9u8; BLOCK_DIGEST_SIZE]);
+        assert!(items(&signatures).is_err());
+    }
+
+    #[test]
+    fn ml_dsa_keys_are_deterministic() {
+        let first = Signer::new_for_test_with_scheme(4, SignatureScheme::MlDsa65);
+        let second = Signer::new_for_test_with_scheme(4, SignatureScheme::MlDsa65);
+        for (a, b) in first.iter().zip(second.iter()) {
+            assert_eq!(a.public_key(), b.public_key());
+        }
+        assert_ne!(first[0].public_key(), first[1].public_key());
+    }
+
+    #[test]
+    fn keys_serde_roundtrip() {
+        for scheme in [SignatureScheme::Ed25519, SignatureScheme::MlDsa65] {
+            let signer = Signer::new_for_test_with_scheme(1, scheme).pop().unwrap();
+
+            let yaml = serde_yaml::to_string(&signer).unwrap();
+            let restored: Signer = serde_yaml::from_str(&yaml).unwrap();
+            assert_eq!(restored.public_key(), signer.public_key());
+
+            let public_key = signer.public_key();
+            let yaml = serde_yaml::to_string(&public_key).unwrap();
+            assert_eq!(
+                serde_yaml::from_str::<PublicKey>(&yaml).unwrap(),
+                public_key
+            );
+            let bytes = bincode::serialize(&public_key).unwrap();
+            assert_eq!(
+                bincode::deserialize::<PublicKey>(&bytes).unwrap(),
+                public_key
+            );
+        }
+    }
+
+    #[test]
+    fn variable_length_signature_serde() {
+        let signer = Signer::new_for_test_with_scheme(1, SignatureScheme::MlDsa65)
+            .pop()
+            .unwrap();
+        let signature = signer.sign_digest(&[1u8; BLOCK_DIGEST_SIZE]);
+        let bytes = bincode::serialize(&signature).unwrap();
+        assert_eq!(
+            bincode::deserialize::<SignatureBytes>(&bytes).unwrap(),
+            signature
+        );
+
+        let oversized =
+            bincode::serialize(&SignatureBytes(vec![0; MAX_SIGNATURE_SIZE + 1])).unwrap();
+        assert!(bincode::deserialize::<SignatureBytes>(&oversized).is_err());
+    }
+}
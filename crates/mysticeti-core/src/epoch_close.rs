@@ -6,56 +6,98 @@ use std::sync::{
     Arc,
 };
 
+use serde::{Deserialize, Serialize};
+
 use crate::{
     committee::{Committee, QuorumThreshold, StakeAggregator},
     data::Data,
+    reconfiguration::CommitteeChange,
     runtime::timestamp_utc,
-    types::{InternalEpochStatus, StatementBlock},
+    types::{BaseStatement, InternalEpochStatus, StatementBlock},
 };
 
 pub struct EpochManager {
+    state: EpochChangeState,
+    epoch_close_time: Arc<AtomicU64>,
+}
+
+/// The progress of the epoch change, written to the wal along with the commits it results from.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EpochChangeState {
     epoch_status: InternalEpochStatus,
     change_aggregator: StakeAggregator<QuorumThreshold>,
-    epoch_close_time: Arc<AtomicU64>,
+    /// When the epoch became safe to close, in milliseconds since the unix epoch. Zero before.
+    epoch_close_time: u64,
+    /// Committee changes committed during the epoch, in commit order.
+    committee_changes: Vec<CommitteeChange>,
 }
 
 impl EpochManager {
     pub fn new() -> Self {
-        Self {
+        Self::recover(EpochChangeState {
             epoch_status: Default::default(),
             change_aggregator: StakeAggregator::new(),
-            epoch_close_time: Arc::new(AtomicU64::new(0)),
+            epoch_close_time: 0,
+            committee_changes: Vec::new(),
+        })
+    }
+
+    pub fn recover(state: EpochChangeState) -> Self {
+        let epoch_close_time = Arc::new(AtomicU64::new(state.epoch_close_time));
+        Self {
+            state,
+            epoch_close_time,
         }
     }
 
+    pub fn state(&self) -> &EpochChangeState {
+        &self.state
+    }
+
     pub fn epoch_change_begun(&mut self) {
-        if let InternalEpochStatus::Open = self.epoch_status {
-            self.epoch_status = InternalEpochStatus::BeginChange;
+        if let InternalEpochStatus::Open = self.state.epoch_status {
+            self.state.epoch_status = InternalEpochStatus::BeginChange;
             tracing::info!("Epoch change has begun");
         }
     }
 
     pub fn observe_committed_block(&mut self, block: &Data<StatementBlock>, committee: &Committee) {
+        // Blocks committed after the epoch is safe to close do not affect the next committee,
+        // every node stops at the same commit
+        if self.closed() {
+            return;
+        }
+        for statement in block.statements() {
+            if let BaseStatement::Reconfigure(change) = statement {
+                self.state.committee_changes.push(change.as_ref().clone());
+            }
+        }
         if block.epoch_changed() {
-            let is_quorum = self.change_aggregator.add(block.author(), committee);
-            if is_quorum && (self.epoch_status != InternalEpochStatus::SafeToClose) {
-                assert!(self.epoch_status == InternalEpochStatus::BeginChange);
+            let is_quorum = self.state.change_aggregator.add(block.author(), committee);
+            if is_quorum {
+                assert!(self.state.epoch_status == InternalEpochStatus::BeginChange);
+                self.state.epoch_status = InternalEpochStatus::SafeToClose;
+                self.state.epoch_close_time = timestamp_utc().as_millis() as u64;
                 self.epoch_close_time
-                    .store(timestamp_utc().as_millis() as u64, Ordering::Relaxed);
+                    .store(self.state.epoch_close_time, Ordering::Relaxed);
                 tracing::info!("Epoch is now safe to close");
             }
         }
     }
 
     pub fn changing(&self) -> bool {
-        self.epoch_status != InternalEpochStatus::Open
+        self.state.epoch_status != InternalEpochStatus::Open
     }
 
     pub fn closed(&self) -> bool {
-        self.epoch_status == InternalEpochStatus::SafeToClose
+        self.state.epoch_status == InternalEpochStatus::SafeToClose
     }
 
     pub fn closing_time(&self) -> Arc<AtomicU64> {
         self.epoch_close_time.clone()
     }
+
+    pub fn committee_changes(&self) -> &[CommitteeChange] {
+        &self.state.committee_changes
+    }
 }
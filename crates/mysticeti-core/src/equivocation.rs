@@ -0,0 +1,342 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{collections::HashSet, sync::Arc};
+
+use eyre::{ensure, Result};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block_store::BlockStore,
+    committee::Committee,
+    crypto::BlockDigest,
+    data::Data,
+    types::{AuthorityIndex, RoundNumber, StatementBlock},
+};
+
+/// Two different blocks signed by the same authority for the same round.
+///
+/// The proof is self-contained: anyone holding the committee can check it with
+/// EquivocationProof::verify, without access to our block store.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EquivocationProof {
+    pub authority: AuthorityIndex,
+    pub round: RoundNumber,
+    #[serde(with = "block_bytes")]
+    pub first: Data<StatementBlock>,
+    #[serde(with = "block_bytes")]
+    pub second: Data<StatementBlock>,
+}
+
+impl EquivocationProof {
+    /// Returns a proof if the two blocks conflict. The blocks are ordered by digest,
+    /// so both orders of arguments produce the same proof.
+    pub fn new(a: Data<StatementBlock>, b: Data<StatementBlock>) -> Option<Self> {
+        if a.author() != b.author() || a.round() != b.round() || a.reference() == b.reference() {
+            return None;
+        }
+        let (first, second) = if a.digest() < b.digest() {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        Some(Self {
+            authority: first.author(),
+            round: first.round(),
+            first,
+            second,
+        })
+    }
+
+    /// Checks that both blocks are signed by the authority for the round and differ. The digests
+    /// are recomputed from the block contents, a copy of a block with an edited digest is no
+    /// equivocation.
+    pub fn verify(&self, committee: &Committee) -> Result<()> {
+        for block in [&self.first, &self.second] {
+            ensure!(
+                block.author() == self.authority && block.round() == self.round,
+                "Block {} does not belong to the equivocation of {} at round {}",
+                block.reference(),
+                self.authority,
+                self.round
+            );
+            let digest = BlockDigest::of_block(block, committee.hash_function());
+            ensure!(
+                digest == block.digest(),
+                "Block {} does not match its digest, computed {digest}",
+                block.reference()
+            );
+            block.verify_signature(committee)?;
+        }
+        ensure!(
+            self.first.digest() != self.second.digest(),
+            "Equivocation proof holds the same block twice"
+        );
+        Ok(())
+    }
+}
+
+/// Decides whether to keep accepting blocks from an authority caught equivocating.
+pub trait EquivocationPolicy: Send {
+    fn keep_accepting(&mut self, proof: &EquivocationProof) -> bool;
+}
+
+/// Keeps accepting blocks from equivocating authorities. This is the default:
+/// blocks of honest authorities may include the equivocating blocks, and those
+/// can only be processed once their includes are.
+pub struct AcceptEquivocators;
+
+impl EquivocationPolicy for AcceptEquivocators {
+    fn keep_accepting(&mut self, _proof: &EquivocationProof) -> bool {
+        true
+    }
+}
+
+/// Drops all further blocks from an authority after its first equivocation.
+/// Blocks of other authorities including them will then never be processed either.
+pub struct RejectEquivocators;
+
+impl EquivocationPolicy for RejectEquivocators {
+    fn keep_accepting(&mut self, _proof: &EquivocationProof) -> bool {
+        false
+    }
+}
+
+/// The equivocation policy of a node, set in its parameters.
+#[derive(Clone, Copy, Eq, PartialEq, Default, Debug, Serialize, Deserialize)]
+pub enum EquivocationPolicyKind {
+    #[default]
+    #[serde(rename = "accept")]
+    Accept,
+    #[serde(rename = "reject")]
+    Reject,
+}
+
+impl EquivocationPolicyKind {
+    pub fn policy(self) -> Box<dyn EquivocationPolicy> {
+        match self {
+            EquivocationPolicyKind::Accept => Box::new(AcceptEquivocators),
+            EquivocationPolicyKind::Reject => Box::new(RejectEquivocators),
+        }
+    }
+}
+
+/// Read access to the equivocation proofs collected by the core.
+#[derive(Clone, Default)]
+pub struct EquivocationProofs(Arc<RwLock<Vec<EquivocationProof>>>);
+
+impl EquivocationProofs {
+    pub fn all(&self) -> Vec<EquivocationProof> {
+        self.0.read().clone()
+    }
+
+    pub fn by_authority(&self, authority: AuthorityIndex) -> Vec<EquivocationProof> {
+        self.0
+            .read()
+            .iter()
+            .filter(|proof| proof.authority == authority)
+            .cloned()
+            .collect()
+    }
+}
+
+pub struct EquivocationTracker {
+    proofs: EquivocationProofs,
+    known: HashSet<(AuthorityIndex, RoundNumber)>,
+    rejected: HashSet<AuthorityIndex>,
+    policy: Box<dyn EquivocationPolicy>,
+}
+
+impl EquivocationTracker {
+    pub fn new(recovered: Vec<EquivocationProof>) -> Self {
+        let known = recovered
+            .iter()
+            .map(|proof| (proof.authority, proof.round))
+            .collect();
+        Self {
+            proofs: EquivocationProofs(Arc::new(RwLock::new(recovered))),
+            known,
+            rejected: Default::default(),
+            policy: Box::new(AcceptEquivocators),
+        }
+    }
+
+    /// Replaces the policy and applies it to the proofs collected so far.
+    pub fn set_policy(&mut self, mut policy: Box<dyn EquivocationPolicy>) {
+        self.rejected = self
+            .proofs
+            .0
+            .read()
+            .iter()
+            .filter(|proof| !policy.keep_accepting(proof))
+            .map(|proof| proof.authority)
+            .collect();
+        self.policy = policy;
+    }
+
+    pub fn proofs(&self) -> EquivocationProofs {
+        self.proofs.clone()
+    }
+
+    pub fn is_rejected(&self, authority: AuthorityIndex) -> bool {
+        self.rejected.contains(&authority)
+    }
+
+    /// Checks a newly stored block against the other blocks stored for the same authority
+    /// and round. Returns the proof if this is a new equivocation.
+    pub fn check(
+        &mut self,
+        block: &Data<StatementBlock>,
+        block_store: &BlockStore,
+    ) -> Option<EquivocationProof> {
+        if self.known.contains(&(block.author(), block.round())) {
+            return None;
+        }
+        let proof = block_store
+            .get_blocks_at_authority_round(block.author(), block.round())
+            .into_iter()
+            .find_map(|other| EquivocationProof::new(other, block.clone()))?;
+        tracing::warn!(
+            "Authority {} equivocated at round {}: {} and {}",
+            proof.authority,
+            proof.round,
+            proof.first.reference(),
+            proof.second.reference()
+        );
+        self.known.insert((proof.authority, proof.round));
+        if !self.policy.keep_accepting(&proof) {
+            self.rejected.insert(proof.authority);
+        }
+        self.proofs.0.write().push(proof.clone());
+        Some(proof)
+    }
+}
+
+// Keep blocks readable as hex in yaml, e.g. when served over http
+mod block_bytes {
+    use minibytes::Bytes;
+    use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::{data::Data, types::StatementBlock};
+
+    pub fn serialize<S: Serializer>(
+        block: &Data<StatementBlock>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&hex::encode(block.serialized_bytes()))
+        } else {
+            block.serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Data<StatementBlock>, D::Error> {
+        if deserializer.is_human_readable() {
+            let encoded = String::deserialize(deserializer)?;
+            let bytes = hex::decode(encoded).map_err(de::Error::custom)?;
+            Data::from_bytes(Bytes::from(bytes)).map_err(de::Error::custom)
+        } else {
+            Data::deserialize(deserializer)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_util::TestBlockWriter,
+        types::{BaseStatement, Transaction},
+    };
+
+    fn block(authority: AuthorityIndex, round: RoundNumber, payload: u8) -> Data<StatementBlock> {
+        let statements = vec![BaseStatement::Share(Transaction::new(vec![payload]))];
+        let block = StatementBlock::new(
+            authority,
+            round,
+            vec![],
+            statements,
+            0,
+            0,
+            false,
+            Default::default(),
+            Default::default(),
+        );
+        Data::new(block.with_computed_digest(Default::default()))
+    }
+
+    #[test]
+    fn proof() {
+        let committee = Committee::new_test(vec![1; 4]);
+        assert!(EquivocationProof::new(block(1, 1, 0), block(1, 1, 0)).is_none());
+        assert!(EquivocationProof::new(block(1, 1, 0), block(1, 2, 1)).is_none());
+        assert!(EquivocationProof::new(block(1, 1, 0), block(2, 1, 1)).is_none());
+        let proof = EquivocationProof::new(block(1, 1, 0), block(1, 1, 1)).unwrap();
+        let reversed = EquivocationProof::new(block(1, 1, 1), block(1, 1, 0)).unwrap();
+        assert_eq!(proof.first.reference(), reversed.first.reference());
+        proof.verify(&committee).unwrap();
+
+        let yaml = serde_yaml::to_string(&proof).unwrap();
+        let decoded: EquivocationProof = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(decoded.second.reference(), proof.second.reference());
+        let bytes = bincode::serialize(&proof).unwrap();
+        let decoded: EquivocationProof = bincode::deserialize(&bytes).unwrap();
+        decoded.verify(&committee).unwrap();
+
+        let mut forged = proof.clone();
+        forged.second = block(2, 1, 1);
+        assert!(forged.verify(&committee).is_err());
+    }
+
+    #[test]
+    fn tampered_proof() {
+        let committee = Committee::new_test(vec![1; 4]);
+        let proof = EquivocationProof::new(block(1, 1, 0), block(1, 1, 1)).unwrap();
+        proof.verify(&committee).unwrap();
+
+        // A copy of a signed block with an edited digest
+        let honest = block(1, 1, 0);
+        let copy = Data::new((*honest).clone().with_test_digest(7));
+        let forged = EquivocationProof::new(honest, copy.clone()).unwrap();
+        assert!(forged.verify(&committee).is_err());
+
+        let mut forged = proof.clone();
+        forged.second = copy;
+        assert!(forged.verify(&committee).is_err());
+
+        let mut forged = proof;
+        forged.second = forged.first.clone();
+        assert!(forged.verify(&committee).is_err());
+    }
+
+    #[test]
+    fn tracker() {
+        let committee = Committee::new_test(vec![1; 4]);
+        let mut writer = TestBlockWriter::new(&committee);
+        let mut tracker = EquivocationTracker::new(vec![]);
+
+        writer.add_block(block(1, 1, 0));
+        assert!(tracker
+            .check(&block(1, 1, 0), &writer.block_store())
+            .is_none());
+        writer.add_block(block(1, 1, 1));
+        assert!(tracker
+            .check(&block(1, 1, 1), &writer.block_store())
+            .is_some());
+        // Further blocks for the same slot do not produce more proofs
+        writer.add_block(block(1, 1, 2));
+        assert!(tracker
+            .check(&block(1, 1, 2), &writer.block_store())
+            .is_none());
+        assert_eq!(tracker.proofs().by_authority(1).len(), 1);
+        assert!(!tracker.is_rejected(1));
+
+        let mut tracker = EquivocationTracker::new(tracker.proofs().all());
+        tracker.set_policy(Box::new(RejectEquivocators));
+        assert!(tracker.is_rejected(1));
+        assert!(!tracker.is_rejected(2));
+    }
+}
@@ -0,0 +1,298 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Genesis ceremony for committees where no single party knows all private keys.
+//!
+//! 1. Every operator generates its own key and signs a [`GenesisSubmission`] announcing its
+//!    public key, addresses and stake.
+//! 2. A coordinator collects the submissions and [`assemble`]s the committee and the public
+//!    config. The coordinator is not trusted: it can only reorder or drop submissions.
+//! 3. Every validator checks the resulting files against its own private key and learns its
+//!    authority index with [`find_own_authority`] when it starts.
+
+use std::{collections::HashSet, net::SocketAddr, sync::Arc};
+
+use eyre::{bail, ensure, eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    committee::{Authority, Committee},
+    config::{ImportExport, NodeIdentifier, NodeParameters, NodePublicConfig},
+    crypto::{HashFunction, PublicKey, SignatureBytes, Signer},
+    types::{AuthorityIndex, Stake},
+};
+
+const GENESIS_SIGNATURE_DOMAIN: &[u8] = b"mysticeti-genesis-submission";
+
+/// Node identifier and stake, signed by the node's own private key.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GenesisSubmission {
+    pub identifier: NodeIdentifier,
+    pub stake: Stake,
+    signature: SignatureBytes,
+}
+
+impl GenesisSubmission {
+    pub const DEFAULT_FILENAME: &'static str = "genesis-submission.yaml";
+
+    pub fn new(
+        signer: &Signer,
+        network_address: SocketAddr,
+        metrics_address: SocketAddr,
+        stake: Stake,
+    ) -> Self {
+        let identifier = NodeIdentifier {
+            public_key: signer.public_key(),
+            network_address,
+            metrics_address,
+        };
+        let signature = signer.sign_message(
+            GENESIS_SIGNATURE_DOMAIN,
+            &signed_payload(&identifier, stake),
+        );
+        Self {
+            identifier,
+            stake,
+            signature,
+        }
+    }
+
+    /// Checks that the submission was signed by the key it announces.
+    pub fn verify(&self) -> Result<()> {
+        self.identifier
+            .public_key
+            .verify_message(
+                GENESIS_SIGNATURE_DOMAIN,
+                &signed_payload(&self.identifier, self.stake),
+                &self.signature,
+            )
+            .map_err(|e| {
+                eyre!(
+                    "Invalid signature on the submission of {}: {e}",
+                    self.identifier.network_address
+                )
+            })
+    }
+}
+
+impl ImportExport for GenesisSubmission {}
+
+fn signed_payload(identifier: &NodeIdentifier, stake: Stake) -> Vec<u8> {
+    bincode::serialize(&(identifier, stake)).expect("Serialization should not fail")
+}
+
+/// Builds the committee and the public config from the submissions of all operators.
+///
+/// Authorities are ordered by public key, so that the result does not depend on the order
+/// in which the submissions were collected.
+pub fn assemble(
+    mut submissions: Vec<GenesisSubmission>,
+    parameters: NodeParameters,
+    hash_function: HashFunction,
+) -> Result<(Arc<Committee>, NodePublicConfig)> {
+    ensure!(!submissions.is_empty(), "No genesis submissions");
+    let scheme = submissions[0].identifier.public_key.scheme();
+    let mut public_keys = HashSet::new();
+    let mut addresses = HashSet::new();
+    for submission in &submissions {
+        submission.verify()?;
+        let identifier = &submission.identifier;
+        ensure!(
+            identifier.public_key.scheme() == scheme,
+            "Submission of {} uses {} while other submissions use {scheme}",
+            identifier.network_address,
+            identifier.public_key.scheme()
+        );
+        ensure!(
+            submission.stake > 0,
+            "Submission of {} has no stake",
+            identifier.network_address
+        );
+        ensure!(
+            public_keys.insert(identifier.public_key.as_ref()),
+            "Public key of {} was submitted more than once",
+            identifier.network_address
+        );
+        for address in [identifier.network_address, identifier.metrics_address] {
+            ensure!(
+                addresses.insert(address),
+                "Address {address} is used by more than one submission"
+            );
+        }
+    }
+    submissions
+        .iter()
+        .try_fold(0 as Stake, |total, s| total.checked_add(s.stake))
+        .ok_or(eyre!("Total stake overflows"))?;
+    submissions.sort_by(|a, b| {
+        a.identifier
+            .public_key
+            .as_ref()
+            .cmp(b.identifier.public_key.as_ref())
+    });
+
+    let authorities = submissions
+        .iter()
+        .map(|s| Authority::new(s.stake, s.identifier.public_key.clone()))
+        .collect();
+    let committee = Committee::new_with_hash_function(authorities, hash_function);
+    let public_config = NodePublicConfig {
+        identifiers: submissions.into_iter().map(|s| s.identifier).collect(),
+        parameters,
+    };
+    Ok((committee, public_config))
+}
+
+/// Returns the authority index of our key, after checking that the committee and the public
+/// config describe the same nodes.
+pub fn find_own_authority(
+    committee: &Committee,
+    public_config: &NodePublicConfig,
+    public_key: &PublicKey,
+) -> Result<AuthorityIndex> {
+    ensure!(
+        committee.len() == public_config.identifiers.len(),
+        "Committee has {} authorities but the public config has {} nodes",
+        committee.len(),
+        public_config.identifiers.len()
+    );
+    for (index, identifier) in public_config.identifiers.iter().enumerate() {
+        let index = index as AuthorityIndex;
+        if committee.get_public_key(index) != Some(&identifier.public_key) {
+            bail!("Public key of authority {index} differs between committee and public config");
+        }
+    }
+    committee
+        .authority_index(public_key)
+        .ok_or(eyre!("Our key is not part of the committee"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+    use crate::crypto::SignatureScheme;
+
+    fn address(port: u16) -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port)
+    }
+
+    fn submissions(signers: &[Signer]) -> Vec<GenesisSubmission> {
+        signers
+            .iter()
+            .enumerate()
+            .map(|(i, signer)| {
+                let port = 1000 + 2 * i as u16;
+                GenesisSubmission::new(signer, address(port), address(port + 1), 1 + i as Stake)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn assemble_and_verify() {
+        for scheme in [SignatureScheme::Ed25519, SignatureScheme::MlDsa65] {
+            let signers = Signer::new_for_test_with_scheme(4, scheme);
+            let submissions = submissions(&signers);
+            let (committee, public_config) = assemble(
+                submissions.clone(),
+                NodeParameters::default(),
+                HashFunction::Sha256,
+            )
+            .unwrap();
+            assert_eq!(committee.len(), 4);
+            assert_eq!(committee.signature_scheme(), scheme);
+            assert_eq!(committee.hash_function(), HashFunction::Sha256);
+
+            // The result does not depend on the order of the submissions
+            let mut reversed = submissions;
+            reversed.reverse();
+            let (other, _) =
+                assemble(reversed, NodeParameters::default(), HashFunction::Sha256).unwrap();
+            assert_eq!(
+                serde_yaml::to_string(&committee).unwrap(),
+                serde_yaml::to_string(&other).unwrap()
+            );
+
+            for (i, signer) in signers.iter().enumerate() {
+                let public_key = signer.public_key();
+                let authority =
+                    find_own_authority(&committee, &public_config, &public_key).unwrap();
+                // Test keys are listed in canonical order
+                assert_eq!(authority, i as AuthorityIndex);
+            }
+            let outsider = Signer::generate(scheme, &mut rand::thread_rng()).public_key();
+            assert!(find_own_authority(&committee, &public_config, &outsider).is_err());
+        }
+    }
+
+    #[test]
+    fn submission_serde() {
+        let signer = Signer::new_for_test(1).pop().unwrap();
+        let submission = GenesisSubmission::new(&signer, address(1000), address(1001), 1);
+        let yaml = serde_yaml::to_string(&submission).unwrap();
+        let submission: GenesisSubmission = serde_yaml::from_str(&yaml).unwrap();
+        submission.verify().unwrap();
+    }
+
+    #[test]
+    fn reject_tampered_submission() {
+        let signers = Signer::new_for_test(4);
+        let mut submissions = submissions(&signers);
+        submissions[2].stake = 100;
+        assert!(submissions[2].verify().is_err());
+        assert!(assemble(
+            submissions,
+            NodeParameters::default(),
+            HashFunction::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn reject_duplicates() {
+        let signers = Signer::new_for_test(4);
+        let mut duplicate_key = submissions(&signers);
+        duplicate_key.push(GenesisSubmission::new(
+            &signers[0],
+            address(2000),
+            address(2001),
+            1,
+        ));
+        assert!(assemble(
+            duplicate_key,
+            NodeParameters::default(),
+            HashFunction::default()
+        )
+        .is_err());
+
+        let mut duplicate_address = submissions(&signers[..3]);
+        duplicate_address.push(GenesisSubmission::new(
+            &signers[3],
+            address(1000),
+            address(2001),
+            1,
+        ));
+        assert!(assemble(
+            duplicate_address,
+            NodeParameters::default(),
+            HashFunction::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn reject_mixed_schemes() {
+        let mut signers = Signer::new_for_test(3);
+        signers.extend(Signer::new_for_test_with_scheme(
+            1,
+            SignatureScheme::MlDsa65,
+        ));
+        assert!(assemble(
+            submissions(&signers),
+            NodeParameters::default(),
+            HashFunction::default()
+        )
+        .is_err());
+    }
+}
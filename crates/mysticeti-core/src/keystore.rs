@@ -0,0 +1,257 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, Key, KeyInit, Nonce};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{
+    config::ImportExport,
+    crypto::{PublicKey, SignatureScheme, Signer},
+};
+
+const KEYSTORE_VERSION: u32 = 1;
+const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+const ENCRYPTION_KEY_SIZE: usize = 32;
+
+/// Validator private key encrypted with a passphrase.
+///
+/// The encryption key is derived from the passphrase with argon2id and the private key is
+/// encrypted with ChaCha20-Poly1305. The public key is kept in the clear so that the keystore
+/// can be matched against a committee without unlocking it.
+#[derive(Serialize, Deserialize)]
+pub struct Keystore {
+    version: u32,
+    public_key: PublicKey,
+    kdf: KdfParameters,
+    #[serde(with = "hex_bytes")]
+    salt: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    nonce: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    ciphertext: Vec<u8>,
+}
+
+/// Argon2id cost parameters, stored in the keystore so that they can be raised later
+/// without breaking existing keystores.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct KdfParameters {
+    /// Memory size in KiB.
+    pub m_cost: u32,
+    /// Number of iterations.
+    pub t_cost: u32,
+    /// Degree of parallelism.
+    pub p_cost: u32,
+}
+
+#[derive(Debug)]
+pub enum KeystoreError {
+    /// Decryption failed, most likely because the passphrase is wrong.
+    InvalidPassphrase,
+    /// The keystore file is malformed or was tampered with.
+    Malformed(String),
+}
+
+impl Default for KdfParameters {
+    fn default() -> Self {
+        Self {
+            m_cost: 64 * 1024,
+            t_cost: 3,
+            p_cost: 1,
+        }
+    }
+}
+
+impl Keystore {
+    pub const DEFAULT_FILENAME: &'static str = "keystore.yaml";
+
+    pub fn encrypt(signer: &Signer, passphrase: &str) -> Self {
+        Self::encrypt_with_parameters(signer, passphrase, KdfParameters::default())
+    }
+
+    pub fn encrypt_with_parameters(signer: &Signer, passphrase: &str, kdf: KdfParameters) -> Self {
+        let mut salt = vec![0u8; SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        let mut nonce = vec![0u8; NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+
+        let key = kdf
+            .derive_key(passphrase, &salt)
+            .expect("Keystore parameters are valid");
+        let plaintext = Zeroizing::new(
+            bincode::serialize(signer).expect("Serialization of private key should not fail"),
+        );
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+            .expect("Encryption should not fail");
+        Self {
+            version: KEYSTORE_VERSION,
+            public_key: signer.public_key(),
+            kdf,
+            salt,
+            nonce,
+            ciphertext,
+        }
+    }
+
+    pub fn decrypt(&self, passphrase: &str) -> Result<Signer, KeystoreError> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(KeystoreError::Malformed(format!(
+                "unsupported keystore version {}",
+                self.version
+            )));
+        }
+        if self.nonce.len() != NONCE_SIZE {
+            return Err(KeystoreError::Malformed("invalid nonce length".to_string()));
+        }
+        let key = self.kdf.derive_key(passphrase, &self.salt)?;
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+            .decrypt(Nonce::from_slice(&self.nonce), self.ciphertext.as_ref())
+            .map_err(|_| KeystoreError::InvalidPassphrase)?;
+        let plaintext = Zeroizing::new(plaintext);
+        let signer: Signer = bincode::deserialize(&plaintext)
+            .map_err(|e| KeystoreError::Malformed(e.to_string()))?;
+        if signer.public_key() != self.public_key {
+            return Err(KeystoreError::Malformed(
+                "public key does not match the private key".to_string(),
+            ));
+        }
+        Ok(signer)
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    pub fn signature_scheme(&self) -> SignatureScheme {
+        self.public_key.scheme()
+    }
+}
+
+impl KdfParameters {
+    fn derive_key(
+        &self,
+        passphrase: &str,
+        salt: &[u8],
+    ) -> Result<Zeroizing<[u8; ENCRYPTION_KEY_SIZE]>, KeystoreError> {
+        let params = Params::new(
+            self.m_cost,
+            self.t_cost,
+            self.p_cost,
+            Some(ENCRYPTION_KEY_SIZE),
+        )
+        .map_err(|e| KeystoreError::Malformed(e.to_string()))?;
+        let mut key = Zeroizing::new([0u8; ENCRYPTION_KEY_SIZE]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+            .map_err(|e| KeystoreError::Malformed(e.to_string()))?;
+        Ok(key)
+    }
+}
+
+impl ImportExport for Keystore {}
+
+impl ImportExport for Signer {}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeystoreError::InvalidPassphrase => write!(f, "invalid passphrase"),
+            KeystoreError::Malformed(e) => write!(f, "malformed keystore: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for KeystoreError {}
+
+mod hex_bytes {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        hex::decode(encoded).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+    use crate::config::NodePrivateConfig;
+
+    // Keep tests fast, the default parameters take a noticeable time to evaluate
+    const TEST_KDF: KdfParameters = KdfParameters {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    #[test]
+    fn encrypt_decrypt() {
+        for scheme in [SignatureScheme::Ed25519, SignatureScheme::MlDsa65] {
+            let signer = Signer::new_for_test_with_scheme(1, scheme).pop().unwrap();
+            let keystore = Keystore::encrypt_with_parameters(&signer, "passphrase", TEST_KDF);
+            assert_eq!(keystore.public_key(), &signer.public_key());
+            assert_eq!(keystore.signature_scheme(), scheme);
+
+            let yaml = serde_yaml::to_string(&keystore).unwrap();
+            let keystore: Keystore = serde_yaml::from_str(&yaml).unwrap();
+            let decrypted = keystore.decrypt("passphrase").unwrap();
+            assert_eq!(decrypted.public_key(), signer.public_key());
+        }
+    }
+
+    #[test]
+    fn wrong_passphrase() {
+        let signer = Signer::new_for_test(1).pop().unwrap();
+        let keystore = Keystore::encrypt_with_parameters(&signer, "passphrase", TEST_KDF);
+        assert!(matches!(
+            keystore.decrypt("wrong"),
+            Err(KeystoreError::InvalidPassphrase)
+        ));
+    }
+
+    #[test]
+    fn tampered_public_key() {
+        let mut signers = Signer::new_for_test(2);
+        let mut keystore = Keystore::encrypt_with_parameters(&signers[0], "passphrase", TEST_KDF);
+        keystore.public_key = signers.pop().unwrap().public_key();
+        assert!(matches!(
+            keystore.decrypt("passphrase"),
+            Err(KeystoreError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn private_config_unlocks_keystore() {
+        let dir = TempDir::new("private_config_unlocks_keystore").unwrap();
+        let signer = Signer::new_for_test(1).pop().unwrap();
+        let keystore_path = dir.path().join(Keystore::DEFAULT_FILENAME);
+        Keystore::encrypt_with_parameters(&signer, "passphrase", TEST_KDF)
+            .print(&keystore_path)
+            .unwrap();
+        let config_path = dir.path().join(NodePrivateConfig::default_filename(0));
+        NodePrivateConfig::new_with_keystore(keystore_path, dir.path().join("storage"))
+            .print(&config_path)
+            .unwrap();
+
+        let mut config = NodePrivateConfig::load(&config_path).unwrap();
+        assert!(config.is_locked());
+        assert!(config.block_signer().is_err());
+        assert!(config.unlock("wrong").is_err());
+        assert!(config.is_locked());
+        config.unlock("passphrase").unwrap();
+        let block_signer = config.block_signer().unwrap();
+        assert_eq!(block_signer.public_key(), signer.public_key());
+    }
+}
@@ -0,0 +1,188 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::time::Duration;
+
+use crate::config::NodeParameters;
+
+/// The leader block of a round is usually received about one RTT after the round starts. The
+/// timeout leaves room for the leader to be a few messages behind.
+const RTT_MULTIPLIER: u32 = 4;
+
+/// The backoff stops growing after this many consecutive timeouts (and is then capped by the
+/// maximum leader timeout anyway).
+const MAX_BACKOFF_EXPONENT: u32 = 16;
+
+/// What determined the timeout of a round. Reported as a metric label.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeoutDecision {
+    /// The configured leader timeout, used when latencies to the leaders are unknown.
+    Configured,
+    /// Derived from the latencies to the leaders of the round.
+    Rtt,
+    /// Increased after consecutive timeouts.
+    Backoff,
+}
+
+impl TimeoutDecision {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeoutDecision::Configured => "configured",
+            TimeoutDecision::Rtt => "rtt",
+            TimeoutDecision::Backoff => "backoff",
+        }
+    }
+}
+
+/// Decides how long a node waits for the leaders of a round before proposing without them.
+pub struct LeaderTimeout {
+    leader_timeout: Duration,
+    min_leader_timeout: Duration,
+    max_leader_timeout: Duration,
+    adaptive: bool,
+    consecutive_timeouts: u32,
+}
+
+impl LeaderTimeout {
+    pub fn new(parameters: &NodeParameters) -> Self {
+        Self {
+            leader_timeout: parameters.leader_timeout,
+            min_leader_timeout: parameters.min_leader_timeout.min(parameters.leader_timeout),
+            max_leader_timeout: parameters.max_leader_timeout.max(parameters.leader_timeout),
+            adaptive: parameters.adaptive_leader_timeout,
+            consecutive_timeouts: 0,
+        }
+    }
+
+    /// The timeout for a round given the round-trip times to its leaders, if known. The round
+    /// waits for its slowest leader; each leader gets a timeout proportional to its RTT, bounded
+    /// by the minimum and the configured leader timeouts.
+    pub fn timeout(
+        &self,
+        leader_rtts: impl IntoIterator<Item = Option<Duration>>,
+    ) -> (Duration, TimeoutDecision) {
+        if !self.adaptive {
+            return (self.leader_timeout, TimeoutDecision::Configured);
+        }
+
+        let mut timeout = Duration::ZERO;
+        let mut decision = TimeoutDecision::Rtt;
+        for rtt in leader_rtts {
+            let leader_timeout = match rtt {
+                Some(rtt) => rtt
+                    .saturating_mul(RTT_MULTIPLIER)
+                    .clamp(self.min_leader_timeout, self.leader_timeout),
+                None => {
+                    decision = TimeoutDecision::Configured;
+                    self.leader_timeout
+                }
+            };
+            timeout = timeout.max(leader_timeout);
+        }
+        if timeout.is_zero() {
+            // No leaders to wait for
+            return (self.leader_timeout, TimeoutDecision::Configured);
+        }
+
+        if self.consecutive_timeouts > 0 {
+            let exponent = self.consecutive_timeouts.min(MAX_BACKOFF_EXPONENT);
+            let timeout = timeout
+                .saturating_mul(1 << exponent)
+                .min(self.max_leader_timeout);
+            return (timeout, TimeoutDecision::Backoff);
+        }
+        (timeout, decision)
+    }
+
+    /// Called when the node proposed a block without waiting for the leaders any further.
+    pub fn on_timeout(&mut self) {
+        self.consecutive_timeouts = self.consecutive_timeouts.saturating_add(1);
+    }
+
+    /// Called when the node commits leaders, which shows that the leaders are making progress.
+    pub fn on_commit(&mut self) {
+        self.consecutive_timeouts = 0;
+    }
+}
+
+impl Default for LeaderTimeout {
+    fn default() -> Self {
+        Self::new(&NodeParameters::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leader_timeout() -> LeaderTimeout {
+        let parameters = NodeParameters {
+            leader_timeout: Duration::from_secs(1),
+            min_leader_timeout: Duration::from_millis(100),
+            max_leader_timeout: Duration::from_secs(5),
+            ..Default::default()
+        };
+        LeaderTimeout::new(&parameters)
+    }
+
+    #[test]
+    fn timeout_follows_slowest_leader() {
+        let timeout = leader_timeout();
+        let ms = |ms| Some(Duration::from_millis(ms));
+        assert_eq!(
+            timeout.timeout([ms(50), ms(100)]),
+            (Duration::from_millis(400), TimeoutDecision::Rtt)
+        );
+        // Bounded by the minimum and the configured timeouts
+        assert_eq!(
+            timeout.timeout([ms(1)]),
+            (Duration::from_millis(100), TimeoutDecision::Rtt)
+        );
+        assert_eq!(
+            timeout.timeout([ms(1), ms(500)]),
+            (Duration::from_secs(1), TimeoutDecision::Rtt)
+        );
+        // Unknown latencies fall back to the configured timeout
+        assert_eq!(
+            timeout.timeout([ms(50), None]),
+            (Duration::from_secs(1), TimeoutDecision::Configured)
+        );
+    }
+
+    #[test]
+    fn backoff_until_commit() {
+        let mut timeout = leader_timeout();
+        let rtt = [Some(Duration::from_millis(100))];
+        timeout.on_timeout();
+        assert_eq!(
+            timeout.timeout(rtt),
+            (Duration::from_millis(800), TimeoutDecision::Backoff)
+        );
+        timeout.on_timeout();
+        assert_eq!(timeout.timeout(rtt).0, Duration::from_millis(1600));
+        for _ in 0..100 {
+            timeout.on_timeout();
+        }
+        assert_eq!(timeout.timeout(rtt).0, Duration::from_secs(5));
+
+        timeout.on_commit();
+        assert_eq!(
+            timeout.timeout(rtt),
+            (Duration::from_millis(400), TimeoutDecision::Rtt)
+        );
+    }
+
+    #[test]
+    fn configured_timeout_when_not_adaptive() {
+        let mut timeout = LeaderTimeout::new(&NodeParameters {
+            leader_timeout: Duration::from_secs(1),
+            adaptive_leader_timeout: false,
+            ..Default::default()
+        });
+        timeout.on_timeout();
+        assert_eq!(
+            timeout.timeout([Some(Duration::from_millis(10))]),
+            (Duration::from_secs(1), TimeoutDecision::Configured)
+        );
+    }
+}
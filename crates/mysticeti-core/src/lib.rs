@@ -3,19 +3,25 @@
 
 pub mod block_handler;
 mod block_manager;
+pub mod block_signer;
 mod block_store;
+mod block_verifier;
 pub mod committee;
 pub mod config;
 pub mod consensus;
 pub mod core;
 mod core_thread;
-mod crypto;
+pub mod crypto;
 mod data;
 mod epoch_close;
+pub mod equivocation;
 mod finalization_interpreter;
 #[cfg(test)]
 #[cfg(feature = "simulator")]
 mod future_simulator;
+pub mod genesis;
+pub mod keystore;
+mod leader_timeout;
 #[allow(dead_code)] // todo - delete if unused after a while
 mod lock;
 mod log;
@@ -24,8 +30,10 @@ pub mod net_sync;
 pub mod network;
 pub mod prometheus;
 mod range_map;
+pub mod reconfiguration;
 mod runtime;
 mod serde;
+pub mod sign_guard;
 #[cfg(test)]
 #[cfg(feature = "simulator")]
 mod simulated_network;
@@ -33,6 +41,7 @@ mod simulated_network;
 mod simulator;
 #[cfg(feature = "simulator")]
 mod simulator_tracing;
+mod snapshot;
 mod stat;
 mod state;
 mod syncer;
@@ -29,7 +29,7 @@ use tokio::time::Instant;
 use crate::{
     committee::Committee,
     data::{IN_MEMORY_BLOCKS, IN_MEMORY_BLOCKS_BYTES},
-    runtime,
+    runtime::{self, JoinHandle},
     stat::{histogram, DivUsize, HistogramSender, PreciseHistogram},
     types::{format_authority_index, AuthorityIndex},
 };
@@ -46,10 +46,13 @@ pub const LATENCY_SQUARED_S: &str = "latency_squared_s";
 #[derive(Clone)]
 pub struct Metrics {
     pub benchmark_duration: IntCounter,
+    pub epoch: IntGauge,
     pub latency_s: HistogramVec,
     pub latency_squared_s: CounterVec,
     pub committed_leaders_total: IntCounterVec,
     pub leader_timeout_total: IntCounter,
+    pub leader_timeout_decisions_total: IntCounterVec,
+    pub leader_timeout_ms: IntGauge,
     pub inter_block_latency_s: HistogramVec,
 
     pub block_store_unloaded_blocks: IntCounter,
@@ -58,6 +61,7 @@ pub struct Metrics {
     pub block_store_cleanup_util: IntCounter,
 
     pub wal_mappings: IntGauge,
+    pub wal_segments: IntGauge,
 
     pub core_lock_util: IntCounter,
     pub core_lock_enqueued: IntCounter,
@@ -72,6 +76,9 @@ pub struct Metrics {
     pub block_sync_requests_sent: IntCounterVec,
     pub block_sync_requests_received: IntCounterVec,
 
+    pub block_verification_batches_total: IntCounterVec,
+    pub equivocations_total: IntCounterVec,
+
     pub transaction_certified_latency: HistogramSender<Duration>,
     pub certificate_committed_latency: HistogramSender<Duration>,
     pub transaction_committed_latency: HistogramSender<Duration>,
@@ -80,7 +87,12 @@ pub struct Metrics {
     pub proposed_block_transaction_count: HistogramSender<usize>,
     pub proposed_block_vote_count: HistogramSender<usize>,
 
+    pub wal_sync_latency: HistogramSender<Duration>,
+    pub wal_sync_batch_size: HistogramSender<usize>,
+
     pub connection_latency_sender: Vec<HistogramSender<Duration>>,
+    /// Latest percentiles of the connection latencies, in microseconds. Updated by the reporter.
+    pub connection_latency: IntGaugeVec,
 
     pub utilization_timer: IntCounterVec,
     pub submitted_transactions: IntCounter,
@@ -97,6 +109,9 @@ pub struct MetricReporter {
     pub proposed_block_transaction_count: HistogramReporter<usize>,
     pub proposed_block_vote_count: HistogramReporter<usize>,
 
+    pub wal_sync_latency: HistogramReporter<Duration>,
+    pub wal_sync_batch_size: HistogramReporter<usize>,
+
     pub connection_latency: VecHistogramReporter<Duration>,
 
     pub global_in_memory_blocks: IntGauge,
@@ -123,15 +138,15 @@ impl Metrics {
         let (proposed_block_transaction_count_hist, proposed_block_transaction_count) = histogram();
         let (proposed_block_vote_count_hist, proposed_block_vote_count) = histogram();
 
+        let (wal_sync_latency_hist, wal_sync_latency) = histogram();
+        let (wal_sync_batch_size_hist, wal_sync_batch_size) = histogram();
+
         let committee_size = committee.map(Committee::len).unwrap_or_default();
         let (connection_latency_hist, connection_latency_sender) = (0..committee_size)
             .map(|peer| {
                 let (hist, sender) = histogram();
                 (
-                    (
-                        hist,
-                        format_authority_index(peer as AuthorityIndex).to_string(),
-                    ),
+                    (hist, format_authority_index(peer as AuthorityIndex)),
                     sender,
                 )
             })
@@ -169,6 +184,17 @@ impl Metrics {
                 "proposed_block_vote_count",
             ),
 
+            wal_sync_latency: HistogramReporter::new_in_registry(
+                wal_sync_latency_hist,
+                registry,
+                "wal_sync_latency",
+            ),
+            wal_sync_batch_size: HistogramReporter::new_in_registry(
+                wal_sync_batch_size_hist,
+                registry,
+                "wal_sync_batch_size",
+            ),
+
             connection_latency: VecHistogramReporter::new_in_registry(
                 connection_latency_hist,
                 "peer",
@@ -214,7 +240,7 @@ impl Metrics {
             committed_leaders_total: register_int_counter_vec_with_registry!(
                 "committed_leaders_total",
                 "Total number of (direct or indirect) committed leaders per authority",
-                &["authority", "commit_type"],
+                &["authority", "commit_type", "leader_schedule"],
                 registry,
             )
             .unwrap(),
@@ -237,6 +263,25 @@ impl Metrics {
                 registry,
             )
             .unwrap(),
+            leader_timeout_decisions_total: register_int_counter_vec_with_registry!(
+                "leader_timeout_decisions_total",
+                "Number of leader timeouts set, by what determined their duration",
+                &["decision"],
+                registry,
+            )
+            .unwrap(),
+            epoch: register_int_gauge_with_registry!(
+                "epoch",
+                "The epoch of the committee the metrics are collected for",
+                registry,
+            )
+            .unwrap(),
+            leader_timeout_ms: register_int_gauge_with_registry!(
+                "leader_timeout_ms",
+                "Latest leader timeout in milliseconds",
+                registry,
+            )
+            .unwrap(),
 
             block_store_loaded_blocks: register_int_counter_with_registry!(
                 "block_store_loaded_blocks",
@@ -269,6 +314,12 @@ impl Metrics {
                 registry,
             )
             .unwrap(),
+            wal_segments: register_int_gauge_with_registry!(
+                "wal_segments",
+                "Number of segment files of the wal",
+                registry,
+            )
+            .unwrap(),
 
             core_lock_util: register_int_counter_with_registry!(
                 "core_lock_util",
@@ -331,6 +382,21 @@ impl Metrics {
             )
             .unwrap(),
 
+            block_verification_batches_total: register_int_counter_vec_with_registry!(
+                "block_verification_batches_total",
+                "Number of verified block batches, by whether individual signature checks were needed",
+                &["outcome"],
+                registry,
+            )
+            .unwrap(),
+            equivocations_total: register_int_counter_vec_with_registry!(
+                "equivocations_total",
+                "Number of equivocation proofs collected, by equivocating authority",
+                &["authority"],
+                registry,
+            )
+            .unwrap(),
+
             utilization_timer: register_int_counter_vec_with_registry!(
                 "utilization_timer",
                 "Utilization timer",
@@ -347,8 +413,15 @@ impl Metrics {
             proposed_block_transaction_count,
             proposed_block_vote_count,
 
+            wal_sync_latency,
+            wal_sync_batch_size,
+
             connection_latency_sender,
+            connection_latency: reporter.connection_latency.gauge.clone(),
         };
+        metrics
+            .epoch
+            .set(committee.map(Committee::epoch).unwrap_or_default() as i64);
 
         (Arc::new(metrics), reporter)
     }
@@ -394,6 +467,19 @@ impl<T: Ord + AddAssign + DivUsize + Copy + Default + AsPrometheusMetric> Histog
     }
 }
 
+impl Metrics {
+    /// The p90 round-trip time to the specified authority, if it was measured.
+    pub fn connection_rtt(&self, authority: AuthorityIndex) -> Option<Duration> {
+        let peer = format_authority_index(authority);
+        let micros = self
+            .connection_latency
+            .get_metric_with_label_values(&[&peer, "p90"])
+            .ok()?
+            .get();
+        (micros > 0).then(|| Duration::from_micros(micros as u64))
+    }
+}
+
 impl<T: Ord + AddAssign + DivUsize + Copy + Default + AsPrometheusMetric> VecHistogramReporter<T> {
     pub fn new_in_registry(
         histograms: Vec<(PreciseHistogram<T>, String)>,
@@ -450,8 +536,8 @@ impl AsPrometheusMetric for usize {
 }
 
 impl MetricReporter {
-    pub fn start(self) {
-        runtime::Handle::current().spawn(self.run());
+    pub fn start(self) -> JoinHandle<()> {
+        runtime::Handle::current().spawn(self.run())
     }
 
     pub fn clear_receive_all(&mut self) {
@@ -463,6 +549,9 @@ impl MetricReporter {
         self.proposed_block_transaction_count.clear_receive_all();
         self.proposed_block_vote_count.clear_receive_all();
 
+        self.wal_sync_latency.clear_receive_all();
+        self.wal_sync_batch_size.clear_receive_all();
+
         self.connection_latency.clear_receive_all();
     }
 
@@ -493,6 +582,9 @@ impl MetricReporter {
         self.proposed_block_transaction_count.report();
         self.proposed_block_vote_count.report();
 
+        self.wal_sync_latency.report();
+        self.wal_sync_batch_size.report();
+
         self.connection_latency.report();
     }
 }
@@ -564,6 +656,6 @@ impl Drop for OwnedUtilizationTimer {
 
 #[derive(Tabled)]
 struct NetworkAddressTable {
-    peer: char,
+    peer: String,
     address: String,
 }
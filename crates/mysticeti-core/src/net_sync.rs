@@ -7,7 +7,7 @@ use std::{
         atomic::{AtomicU64, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use futures::future::join_all;
@@ -19,17 +19,20 @@ use tokio::{
 use crate::{
     block_handler::BlockHandler,
     block_store::BlockStore,
+    block_verifier::{BlockVerifier, BlockVerifierParameters, Rejection},
     committee::Committee,
     config::NodePublicConfig,
     core::Core,
     core_thread::CoreThreadDispatcher,
+    leader_timeout::LeaderTimeout,
     metrics::Metrics,
     network::{Connection, Network, NetworkMessage},
+    reconfiguration::CommitteeChange,
     runtime::{self, timestamp_utc, Handle, JoinError, JoinHandle},
     syncer::{CommitObserver, Syncer, SyncerSignals},
     synchronizer::{BlockDisseminator, BlockFetcher, SynchronizerParameters},
     types::{format_authority_index, AuthorityIndex},
-    wal::WalSyncer,
+    wal::{WalDurability, WalSyncer},
 };
 
 /// The maximum number of blocks that can be requested in a single message.
@@ -46,6 +49,7 @@ pub struct NetworkSyncerInner<H: BlockHandler, C: CommitObserver> {
     pub syncer: CoreThreadDispatcher<H, Arc<Notify>, C>,
     pub block_store: BlockStore,
     pub notify: Arc<Notify>,
+    pub wal_durability: Arc<WalDurability>,
     committee: Arc<Committee>,
     stop: mpsc::Sender<()>,
     epoch_close_signal: mpsc::Sender<()>,
@@ -56,7 +60,6 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
     pub fn start(
         network: Network,
         mut core: Core<H>,
-        commit_period: u64,
         mut commit_observer: C,
         shutdown_grace_period: Duration,
         metrics: Arc<Metrics>,
@@ -66,19 +69,15 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         let handle = Handle::current();
         let notify = Arc::new(Notify::new());
         // todo - ugly, probably need to merge syncer and core
-        let (committed, state) = core.take_recovered_committed_blocks();
-        commit_observer.recover_committed(committed, state);
+        let (committed, state, last_timestamp_ns) = core.take_recovered_committed_blocks();
+        commit_observer.recover_committed(committed, state, last_timestamp_ns);
         let committee = core.committee().clone();
         let wal_syncer = core.wal_syncer();
+        let wal_durability = core.wal_durability();
         let block_store = core.block_store().clone();
         let epoch_closing_time = core.epoch_closing_time();
-        let mut syncer = Syncer::new(
-            core,
-            commit_period,
-            notify.clone(),
-            commit_observer,
-            metrics.clone(),
-        );
+        let mut syncer = Syncer::new(core, notify.clone(), commit_observer, metrics.clone())
+            .with_leader_timeout(LeaderTimeout::new(&public_config.parameters));
         syncer.force_new_block(0);
         let syncer = CoreThreadDispatcher::start(syncer);
         let (stop_sender, stop_receiver) = mpsc::channel(1);
@@ -89,6 +88,7 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
             notify,
             syncer,
             block_store,
+            wal_durability,
             committee,
             stop: stop_sender.clone(),
             epoch_close_signal: epoch_sender.clone(),
@@ -100,15 +100,32 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
             metrics.clone(),
             public_config.parameters.enable_synchronizer,
         ));
+        let block_verifier = Arc::new(BlockVerifier::start(
+            inner.clone(),
+            inner.committee.clone(),
+            BlockVerifierParameters {
+                max_clock_skew: public_config.parameters.max_clock_skew,
+                block_limits: public_config.parameters.block_limits(),
+                ..Default::default()
+            },
+            metrics.clone(),
+        ));
         let main_task = handle.spawn(Self::run(
             network,
             inner.clone(),
             epoch_receiver,
             shutdown_grace_period,
             block_fetcher,
+            block_verifier,
             metrics.clone(),
         ));
-        let syncer_task = AsyncWalSyncer::start(wal_syncer, stop_sender, epoch_sender);
+        let syncer_task = AsyncWalSyncer::start(
+            wal_syncer,
+            stop_sender,
+            epoch_sender,
+            public_config.parameters.wal_sync_interval,
+            metrics,
+        );
         Self {
             inner,
             main_task,
@@ -134,6 +151,7 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         epoch_close_signal: mpsc::Receiver<()>,
         shutdown_grace_period: Duration,
         block_fetcher: Arc<BlockFetcher>,
+        block_verifier: Arc<BlockVerifier>,
         metrics: Arc<Metrics>,
     ) {
         let mut connections: HashMap<usize, JoinHandle<Option<()>>> = HashMap::new();
@@ -144,6 +162,7 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
             shutdown_grace_period,
         ));
         let cleanup_task = handle.spawn(Self::cleanup_task(inner.clone()));
+        let durability_task = handle.spawn(Self::durability_task(inner.clone()));
         while let Some(connection) = inner.recv_or_stopped(network.connection_receiver()).await {
             let peer_id = connection.peer_id;
             if let Some(task) = connections.remove(&peer_id) {
@@ -159,6 +178,7 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
                 connection,
                 inner.clone(),
                 block_fetcher.clone(),
+                block_verifier.clone(),
                 metrics.clone(),
             ));
             connections.insert(peer_id, task);
@@ -166,19 +186,25 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         join_all(
             connections
                 .into_values()
-                .chain([leader_timeout_task, cleanup_task].into_iter()),
+                .chain([leader_timeout_task, cleanup_task, durability_task].into_iter()),
         )
         .await;
         Arc::try_unwrap(block_fetcher)
             .unwrap_or_else(|_| panic!("Failed to drop all connections"))
             .shutdown()
             .await;
+        Arc::try_unwrap(block_verifier)
+            .unwrap_or_else(|_| panic!("Failed to drop all connections"))
+            .shutdown()
+            .await;
+        network.shutdown().await;
     }
 
     async fn connection_task(
         mut connection: Connection,
         inner: Arc<NetworkSyncerInner<H, C>>,
         block_fetcher: Arc<BlockFetcher>,
+        block_verifier: Arc<BlockVerifier>,
         metrics: Arc<Metrics>,
     ) -> Option<()> {
         let last_seen = inner
@@ -201,24 +227,35 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         inner.syncer.authority_connection(id, true).await;
 
         let peer = format_authority_index(id);
-        while let Some(message) = inner.recv_or_stopped(&mut connection.receiver).await {
+        let (rejections, mut rejected) = mpsc::unbounded_channel::<Rejection>();
+        loop {
+            let message = select! {
+                message = inner.recv_or_stopped(&mut connection.receiver) => message,
+                Some(rejection) = rejected.recv() => {
+                    tracing::warn!(
+                        "Rejected incorrect block {} from {}: {:?}",
+                        rejection.reference,
+                        peer,
+                        rejection.error
+                    );
+                    // Terminate connection upon receiving incorrect block.
+                    break;
+                }
+            };
+            let Some(message) = message else {
+                break;
+            };
             match message {
                 NetworkMessage::SubscribeOwnFrom(round) => {
                     disseminator.disseminate_own_blocks(round).await
                 }
                 NetworkMessage::Block(block) => {
                     tracing::debug!("Received {} from {}", block.reference(), peer);
-                    if let Err(e) = block.verify(&inner.committee) {
-                        tracing::warn!(
-                            "Rejected incorrect block {} from {}: {:?}",
-                            block.reference(),
-                            peer,
-                            e
-                        );
-                        // Terminate connection upon receiving incorrect block.
+                    // Verified along with the blocks of other connections, rejections come back
+                    // on the channel above
+                    if block_verifier.submit(block, &rejections).await.is_err() {
                         break;
                     }
-                    inner.syncer.add_blocks(vec![block]).await;
                 }
                 NetworkMessage::RequestBlocks(references) => {
                     if references.len() > MAXIMUM_BLOCK_REQUEST {
@@ -250,7 +287,8 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         mut epoch_close_signal: mpsc::Receiver<()>,
         shutdown_grace_period: Duration,
     ) -> Option<()> {
-        let leader_timeout = Duration::from_secs(1);
+        // The round for which a new block was already forced
+        let mut timed_out_round = None;
         loop {
             let notified = inner.notify.notified();
             let round = inner
@@ -269,11 +307,18 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
             if Duration::is_zero(&shutdown_duration) {
                 return None;
             }
+            // A forced block is created as soon as possible, there is no point timing out again
+            // before the next round
+            let leader_timeout = if timed_out_round == Some(round) {
+                Duration::MAX
+            } else {
+                inner.syncer.leader_timeout(round).await
+            };
             select! {
                 _sleep = runtime::sleep(leader_timeout) => {
-                    tracing::debug!("Timeout {round}");
-                    // todo - more then one round timeout can happen, need to fix this
+                    tracing::debug!("Timeout {round} after {leader_timeout:?}");
                     inner.syncer.force_new_block(round).await;
+                    timed_out_round = Some(round);
                 }
                 _notified = notified => {
                     // restart loop
@@ -304,9 +349,36 @@ impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncer<H, C>
         }
     }
 
+    // The next own block is only signed once the last one is durable
+    async fn durability_task(inner: Arc<NetworkSyncerInner<H, C>>) -> Option<()> {
+        let mut durable = inner.wal_durability.subscribe();
+        loop {
+            select! {
+                changed = durable.changed() => {
+                    changed.ok()?;
+                    inner.syncer.own_block_durable().await;
+                }
+                _stopped = inner.stopped() => {
+                    return None;
+                }
+            }
+        }
+    }
+
     pub async fn await_completion(self) -> Result<(), JoinError> {
         self.main_task.await
     }
+
+    /// Completes once the epoch is closed and the grace period to help other validators close it
+    /// has passed. The syncer then stops on its own, `shutdown` returns the final state.
+    pub async fn epoch_closed(&self) {
+        let closed = self.inner.epoch_close_signal.send(()).await;
+        assert!(closed.is_err());
+    }
+
+    pub async fn submit_committee_change(&self, change: CommitteeChange) -> eyre::Result<()> {
+        self.inner.syncer.submit_committee_change(change).await
+    }
 }
 
 impl<H: BlockHandler + 'static, C: CommitObserver + 'static> NetworkSyncerInner<H, C> {
@@ -345,10 +417,14 @@ impl SyncerSignals for Arc<Notify> {
     }
 }
 
+/// Syncs the wal on its own thread. The sync requests of own blocks and commits are batched
+/// for the sync interval into a single sync, the rest of the wal is synced every second.
 pub struct AsyncWalSyncer {
     wal_syncer: WalSyncer,
     stop: mpsc::Sender<()>,
     epoch_signal: mpsc::Sender<()>,
+    sync_interval: Duration,
+    metrics: Arc<Metrics>,
     _sender: oneshot::Sender<()>,
     runtime: tokio::runtime::Handle,
 }
@@ -359,12 +435,16 @@ impl AsyncWalSyncer {
         wal_syncer: WalSyncer,
         stop: mpsc::Sender<()>,
         epoch_signal: mpsc::Sender<()>,
+        sync_interval: Duration,
+        metrics: Arc<Metrics>,
     ) -> oneshot::Receiver<()> {
         let (sender, receiver) = oneshot::channel();
         let this = Self {
             wal_syncer,
             stop,
             epoch_signal,
+            sync_interval,
+            metrics,
             _sender: sender,
             runtime: tokio::runtime::Handle::current(),
         };
@@ -375,28 +455,45 @@ impl AsyncWalSyncer {
         receiver
     }
 
+    // The wal is not synced in the simulator, cores there run without fsync
     #[cfg(feature = "simulator")]
     pub fn start(
         _wal_syncer: WalSyncer,
         _stop: mpsc::Sender<()>,
         _epoch_signal: mpsc::Sender<()>,
+        _sync_interval: Duration,
+        _metrics: Arc<Metrics>,
     ) -> oneshot::Receiver<()> {
         oneshot::channel().1
     }
 
     pub fn run(mut self) {
         let runtime = self.runtime.clone();
+        let mut last_sync = Instant::now();
         loop {
-            if runtime.block_on(self.wait_next()) {
+            let stop = runtime.block_on(self.wait_next(last_sync));
+            let start = Instant::now();
+            let served = self.wal_syncer.sync().expect("Failed to sync wal");
+            self.metrics.wal_sync_latency.observe(start.elapsed());
+            if served > 0 {
+                self.metrics.wal_sync_batch_size.observe(served as usize);
+            }
+            last_sync = Instant::now();
+            if stop {
                 return;
             }
-            self.wal_syncer.sync().expect("Failed to sync wal");
         }
     }
 
-    // Returns true to stop the task
-    async fn wait_next(&mut self) -> bool {
+    // Returns true to stop the task, after a last sync
+    async fn wait_next(&mut self, last_sync: Instant) -> bool {
+        // Requests made until the end of the interval are served by the same sync
+        let batch_delay = self.sync_interval.saturating_sub(last_sync.elapsed());
         select! {
+            _requested = self.wal_syncer.sync_requested() => {
+                runtime::sleep(batch_delay).await;
+                false
+            }
             _wait = runtime::sleep(Duration::from_secs(1)) => {
                 false
             }
@@ -404,7 +501,6 @@ impl AsyncWalSyncer {
                 true
             }
             _ = self.epoch_signal.send(()) => {
-                // might need to sync wal completely before shutting down
                 true
             }
         }
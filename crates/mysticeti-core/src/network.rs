@@ -4,35 +4,41 @@
 use std::{collections::HashMap, io, net::SocketAddr, ops::Range, sync::Arc, time::Duration};
 
 use futures::{
-    future::{select, select_all, Either},
+    future::{join_all, select, select_all, Either},
     FutureExt,
 };
-use rand::{prelude::ThreadRng, Rng};
+use rand::{prelude::ThreadRng, rngs::OsRng, Rng, RngCore};
 use serde::{Deserialize, Serialize};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::{
         tcp::{OwnedReadHalf, OwnedWriteHalf},
         TcpListener,
-        TcpSocket,
         TcpStream,
     },
     runtime::Handle,
     select,
     sync::mpsc,
+    task::JoinHandle,
     time::Instant,
 };
 
 use crate::{
-    config::NodePublicConfig,
+    block_signer::{BlockSigner, HANDSHAKE_SIGNATURE_DOMAIN},
+    config::{ConsensusParameters, NodePublicConfig},
+    crypto::{SignatureBytes, MAX_SIGNATURE_SIZE},
     data::Data,
     metrics::{print_network_address_table, Metrics},
     runtime,
     stat::HistogramSender,
-    types::{AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
+    types::{AuthorityIndex, BlockReference, Epoch, PublicKey, RoundNumber, StatementBlock},
 };
 
 const PING_INTERVAL: Duration = Duration::from_secs(30);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+// Large enough for all supported signature schemes
+const MAX_PUBLIC_KEY_SIZE: u32 = 4096;
+const HANDSHAKE_NONCE_SIZE: usize = 32;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum NetworkMessage {
@@ -46,6 +52,8 @@ pub enum NetworkMessage {
 
 pub struct Network {
     connection_receiver: mpsc::Receiver<Connection>,
+    // The server and the workers, stopped on shutdown
+    tasks: Vec<JoinHandle<()>>,
 }
 
 pub struct Connection {
@@ -59,127 +67,323 @@ impl Network {
     pub(crate) fn new_from_raw(connection_receiver: mpsc::Receiver<Connection>) -> Self {
         Self {
             connection_receiver,
+            tasks: Vec::new(),
         }
     }
 
     pub async fn load(
         parameters: &NodePublicConfig,
         our_id: AuthorityIndex,
+        signer: Arc<dyn BlockSigner>,
+        epoch: Epoch,
         local_addr: SocketAddr,
         metrics: Arc<Metrics>,
     ) -> Self {
         let addresses = parameters.all_network_addresses().collect::<Vec<_>>();
         print_network_address_table(&addresses);
-        Self::from_socket_addresses(&addresses, our_id as usize, local_addr, metrics).await
+        let peers = parameters
+            .identifiers
+            .iter()
+            .map(|id| (id.public_key.clone(), id.network_address))
+            .collect::<Vec<_>>();
+        let consensus_parameters = parameters.parameters.consensus_parameters(epoch);
+        Self::from_peers(
+            &peers,
+            our_id as usize,
+            signer,
+            epoch,
+            &consensus_parameters,
+            local_addr,
+            metrics,
+        )
+        .await
     }
 
     pub fn connection_receiver(&mut self) -> &mut mpsc::Receiver<Connection> {
         &mut self.connection_receiver
     }
 
-    pub async fn from_socket_addresses(
-        addresses: &[SocketAddr],
+    /// Peers are identified by the public key they announce when connecting, not by the address
+    /// their connection comes from. Both ends sign a fresh challenge of the other to prove that
+    /// they hold their key. Only peers of the same epoch running the same consensus parameters
+    /// connect.
+    pub async fn from_peers(
+        peers: &[(PublicKey, SocketAddr)],
         our_id: usize,
+        signer: Arc<dyn BlockSigner>,
+        epoch: Epoch,
+        consensus_parameters: &ConsensusParameters,
         local_addr: SocketAddr,
         metrics: Arc<Metrics>,
     ) -> Self {
-        if our_id >= addresses.len() {
+        if our_id >= peers.len() {
             panic!(
-                "our_id {our_id} is larger then address length {}",
-                addresses.len()
+                "our_id {our_id} is larger then peers length {}",
+                peers.len()
             );
         }
         let server = TcpListener::bind(local_addr)
             .await
             .expect("Failed to bind to local socket");
-        let mut worker_senders: HashMap<SocketAddr, mpsc::UnboundedSender<TcpStream>> =
-            HashMap::default();
+        let parameters_digest = consensus_parameters.digest();
+        let handshake = Arc::new(Worker::active_handshake(
+            epoch,
+            &parameters_digest,
+            &peers[our_id].0,
+        ));
+        let mut worker_senders = WorkerSenders::default();
         let handle = Handle::current();
         let (connection_sender, connection_receiver) = mpsc::channel(16);
-        for (id, address) in addresses.iter().enumerate() {
+        let mut tasks = Vec::with_capacity(peers.len());
+        for (id, (public_key, address)) in peers.iter().enumerate() {
             if id == our_id {
                 continue;
             }
             let (sender, receiver) = mpsc::unbounded_channel();
             assert!(
-                worker_senders.insert(*address, sender).is_none(),
-                "Duplicated address {} in list",
-                address
+                worker_senders
+                    .insert(public_key.as_ref().to_vec(), (public_key.clone(), sender))
+                    .is_none(),
+                "Duplicated public key of peer {id} in list"
             );
-            handle.spawn(
+            let worker = handle.spawn(
                 Worker {
                     peer: *address,
                     peer_id: id,
+                    peer_public_key: public_key.clone(),
+                    public_key: peers[our_id].0.clone(),
                     connection_sender: connection_sender.clone(),
-                    bind_addr: bind_addr(local_addr),
+                    handshake: handshake.clone(),
+                    signer: signer.clone(),
+                    epoch,
                     active_immediately: id < our_id,
                     latency_sender: metrics.connection_latency_sender.get(id).expect("Can not locate connection_latency_sender metric - did you initialize metrics with correct committee?").clone()
                 }
-                .run(receiver),
+                .run(receiver)
+                .map(|_| ()),
             );
+            tasks.push(worker);
         }
-        handle.spawn(
+        let server = handle.spawn(
             Server {
                 server,
-                worker_senders,
+                handshake: Arc::new(ServerHandshake {
+                    epoch,
+                    parameters_digest,
+                    public_key: peers[our_id].0.clone(),
+                    signer,
+                    worker_senders,
+                }),
             }
             .run(),
         );
+        tasks.push(server);
         Self {
             connection_receiver,
+            tasks,
         }
     }
+
+    /// Stop accepting and opening connections. The listening address is free once this returns,
+    /// the validator binds it again in the next epoch.
+    pub async fn shutdown(self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        join_all(self.tasks).await;
+    }
 }
 
 struct Server {
     server: TcpListener,
-    worker_senders: HashMap<SocketAddr, mpsc::UnboundedSender<TcpStream>>,
+    handshake: Arc<ServerHandshake>,
+}
+
+/// Authenticates the peers connecting to the server. The handshakes in progress hold it, not the
+/// listener, so that the address is free once the server stops.
+struct ServerHandshake {
+    epoch: Epoch,
+    // Digest of the consensus parameters of the epoch, peers must announce the same
+    parameters_digest: [u8; 32],
+    public_key: PublicKey,
+    // Signs the challenges of the peers connecting to us
+    signer: Arc<dyn BlockSigner>,
+    worker_senders: WorkerSenders,
 }
 
+// The workers of the peers, by the bytes of their public key
+type WorkerSenders = HashMap<Vec<u8>, (PublicKey, mpsc::UnboundedSender<TcpStream>)>;
+
 impl Server {
     async fn run(self) {
+        let handle = Handle::current();
         loop {
             let (socket, remote_peer) = self.server.accept().await.expect("Accept failed");
-            let remote_peer = remote_to_local_port(remote_peer);
-            if let Some(sender) = self.worker_senders.get(&remote_peer) {
-                sender.send(socket).ok();
-            } else {
-                tracing::warn!("Dropping connection from unknown peer {remote_peer}");
-            }
+            let handshake = self.handshake.clone();
+            // Read the handshake off the accept loop, so that a slow peer does not block others
+            handle.spawn(async move {
+                let handshake =
+                    tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake.read_handshake(socket)).await;
+                match handshake {
+                    Ok(Ok((socket, sender))) => {
+                        sender.send(socket).ok();
+                    }
+                    Ok(Err(err)) => {
+                        tracing::warn!("Invalid handshake from {remote_peer}: {err}");
+                    }
+                    Err(_) => {
+                        tracing::warn!("Handshake from {remote_peer} timed out");
+                    }
+                }
+            });
         }
     }
 }
 
-// just ignore these two functions for now :)
-fn remote_to_local_port(mut remote_peer: SocketAddr) -> SocketAddr {
-    match &mut remote_peer {
-        SocketAddr::V4(v4) => {
-            v4.set_port(v4.port() / 10);
+impl ServerHandshake {
+    /// Reads the handshake of a connecting peer, answers its challenge and challenges it to sign
+    /// a fresh nonce with the key it announces. Returns the worker of the peer once the signature
+    /// checks out.
+    async fn read_handshake(
+        &self,
+        mut stream: TcpStream,
+    ) -> io::Result<(TcpStream, &mpsc::UnboundedSender<TcpStream>)> {
+        let epoch = self.epoch;
+        let invalid = |reason: String| io::Error::new(io::ErrorKind::InvalidData, reason);
+        let handshake = stream.read_u64().await?;
+        if handshake != Worker::ACTIVE_HANDSHAKE {
+            return Err(invalid(format!("unexpected active handshake {handshake}")));
+        }
+        // The peer retries until both sides run the same epoch
+        let peer_epoch = stream.read_u64().await?;
+        if peer_epoch != epoch {
+            return Err(invalid(format!(
+                "peer runs epoch {peer_epoch}, we run epoch {epoch}"
+            )));
+        }
+        // Committing different leaders would fork, refuse peers until the operators fix the
+        // configuration
+        let mut peer_parameters_digest = [0u8; 32];
+        stream.read_exact(&mut peer_parameters_digest).await?;
+        if peer_parameters_digest != self.parameters_digest {
+            return Err(invalid(format!(
+                "peer runs different consensus parameters in epoch {epoch}"
+            )));
         }
-        SocketAddr::V6(v6) => {
-            v6.set_port(v6.port() / 10);
+        let size = stream.read_u32().await?;
+        if size > MAX_PUBLIC_KEY_SIZE {
+            return Err(invalid(format!("public key of {size} bytes is too large")));
         }
+        let mut public_key = vec![0u8; size as usize];
+        stream.read_exact(&mut public_key).await?;
+        let Some((public_key, sender)) = self.worker_senders.get(&public_key) else {
+            return Err(invalid("unknown peer".to_string()));
+        };
+        let mut peer_nonce = [0u8; HANDSHAKE_NONCE_SIZE];
+        stream.read_exact(&mut peer_nonce).await?;
+
+        let nonce = handshake_nonce();
+        stream.write_all(&nonce).await?;
+        prove_key(
+            &mut stream,
+            epoch,
+            public_key,
+            &self.public_key,
+            &self.signer,
+            &peer_nonce,
+        )
+        .await?;
+        verify_key(&mut stream, epoch, &self.public_key, public_key, &nonce).await?;
+        Ok((stream, sender))
     }
-    remote_peer
 }
 
-fn bind_addr(mut local_peer: SocketAddr) -> SocketAddr {
-    match &mut local_peer {
-        SocketAddr::V4(v4) => {
-            v4.set_port(v4.port() * 10);
-        }
-        SocketAddr::V6(v6) => {
-            v6.set_port(v6.port() * 10);
-        }
+fn handshake_nonce() -> [u8; HANDSHAKE_NONCE_SIZE] {
+    let mut nonce = [0u8; HANDSHAKE_NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+    nonce
+}
+
+/// What a peer signs to prove that it holds its key: the nonce of the verifier, bound to the
+/// epoch and to the keys of both ends. A signature obtained by a peer can not be relayed to log
+/// in elsewhere as the prover.
+fn handshake_challenge(
+    epoch: Epoch,
+    verifier: &PublicKey,
+    prover: &PublicKey,
+    nonce: &[u8; HANDSHAKE_NONCE_SIZE],
+) -> Vec<u8> {
+    let (verifier, prover) = (verifier.as_ref(), prover.as_ref());
+    let mut challenge =
+        Vec::with_capacity(16 + verifier.len() + prover.len() + HANDSHAKE_NONCE_SIZE);
+    challenge.extend_from_slice(&epoch.to_be_bytes());
+    challenge.extend_from_slice(&(verifier.len() as u32).to_be_bytes());
+    challenge.extend_from_slice(verifier);
+    challenge.extend_from_slice(&(prover.len() as u32).to_be_bytes());
+    challenge.extend_from_slice(prover);
+    challenge.extend_from_slice(nonce);
+    challenge
+}
+
+/// Signs the challenge of the verifier with our key.
+async fn prove_key(
+    stream: &mut TcpStream,
+    epoch: Epoch,
+    verifier: &PublicKey,
+    prover: &PublicKey,
+    signer: &Arc<dyn BlockSigner>,
+    nonce: &[u8; HANDSHAKE_NONCE_SIZE],
+) -> io::Result<()> {
+    let challenge = handshake_challenge(epoch, verifier, prover, nonce);
+    let signer = signer.clone();
+    // A remote signer blocks on its socket, so it must not run on the async runtime
+    let signature = tokio::task::spawn_blocking(move || signer.sign_handshake(&challenge))
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    let signature = bincode::serialize(&signature).expect("Serialization should not fail");
+    stream.write_u32(signature.len() as u32).await?;
+    stream.write_all(&signature).await
+}
+
+/// Checks that the prover signed our challenge with the key it claims.
+async fn verify_key(
+    stream: &mut TcpStream,
+    epoch: Epoch,
+    verifier: &PublicKey,
+    prover: &PublicKey,
+    nonce: &[u8; HANDSHAKE_NONCE_SIZE],
+) -> io::Result<()> {
+    let invalid = |reason: String| io::Error::new(io::ErrorKind::InvalidData, reason);
+    // Serialized with its length
+    let size = stream.read_u32().await?;
+    if size as usize > MAX_SIGNATURE_SIZE + 8 {
+        return Err(invalid(format!("signature of {size} bytes is too large")));
     }
-    local_peer
+    let mut signature = vec![0u8; size as usize];
+    stream.read_exact(&mut signature).await?;
+    let signature: SignatureBytes =
+        bincode::deserialize(&signature).map_err(|e| invalid(e.to_string()))?;
+    prover
+        .verify_message(
+            HANDSHAKE_SIGNATURE_DOMAIN,
+            &handshake_challenge(epoch, verifier, prover, nonce),
+            &signature,
+        )
+        .map_err(|e| invalid(format!("peer does not hold its key: {e:?}")))
 }
 
 struct Worker {
     peer: SocketAddr,
     peer_id: usize,
+    peer_public_key: PublicKey,
+    public_key: PublicKey,
     connection_sender: mpsc::Sender<Connection>,
-    bind_addr: SocketAddr,
+    // Our active handshake, announcing our public key
+    handshake: Arc<Vec<u8>>,
+    // Signs the challenges of the peers we connect to
+    signer: Arc<dyn BlockSigner>,
+    epoch: Epoch,
     active_immediately: bool,
     latency_sender: HistogramSender<Duration>,
 }
@@ -196,6 +400,21 @@ impl Worker {
     const PASSIVE_HANDSHAKE: u64 = 0x0000AEAE;
     const MAX_SIZE: u32 = 16 * 1024 * 1024;
 
+    fn active_handshake(
+        epoch: Epoch,
+        parameters_digest: &[u8; 32],
+        public_key: &PublicKey,
+    ) -> Vec<u8> {
+        let public_key = public_key.as_ref();
+        let mut handshake = Vec::with_capacity(52 + public_key.len());
+        handshake.extend_from_slice(&Self::ACTIVE_HANDSHAKE.to_be_bytes());
+        handshake.extend_from_slice(&epoch.to_be_bytes());
+        handshake.extend_from_slice(parameters_digest);
+        handshake.extend_from_slice(&(public_key.len() as u32).to_be_bytes());
+        handshake.extend_from_slice(public_key);
+        handshake
+    }
+
     async fn run(self, mut receiver: mpsc::UnboundedReceiver<TcpStream>) -> Option<()> {
         let initial_delay = if self.active_immediately {
             Duration::ZERO
@@ -226,14 +445,7 @@ impl Worker {
         // this is critical to avoid race between active and passive connections
         runtime::sleep(delay).await;
         let mut stream = loop {
-            let socket = if self.bind_addr.is_ipv4() {
-                TcpSocket::new_v4().unwrap()
-            } else {
-                TcpSocket::new_v6().unwrap()
-            };
-            socket.set_reuseport(true).unwrap();
-            socket.bind(self.bind_addr).unwrap();
-            match socket.connect(peer).await {
+            match TcpStream::connect(peer).await {
                 Ok(stream) => break stream,
                 Err(_err) => {
                     tokio::time::sleep(Duration::from_secs(1)).await;
@@ -241,7 +453,29 @@ impl Worker {
             }
         };
         stream.set_nodelay(true)?;
-        stream.write_u64(Self::ACTIVE_HANDSHAKE).await?;
+        stream.write_all(&self.handshake).await?;
+        let nonce = handshake_nonce();
+        stream.write_all(&nonce).await?;
+        let mut peer_nonce = [0u8; HANDSHAKE_NONCE_SIZE];
+        stream.read_exact(&mut peer_nonce).await?;
+        // The server proves its key first, we do not sign anything for an impostor
+        verify_key(
+            &mut stream,
+            self.epoch,
+            &self.public_key,
+            &self.peer_public_key,
+            &nonce,
+        )
+        .await?;
+        prove_key(
+            &mut stream,
+            self.epoch,
+            &self.peer_public_key,
+            &self.public_key,
+            &self.signer,
+            &peer_nonce,
+        )
+        .await?;
         let handshake = stream.read_u64().await?;
         if handshake != Self::PASSIVE_HANDSHAKE {
             tracing::warn!("Invalid passive handshake: {handshake}");
@@ -256,12 +490,8 @@ impl Worker {
 
     async fn handle_passive_stream(&self, mut stream: TcpStream) -> io::Result<()> {
         stream.set_nodelay(true)?;
+        // The active handshake was already read by the server
         stream.write_u64(Self::PASSIVE_HANDSHAKE).await?;
-        let handshake = stream.read_u64().await?;
-        if handshake != Self::ACTIVE_HANDSHAKE {
-            tracing::warn!("Invalid active handshake: {handshake}");
-            return Ok(());
-        }
         let Some(connection) = self.make_connection().await else {
             // todo - pass signal to break the main loop
             return Ok(());
@@ -447,7 +677,138 @@ mod test {
 
     use prometheus::Registry;
 
-    use crate::{committee::Committee, metrics::Metrics, test_util::networks_and_addresses};
+    use super::*;
+    use crate::{
+        committee::Committee,
+        config::NodeParameters,
+        crypto::Signer,
+        metrics::Metrics,
+        test_util::networks_and_addresses,
+    };
+
+    /// Connects to the server as the peer announcing the public key and consensus parameters,
+    /// checks that the server holds its key, then answers the challenge of the verifier with the
+    /// signer. Returns the passive handshake of the server.
+    async fn connect_as(
+        address: SocketAddr,
+        server_key: &PublicKey,
+        verifier: &PublicKey,
+        public_key: &PublicKey,
+        consensus_parameters: &ConsensusParameters,
+        signer: &Arc<dyn BlockSigner>,
+    ) -> io::Result<u64> {
+        let mut stream = TcpStream::connect(address).await?;
+        let handshake = Worker::active_handshake(0, &consensus_parameters.digest(), public_key);
+        stream.write_all(&handshake).await?;
+        let nonce = handshake_nonce();
+        stream.write_all(&nonce).await?;
+        let mut server_nonce = [0u8; HANDSHAKE_NONCE_SIZE];
+        stream.read_exact(&mut server_nonce).await?;
+        verify_key(&mut stream, 0, public_key, server_key, &nonce).await?;
+        prove_key(&mut stream, 0, verifier, public_key, signer, &server_nonce).await?;
+        stream.read_u64().await
+    }
+
+    #[tokio::test]
+    async fn handshake_proves_public_key() {
+        let committee = Committee::new_test(vec![1, 1, 1]);
+        let (metrics, _) = Metrics::new(&Registry::default(), Some(&committee));
+        let mut signers = Signer::new_for_test(3);
+        // Accepts the connections of the worker of peer 0, which is never answered
+        let peer = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let peers = [
+            (signers[0].public_key(), peer.local_addr().unwrap()),
+            (signers[1].public_key(), address),
+        ];
+        let parameters = NodeParameters::default().consensus_parameters(0);
+        let signer = Arc::new(signers.remove(1));
+        let mut network =
+            Network::from_peers(&peers, 1, signer, 0, &parameters, address, metrics).await;
+        let signers: Vec<Arc<dyn BlockSigner>> = signers
+            .into_iter()
+            .map(|signer| Arc::new(signer) as Arc<dyn BlockSigner>)
+            .collect();
+
+        let server_key = &peers[1].0;
+        let other_key = signers[1].public_key();
+        // Announces the key of peer 0 without holding it
+        let impostor = connect_as(
+            address,
+            server_key,
+            server_key,
+            &peers[0].0,
+            &parameters,
+            &signers[1],
+        )
+        .await;
+        assert!(impostor.is_err(), "Impostor was accepted");
+        let unknown = connect_as(
+            address,
+            server_key,
+            server_key,
+            &other_key,
+            &parameters,
+            &signers[1],
+        )
+        .await;
+        assert!(unknown.is_err(), "Unknown peer was accepted");
+        let other_parameters = ConsensusParameters {
+            wave_length: parameters.wave_length + 1,
+            ..parameters
+        };
+        let mismatch = connect_as(
+            address,
+            server_key,
+            server_key,
+            &peers[0].0,
+            &other_parameters,
+            &signers[0],
+        )
+        .await;
+        assert!(mismatch.is_err(), "Peer with other parameters was accepted");
+        // A signature obtained by another verifier does not log in as its prover
+        let relayed = connect_as(
+            address,
+            server_key,
+            &other_key,
+            &peers[0].0,
+            &parameters,
+            &signers[0],
+        )
+        .await;
+        assert!(relayed.is_err(), "Relayed signature was accepted");
+        // The server proves its key as well
+        let server_impostor = connect_as(
+            address,
+            &other_key,
+            server_key,
+            &peers[0].0,
+            &parameters,
+            &signers[0],
+        )
+        .await;
+        assert!(server_impostor.is_err(), "Server key was not checked");
+
+        let handshake = connect_as(
+            address,
+            server_key,
+            server_key,
+            &peers[0].0,
+            &parameters,
+            &signers[0],
+        )
+        .await
+        .unwrap();
+        assert_eq!(handshake, Worker::PASSIVE_HANDSHAKE);
+        let connection = network.connection_receiver().recv().await.unwrap();
+        assert_eq!(connection.peer_id, 0);
+        network.shutdown().await;
+    }
 
     #[ignore]
     #[tokio::test]
@@ -3,20 +3,50 @@
 
 use std::net::SocketAddr;
 
-use axum::{http::StatusCode, routing::get, Extension, Router, Server};
+use axum::{
+    http::StatusCode,
+    routing::{get, post},
+    Extension,
+    Router,
+    Server,
+};
 use prometheus::{Registry, TextEncoder};
+use tokio::sync::{mpsc, oneshot};
 
-use crate::runtime::{Handle, JoinHandle};
+use crate::{
+    equivocation::EquivocationProofs,
+    reconfiguration::CommitteeChange,
+    runtime::{Handle, JoinHandle},
+    types::AuthorityIndex,
+};
 
 pub const METRICS_ROUTE: &str = "/metrics";
+pub const EQUIVOCATIONS_ROUTE: &str = "/equivocations";
+pub const COMMITTEE_CHANGES_ROUTE: &str = "/committee-changes";
+
+/// Committee changes posted to the server, each answered once the validator proposed or refused it.
+pub type CommitteeChangeSender = mpsc::Sender<(CommitteeChange, oneshot::Sender<eyre::Result<()>>)>;
+
+/// The authority of the validator. The server is reachable by anyone, so it only takes the
+/// committee changes its operator approved with the key of the validator.
+#[derive(Clone, Copy)]
+struct Operator(AuthorityIndex);
 
 pub fn start_prometheus_server(
     address: SocketAddr,
     registry: &Registry,
+    equivocations: EquivocationProofs,
+    committee_changes: CommitteeChangeSender,
+    operator: AuthorityIndex,
 ) -> JoinHandle<Result<(), hyper::Error>> {
     let app = Router::new()
         .route(METRICS_ROUTE, get(metrics))
-        .layer(Extension(registry.clone()));
+        .route(EQUIVOCATIONS_ROUTE, get(equivocation_proofs))
+        .route(COMMITTEE_CHANGES_ROUTE, post(submit_committee_change))
+        .layer(Extension(registry.clone()))
+        .layer(Extension(equivocations))
+        .layer(Extension(committee_changes))
+        .layer(Extension(Operator(operator)));
 
     tracing::info!("Prometheus server booted on {address}");
     Handle::current()
@@ -33,3 +63,56 @@ async fn metrics(registry: Extension<Registry>) -> (StatusCode, String) {
         ),
     }
 }
+
+async fn equivocation_proofs(equivocations: Extension<EquivocationProofs>) -> (StatusCode, String) {
+    match serde_yaml::to_string(&equivocations.all()) {
+        Ok(proofs) => (StatusCode::OK, proofs),
+        Err(error) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Unable to encode equivocation proofs: {error}"),
+        ),
+    }
+}
+
+/// Takes a committee change in the yaml format of `mysticeti committee-change`, approved by the
+/// validator with `mysticeti approve-committee-change`.
+async fn submit_committee_change(
+    committee_changes: Extension<CommitteeChangeSender>,
+    Extension(Operator(operator)): Extension<Operator>,
+    body: String,
+) -> (StatusCode, String) {
+    let change: CommitteeChange = match serde_yaml::from_str(&body) {
+        Ok(change) => change,
+        Err(error) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Unable to decode committee change: {error}"),
+            )
+        }
+    };
+    // The approval itself is verified by the validator with the rest of the change
+    if !change.approved_by(operator) {
+        return (
+            StatusCode::FORBIDDEN,
+            "Committee change is not approved by this validator".to_string(),
+        );
+    }
+    let (sender, receiver) = oneshot::channel();
+    if committee_changes.send((change, sender)).await.is_err() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Validator is stopping".to_string(),
+        );
+    }
+    match receiver.await {
+        Ok(Ok(())) => (StatusCode::OK, "Committee change proposed".to_string()),
+        Ok(Err(error)) => (
+            StatusCode::BAD_REQUEST,
+            format!("Committee change refused: {error}"),
+        ),
+        Err(_) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Validator is stopping".to_string(),
+        ),
+    }
+}
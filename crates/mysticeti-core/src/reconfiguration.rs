@@ -0,0 +1,404 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Committee changes from one epoch to the next.
+//!
+//! A node joins, leaves or changes its stake by signing a [`CommitteeChange`] with its own key.
+//! Joining, which also covers changing the stake, additionally needs the approval of a quorum of
+//! the current committee. Validators propose the change in their blocks, and committed changes
+//! shape the committee of the next epoch. Every validator commits the same blocks in the same
+//! order, so all of them derive the same next committee with [`next_committee`]. Rotating a key
+//! amounts to the new key joining and the old one leaving.
+
+use std::{collections::BTreeMap, net::SocketAddr, sync::Arc};
+
+use digest::Digest;
+use eyre::{ensure, eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    committee::{Authority, Committee, QuorumThreshold, StakeAggregator},
+    config::{ImportExport, NodeIdentifier, NodePublicConfig},
+    crypto::{CryptoHash, PublicKey, SignatureBytes, Signer},
+    types::{AuthorityIndex, Epoch, Stake},
+};
+
+const COMMITTEE_CHANGE_SIGNATURE_DOMAIN: &[u8] = b"mysticeti-committee-change";
+const COMMITTEE_CHANGE_APPROVAL_SIGNATURE_DOMAIN: &[u8] = b"mysticeti-committee-change-approval";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum CommitteeChangeKind {
+    /// Join the committee of the next epoch, or update the addresses and stake of a member.
+    Join {
+        network_address: SocketAddr,
+        metrics_address: SocketAddr,
+        stake: Stake,
+    },
+    /// Leave the committee of the next epoch.
+    Leave,
+}
+
+/// A change of the committee requested by the node it concerns, signed by the node's key and
+/// approved by members of the current committee.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CommitteeChange {
+    /// The epoch the change is submitted in, it takes effect in the next one. Changes are only
+    /// accepted in blocks of that epoch, so they can not be replayed later.
+    pub epoch: Epoch,
+    pub public_key: PublicKey,
+    pub kind: CommitteeChangeKind,
+    signature: SignatureBytes,
+    /// Approvals of members of the committee of the epoch, ordered by authority.
+    approvals: Vec<(AuthorityIndex, SignatureBytes)>,
+}
+
+impl CommitteeChange {
+    pub const DEFAULT_FILENAME: &'static str = "committee-change.yaml";
+
+    pub fn new(signer: &Signer, epoch: Epoch, kind: CommitteeChangeKind) -> Self {
+        let public_key = signer.public_key();
+        let signature = signer.sign_message(
+            COMMITTEE_CHANGE_SIGNATURE_DOMAIN,
+            &signed_payload(epoch, &public_key, &kind),
+        );
+        Self {
+            epoch,
+            public_key,
+            kind,
+            signature,
+            approvals: Vec::new(),
+        }
+    }
+
+    /// Adds the approval of a member of the committee of the epoch of the change.
+    pub fn approve(&mut self, signer: &Signer, committee: &Committee) -> Result<()> {
+        ensure!(
+            committee.epoch() == self.epoch,
+            "The change is for epoch {}, the committee is of epoch {}",
+            self.epoch,
+            committee.epoch()
+        );
+        let authority = committee
+            .authority_index(&signer.public_key())
+            .ok_or(eyre!("Only members of the committee approve changes"))?;
+        let signature = signer.sign_message(
+            COMMITTEE_CHANGE_APPROVAL_SIGNATURE_DOMAIN,
+            &signed_payload(self.epoch, &self.public_key, &self.kind),
+        );
+        match self
+            .approvals
+            .binary_search_by_key(&authority, |(approver, _)| *approver)
+        {
+            Ok(index) => self.approvals[index].1 = signature,
+            Err(index) => self.approvals.insert(index, (authority, signature)),
+        }
+        Ok(())
+    }
+
+    pub fn approved_by(&self, authority: AuthorityIndex) -> bool {
+        self.approvals
+            .iter()
+            .any(|(approver, _)| *approver == authority)
+    }
+
+    /// Checks that the change was signed by the node it concerns, and that a join was approved
+    /// by a quorum of the committee of its epoch. A member can stop taking part in consensus at
+    /// any time, so leaving only needs its own signature.
+    pub fn verify(&self, committee: &Committee) -> Result<()> {
+        ensure!(
+            committee.epoch() == self.epoch,
+            "Committee change of epoch {} verified in epoch {}",
+            self.epoch,
+            committee.epoch()
+        );
+        if let CommitteeChangeKind::Join { stake, .. } = self.kind {
+            ensure!(stake > 0, "Joining the committee requires a positive stake");
+        }
+        let payload = signed_payload(self.epoch, &self.public_key, &self.kind);
+        self.public_key
+            .verify_message(COMMITTEE_CHANGE_SIGNATURE_DOMAIN, &payload, &self.signature)
+            .map_err(|e| eyre!("Invalid signature on the committee change: {e}"))?;
+
+        ensure!(
+            self.approvals.windows(2).all(|pair| pair[0].0 < pair[1].0),
+            "Approvals are not ordered by authority"
+        );
+        let mut aggregator = StakeAggregator::<QuorumThreshold>::new();
+        let mut is_quorum = false;
+        for (authority, signature) in &self.approvals {
+            let public_key = committee
+                .get_public_key(*authority)
+                .ok_or(eyre!("Approval of unknown authority {authority}"))?;
+            public_key
+                .verify_message(
+                    COMMITTEE_CHANGE_APPROVAL_SIGNATURE_DOMAIN,
+                    &payload,
+                    signature,
+                )
+                .map_err(|e| eyre!("Invalid approval of authority {authority}: {e}"))?;
+            is_quorum = aggregator.add(*authority, committee);
+        }
+        if let CommitteeChangeKind::Join { .. } = self.kind {
+            ensure!(
+                is_quorum,
+                "Joining the committee requires the approval of a quorum"
+            );
+        }
+        Ok(())
+    }
+}
+
+impl ImportExport for CommitteeChange {}
+
+impl CryptoHash for CommitteeChange {
+    fn crypto_hash(&self, state: &mut impl Digest) {
+        state.update(signed_payload(self.epoch, &self.public_key, &self.kind));
+        self.signature.crypto_hash(state);
+        for (authority, signature) in &self.approvals {
+            authority.crypto_hash(state);
+            signature.crypto_hash(state);
+        }
+    }
+}
+
+fn signed_payload(epoch: Epoch, public_key: &PublicKey, kind: &CommitteeChangeKind) -> Vec<u8> {
+    bincode::serialize(&(epoch, public_key, kind)).expect("Serialization should not fail")
+}
+
+/// Applies the committed changes, in commit order, to the committee and the public config of an
+/// epoch. Changes that would leave an invalid committee (no member left, another signature
+/// scheme, addresses used twice, total stake overflow) are ignored. So are changes once the stake
+/// added, removed or moved by the changes of the epoch would reach the validity threshold of the
+/// committee: a single epoch can not hand over a third of the stake or more.
+pub fn next_committee(
+    committee: &Committee,
+    public_config: &NodePublicConfig,
+    changes: &[CommitteeChange],
+) -> (Arc<Committee>, NodePublicConfig) {
+    // Ordered by public key, like the authorities of a committee
+    let mut members: BTreeMap<Vec<u8>, (NodeIdentifier, Stake)> = committee
+        .authorities()
+        .zip(&public_config.identifiers)
+        .map(|(authority, identifier)| {
+            let stake = committee.get_stake(authority).unwrap();
+            (
+                identifier.public_key.as_ref().to_vec(),
+                (identifier.clone(), stake),
+            )
+        })
+        .collect();
+    let mut moved_stake = 0;
+    for change in changes {
+        if let Err(e) = apply(&mut members, &mut moved_stake, committee, change) {
+            tracing::warn!(
+                "Ignoring committee change of epoch {} by {:?}: {e}",
+                change.epoch,
+                change.public_key
+            );
+        }
+    }
+
+    let (identifiers, authorities) = members
+        .into_values()
+        .map(|(identifier, stake)| {
+            let authority = Authority::new(stake, identifier.public_key.clone());
+            (identifier, authority)
+        })
+        .unzip();
+    let next = committee
+        .next_epoch(authorities)
+        .expect("Changes are checked as they are applied");
+    let public_config = NodePublicConfig {
+        identifiers,
+        parameters: public_config.parameters.clone(),
+    };
+    (next, public_config)
+}
+
+fn apply(
+    members: &mut BTreeMap<Vec<u8>, (NodeIdentifier, Stake)>,
+    moved_stake: &mut Stake,
+    committee: &Committee,
+    change: &CommitteeChange,
+) -> Result<()> {
+    let key = change.public_key.as_ref().to_vec();
+    let current_stake = members.get(&key).map_or(0, |(_, stake)| *stake);
+    let moved = match &change.kind {
+        CommitteeChangeKind::Join { stake, .. } => stake.abs_diff(current_stake),
+        CommitteeChangeKind::Leave => current_stake,
+    };
+    let total_moved = moved_stake
+        .checked_add(moved)
+        .filter(|total| !committee.is_valid(*total))
+        .ok_or(eyre!("the epoch would move too much stake"))?;
+    match &change.kind {
+        CommitteeChangeKind::Join {
+            network_address,
+            metrics_address,
+            stake,
+        } => {
+            ensure!(
+                change.public_key.scheme() == committee.signature_scheme(),
+                "the committee signs with {}",
+                committee.signature_scheme()
+            );
+            for (other, (identifier, _)) in members.iter() {
+                if *other == key {
+                    continue;
+                }
+                for address in [network_address, metrics_address] {
+                    ensure!(
+                        *address != identifier.network_address
+                            && *address != identifier.metrics_address,
+                        "address {address} is already used"
+                    );
+                }
+            }
+            members
+                .iter()
+                .filter(|(other, _)| **other != key)
+                .try_fold(*stake, |total, (_, (_, stake))| total.checked_add(*stake))
+                .ok_or(eyre!("total stake overflows"))?;
+            let identifier = NodeIdentifier {
+                public_key: change.public_key.clone(),
+                network_address: *network_address,
+                metrics_address: *metrics_address,
+            };
+            members.insert(key, (identifier, *stake));
+        }
+        CommitteeChangeKind::Leave => {
+            ensure!(members.contains_key(&key), "not a member of the committee");
+            ensure!(members.len() > 1, "the last member can not leave");
+            members.remove(&key);
+        }
+    }
+    *moved_stake = total_moved;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::crypto::SignatureScheme;
+
+    fn address(port: u16) -> SocketAddr {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port)
+    }
+
+    fn join(signer: &Signer, port: u16, stake: Stake) -> CommitteeChange {
+        let kind = CommitteeChangeKind::Join {
+            network_address: address(port),
+            metrics_address: address(port + 1),
+            stake,
+        };
+        CommitteeChange::new(signer, 0, kind)
+    }
+
+    /// Approved by the given members of the committee.
+    fn approved(
+        mut change: CommitteeChange,
+        approvers: &[Signer],
+        committee: &Committee,
+    ) -> CommitteeChange {
+        for approver in approvers {
+            change.approve(approver, committee).unwrap();
+        }
+        change
+    }
+
+    #[test]
+    fn committee_change_signature() {
+        let committee = Committee::new_test(vec![1; 4]);
+        let signers = Signer::new_for_test(4);
+        let joining = Signer::generate(SignatureScheme::Ed25519, &mut StdRng::seed_from_u64(1));
+        let change = approved(join(&joining, 9000, 1), &signers[..3], &committee);
+        change.verify(&committee).unwrap();
+
+        let mut forged = change.clone();
+        forged.public_key = signers[1].public_key();
+        assert!(forged.verify(&committee).is_err());
+        let mut replayed = change;
+        replayed.epoch = 1;
+        assert!(replayed.verify(&committee).is_err());
+        let zero_stake = approved(join(&joining, 9000, 0), &signers[..3], &committee);
+        assert!(zero_stake.verify(&committee).is_err());
+    }
+
+    #[test]
+    fn unapproved_join_is_rejected() {
+        let committee = Committee::new_test(vec![1; 4]);
+        let signers = Signer::new_for_test(4);
+        let joining = Signer::generate(SignatureScheme::Ed25519, &mut StdRng::seed_from_u64(1));
+        let change = join(&joining, 9000, 1);
+        assert!(change.verify(&committee).is_err());
+        // Below the quorum of the committee
+        let mut change = approved(change, &signers[..2], &committee);
+        assert!(change.verify(&committee).is_err());
+        // Only members approve
+        assert!(change.approve(&joining, &committee).is_err());
+        // Approving twice counts once
+        let twice = approved(change.clone(), &signers[..2], &committee);
+        assert!(twice.verify(&committee).is_err());
+        // The approval covers the signed change
+        let mut forged = approved(change.clone(), &signers[2..3], &committee);
+        forged.kind = CommitteeChangeKind::Join {
+            network_address: address(9000),
+            metrics_address: address(9001),
+            stake: 2,
+        };
+        assert!(forged.verify(&committee).is_err());
+
+        let change = approved(change, &signers[2..3], &committee);
+        change.verify(&committee).unwrap();
+        // Leaving needs no approval
+        let leave = CommitteeChange::new(&signers[0], 0, CommitteeChangeKind::Leave);
+        leave.verify(&committee).unwrap();
+    }
+
+    #[test]
+    fn next_committee_applies_changes_in_order() {
+        let committee = Committee::new_test(vec![1; 10]);
+        let public_config = NodePublicConfig::new_for_tests(10);
+        let mut signers = Signer::new_for_test(10);
+        let mut rng = StdRng::seed_from_u64(1);
+        signers.extend((0..3).map(|_| Signer::generate(SignatureScheme::Ed25519, &mut rng)));
+        let approvers = &signers[..7];
+        let changes = [
+            // A new member joins, then changes its stake
+            approved(join(&signers[10], 9000, 1), approvers, &committee),
+            approved(join(&signers[10], 9000, 2), approvers, &committee),
+            // Invalid: the address is taken
+            approved(join(&signers[11], 9000, 1), approvers, &committee),
+            CommitteeChange::new(&signers[0], 0, CommitteeChangeKind::Leave),
+            // Invalid: the changes would move a third of the stake
+            approved(join(&signers[12], 9100, 1), approvers, &committee),
+        ];
+        let (next, next_config) = next_committee(&committee, &public_config, &changes);
+
+        assert_eq!(next.epoch(), 1);
+        assert_eq!(next.len(), 10);
+        assert_eq!(next.total_stake(), 11);
+        assert!(next.authority_index(&signers[0].public_key()).is_none());
+        assert!(next.authority_index(&signers[11].public_key()).is_none());
+        assert!(next.authority_index(&signers[12].public_key()).is_none());
+        let joined = next.authority_index(&signers[10].public_key()).unwrap();
+        assert_eq!(next.get_stake(joined), Some(2));
+        assert_eq!(next_config.network_address(joined), Some(address(9000)));
+        for authority in next.authorities() {
+            assert_eq!(
+                &next_config.identifiers[authority as usize].public_key,
+                next.get_public_key(authority).unwrap()
+            );
+        }
+
+        // Without changes, only the epoch moves forward
+        let (same, _) = next_committee(&committee, &public_config, &[]);
+        assert_eq!(same.epoch(), 1);
+        assert_eq!(same.len(), committee.len());
+        assert_eq!(same.quorum_threshold(), committee.quorum_threshold());
+    }
+}
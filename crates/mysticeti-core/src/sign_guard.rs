@@ -0,0 +1,240 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    fmt,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    crypto::BLOCK_DIGEST_SIZE,
+    types::{AuthorityIndex, BlockReference, Epoch, RoundNumber},
+};
+
+const CRC_SIZE: usize = 4;
+
+/// The last own block that was signed, identified by the digest covered by its signature.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SignedBlockRecord {
+    pub authority: AuthorityIndex,
+    pub epoch: Epoch,
+    pub round: RoundNumber,
+    /// Round of the previous own block, the block includes it.
+    pub parent_round: RoundNumber,
+    pub digest: [u8; BLOCK_DIGEST_SIZE],
+}
+
+impl SignedBlockRecord {
+    pub fn new(
+        authority: AuthorityIndex,
+        epoch: Epoch,
+        round: RoundNumber,
+        includes: &[BlockReference],
+        digest: [u8; BLOCK_DIGEST_SIZE],
+    ) -> Self {
+        // Own blocks include the previous own block first
+        let parent_round = includes
+            .first()
+            .filter(|include| include.authority == authority)
+            .map_or(0, |include| include.round);
+        Self {
+            authority,
+            epoch,
+            round,
+            parent_round,
+            digest,
+        }
+    }
+}
+
+/// Persistent protection against signing two different blocks for the same round.
+/// Rounds start over in every epoch, and so may the authority index of the validator.
+///
+/// The guard is a small file kept apart from the wal. It is replaced atomically and fsynced
+/// before a signature is handed out, so that it survives crashes, a lost wal and restarts of
+/// the validator with an older copy of its storage.
+pub struct SignGuard {
+    path: PathBuf,
+    last_signed: Option<SignedBlockRecord>,
+}
+
+impl SignGuard {
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let last_signed = match fs::read(&path) {
+            Ok(bytes) => Some(Self::decode(&bytes)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        Ok(Self { path, last_signed })
+    }
+
+    pub fn last_signed(&self) -> Option<&SignedBlockRecord> {
+        self.last_signed.as_ref()
+    }
+
+    /// Persists the record, unless it conflicts with a block signed before.
+    /// Signing the last signed block again is allowed, it produces the same signature.
+    pub fn record(&mut self, record: SignedBlockRecord) -> Result<(), SignGuardError> {
+        if let Some(last) = &self.last_signed {
+            if record.epoch < last.epoch {
+                return Err(SignGuardError::Conflict(format!(
+                    "epoch {} is below the last signed epoch {}",
+                    record.epoch, last.epoch
+                )));
+            }
+        }
+        if let Some(last) = self
+            .last_signed
+            .as_ref()
+            .filter(|l| l.epoch == record.epoch)
+        {
+            if record.authority != last.authority {
+                return Err(SignGuardError::Conflict(format!(
+                    "guard belongs to authority {}, not {}",
+                    last.authority, record.authority
+                )));
+            }
+            if record.round < last.round {
+                return Err(SignGuardError::Conflict(format!(
+                    "round {} is below the last signed round {}",
+                    record.round, last.round
+                )));
+            }
+            if record.round == last.round && record.digest != last.digest {
+                return Err(SignGuardError::Conflict(format!(
+                    "a different block was already signed for round {}",
+                    record.round
+                )));
+            }
+            if record == *last {
+                return Ok(());
+            }
+        }
+        self.persist(&record).map_err(SignGuardError::Io)?;
+        self.last_signed = Some(record);
+        Ok(())
+    }
+
+    fn persist(&self, record: &SignedBlockRecord) -> io::Result<()> {
+        let mut bytes = bincode::serialize(record).expect("Serialization should not fail");
+        let crc = crc32fast::hash(&bytes);
+        bytes.extend_from_slice(&crc.to_le_bytes());
+
+        let tmp = self.path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        // Make the rename itself durable
+        let parent = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        fs::File::open(parent)?.sync_all()
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<SignedBlockRecord> {
+        let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, reason);
+        if bytes.len() < CRC_SIZE {
+            return Err(invalid("Sign guard file is truncated"));
+        }
+        let (record, crc) = bytes.split_at(bytes.len() - CRC_SIZE);
+        if crc32fast::hash(record).to_le_bytes() != crc {
+            return Err(invalid("Sign guard file is corrupted"));
+        }
+        bincode::deserialize(record).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+#[derive(Debug)]
+pub enum SignGuardError {
+    /// Signing would conflict with an earlier signature.
+    Conflict(String),
+    /// The guard could not be persisted.
+    Io(io::Error),
+}
+
+impl fmt::Display for SignGuardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignGuardError::Conflict(reason) => write!(f, "{reason}"),
+            SignGuardError::Io(e) => write!(f, "failed to persist sign guard: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SignGuardError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(round: RoundNumber, digest: u8) -> SignedBlockRecord {
+        SignedBlockRecord {
+            authority: 1,
+            epoch: 0,
+            round,
+            parent_round: round.saturating_sub(1),
+            digest: [digest; BLOCK_DIGEST_SIZE],
+        }
+    }
+
+    #[test]
+    fn guard_survives_reopen() {
+        let dir = tempdir::TempDir::new("sign_guard").unwrap();
+        let path = dir.path().join("sign-guard");
+        let mut guard = SignGuard::open(&path).unwrap();
+        assert!(guard.last_signed().is_none());
+        guard.record(record(1, 1)).unwrap();
+        guard.record(record(1, 1)).unwrap();
+        guard.record(record(2, 2)).unwrap();
+        drop(guard);
+
+        let mut guard = SignGuard::open(&path).unwrap();
+        assert_eq!(guard.last_signed(), Some(&record(2, 2)));
+        assert!(matches!(
+            guard.record(record(2, 3)),
+            Err(SignGuardError::Conflict(_))
+        ));
+        assert!(matches!(
+            guard.record(record(1, 1)),
+            Err(SignGuardError::Conflict(_))
+        ));
+        let mut other = record(3, 3);
+        other.authority = 2;
+        assert!(matches!(
+            guard.record(other),
+            Err(SignGuardError::Conflict(_))
+        ));
+        guard.record(record(3, 3)).unwrap();
+
+        // Rounds and authority indices start over in the next epoch
+        let mut next_epoch = record(1, 4);
+        next_epoch.epoch = 1;
+        next_epoch.authority = 2;
+        guard.record(next_epoch).unwrap();
+        assert!(matches!(
+            guard.record(record(4, 4)),
+            Err(SignGuardError::Conflict(_))
+        ));
+    }
+
+    #[test]
+    fn corrupted_guard() {
+        let dir = tempdir::TempDir::new("sign_guard").unwrap();
+        let path = dir.path().join("sign-guard");
+        SignGuard::open(&path)
+            .unwrap()
+            .record(record(1, 1))
+            .unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[0] ^= 1;
+        fs::write(&path, bytes).unwrap();
+        assert!(SignGuard::open(&path).is_err());
+    }
+}
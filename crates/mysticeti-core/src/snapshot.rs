@@ -0,0 +1,225 @@
+// Copyright (c) Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block_store::IndexSnapshot,
+    state::RecoveredStateBuilder,
+    wal::{WalPosition, WalWriter},
+};
+
+/// Snapshots older than the last ones are deleted.
+const RETAINED_SNAPSHOTS: usize = 2;
+
+/// The state recovered from the wal up to a position: the block index above a gc round, the last
+/// own block, the pending statements, the committed state and the aggregator state. Recovery loads
+/// the latest snapshot and only replays the wal entries written after it.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    /// The wal entries before this position are captured by the snapshot.
+    pub position: WalPosition,
+    pub(crate) index: IndexSnapshot,
+    pub(crate) state: RecoveredStateBuilder,
+}
+
+/// The snapshot files of a wal, named after the wal position they were taken at. Each file holds
+/// the crc of the serialized snapshot followed by the snapshot.
+#[derive(Clone)]
+pub struct Snapshots {
+    dir: PathBuf,
+}
+
+impl Snapshots {
+    /// Opens the directory of the snapshots, creating it if needed.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// The latest snapshot that is readable and within the wal. A snapshot past the end captures
+    /// entries that were lost in a crash, and replaying from a snapshot before the first retained
+    /// segment would skip the deleted entries in between. Both are skipped along with damaged ones.
+    pub fn latest(&self, wal_writer: &WalWriter) -> io::Result<Option<Snapshot>> {
+        self.latest_within(wal_writer.first_position(), wal_writer.position())
+    }
+
+    /// Works as Snapshots::latest, for a wal from the start position up to the end position.
+    pub fn latest_within(
+        &self,
+        wal_start: WalPosition,
+        wal_end: WalPosition,
+    ) -> io::Result<Option<Snapshot>> {
+        for path in self.paths()?.into_iter().rev() {
+            let snapshot = match read_snapshot(&path) {
+                Ok(snapshot) => snapshot,
+                Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                    tracing::warn!("Skipping damaged snapshot {}: {err}", path.display());
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            if snapshot.position > wal_end {
+                tracing::warn!(
+                    "Skipping snapshot {} past the end of the wal at {wal_end}",
+                    path.display()
+                );
+                continue;
+            }
+            if snapshot.position < wal_start {
+                tracing::warn!(
+                    "Skipping snapshot {} before the first wal segment at {wal_start}",
+                    path.display()
+                );
+                continue;
+            }
+            return Ok(Some(snapshot));
+        }
+        Ok(None)
+    }
+
+    /// Writes the snapshot, then deletes the older ones but the last. The snapshot is only visible
+    /// once fully written, a crash leaves the previous one as the latest.
+    pub fn write(&self, snapshot: &Snapshot) -> io::Result<()> {
+        let serialized = bincode::serialize(snapshot).expect("Snapshot serialization failed");
+        let path = self
+            .dir
+            .join(format!("{:0>20}.snapshot", snapshot.position.to_string()));
+        let tmp_path = path.with_extension("tmp");
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&crc32fast::hash(&serialized).to_le_bytes())?;
+        file.write_all(&serialized)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &path)?;
+        File::open(&self.dir)?.sync_all()?;
+
+        let paths = self.paths()?;
+        for path in &paths[..paths.len().saturating_sub(RETAINED_SNAPSHOTS)] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    // Paths of the snapshot files, oldest first
+    fn paths(&self) -> io::Result<Vec<PathBuf>> {
+        let mut paths = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "snapshot")
+            {
+                paths.push(path);
+            }
+        }
+        // Names are zero padded positions
+        paths.sort();
+        Ok(paths)
+    }
+}
+
+fn read_snapshot(path: &Path) -> io::Result<Snapshot> {
+    let bytes = fs::read(path)?;
+    let Some((crc, serialized)) = bytes.split_first_chunk::<4>() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "snapshot too short",
+        ));
+    };
+    if u32::from_le_bytes(*crc) != crc32fast::hash(serialized) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "crc mismatch"));
+    }
+    bincode::deserialize(serialized).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(position: WalPosition) -> Snapshot {
+        Snapshot {
+            position,
+            index: IndexSnapshot::default(),
+            state: RecoveredStateBuilder::new(),
+        }
+    }
+
+    fn position(start: u64) -> WalPosition {
+        WalPosition::default().add(start)
+    }
+
+    fn latest_position(
+        snapshots: &Snapshots,
+        wal_start: WalPosition,
+        wal_end: WalPosition,
+    ) -> Option<WalPosition> {
+        snapshots
+            .latest_within(wal_start, wal_end)
+            .unwrap()
+            .map(|snapshot| snapshot.position)
+    }
+
+    fn open_snapshots(dir: &tempdir::TempDir) -> Snapshots {
+        let snapshots = Snapshots::open(dir.path()).unwrap();
+        snapshots.write(&snapshot(position(10))).unwrap();
+        snapshots.write(&snapshot(position(20))).unwrap();
+        snapshots
+    }
+
+    #[test]
+    fn latest_skips_damaged_snapshots() {
+        let dir = tempdir::TempDir::new("latest_skips_damaged_snapshots").unwrap();
+        let snapshots = open_snapshots(&dir);
+        let end = position(30);
+        assert_eq!(
+            latest_position(&snapshots, WalPosition::default(), end),
+            Some(position(20))
+        );
+
+        let path = snapshots.paths().unwrap().pop().unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(&path, bytes).unwrap();
+        assert_eq!(
+            latest_position(&snapshots, WalPosition::default(), end),
+            Some(position(10))
+        );
+    }
+
+    #[test]
+    fn latest_skips_snapshots_past_the_end() {
+        let dir = tempdir::TempDir::new("latest_skips_snapshots_past_the_end").unwrap();
+        let snapshots = open_snapshots(&dir);
+        // The wal lost the entries after position 15 in a crash
+        assert_eq!(
+            latest_position(&snapshots, WalPosition::default(), position(15)),
+            Some(position(10))
+        );
+        assert_eq!(
+            latest_position(&snapshots, WalPosition::default(), position(5)),
+            None
+        );
+    }
+
+    #[test]
+    fn latest_skips_snapshots_before_the_first_segment() {
+        let dir = tempdir::TempDir::new("latest_skips_snapshots_before_the_first_segment").unwrap();
+        let snapshots = open_snapshots(&dir);
+        // The segments before position 15 were deleted
+        assert_eq!(
+            latest_position(&snapshots, position(15), position(30)),
+            Some(position(20))
+        );
+        assert_eq!(
+            latest_position(&snapshots, position(25), position(30)),
+            None
+        );
+    }
+}
@@ -4,12 +4,16 @@
 use std::collections::{BTreeMap, HashSet, VecDeque};
 
 use minibytes::Bytes;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     block_store::{BlockStore, CommitData, OwnBlockData},
+    consensus::leader_schedule::LeaderReputation,
     core::MetaStatement,
     data::Data,
-    types::{BlockReference, StatementBlock},
+    epoch_close::EpochChangeState,
+    equivocation::EquivocationProof,
+    types::{BlockReference, RoundNumber, StatementBlock, TimestampNs},
     wal::WalPosition,
 };
 
@@ -22,10 +26,16 @@ pub struct RecoveredState {
 
     pub last_committed_leader: Option<BlockReference>,
     pub committed_blocks: HashSet<BlockReference>,
+    pub last_commit_timestamp_ns: TimestampNs,
     pub committed_state: Option<Bytes>,
+    pub leader_reputation: Option<LeaderReputation>,
+    pub epoch_change: Option<EpochChangeState>,
+
+    pub equivocations: Vec<(WalPosition, EquivocationProof)>,
 }
 
-#[derive(Default)]
+// Serialized as is in snapshots, recovery carries on replaying the wal from there
+#[derive(Default, Serialize, Deserialize)]
 pub struct RecoveredStateBuilder {
     pending: BTreeMap<WalPosition, RawMetaStatement>,
     last_own_block: Option<OwnBlockData>,
@@ -34,7 +44,12 @@ pub struct RecoveredStateBuilder {
 
     last_committed_leader: Option<BlockReference>,
     committed_blocks: HashSet<BlockReference>,
+    last_commit_timestamp_ns: TimestampNs,
     committed_state: Option<Bytes>,
+    leader_reputation: Option<LeaderReputation>,
+    epoch_change: Option<EpochChangeState>,
+
+    equivocations: Vec<(WalPosition, EquivocationProof)>,
 }
 
 impl RecoveredStateBuilder {
@@ -64,13 +79,42 @@ impl RecoveredStateBuilder {
         self.unprocessed_blocks.clear();
     }
 
-    pub fn commit_data(&mut self, commits: Vec<CommitData>, committed_state: Bytes) {
+    pub fn commit_data(
+        &mut self,
+        commits: Vec<CommitData>,
+        committed_state: Bytes,
+        leader_reputation: LeaderReputation,
+        epoch_change: EpochChangeState,
+    ) {
         for commit_data in commits {
             self.last_committed_leader = Some(commit_data.leader);
+            self.last_commit_timestamp_ns = commit_data.timestamp_ns;
             self.committed_blocks
                 .extend(commit_data.sub_dag.into_iter());
         }
         self.committed_state = Some(committed_state);
+        self.leader_reputation = Some(leader_reputation);
+        self.epoch_change = Some(epoch_change);
+    }
+
+    pub fn equivocation(&mut self, pos: WalPosition, proof: Bytes) {
+        let proof: EquivocationProof =
+            bincode::deserialize(&proof).expect("Failed to deserialize equivocation proof");
+        // Proofs are written again when the wal is compacted, the last copy is the one retained
+        match self
+            .equivocations
+            .iter_mut()
+            .find(|(_, known)| known.authority == proof.authority && known.round == proof.round)
+        {
+            Some((known_pos, _)) => *known_pos = pos,
+            None => self.equivocations.push((pos, proof)),
+        }
+    }
+
+    /// Forget the committed blocks at or below the round, they are not kept in snapshots.
+    pub fn forget_committed_at_or_below(&mut self, gc_round: RoundNumber) {
+        self.committed_blocks
+            .retain(|reference| reference.round > gc_round);
     }
 
     pub fn build(self, block_store: BlockStore) -> RecoveredState {
@@ -87,11 +131,16 @@ impl RecoveredStateBuilder {
             unprocessed_blocks: self.unprocessed_blocks,
             last_committed_leader: self.last_committed_leader,
             committed_blocks: self.committed_blocks,
+            last_commit_timestamp_ns: self.last_commit_timestamp_ns,
             committed_state: self.committed_state,
+            leader_reputation: self.leader_reputation,
+            epoch_change: self.epoch_change,
+            equivocations: self.equivocations,
         }
     }
 }
 
+#[derive(Serialize, Deserialize)]
 enum RawMetaStatement {
     Include(BlockReference),
     Payload(Bytes),
@@ -1,7 +1,7 @@
 // Copyright (c) Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{collections::HashSet, sync::Arc};
+use std::{collections::HashSet, sync::Arc, time::Duration};
 
 use minibytes::Bytes;
 
@@ -11,18 +11,22 @@ use crate::{
     consensus::linearizer::CommittedSubDag,
     core::Core,
     data::Data,
+    leader_timeout::LeaderTimeout,
     metrics::{Metrics, UtilizationTimerVecExt},
+    reconfiguration::CommitteeChange,
     runtime::timestamp_utc,
-    types::{AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
+    types::{AuthorityIndex, BlockReference, RoundNumber, StatementBlock, TimestampNs},
 };
 
 pub struct Syncer<H: BlockHandler, S: SyncerSignals, C: CommitObserver> {
     core: Core<H>,
     force_new_block: bool,
-    commit_period: u64,
     signals: S,
     commit_observer: C,
     pub(crate) connected_authorities: HashSet<AuthorityIndex>,
+    leader_timeout: LeaderTimeout,
+    // The timeout decided for the round the leader timeout task is waiting on
+    round_timeout: Option<(RoundNumber, Duration)>,
     metrics: Arc<Metrics>,
 }
 
@@ -39,29 +43,36 @@ pub trait CommitObserver: Send + Sync {
 
     fn aggregator_state(&self) -> Bytes;
 
-    fn recover_committed(&mut self, committed: HashSet<BlockReference>, state: Option<Bytes>);
+    /// Restore the committed blocks, the aggregator state and the consensus time of the last
+    /// committed sub-dag after a restart.
+    fn recover_committed(
+        &mut self,
+        committed: HashSet<BlockReference>,
+        state: Option<Bytes>,
+        last_timestamp_ns: TimestampNs,
+    );
 }
 
 impl<H: BlockHandler, S: SyncerSignals, C: CommitObserver> Syncer<H, S, C> {
-    pub fn new(
-        core: Core<H>,
-        commit_period: u64,
-        signals: S,
-        commit_observer: C,
-        metrics: Arc<Metrics>,
-    ) -> Self {
+    pub fn new(core: Core<H>, signals: S, commit_observer: C, metrics: Arc<Metrics>) -> Self {
         let committee_size = core.committee().len();
         Self {
             core,
             force_new_block: false,
-            commit_period,
             signals,
             commit_observer,
             connected_authorities: HashSet::with_capacity(committee_size),
+            leader_timeout: Default::default(),
+            round_timeout: None,
             metrics,
         }
     }
 
+    pub fn with_leader_timeout(mut self, leader_timeout: LeaderTimeout) -> Self {
+        self.leader_timeout = leader_timeout;
+        self
+    }
+
     pub fn add_blocks(&mut self, blocks: Vec<Data<StatementBlock>>) {
         let _timer = self
             .metrics
@@ -74,6 +85,7 @@ impl<H: BlockHandler, S: SyncerSignals, C: CommitObserver> Syncer<H, S, C> {
     pub fn force_new_block(&mut self, round: RoundNumber) -> bool {
         if self.core.last_proposed() == round {
             self.metrics.leader_timeout_total.inc();
+            self.leader_timeout.on_timeout();
             self.force_new_block = true;
             self.try_new_block();
             true
@@ -82,16 +94,45 @@ impl<H: BlockHandler, S: SyncerSignals, C: CommitObserver> Syncer<H, S, C> {
         }
     }
 
+    /// The last own block became durable, the next one may have been waiting for it.
+    pub fn own_block_durable(&mut self) {
+        self.try_new_block();
+    }
+
+    /// How long to wait for the leaders of the specified round before forcing a new block. The
+    /// timeout is decided once per round, the leader timeout task asks again on every wake-up.
+    pub fn leader_timeout(&mut self, round: RoundNumber) -> Duration {
+        if let Some((timeout_round, timeout)) = self.round_timeout {
+            if timeout_round == round {
+                return timeout;
+            }
+        }
+        let authority = self.core.authority();
+        let leader_rtts = self.core.leaders(round).into_iter().map(|leader| {
+            if leader == authority {
+                Some(Duration::ZERO)
+            } else {
+                self.metrics.connection_rtt(leader)
+            }
+        });
+        let (timeout, decision) = self.leader_timeout.timeout(leader_rtts);
+        self.metrics
+            .leader_timeout_decisions_total
+            .with_label_values(&[decision.as_str()])
+            .inc();
+        self.metrics
+            .leader_timeout_ms
+            .set(timeout.as_millis() as i64);
+        self.round_timeout = Some((round, timeout));
+        timeout
+    }
+
     fn try_new_block(&mut self) {
         let _timer = self
             .metrics
             .utilization_timer
             .utilization_timer("Syncer::try_new_block");
-        if self.force_new_block
-            || self
-                .core
-                .ready_new_block(self.commit_period, &self.connected_authorities)
-        {
+        if self.force_new_block || self.core.ready_new_block(&self.connected_authorities) {
             if self.core.try_new_block().is_none() {
                 return;
             }
@@ -105,6 +146,7 @@ impl<H: BlockHandler, S: SyncerSignals, C: CommitObserver> Syncer<H, S, C> {
             let newly_committed = self.core.try_commit();
             let utc_now = timestamp_utc();
             if !newly_committed.is_empty() {
+                self.leader_timeout.on_commit();
                 let committed_refs: Vec<_> = newly_committed
                     .iter()
                     .map(|block| {
@@ -126,14 +168,26 @@ impl<H: BlockHandler, S: SyncerSignals, C: CommitObserver> Syncer<H, S, C> {
         }
     }
 
+    pub fn submit_committee_change(&mut self, change: CommitteeChange) -> eyre::Result<()> {
+        self.core.submit_committee_change(change)
+    }
+
     pub fn commit_observer(&self) -> &C {
         &self.commit_observer
     }
 
+    pub fn cleanup(&mut self) {
+        self.core.cleanup();
+    }
+
     pub fn core(&self) -> &Core<H> {
         &self.core
     }
 
+    pub fn into_core(self) -> Core<H> {
+        self.core
+    }
+
     #[cfg(test)]
     pub fn scheduler_state_id(&self) -> usize {
         self.core.authority() as usize
@@ -209,6 +263,29 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_leader_timeout_decided_once_per_round() {
+        let (_committee, mut syncers) = committee_and_syncers(4);
+        let syncer = &mut syncers[0];
+        let decisions = |syncer: &Syncer<_, _, _>| -> u64 {
+            ["configured", "rtt", "backoff"]
+                .iter()
+                .map(|decision| {
+                    syncer
+                        .metrics
+                        .leader_timeout_decisions_total
+                        .with_label_values(&[decision])
+                        .get()
+                })
+                .sum()
+        };
+        let timeout = syncer.leader_timeout(1);
+        assert_eq!(syncer.leader_timeout(1), timeout);
+        assert_eq!(decisions(syncer), 1);
+        syncer.leader_timeout(2);
+        assert_eq!(decisions(syncer), 2);
+    }
+
     #[test]
     pub fn test_syncer() {
         for seed in 0..10 {
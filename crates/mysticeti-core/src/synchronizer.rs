@@ -9,12 +9,13 @@ use tokio::sync::mpsc;
 
 use crate::{
     block_handler::BlockHandler,
+    data::Data,
     metrics::Metrics,
     net_sync::{self, NetworkSyncerInner},
     network::NetworkMessage,
     runtime::{sleep, timestamp_utc, Handle, JoinHandle},
     syncer::CommitObserver,
-    types::{AuthorityIndex, BlockReference, RoundNumber},
+    types::{AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
 };
 
 // TODO: A central controller will eventually dynamically update these parameters.
@@ -104,12 +105,18 @@ where
         references: Vec<BlockReference>,
     ) -> Option<()> {
         let mut missing = Vec::new();
+        let authority = self.inner.block_store.authority();
         for reference in references {
             let stored_block = self.inner.block_store.get_block(reference);
             let found = stored_block.is_some();
             match stored_block {
                 // TODO: Should we be able to send more than one block in a single network message?
-                Some(block) => self.sender.send(NetworkMessage::Block(block)).await.ok()?,
+                Some(block) => {
+                    if block.author() == authority {
+                        Self::wait_durable(&self.inner, &block).await;
+                    }
+                    self.sender.send(NetworkMessage::Block(block)).await.ok()?
+                }
                 None => missing.push(reference),
             }
             self.metrics
@@ -148,6 +155,7 @@ where
             let notified = inner.notify.notified();
             let blocks = inner.block_store.get_own_blocks(round, batch_size);
             for block in blocks {
+                Self::wait_durable(&inner, &block).await;
                 round = block.round();
                 to.send(NetworkMessage::Block(block)).await.ok()?;
             }
@@ -155,6 +163,14 @@ where
         }
     }
 
+    // Only own blocks that survive a crash are sent, a restarted node that lost one could otherwise
+    // sign another block for the same round
+    async fn wait_durable(inner: &NetworkSyncerInner<H, C>, block: &Data<StatementBlock>) {
+        if let Some(position) = inner.block_store.block_position(*block.reference()) {
+            inner.wal_durability.wait_durable(position).await;
+        }
+    }
+
     // TODO:
     // * There should be a new protocol message that indicate when we should stop this task.
     // * Decide when to subscribe to a stream versus requesting specific blocks by ids.
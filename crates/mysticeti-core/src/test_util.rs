@@ -17,17 +17,20 @@ use crate::future_simulator::OverrideNodeContext;
 use crate::simulated_network::SimulatedNetwork;
 use crate::{
     block_handler::{BlockHandler, TestBlockHandler, TestCommitHandler},
+    block_signer::BlockSigner,
     block_store::{BlockStore, BlockWriter, OwnBlockData, WAL_ENTRY_BLOCK},
     committee::Committee,
-    config::{self, NodePrivateConfig, NodePublicConfig},
+    config::{self, NodeParameters, NodePublicConfig},
+    consensus::leader_schedule::{LeaderSchedule, StakeWeightedSchedule},
     core::{Core, CoreOptions},
+    crypto::{dummy_signer, HashFunction, Signer},
     data::Data,
     metrics::{MetricReporter, Metrics},
     net_sync::NetworkSyncer,
     network::Network,
     syncer::{Syncer, SyncerSignals},
     types::{format_authority_index, AuthorityIndex, BlockReference, RoundNumber, StatementBlock},
-    wal::{open_file_for_wal, walf, WalPosition, WalWriter},
+    wal::{segmented_wal, walf, WalPosition, WalWriter},
 };
 
 pub fn test_metrics() -> Arc<Metrics> {
@@ -38,6 +41,15 @@ pub fn committee(n: usize) -> Arc<Committee> {
     Committee::new_test(vec![1; n])
 }
 
+/// The leader elected by the default schedule of the committers.
+pub fn elect_leader(
+    committee: &Arc<Committee>,
+    round: RoundNumber,
+    leader_offset: u64,
+) -> AuthorityIndex {
+    StakeWeightedSchedule::new(committee.clone(), 0).elect_leader(round, leader_offset)
+}
+
 pub fn committee_and_cores(
     n: usize,
 ) -> (
@@ -82,51 +94,75 @@ pub fn committee_and_cores_persisted_epoch_duration(
     Vec<MetricReporter>,
 ) {
     let committee = committee(n);
+    let (cores, reporters) = cores_for_committee(&committee, path, public_config);
+    (committee, cores, reporters)
+}
+
+/// Open a core for every authority of the committee, such as the committee of a later epoch.
+pub fn cores_for_committee(
+    committee: &Arc<Committee>,
+    path: Option<&Path>,
+    public_config: &NodePublicConfig,
+) -> (Vec<Core<TestBlockHandler>>, Vec<MetricReporter>) {
     let cores: Vec<_> = committee
         .authorities()
         .map(|authority| {
-            let last_transaction = first_transaction_for_authority(authority);
-            let (metrics, reporter) = Metrics::new(&Registry::new(), Some(&committee));
-            let block_handler = TestBlockHandler::new(
-                last_transaction,
-                committee.clone(),
+            open_core(
+                committee,
                 authority,
-                metrics.clone(),
-            );
-            let wal_file = if let Some(path) = path {
-                let wal_path = path.join(format!("{:03}.wal", authority));
-                open_file_for_wal(&wal_path).unwrap()
-            } else {
-                tempfile::tempfile().unwrap()
-            };
-            let (wal_writer, wal_reader) = walf(wal_file).expect("Failed to open wal");
-            let recovered = BlockStore::open(
-                authority,
-                Arc::new(wal_reader),
-                &wal_writer,
-                metrics.clone(),
-                &committee,
-            );
-
-            let private_config = NodePrivateConfig::new_for_tests(authority);
-
-            println!("Opening core {authority}");
-            let core = Core::open(
-                block_handler,
-                authority,
-                committee.clone(),
-                private_config,
+                path,
                 public_config,
-                metrics,
-                recovered,
-                wal_writer,
-                CoreOptions::test(),
-            );
-            (core, reporter)
+                Box::new(dummy_signer()),
+            )
         })
         .collect();
-    let (cores, reporters) = cores.into_iter().unzip();
-    (committee, cores, reporters)
+    cores.into_iter().unzip()
+}
+
+/// Open the core of one authority, recovering it from its wal when a path is given.
+pub fn open_core(
+    committee: &Arc<Committee>,
+    authority: AuthorityIndex,
+    path: Option<&Path>,
+    public_config: &NodePublicConfig,
+    signer: Box<dyn BlockSigner>,
+) -> (Core<TestBlockHandler>, MetricReporter) {
+    let last_transaction = first_transaction_for_authority(authority);
+    let (metrics, reporter) = Metrics::new(&Registry::new(), Some(committee));
+    let block_handler = TestBlockHandler::new(
+        last_transaction,
+        committee.clone(),
+        authority,
+        metrics.clone(),
+    );
+    let (wal_writer, wal_reader) = if let Some(path) = path {
+        let wal_path = path.join(format!("{:03}.wal", authority));
+        segmented_wal(wal_path).expect("Failed to open wal")
+    } else {
+        walf(tempfile::tempfile().unwrap()).expect("Failed to open wal")
+    };
+    let recovered = BlockStore::open(
+        authority,
+        Arc::new(wal_reader),
+        &wal_writer,
+        metrics.clone(),
+        committee,
+    )
+    .expect("Failed to recover from wal");
+
+    println!("Opening core {authority}");
+    let core = Core::open(
+        block_handler,
+        authority,
+        committee.clone(),
+        signer,
+        public_config,
+        metrics,
+        recovered,
+        wal_writer,
+        CoreOptions::test(),
+    );
+    (core, reporter)
 }
 
 fn first_transaction_for_authority(authority: AuthorityIndex) -> u64 {
@@ -150,7 +186,7 @@ pub fn committee_and_syncers(
                     core.block_handler().transaction_time.clone(),
                     test_metrics(),
                 );
-                Syncer::new(core, 3, Default::default(), commit_handler, test_metrics())
+                Syncer::new(core, Default::default(), commit_handler, test_metrics())
             })
             .collect(),
     )
@@ -161,14 +197,28 @@ pub async fn networks_and_addresses(metrics: &[Arc<Metrics>]) -> (Vec<Network>,
     let addresses: Vec<_> = (0..metrics.len())
         .map(|i| SocketAddr::V4(SocketAddrV4::new(host, 5001 + i as u16)))
         .collect();
-    let networks =
-        addresses
-            .iter()
-            .zip(metrics.iter())
-            .enumerate()
-            .map(|(i, (address, metrics))| {
-                Network::from_socket_addresses(&addresses, i, *address, metrics.clone())
-            });
+    let signers = Signer::new_for_test(metrics.len());
+    let parameters = NodeParameters::default().consensus_parameters(0);
+    let peers: Vec<_> = signers
+        .iter()
+        .map(|signer| signer.public_key())
+        .zip(addresses.iter().copied())
+        .collect();
+    let networks = signers
+        .into_iter()
+        .zip(addresses.iter().zip(metrics.iter()))
+        .enumerate()
+        .map(|(i, (signer, (address, metrics)))| {
+            Network::from_peers(
+                &peers,
+                i,
+                Arc::new(signer),
+                0,
+                &parameters,
+                *address,
+                metrics.clone(),
+            )
+        });
     let networks = join_all(networks).await;
     (networks, addresses)
 }
@@ -209,7 +259,6 @@ pub fn simulated_network_syncers_with_epoch_duration(
         let network_syncer = NetworkSyncer::start(
             network,
             core,
-            3,
             commit_handler,
             config::node_defaults::default_shutdown_grace_period(),
             test_metrics(),
@@ -241,7 +290,6 @@ pub async fn network_syncers_with_epoch_duration(
         let network_syncer = NetworkSyncer::start(
             network,
             core,
-            3,
             commit_handler,
             config::node_defaults::default_shutdown_grace_period(),
             test_metrics(),
@@ -355,7 +403,8 @@ impl TestBlockWriter {
             &wal_writer,
             test_metrics(),
             committee,
-        );
+        )
+        .unwrap();
         let block_store = state.block_store;
         Self {
             block_store,
@@ -417,7 +466,9 @@ pub fn build_dag(
         None => {
             let (references, genesis): (Vec<_>, Vec<_>) = committee
                 .authorities()
-                .map(|index| StatementBlock::new_genesis(index))
+                .map(|index| {
+                    StatementBlock::new_genesis(index, committee.epoch(), committee.hash_function())
+                })
                 .map(|block| (*block.reference(), block))
                 .unzip();
             block_writer.add_blocks(genesis);
@@ -436,8 +487,10 @@ pub fn build_dag(
                     includes.clone(),
                     vec![],
                     0,
+                    committee.epoch(),
                     false,
                     Default::default(),
+                    committee.hash_function(),
                 ));
                 (*block.reference(), block)
             })
@@ -464,8 +517,10 @@ pub fn build_dag_layer(
             parents,
             vec![],
             0,
+            0,
             false,
             Default::default(),
+            HashFunction::default(),
         ));
 
         references.push(*block.reference());
@@ -9,12 +9,15 @@ pub struct Transaction {
 }
 
 pub type RoundNumber = u64;
+pub type Epoch = u64;
 pub type BlockDigest = crate::crypto::BlockDigest;
 pub type Stake = u64;
 pub type KeyPair = u64;
 pub type PublicKey = crate::crypto::PublicKey;
+pub type SignatureScheme = crate::crypto::SignatureScheme;
 
 use std::{
+    collections::{HashMap, HashSet},
     fmt,
     hash::{Hash, Hasher},
     ops::Range,
@@ -29,8 +32,9 @@ pub use test::Dag;
 
 use crate::{
     committee::{Committee, VoteRangeBuilder},
-    crypto::{AsBytes, CryptoHash, SignatureBytes, Signer},
+    crypto::{AsBytes, CryptoHash, HashFunction, SignatureBytes},
     data::Data,
+    reconfiguration::CommitteeChange,
     threshold_clock::threshold_clock_valid_non_genesis,
 };
 
@@ -67,6 +71,8 @@ pub enum BaseStatement {
     Vote(TransactionLocator, Vote),
     // For now only accept votes are batched
     VoteRange(TransactionLocatorRange),
+    /// Proposes a change to the committee of the next epoch.
+    Reconfigure(Box<CommitteeChange>),
 }
 
 impl Hash for BlockReference {
@@ -88,17 +94,27 @@ pub struct StatementBlock {
     // A list of base statements in order.
     statements: Vec<BaseStatement>,
 
-    // Creation time of the block as reported by creator, currently not enforced
+    // Creation time of the block as reported by creator. It is not earlier than the creation time
+    // of the included blocks, and not later than the clock of the receiver plus the allowed skew
     meta_creation_time_ns: TimestampNs,
 
+    // The epoch of the committee the block was created for
+    epoch: Epoch,
+
     epoch_marker: EpochStatus,
 
     // Signature by the block author
     signature: SignatureBytes,
 }
 
-#[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize, Default)]
-pub struct AuthoritySet(u128); // todo - support more then 128 authorities
+/// Bitset of authorities. The first 128 authorities are kept inline, so small committees
+/// never allocate; larger indices spill into `overflow`, one bit per authority.
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize, Default)]
+pub struct AuthoritySet {
+    inline: u128,
+    // Never has trailing zero words, so that equal sets compare equal
+    overflow: Vec<u64>,
+}
 
 pub type TimestampNs = u128;
 const NANOS_IN_SEC: u128 = Duration::from_secs(1).as_nanos();
@@ -118,54 +134,36 @@ impl Ord for BlockReference {
 }
 
 impl StatementBlock {
-    pub fn new_genesis(authority: AuthorityIndex) -> Data<Self> {
+    /// Each epoch starts a new dag from its own genesis blocks.
+    pub fn new_genesis(
+        authority: AuthorityIndex,
+        epoch: Epoch,
+        hash_function: HashFunction,
+    ) -> Data<Self> {
         Data::new(Self::new(
             authority,
             GENESIS_ROUND,
             vec![],
             vec![],
             0,
+            epoch,
             false,
             SignatureBytes::default(),
+            hash_function,
         ))
     }
 
-    pub fn new_with_signer(
-        authority: AuthorityIndex,
-        round: RoundNumber,
-        includes: Vec<BlockReference>,
-        statements: Vec<BaseStatement>,
-        meta_creation_time_ns: TimestampNs,
-        epoch_marker: EpochStatus,
-        signer: &Signer,
-    ) -> Self {
-        let signature = signer.sign_block(
-            authority,
-            round,
-            &includes,
-            &statements,
-            meta_creation_time_ns,
-            epoch_marker,
-        );
-        Self::new(
-            authority,
-            round,
-            includes,
-            statements,
-            meta_creation_time_ns,
-            epoch_marker,
-            signature,
-        )
-    }
-
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         authority: AuthorityIndex,
         round: RoundNumber,
         includes: Vec<BlockReference>,
         statements: Vec<BaseStatement>,
         meta_creation_time_ns: TimestampNs,
+        epoch: Epoch,
         epoch_marker: EpochStatus,
         signature: SignatureBytes,
+        hash_function: HashFunction,
     ) -> Self {
         Self {
             reference: BlockReference {
@@ -177,13 +175,16 @@ impl StatementBlock {
                     &includes,
                     &statements,
                     meta_creation_time_ns,
+                    epoch,
                     epoch_marker,
                     &signature,
+                    hash_function,
                 ),
             },
             includes,
             statements,
             meta_creation_time_ns,
+            epoch,
             epoch_marker,
             signature,
         }
@@ -260,6 +261,10 @@ impl StatementBlock {
         self.meta_creation_time_ns
     }
 
+    pub fn epoch(&self) -> Epoch {
+        self.epoch
+    }
+
     pub fn epoch_changed(&self) -> EpochStatus {
         self.epoch_marker
     }
@@ -271,7 +276,26 @@ impl StatementBlock {
         Duration::new(secs as u64, nanos as u32)
     }
 
-    pub fn verify(&self, committee: &Committee) -> eyre::Result<()> {
+    pub fn verify(&self, committee: &Committee, limits: &BlockLimits) -> eyre::Result<()> {
+        self.verify_without_signature(committee, limits)?;
+        self.verify_signature(committee)
+    }
+
+    /// Performs all checks of Self::verify except for the signature check,
+    /// which allows to verify signatures of several blocks in one batch.
+    pub fn verify_without_signature(
+        &self,
+        committee: &Committee,
+        limits: &BlockLimits,
+    ) -> Result<(), BlockVerificationError> {
+        // Checked first, before spending time on hashing a large block
+        let size = bincode::serialized_size(self).expect("Serialization should not fail") as usize;
+        if size > limits.max_block_size {
+            return Err(BlockVerificationError::Oversized {
+                size,
+                max_block_size: limits.max_block_size,
+            });
+        }
         let round = self.round();
         let digest = BlockDigest::new(
             self.author(),
@@ -279,59 +303,252 @@ impl StatementBlock {
             &self.includes,
             &self.statements,
             self.meta_creation_time_ns,
+            self.epoch,
             self.epoch_marker,
             &self.signature,
+            committee.hash_function(),
         );
-        ensure!(
-            digest == self.digest(),
-            "Digest does not match, calculated {:?}, provided {:?}",
-            digest,
-            self.digest()
-        );
-        let pub_key = committee.get_public_key(self.author());
-        let Some(pub_key) = pub_key else {
-            bail!("Unknown block author {}", self.author())
-        };
+        if digest != self.digest() {
+            return Err(BlockVerificationError::DigestMismatch {
+                calculated: digest,
+                provided: self.digest(),
+            });
+        }
+        if self.epoch != committee.epoch() {
+            return Err(BlockVerificationError::WrongEpoch {
+                block: self.epoch,
+                committee: committee.epoch(),
+            });
+        }
+        if !committee.known_authority(self.author()) {
+            return Err(BlockVerificationError::UnknownAuthor(self.author()));
+        }
         if round == GENESIS_ROUND {
-            bail!("Genesis block should not go through verification");
+            return Err(BlockVerificationError::Genesis);
         }
-        if let Err(e) = pub_key.verify_block(self) {
-            bail!("Block signature verification has failed: {:?}", e);
+        self.verify_includes(committee, limits)?;
+        self.verify_statements(committee)?;
+        if !threshold_clock_valid_non_genesis(self, committee) {
+            return Err(BlockVerificationError::ThresholdClock);
         }
+        Ok(())
+    }
+
+    fn verify_includes(
+        &self,
+        committee: &Committee,
+        limits: &BlockLimits,
+    ) -> Result<(), BlockVerificationError> {
+        let round = self.round();
+        let mut includes = HashSet::with_capacity(self.includes.len());
+        let mut includes_own_block = false;
         for include in &self.includes {
-            // Also check duplicate includes?
-            ensure!(
-                committee.known_authority(include.authority),
-                "Include {:?} references unknown authority",
-                include
-            );
-            ensure!(
-                include.round < round,
-                "Include {:?} round is greater or equal to own round {}",
-                include,
-                round
-            );
+            if !committee.known_authority(include.authority) {
+                return Err(BlockVerificationError::UnknownIncludeAuthority(*include));
+            }
+            if include.round >= round {
+                return Err(BlockVerificationError::IncludeRoundNotLower(*include));
+            }
+            if !includes.insert(include) {
+                return Err(BlockVerificationError::DuplicateInclude(*include));
+            }
+            // The own previous block may be arbitrarily old, after the author was offline
+            if include.authority == self.author() {
+                includes_own_block = true;
+            } else if round - include.round > limits.max_include_round_gap {
+                return Err(BlockVerificationError::IncludeRoundGap {
+                    include: *include,
+                    max_include_round_gap: limits.max_include_round_gap,
+                });
+            }
+        }
+        if !includes_own_block {
+            return Err(BlockVerificationError::MissingOwnPreviousBlock);
         }
+        Ok(())
+    }
+
+    fn verify_statements(&self, committee: &Committee) -> Result<(), BlockVerificationError> {
+        // Only votes must be unique. Clients may submit the same transaction twice, and committee
+        // changes are applied in order, so repeating one has no effect.
+        let mut votes: HashMap<BlockReference, Vec<Range<u64>>> = HashMap::new();
         for statement in &self.statements {
-            // Also check duplicate statements?
             match statement {
                 BaseStatement::Share(_) => {}
-                BaseStatement::Vote(_, _) => {}
-                BaseStatement::VoteRange(range) => range.verify()?,
+                BaseStatement::Vote(locator, _) => votes
+                    .entry(locator.block)
+                    .or_default()
+                    .push(locator.offset..locator.offset + 1),
+                BaseStatement::VoteRange(range) => {
+                    range
+                        .verify()
+                        .map_err(|e| BlockVerificationError::InvalidStatement(e.to_string()))?;
+                    votes.entry(range.block).or_default().push(range.range());
+                }
+                BaseStatement::Reconfigure(change) => {
+                    if change.epoch != self.epoch {
+                        return Err(BlockVerificationError::InvalidStatement(format!(
+                            "committee change of epoch {} in a block of epoch {}",
+                            change.epoch, self.epoch
+                        )));
+                    }
+                    change
+                        .verify(committee)
+                        .map_err(|e| BlockVerificationError::InvalidStatement(e.to_string()))?;
+                }
+            }
+        }
+        // Ranges are bounded but may be large, compare them rather than the votes they contain
+        for (block, mut ranges) in votes {
+            ranges.retain(|range| !range.is_empty());
+            ranges.sort_by_key(|range| range.start);
+            for pair in ranges.windows(2) {
+                if pair[1].start < pair[0].end {
+                    let locator = TransactionLocator::new(block, pair[1].start);
+                    return Err(BlockVerificationError::DuplicateVote(locator));
+                }
             }
         }
+        Ok(())
+    }
+
+    /// Checks that the block was not created before any of the blocks it includes. The includes
+    /// are not known to `Self::verify`, this is checked once they are all received.
+    pub fn verify_creation_time_after<'a>(
+        &self,
+        includes: impl IntoIterator<Item = &'a StatementBlock>,
+    ) -> eyre::Result<()> {
+        for include in includes {
+            ensure!(
+                self.meta_creation_time_ns >= include.meta_creation_time_ns,
+                "Block created at {:?}, before its include {} created at {:?}",
+                self.meta_creation_time(),
+                include.reference,
+                include.meta_creation_time()
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks that the block was not created further in the future than the allowed clock skew.
+    pub fn verify_creation_time_skew(&self, now: Duration, max_skew: Duration) -> eyre::Result<()> {
         ensure!(
-            threshold_clock_valid_non_genesis(self, committee),
-            "Threshold clock is not valid"
+            self.meta_creation_time() <= now + max_skew,
+            "Block created at {:?}, more than {:?} ahead of our clock at {:?}",
+            self.meta_creation_time(),
+            max_skew,
+            now
         );
         Ok(())
     }
 
+    pub fn verify_signature(&self, committee: &Committee) -> eyre::Result<()> {
+        let Some(pub_key) = committee.get_public_key(self.author()) else {
+            bail!("Unknown block author {}", self.author())
+        };
+        if let Err(e) = pub_key.verify_block(self, committee.hash_function()) {
+            bail!("Block signature verification has failed: {:?}", e);
+        }
+        Ok(())
+    }
+
     pub fn detailed(&self) -> Detailed {
         Detailed(self)
     }
 }
 
+/// Limits on the shape of the blocks accepted from other authorities.
+#[derive(Clone, Copy, Debug)]
+pub struct BlockLimits {
+    /// The maximum size of a serialized block.
+    pub max_block_size: usize,
+    /// How many rounds below its own round a block may include blocks of other authorities.
+    pub max_include_round_gap: RoundNumber,
+}
+
+/// Why a block failed verification.
+#[derive(Debug)]
+pub enum BlockVerificationError {
+    Oversized {
+        size: usize,
+        max_block_size: usize,
+    },
+    DigestMismatch {
+        calculated: BlockDigest,
+        provided: BlockDigest,
+    },
+    WrongEpoch {
+        block: Epoch,
+        committee: Epoch,
+    },
+    UnknownAuthor(AuthorityIndex),
+    /// Genesis blocks are known to all authorities, they are never received.
+    Genesis,
+    UnknownIncludeAuthority(BlockReference),
+    IncludeRoundNotLower(BlockReference),
+    DuplicateInclude(BlockReference),
+    IncludeRoundGap {
+        include: BlockReference,
+        max_include_round_gap: RoundNumber,
+    },
+    /// The block does not include an earlier block of its author.
+    MissingOwnPreviousBlock,
+    /// A transaction is voted on twice, directly or as part of a range.
+    DuplicateVote(TransactionLocator),
+    InvalidStatement(String),
+    /// The block does not include blocks from a quorum of the previous round.
+    ThresholdClock,
+}
+
+impl fmt::Display for BlockVerificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Oversized {
+                size,
+                max_block_size,
+            } => write!(f, "block of {size} bytes exceeds {max_block_size} bytes"),
+            Self::DigestMismatch {
+                calculated,
+                provided,
+            } => write!(
+                f,
+                "digest does not match, calculated {calculated:?}, provided {provided:?}"
+            ),
+            Self::WrongEpoch { block, committee } => write!(
+                f,
+                "block of epoch {block} does not belong to epoch {committee}"
+            ),
+            Self::UnknownAuthor(author) => write!(f, "unknown block author {author}"),
+            Self::Genesis => write!(f, "genesis block should not go through verification"),
+            Self::UnknownIncludeAuthority(include) => {
+                write!(f, "include {include} references unknown authority")
+            }
+            Self::IncludeRoundNotLower(include) => {
+                write!(
+                    f,
+                    "include {include} round is greater or equal to own round"
+                )
+            }
+            Self::DuplicateInclude(include) => write!(f, "include {include} is repeated"),
+            Self::IncludeRoundGap {
+                include,
+                max_include_round_gap,
+            } => write!(
+                f,
+                "include {include} is more than {max_include_round_gap} rounds older than the block"
+            ),
+            Self::MissingOwnPreviousBlock => {
+                write!(f, "block does not include a previous block of its author")
+            }
+            Self::DuplicateVote(locator) => write!(f, "transaction {locator} is voted on twice"),
+            Self::InvalidStatement(reason) => write!(f, "invalid statement: {reason}"),
+            Self::ThresholdClock => write!(f, "threshold clock is not valid"),
+        }
+    }
+}
+
+impl std::error::Error for BlockVerificationError {}
+
 #[derive(Clone, Copy, Ord, PartialOrd, Eq, PartialEq, Hash, Serialize, Deserialize, Default)]
 pub struct TransactionLocator {
     block: BlockReference,
@@ -420,7 +637,7 @@ impl BlockReference {
     #[cfg(test)]
     pub fn new_test(authority: AuthorityIndex, round: RoundNumber) -> Self {
         if round == 0 {
-            StatementBlock::new_genesis(authority).reference
+            StatementBlock::new_genesis(authority, 0, HashFunction::default()).reference
         } else {
             Self {
                 authority,
@@ -451,42 +668,75 @@ impl fmt::Debug for BlockReference {
 
 impl fmt::Display for BlockReference {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.authority < 26 {
-            write!(
-                f,
-                "{}{}",
-                format_authority_index(self.authority),
-                self.round
-            )
-        } else {
-            write!(f, "[{:02}]{}", self.authority, self.round)
-        }
+        write!(
+            f,
+            "{}{}",
+            format_authority_index(self.authority),
+            self.round
+        )
     }
 }
 
 impl AuthoritySet {
+    const INLINE_BITS: AuthorityIndex = u128::BITS as AuthorityIndex;
+    const WORD_BITS: AuthorityIndex = u64::BITS as AuthorityIndex;
+
     #[inline]
     pub fn insert(&mut self, v: AuthorityIndex) -> bool {
-        let bit = 1u128 << v;
-        if self.0 & bit == bit {
+        if v < Self::INLINE_BITS {
+            let bit = 1u128 << v;
+            if self.inline & bit == bit {
+                return false;
+            }
+            self.inline |= bit;
+            return true;
+        }
+        let v = v - Self::INLINE_BITS;
+        let word = (v / Self::WORD_BITS) as usize;
+        let bit = 1u64 << (v % Self::WORD_BITS);
+        if word >= self.overflow.len() {
+            self.overflow.resize(word + 1, 0);
+        }
+        if self.overflow[word] & bit == bit {
             return false;
         }
-        self.0 |= bit;
+        self.overflow[word] |= bit;
         true
     }
 
+    pub fn contains(&self, v: AuthorityIndex) -> bool {
+        if v < Self::INLINE_BITS {
+            return self.inline & (1u128 << v) != 0;
+        }
+        let v = v - Self::INLINE_BITS;
+        self.overflow
+            .get((v / Self::WORD_BITS) as usize)
+            .is_some_and(|word| word & (1u64 << (v % Self::WORD_BITS)) != 0)
+    }
+
     pub fn present(&self) -> impl Iterator<Item = AuthorityIndex> + '_ {
-        (0..128).filter(|bit| (self.0 & 1 << bit) != 0)
+        let inline = (0..Self::INLINE_BITS).filter(|bit| (self.inline & 1 << bit) != 0);
+        let overflow = self.overflow.iter().enumerate().flat_map(|(word, bits)| {
+            (0..Self::WORD_BITS)
+                .filter(move |bit| bits & (1u64 << bit) != 0)
+                .map(move |bit| Self::INLINE_BITS + word as AuthorityIndex * Self::WORD_BITS + bit)
+        });
+        inline.chain(overflow)
     }
 
     #[inline]
     pub fn clear(&mut self) {
-        self.0 = 0;
+        self.inline = 0;
+        self.overflow.clear();
     }
 }
 
-pub fn format_authority_index(i: AuthorityIndex) -> char {
-    ('A' as u64 + i) as u8 as char
+pub fn format_authority_index(i: AuthorityIndex) -> String {
+    if i < 26 {
+        char::from(b'A' + i as u8).to_string()
+    } else {
+        format!("[{i:02}]")
+    }
 }
 
 pub fn format_authority_round(i: AuthorityIndex, r: RoundNumber) -> String {
@@ -609,6 +859,7 @@ impl fmt::Display for BaseStatement {
                 "+{}:{}:{}",
                 range.block, range.offset_start_inclusive, range.offset_end_exclusive
             ),
+            BaseStatement::Reconfigure(change) => write!(f, "reconfigure@{}", change.epoch),
         }
     }
 }
@@ -645,9 +896,28 @@ mod test {
     use rand::{prelude::SliceRandom, Rng};
 
     use super::*;
+    use crate::config::NodeParameters;
 
     pub struct Dag(HashMap<BlockReference, Data<StatementBlock>>);
 
+    impl StatementBlock {
+        pub fn with_test_digest(mut self, byte: u8) -> Self {
+            self.reference.digest = BlockDigest::new_test(byte);
+            self
+        }
+
+        /// Sets the digest that BlockDigest::new would compute outside of tests.
+        pub fn with_computed_digest(mut self, hash_function: HashFunction) -> Self {
+            self.reference.digest = BlockDigest::of_block(&self, hash_function);
+            self
+        }
+
+        pub fn with_creation_time(mut self, meta_creation_time_ns: TimestampNs) -> Self {
+            self.meta_creation_time_ns = meta_creation_time_ns;
+            self
+        }
+    }
+
     #[cfg(test)]
     impl Dag {
         /// Takes a string in form "Block:[Dependencies, ...]; ..."
@@ -683,6 +953,7 @@ mod test {
                 includes,
                 statements: vec![],
                 meta_creation_time_ns: 0,
+                epoch: 0,
                 epoch_marker: false,
                 signature: Default::default(),
             }
@@ -703,7 +974,7 @@ mod test {
         /// For each authority add a 0 round block if not present
         pub fn add_genesis_blocks(mut self) -> Self {
             for authority in self.authorities() {
-                let block = StatementBlock::new_genesis(authority);
+                let block = StatementBlock::new_genesis(authority, 0, HashFunction::default());
                 let entry = self.0.entry(*block.reference());
                 entry.or_insert_with(move || block);
             }
@@ -792,4 +1063,184 @@ mod test {
         }
         assert_eq!(present, a.present().collect::<Vec<_>>());
     }
+
+    #[test]
+    fn authority_set_large_test() {
+        let mut a = AuthoritySet::default();
+        let present = vec![0, 127, 128, 191, 192, 500, 999];
+        for x in &present {
+            assert!(a.insert(*x));
+            assert!(!a.insert(*x));
+        }
+        assert_eq!(present, a.present().collect::<Vec<_>>());
+        assert!(a.contains(999));
+        assert!(!a.contains(998));
+        assert!(!a.contains(5000));
+
+        let mut b = AuthoritySet::default();
+        for x in present.iter().rev() {
+            b.insert(*x);
+        }
+        assert!(a == b);
+        a.clear();
+        assert!(a == AuthoritySet::default());
+        assert_eq!(a.present().count(), 0);
+    }
+
+    #[test]
+    fn verify_large_committee_block() {
+        let committee = Committee::new_test(vec![1; 1000]);
+        let includes = committee
+            .authorities()
+            .map(|authority| BlockReference::new_test(authority, 1))
+            .collect();
+        let block = StatementBlock::new(
+            999,
+            2,
+            includes,
+            vec![],
+            0,
+            committee.epoch(),
+            false,
+            Default::default(),
+            committee.hash_function(),
+        );
+        block
+            .verify(&committee, &NodeParameters::default().block_limits())
+            .unwrap();
+    }
+
+    /// Blocks a Byzantine authority may send, each breaking one rule of `verify`.
+    #[test]
+    fn malformed_blocks() {
+        use BlockVerificationError::*;
+        type Expected = fn(&BlockVerificationError) -> bool;
+
+        let committee = Committee::new_test(vec![1; 4]);
+        let limits = BlockLimits {
+            max_block_size: 16 * 1024,
+            max_include_round_gap: 10,
+        };
+        let block = |authority, round, includes: &[(AuthorityIndex, RoundNumber)], statements| {
+            let includes = includes
+                .iter()
+                .map(|(authority, round)| BlockReference::new_test(*authority, *round))
+                .collect();
+            StatementBlock::new(
+                authority,
+                round,
+                includes,
+                statements,
+                0,
+                committee.epoch(),
+                false,
+                Default::default(),
+                committee.hash_function(),
+            )
+        };
+        let parents = [(0, 19), (1, 19), (2, 19)];
+        let locator = TransactionLocator::new(BlockReference::new_test(1, 19), 3);
+        let vote = BaseStatement::Vote(locator, Vote::Accept);
+
+        let valid = block(0, 20, &parents, vec![vote.clone()]);
+        valid.verify_without_signature(&committee, &limits).unwrap();
+        // The own previous block may be older than the allowed gap
+        let returning = block(0, 20, &[(0, 2), (1, 19), (2, 19), (3, 19)], vec![]);
+        returning
+            .verify_without_signature(&committee, &limits)
+            .unwrap();
+
+        let large = BaseStatement::Share(Transaction::new(vec![0; 16 * 1024]));
+        let vote_range =
+            |range| BaseStatement::VoteRange(TransactionLocatorRange::new(*locator.block(), range));
+        let corpus: Vec<(StatementBlock, Expected)> = vec![
+            (block(0, 20, &parents, vec![large]), |e| {
+                matches!(e, Oversized { .. })
+            }),
+            (valid.clone().with_test_digest(1), |e| {
+                matches!(e, DigestMismatch { .. })
+            }),
+            (
+                StatementBlock {
+                    epoch: 1,
+                    ..valid.clone()
+                },
+                |e| matches!(e, WrongEpoch { .. }),
+            ),
+            (block(4, 20, &parents, vec![]), |e| {
+                matches!(e, UnknownAuthor(4))
+            }),
+            (block(0, 0, &[], vec![]), |e| matches!(e, Genesis)),
+            (
+                block(0, 20, &[(0, 19), (1, 19), (2, 19), (5, 19)], vec![]),
+                |e| matches!(e, UnknownIncludeAuthority(..)),
+            ),
+            (block(0, 20, &[(0, 19), (1, 19), (2, 20)], vec![]), |e| {
+                matches!(e, IncludeRoundNotLower(..))
+            }),
+            (
+                block(0, 20, &[(0, 19), (1, 19), (2, 19), (1, 19)], vec![]),
+                |e| matches!(e, DuplicateInclude(..)),
+            ),
+            (
+                block(0, 20, &[(0, 19), (1, 19), (2, 19), (3, 9)], vec![]),
+                |e| matches!(e, IncludeRoundGap { .. }),
+            ),
+            (block(0, 20, &[(1, 19), (2, 19), (3, 19)], vec![]), |e| {
+                matches!(e, MissingOwnPreviousBlock)
+            }),
+            (
+                block(0, 20, &parents, vec![vote.clone(), vote.clone()]),
+                |e| matches!(e, DuplicateVote(..)),
+            ),
+            (
+                block(0, 20, &parents, vec![vote_range(0..2), vote_range(1..4)]),
+                |e| matches!(e, DuplicateVote(..)),
+            ),
+            (block(0, 20, &parents, vec![vote_range(0..4), vote]), |e| {
+                matches!(e, DuplicateVote(..))
+            }),
+            (
+                block(
+                    0,
+                    20,
+                    &parents,
+                    vec![vote_range(Range { start: 2, end: 1 })],
+                ),
+                |e| matches!(e, InvalidStatement(..)),
+            ),
+            (block(0, 20, &[(0, 19), (1, 19), (2, 18)], vec![]), |e| {
+                matches!(e, ThresholdClock)
+            }),
+        ];
+        for (block, expected) in corpus {
+            let error = block
+                .verify_without_signature(&committee, &limits)
+                .expect_err(&format!("{block} should be rejected"));
+            assert!(expected(&error), "{block} rejected with: {error}");
+        }
+
+        // Adjacent vote ranges do not overlap
+        block(0, 20, &parents, vec![vote_range(0..3), vote_range(3..5)])
+            .verify_without_signature(&committee, &limits)
+            .unwrap();
+    }
+
+    #[test]
+    fn verify_creation_time() {
+        let a1 = Dag::draw_block("A1:[A0, B0]").with_creation_time(1_000);
+        let b1 = Dag::draw_block("B1:[A0, B0]").with_creation_time(3_000);
+        let a2 = Dag::draw_block("A2:[A1, B1]").with_creation_time(2_000);
+        a2.verify_creation_time_after([&a1]).unwrap();
+        assert!(a2.verify_creation_time_after([&a1, &b1]).is_err());
+        a2.clone()
+            .with_creation_time(3_000)
+            .verify_creation_time_after([&a1, &b1])
+            .unwrap();
+
+        let now = Duration::from_nanos(1_000);
+        let skew = Duration::from_nanos(1_000);
+        a2.verify_creation_time_skew(now, skew).unwrap();
+        assert!(b1.verify_creation_time_skew(now, skew).is_err());
+    }
 }
@@ -2,43 +2,201 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::{
+    fs,
     net::{IpAddr, Ipv4Addr},
     sync::Arc,
 };
 
 use ::prometheus::Registry;
-use eyre::{eyre, Context, Result};
+use eyre::{ensure, eyre, Context, Result};
+use tokio::{
+    select,
+    sync::{mpsc, oneshot},
+};
 
 use crate::{
     block_handler::{RealBlockHandler, TestCommitHandler},
-    block_store::BlockStore,
+    block_signer::{BlockSigner, GuardedSigner},
+    block_store::{BlockStore, WAL_COMPRESSED_ENTRIES},
     committee::Committee,
-    config::{ClientParameters, NodePrivateConfig, NodePublicConfig},
+    config::{ClientParameters, ImportExport, NodePrivateConfig, NodePublicConfig},
     core::{Core, CoreOptions},
+    genesis,
     log::TransactionLog,
     metrics::Metrics,
     net_sync::NetworkSyncer,
     network::Network,
-    prometheus,
-    runtime::{JoinError, JoinHandle},
+    prometheus::{self, CommitteeChangeSender},
+    reconfiguration::CommitteeChange,
+    runtime::{Handle, JoinError, JoinHandle},
+    sign_guard::SignGuard,
+    snapshot::Snapshots,
     transactions_generator::TransactionGenerator,
-    types::AuthorityIndex,
-    wal::{self, walf},
+    types::{AuthorityIndex, Epoch},
+    wal,
 };
 
+/// A validator runs one epoch after the other, until it is stopped or leaves the committee.
 pub struct Validator {
-    network_synchronizer: NetworkSyncer<RealBlockHandler, TestCommitHandler<TransactionLog>>,
-    metrics_handle: JoinHandle<Result<(), hyper::Error>>,
+    committee_changes: CommitteeChangeSender,
+    stop: oneshot::Sender<()>,
+    epochs: JoinHandle<Result<()>>,
 }
 
 impl Validator {
+    /// Starts the validator holding the private key of the private config. Its authority index
+    /// is looked up in the committee. A validator that already went through epoch changes
+    /// resumes from the last epoch in its storage.
     pub async fn start(
-        authority: AuthorityIndex,
         committee: Arc<Committee>,
         public_config: NodePublicConfig,
+        mut private_config: NodePrivateConfig,
+        client_parameters: ClientParameters,
+    ) -> Result<Self> {
+        let signer = private_config
+            .block_signer()
+            .wrap_err("Private key is not available")?;
+        let public_key = signer.public_key();
+        ensure!(
+            public_key.scheme() == committee.signature_scheme(),
+            "Private key uses {} while the committee uses {}",
+            public_key.scheme(),
+            committee.signature_scheme()
+        );
+        let (committee, public_config) = latest_epoch(&private_config, committee, public_config)?;
+        let sign_guard = SignGuard::open(private_config.sign_guard())
+            .wrap_err("Failed to open the sign guard")?;
+        let signer: Arc<dyn BlockSigner> = Arc::new(GuardedSigner::new(signer, sign_guard));
+
+        let (change_sender, change_receiver) = mpsc::channel(16);
+        let epoch = RunningEpoch::start(
+            committee,
+            public_config,
+            &private_config,
+            &client_parameters,
+            signer,
+            change_sender.clone(),
+        )
+        .await?;
+        let (stop_sender, stop_receiver) = oneshot::channel();
+        let epochs = Handle::current().spawn(Self::run(
+            epoch,
+            private_config,
+            client_parameters,
+            change_sender.clone(),
+            change_receiver,
+            stop_receiver,
+        ));
+        Ok(Self {
+            committee_changes: change_sender,
+            stop: stop_sender,
+            epochs,
+        })
+    }
+
+    async fn run(
+        mut epoch: RunningEpoch,
         private_config: NodePrivateConfig,
         client_parameters: ClientParameters,
+        change_sender: CommitteeChangeSender,
+        mut change_receiver: mpsc::Receiver<(CommitteeChange, oneshot::Sender<Result<()>>)>,
+        mut stop: oneshot::Receiver<()>,
+    ) -> Result<()> {
+        loop {
+            let stopped = select! {
+                _stopped = &mut stop => true,
+                Some((change, reply)) = change_receiver.recv() => {
+                    let result = epoch.network_synchronizer.submit_committee_change(change).await;
+                    reply.send(result).ok();
+                    continue;
+                }
+                _closed = epoch.network_synchronizer.epoch_closed() => false,
+            };
+            if stopped {
+                epoch.close().await;
+                return Ok(());
+            }
+
+            let authority = epoch.authority;
+            let signer = epoch.signer.clone();
+            let (core, public_config) = epoch.close().await;
+            let (committee, public_config) = core
+                .next_epoch(&public_config)
+                .expect("The epoch is closed");
+            drop(core);
+            persist_epoch(&private_config, &committee, &public_config)?;
+            tracing::info!(
+                "Validator {authority} closed epoch {}, the next committee has {} members",
+                committee.epoch() - 1,
+                committee.len()
+            );
+            if committee.authority_index(&signer.public_key()).is_none() {
+                tracing::info!("Validator {authority} left the committee");
+                return Ok(());
+            }
+            epoch = RunningEpoch::start(
+                committee,
+                public_config,
+                &private_config,
+                &client_parameters,
+                signer,
+                change_sender.clone(),
+            )
+            .await?;
+        }
+    }
+
+    /// Proposes a committee change in the blocks of the current epoch.
+    pub async fn submit_committee_change(&self, change: CommitteeChange) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        self.committee_changes
+            .send((change, sender))
+            .await
+            .map_err(|_| eyre!("Validator stopped"))?;
+        receiver.await.map_err(|_| eyre!("Validator stopped"))?
+    }
+
+    /// Completes once the validator left the committee or failed to start an epoch.
+    pub async fn await_completion(self) -> Result<Result<()>, JoinError> {
+        let Self { stop, epochs, .. } = self;
+        let result = epochs.await;
+        drop(stop);
+        result
+    }
+
+    pub async fn stop(self) {
+        self.stop.send(()).ok();
+        self.epochs.await.ok();
+    }
+}
+
+/// The components of a validator running a single epoch.
+struct RunningEpoch {
+    authority: AuthorityIndex,
+    // Shared by the core and the network, and carried over to the next epoch
+    signer: Arc<dyn BlockSigner>,
+    public_config: NodePublicConfig,
+    network_synchronizer: NetworkSyncer<RealBlockHandler, TestCommitHandler<TransactionLog>>,
+    metrics_handle: JoinHandle<Result<(), hyper::Error>>,
+    reporter_handle: JoinHandle<()>,
+}
+
+impl RunningEpoch {
+    async fn start(
+        committee: Arc<Committee>,
+        public_config: NodePublicConfig,
+        private_config: &NodePrivateConfig,
+        client_parameters: &ClientParameters,
+        signer: Arc<dyn BlockSigner>,
+        committee_changes: CommitteeChangeSender,
     ) -> Result<Self> {
+        let epoch = committee.epoch();
+        let authority =
+            genesis::find_own_authority(&committee, &public_config, &signer.public_key())
+                .wrap_err(format!(
+                    "Committee files of epoch {epoch} do not match our private key"
+                ))?;
+
         let network_address = public_config
             .network_address(authority)
             .ok_or(eyre!("No network address for authority {authority}"))
@@ -53,31 +211,40 @@ impl Validator {
         let mut binding_metrics_address = metrics_address;
         binding_metrics_address.set_ip(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
 
-        // Boot the prometheus server.
         let registry = Registry::new();
         let (metrics, reporter) = Metrics::new(&registry, Some(&committee));
-        reporter.start();
-
-        let metrics_handle =
-            prometheus::start_prometheus_server(binding_metrics_address, &registry);
+        let reporter_handle = reporter.start();
 
         // Open the block store.
-        let wal_file =
-            wal::open_file_for_wal(private_config.wal()).expect("Failed to open wal file");
-        let (wal_writer, wal_reader) = walf(wal_file).expect("Failed to open wal");
-        let recovered = BlockStore::open(
+        fs::create_dir_all(private_config.epoch_storage_path(epoch))
+            .wrap_err("Failed to create the storage of the epoch")?;
+        let (mut wal_writer, wal_reader) =
+            wal::segmented_wal(private_config.wal(epoch)).expect("Failed to open wal");
+        if public_config.parameters.wal_compression {
+            for tag in WAL_COMPRESSED_ENTRIES {
+                wal_writer.compress_tag(tag);
+            }
+        }
+        let snapshots = Snapshots::open(private_config.snapshots(epoch))
+            .wrap_err("Failed to open the wal snapshots")?;
+        let snapshot = snapshots
+            .latest(&wal_writer)
+            .wrap_err("Failed to read the wal snapshots")?;
+        let recovered = BlockStore::open_from_snapshot(
             authority,
             Arc::new(wal_reader),
             &wal_writer,
             metrics.clone(),
             &committee,
-        );
+            snapshot,
+        )
+        .wrap_err("Failed to recover from the wal")?;
 
         // Boot the validator node.
         let (block_handler, block_sender) = RealBlockHandler::new(
             committee.clone(),
             authority,
-            &private_config.certified_transactions_log(),
+            &private_config.certified_transactions_log(epoch),
             recovered.block_store.clone(),
             metrics.clone(),
             public_config.parameters.consensus_only,
@@ -86,12 +253,12 @@ impl Validator {
         TransactionGenerator::start(
             block_sender,
             authority,
-            client_parameters,
+            client_parameters.clone(),
             public_config.clone(),
             metrics.clone(),
         );
         let committed_transaction_log =
-            TransactionLog::start(private_config.committed_transactions_log())
+            TransactionLog::start(private_config.committed_transactions_log(epoch))
                 .expect("Failed to open committed transaction log for write");
         let commit_handler = TestCommitHandler::new_with_handler(
             committee.clone(),
@@ -103,16 +270,28 @@ impl Validator {
             block_handler,
             authority,
             committee.clone(),
-            private_config,
+            Box::new(signer.clone()),
             &public_config,
             metrics.clone(),
             recovered,
             wal_writer,
-            CoreOptions::default(),
+            CoreOptions::production(),
+        )
+        .with_snapshots(snapshots);
+
+        // Boot the prometheus server.
+        let metrics_handle = prometheus::start_prometheus_server(
+            binding_metrics_address,
+            &registry,
+            core.equivocation_proofs(),
+            committee_changes,
+            authority,
         );
         let network = Network::load(
             &public_config,
             authority,
+            signer.clone(),
+            epoch,
             binding_network_address,
             metrics.clone(),
         )
@@ -120,39 +299,80 @@ impl Validator {
         let network_synchronizer = NetworkSyncer::start(
             network,
             core,
-            public_config.parameters.wave_length,
             commit_handler,
             public_config.parameters.shutdown_grace_period,
             metrics,
             &public_config,
         );
 
+        tracing::info!("Validator {authority} running epoch {epoch}");
         tracing::info!("Validator {authority} listening on {network_address}");
         tracing::info!("Validator {authority} exposing metrics on {metrics_address}");
 
         Ok(Self {
+            authority,
+            signer,
+            public_config,
             network_synchronizer,
             metrics_handle,
+            reporter_handle,
         })
     }
 
-    pub async fn await_completion(
-        self,
-    ) -> (
-        Result<(), JoinError>,
-        Result<Result<(), hyper::Error>, JoinError>,
-    ) {
-        tokio::join!(
-            self.network_synchronizer.await_completion(),
-            self.metrics_handle
-        )
+    /// Stops all the components of the epoch and frees their addresses.
+    async fn close(self) -> (Core<RealBlockHandler>, NodePublicConfig) {
+        let core = self.network_synchronizer.shutdown().await.into_core();
+        self.reporter_handle.abort();
+        self.metrics_handle.abort();
+        self.metrics_handle.await.ok();
+        (core, self.public_config)
     }
+}
 
-    pub async fn stop(self) {
-        self.network_synchronizer.shutdown().await;
+/// Follows the committee files of the epochs this validator already reached.
+fn latest_epoch(
+    private_config: &NodePrivateConfig,
+    mut committee: Arc<Committee>,
+    mut public_config: NodePublicConfig,
+) -> Result<(Arc<Committee>, NodePublicConfig)> {
+    loop {
+        let next: Epoch = committee.epoch() + 1;
+        let path = private_config.epoch_storage_path(next);
+        let committee_path = path.join(Committee::DEFAULT_FILENAME);
+        if !committee_path.exists() {
+            return Ok((committee, public_config));
+        }
+        committee = Arc::new(Committee::load(&committee_path).wrap_err(format!(
+            "Failed to load committee file '{}'",
+            committee_path.display()
+        ))?);
+        let public_config_path = path.join(NodePublicConfig::DEFAULT_FILENAME);
+        public_config = NodePublicConfig::load(&public_config_path).wrap_err(format!(
+            "Failed to load parameters file '{}'",
+            public_config_path.display()
+        ))?;
     }
 }
 
+/// Writes the committee files of the next epoch before running it, so that a restarted validator
+/// resumes from there. Joining validators start from a copy of these files.
+fn persist_epoch(
+    private_config: &NodePrivateConfig,
+    committee: &Committee,
+    public_config: &NodePublicConfig,
+) -> Result<()> {
+    let path = private_config.epoch_storage_path(committee.epoch());
+    fs::create_dir_all(&path)
+        .wrap_err(format!("Failed to create directory '{}'", path.display()))?;
+    committee
+        .print(path.join(Committee::DEFAULT_FILENAME))
+        .wrap_err("Failed to print committee file")?;
+    public_config
+        .print(path.join(NodePublicConfig::DEFAULT_FILENAME))
+        .wrap_err("Failed to print parameters file")?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod smoke_tests {
     use std::{collections::VecDeque, fs, net::SocketAddr, time::Duration};
@@ -163,9 +383,11 @@ mod smoke_tests {
     use super::Validator;
     use crate::{
         committee::Committee,
-        config::{self, ClientParameters, NodePrivateConfig, NodePublicConfig},
+        config::{self, ClientParameters, ImportExport, NodePrivateConfig, NodePublicConfig},
+        crypto::Signer,
         prometheus,
-        types::AuthorityIndex,
+        reconfiguration::{CommitteeChange, CommitteeChangeKind},
+        types::Epoch,
     };
 
     /// Check whether the validator specified by its metrics address has committed at least once.
@@ -177,6 +399,32 @@ mod smoke_tests {
         Ok(commit)
     }
 
+    /// Check whether the validator specified by its metrics address committed in the given epoch.
+    async fn check_epoch_commit(
+        address: &SocketAddr,
+        epoch: Epoch,
+    ) -> Result<bool, reqwest::Error> {
+        let route = prometheus::METRICS_ROUTE;
+        let res = reqwest::get(format! {"http://{address}{route}"}).await?;
+        let string = res.text().await?;
+        let commit = string.contains("committed_leaders_total")
+            && string.lines().any(|line| line == format!("epoch {epoch}"));
+        Ok(commit)
+    }
+
+    /// Await for all the validators specified by their metrics addresses to commit in the given
+    /// epoch.
+    async fn await_for_epoch_commits(addresses: Vec<SocketAddr>, epoch: Epoch) {
+        let mut queue = VecDeque::from(addresses);
+        while let Some(address) = queue.pop_front() {
+            time::sleep(Duration::from_millis(100)).await;
+            match check_epoch_commit(&address, epoch).await {
+                Ok(commits) if commits => (),
+                _ => queue.push_back(address),
+            }
+        }
+    }
+
     /// Await for all the validators specified by their metrics addresses to commit.
     async fn await_for_commits(addresses: Vec<SocketAddr>) {
         let mut queue = VecDeque::from(addresses);
@@ -204,11 +452,8 @@ mod smoke_tests {
             fs::create_dir_all(&private_config.storage_path).unwrap();
         });
 
-        for (i, private_config) in private_configs.into_iter().enumerate() {
-            let authority = i as AuthorityIndex;
-
+        for private_config in private_configs {
             let validator = Validator::start(
-                authority,
                 committee.clone(),
                 public_config.clone(),
                 private_config,
@@ -251,9 +496,7 @@ mod smoke_tests {
             if i == 0 {
                 continue;
             }
-            let authority = i as AuthorityIndex;
             let validator = Validator::start(
-                authority,
                 committee.clone(),
                 public_config.clone(),
                 private_config,
@@ -277,11 +520,9 @@ mod smoke_tests {
         }
 
         // Boot the last validator.
-        let authority = 0;
         let private_config =
-            NodePrivateConfig::new_for_benchmarks(dir.as_ref(), committee_size).remove(authority);
+            NodePrivateConfig::new_for_benchmarks(dir.as_ref(), committee_size).remove(0);
         let validator = Validator::start(
-            authority as AuthorityIndex,
             committee.clone(),
             public_config.clone(),
             private_config,
@@ -324,9 +565,7 @@ mod smoke_tests {
                 continue;
             }
 
-            let authority = i as AuthorityIndex;
             let validator = Validator::start(
-                authority,
                 committee.clone(),
                 public_config.clone(),
                 private_config,
@@ -349,4 +588,74 @@ mod smoke_tests {
             _ = time::sleep(timeout) => panic!("Failed to gather commits within a few timeouts"),
         }
     }
+
+    /// Ensure that validators move on to the next epoch, without the validator that left.
+    #[tokio::test]
+    async fn validator_epoch_change() {
+        let committee_size = 4;
+        let committee = Committee::new_for_benchmarks(committee_size);
+        let mut public_config =
+            NodePublicConfig::new_for_tests(committee_size).with_port_offset(300);
+        public_config.parameters.rounds_in_epoch = 10;
+        let client_parameters = ClientParameters::default();
+
+        let dir = TempDir::new("validator_epoch_change").unwrap();
+        let private_configs = NodePrivateConfig::new_for_benchmarks(dir.as_ref(), committee_size);
+        let storage_paths: Vec<_> = private_configs
+            .iter()
+            .map(|private_config| private_config.storage_path.clone())
+            .collect();
+        storage_paths.iter().for_each(|path| {
+            fs::create_dir_all(path).unwrap();
+        });
+
+        let mut validators = Vec::new();
+        for private_config in private_configs {
+            let validator = Validator::start(
+                committee.clone(),
+                public_config.clone(),
+                private_config,
+                client_parameters.clone(),
+            )
+            .await
+            .unwrap();
+            validators.push(validator);
+            if validators.len() == 1 {
+                // Proposed before the others start, well ahead of the end of the epoch
+                let leaving = &Signer::new_for_test(committee_size)[3];
+                let change = CommitteeChange::new(leaving, 0, CommitteeChangeKind::Leave);
+                validators[0].submit_committee_change(change).await.unwrap();
+            }
+        }
+
+        let leaving = validators.pop().unwrap();
+        let timeout = config::node_defaults::default_leader_timeout() * 30;
+        tokio::select! {
+            result = leaving.await_completion() => result.unwrap().unwrap(),
+            _ = time::sleep(timeout) => panic!("The leaving validator did not stop"),
+        }
+
+        let next_committee_path =
+            |path: &std::path::PathBuf| path.join("epoch-1").join(Committee::DEFAULT_FILENAME);
+        let next_committee = Committee::load(next_committee_path(&storage_paths[3])).unwrap();
+        assert_eq!(next_committee.epoch(), 1);
+        assert_eq!(next_committee.len(), committee_size - 1);
+
+        // The remaining validators commit in the next epoch
+        let addresses = public_config
+            .all_metric_addresses()
+            .take(committee_size - 1)
+            .map(|address| address.to_owned())
+            .collect();
+        tokio::select! {
+            _ = await_for_epoch_commits(addresses, 1) => (),
+            _ = time::sleep(timeout) => panic!("Failed to gather commits in the next epoch"),
+        }
+        for path in &storage_paths[..committee_size - 1] {
+            assert!(next_committee_path(path).exists());
+        }
+        for validator in validators {
+            validator.stop().await;
+        }
+    }
 }
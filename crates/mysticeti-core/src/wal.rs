@@ -2,32 +2,69 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::{
-    collections::{btree_map::Entry, BTreeMap},
+    collections::{btree_map::Entry, BTreeMap, HashSet, VecDeque},
+    ffi::OsStr,
     fmt,
-    fs::{File, OpenOptions},
+    fs::{self, File, OpenOptions},
     io,
     io::{IoSlice, Seek, SeekFrom, Write},
-    os::fd::{AsRawFd, RawFd},
-    path::Path,
+    os::{fd::AsRawFd, unix::fs::FileExt},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
 };
 
 use memmap2::{Mmap, MmapOptions};
 use minibytes::Bytes;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
+use tokio::sync::{watch, Notify};
 
 pub struct WalWriter {
-    file: File,
+    files: Arc<WalFiles>,
+    file: Arc<File>,
+    segment: u64,
     pos: u64,
+    dropped_tail: Option<DroppedTail>,
+    durability: Arc<WalDurability>,
+    compressed_tags: HashSet<Tag>,
 }
 
 pub struct WalReader {
-    fd: RawFd,
+    files: Arc<WalFiles>,
     maps: Mutex<BTreeMap<u64, Bytes>>,
 }
 
 pub struct WalSyncer {
-    file: File,
+    files: Arc<WalFiles>,
+    durability: Arc<WalDurability>,
+}
+
+/// How far the wal survives a crash. The writer requests its entries to be synced without waiting,
+/// the syncer serves all the pending requests with a single sync and then notifies the waiters.
+pub struct WalDurability {
+    // End of the entries the writer requested to be synced
+    requested: AtomicU64,
+    // Number of requests since the last sync
+    pending_requests: AtomicU64,
+    requests: Notify,
+    // End of the entries that are durable
+    durable: watch::Sender<u64>,
+}
+
+/// The segment files of a wal, shared by its reader and writer.
+///
+/// Segment `n` holds the entries at positions `n * segment_size..(n + 1) * segment_size`, so that
+/// positions stay valid when the wal rolls to a new segment or deletes old ones.
+struct WalFiles {
+    // None for a wal in a single file, that never rolls to a new segment
+    dir: Option<PathBuf>,
+    segment_size: u64,
+    segments: RwLock<BTreeMap<u64, Arc<File>>>,
+    // Records the end of the synced entries, None for a wal in a single file
+    synced: Option<File>,
 }
 
 #[derive(
@@ -39,18 +76,52 @@ pub struct WalPosition {
 
 pub type Tag = u32;
 
-pub fn walf(mut file: File) -> io::Result<(WalWriter, WalReader)> {
-    file.seek(SeekFrom::End(0))?;
-    make_wal(file)
+// Set in the tag of the header of the entries compressed with lz4, it is not part of the tag
+const COMPRESSED_TAG_FLAG: Tag = 1 << 31;
+
+/// The end of the last segment, dropped when opening the wal as it was not fully written before a crash.
+#[derive(Clone, Copy, Debug)]
+pub struct DroppedTail {
+    pub position: WalPosition,
+    pub bytes: u64,
 }
 
-/// Opens file with mode suitable for walf
-pub fn open_file_for_wal(p: impl AsRef<Path>) -> io::Result<File> {
-    OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(p)
+/// Why the wal could not be read.
+#[derive(Debug)]
+pub enum WalError {
+    Io(io::Error),
+    /// The length or the tag of the entry is corrupted.
+    InvalidHeader {
+        position: WalPosition,
+        crc: u64,
+        len: u64,
+    },
+    /// The content of the entry does not match its crc.
+    CrcMismatch {
+        position: WalPosition,
+        expected: u32,
+        found: u32,
+    },
+    /// The entry extends past the data written to its segment.
+    Truncated {
+        position: WalPosition,
+        end: u64,
+    },
+    /// The entry is flagged as compressed but its content does not decompress.
+    InvalidCompressed {
+        position: WalPosition,
+        reason: String,
+    },
+    /// The entry is intact but its content cannot be decoded.
+    InvalidEntry {
+        position: WalPosition,
+        reason: String,
+    },
+}
+
+pub fn walf(mut file: File) -> io::Result<(WalWriter, WalReader)> {
+    file.seek(SeekFrom::End(0))?;
+    make_wal(WalFiles::single(file), true)
 }
 
 /// Creates wal reader and wal writer on the file.
@@ -82,22 +153,77 @@ pub fn wal(path: impl AsRef<Path>) -> io::Result<(WalWriter, WalReader)> {
         .append(true)
         .read(true)
         .open(path)?;
-    make_wal(file)
+    make_wal(WalFiles::single(file), true)
 }
 
-fn make_wal(file: File) -> io::Result<(WalWriter, WalReader)> {
-    // todo - replace dup with File::try_clone()
-    let fd = unsafe { libc::dup(file.as_raw_fd()) };
-    if fd <= 0 {
-        return Err(io::Error::last_os_error());
-    }
+/// Creates wal reader and wal writer on a directory of segment files, creating the directory if needed.
+/// The wal works as the one created by [wal], except that it rolls to a new segment file once the
+/// current one is full, and that WalWriter::truncate_before can delete the segments that are no longer needed.
+///
+/// Opening a wal truncates the entries at the end of the last segment that were not fully written
+/// before a crash, see WalWriter::dropped_tail. Damage in entries that were synced, followed by
+/// valid entries, is not a torn tail: opening the wal then fails.
+pub fn segmented_wal(path: impl AsRef<Path>) -> io::Result<(WalWriter, WalReader)> {
+    make_wal(WalFiles::open_dir(path.as_ref(), SEGMENT_SIZE, true)?, true)
+}
+
+/// Opens an existing wal for reading only, either a directory of segment files or a single file.
+/// A torn tail is skipped rather than truncated, and writing to the wal fails.
+pub fn open_wal_for_reading(path: impl AsRef<Path>) -> io::Result<(WalWriter, WalReader)> {
+    let path = path.as_ref();
+    let files = if path.is_dir() {
+        WalFiles::open_dir(path, SEGMENT_SIZE, false)?
+    } else {
+        WalFiles::single(File::open(path)?)
+    };
+    make_wal(files, false)
+}
+
+fn make_wal(files: WalFiles, truncate_tail: bool) -> io::Result<(WalWriter, WalReader)> {
+    let (segment, file) = files.last_segment();
+    let start = files.segment_start(segment);
+    let file_end = start + file.metadata()?.len();
+    let synced = files.synced()?;
+    let files = Arc::new(files);
     let reader = WalReader {
-        fd,
+        files: files.clone(),
         maps: Default::default(),
     };
+    let pos = reader.recover_tail(start, file_end, synced)?;
+    let dropped_tail = (pos < file_end).then(|| DroppedTail {
+        position: WalPosition { start: pos },
+        bytes: file_end - pos,
+    });
+    if let Some(dropped_tail) = &dropped_tail {
+        tracing::warn!(
+            "Wal tail was not fully written, dropping {} bytes from position {}",
+            dropped_tail.bytes,
+            dropped_tail.position
+        );
+        if truncate_tail {
+            file.set_len(pos - start)?;
+            (&*file).seek(SeekFrom::Start(pos - start))?;
+            file.sync_data()?;
+            // The entries written next at the dropped positions are not synced yet
+            files.record_synced(synced.min(pos))?;
+        }
+    }
+    // The recovered entries may not have reached the disk before the crash, they are durable once
+    // the first sync completes
+    let durability = Arc::new(WalDurability {
+        requested: AtomicU64::new(pos),
+        pending_requests: AtomicU64::new(0),
+        requests: Notify::new(),
+        durable: watch::channel(0).0,
+    });
     let writer = WalWriter {
-        pos: file.metadata()?.len(),
+        files,
         file,
+        segment,
+        pos,
+        dropped_tail,
+        durability,
+        compressed_tags: HashSet::new(),
     };
     Ok((writer, reader))
 }
@@ -111,12 +237,14 @@ const MAP_SIZE: u64 = 0x10_000; // 16 pages
 #[cfg(test)]
 const MAP_MASK: u64 = !0xffff;
 const ZERO_MAP: [u8; MAP_SIZE as usize] = [0u8; MAP_SIZE as usize];
+// Segments are made of whole mappings, so that an entry never spans two segment files
+const SEGMENT_SIZE: u64 = 4 * MAP_SIZE;
 const _: () = assert_constants();
 
 pub const MAX_ENTRY_SIZE: usize = (MAP_SIZE - HEADER_LEN_BYTES) as usize;
 
-// todo - we still allocate 64 bits for crc in wal header, reconsider it
-const HEADER_LEN_BYTES: u64 = 8 + 8;
+// The 64 bits of crc hold the crc of the content and the crc of the length and tag
+pub(crate) const HEADER_LEN_BYTES: u64 = 8 + 8;
 // CRC and length
 const HEADER_LEN_BYTES_USIZE: usize = HEADER_LEN_BYTES as usize;
 
@@ -157,9 +285,37 @@ impl WalWriter {
     }
 
     pub fn writev(&mut self, tag: Tag, v: &[IoSlice]) -> io::Result<WalPosition> {
+        assert_eq!(tag & COMPRESSED_TAG_FLAG, 0, "Wal tag {tag} is too large");
+        if self.compressed_tags.contains(&tag) {
+            let mut content = Vec::with_capacity(v.iter().map(|s| s.len()).sum());
+            for slice in v {
+                content.extend_from_slice(slice);
+            }
+            let compressed = lz4_flex::compress_prepend_size(&content);
+            if compressed.len() < content.len() {
+                return self.write_entry(tag | COMPRESSED_TAG_FLAG, &[IoSlice::new(&compressed)]);
+            }
+        }
+        self.write_entry(tag, v)
+    }
+
+    /// Compresses the entries of the tag written from now on, when it makes them smaller. The
+    /// header flags compressed entries, so a wal mixing compressed and uncompressed entries stays
+    /// readable and compression can be turned on or off across restarts. Readers get the entries
+    /// of uncompressed tags without a copy.
+    pub fn compress_tag(&mut self, tag: Tag) {
+        assert_eq!(tag & COMPRESSED_TAG_FLAG, 0, "Wal tag {tag} is too large");
+        self.compressed_tags.insert(tag);
+    }
+
+    fn write_entry(&mut self, tag: Tag, v: &[IoSlice]) -> io::Result<WalPosition> {
         let v_len = v.iter().map(|s| s.len()).sum::<usize>();
         let len = v_len as u64 + HEADER_LEN_BYTES;
         assert!(len <= MAP_SIZE, "Wal entry too big, {len} < {MAP_SIZE}");
+        let segment = self.files.segment(self.pos + len - 1);
+        if segment != self.segment {
+            self.roll_segment(segment)?;
+        }
         let mut buffs = vec![];
         let mut written_expected = 0usize;
         tracing::trace!(
@@ -183,13 +339,13 @@ impl WalWriter {
         for slice in v {
             crc.update(slice);
         }
-        let crc = crc.finalize() as u64;
+        let crc = combine_crc(crc.finalize(), header_crc(len, tag));
         let header = combine_header(crc, len, tag);
         let header = header.to_le_bytes();
         buffs.push(IoSlice::new(&header));
         buffs.extend_from_slice(v);
         written_expected += len as usize;
-        let written = self.file.write_vectored(&buffs)?;
+        let written = (&*self.file).write_vectored(&buffs)?;
         assert_eq!(written, written_expected);
         let position = WalPosition { start: self.pos };
         self.pos += len;
@@ -197,7 +353,27 @@ impl WalWriter {
     }
 
     pub fn sync(&self) -> io::Result<()> {
-        self.file.sync_data()
+        // Previous segments were synced when rolling to this one
+        self.file.sync_data()?;
+        self.files.record_synced(self.pos)?;
+        self.durability.publish(self.pos);
+        Ok(())
+    }
+
+    /// Asks the syncer to make all the entries written so far durable, without waiting for it.
+    /// Requests made until the syncer runs are served by a single sync.
+    pub fn request_sync(&self) {
+        self.durability.request(self.pos);
+    }
+
+    /// Reports all the entries written so far as durable without syncing them. Only for tests and
+    /// benchmarks that can afford losing the wal on a crash.
+    pub fn assume_durable(&self) {
+        self.durability.publish(self.pos);
+    }
+
+    pub fn durability(&self) -> Arc<WalDurability> {
+        self.durability.clone()
     }
 
     /// Allow to retrieve a 'syncer' instance that allows
@@ -206,17 +382,335 @@ impl WalWriter {
     /// In mysticeti specifically this allows to have an independent syncer thread that
     /// does not share locks with consensus thread.
     pub fn syncer(&self) -> io::Result<WalSyncer> {
-        let file = self.file.try_clone()?;
-        Ok(WalSyncer { file })
+        Ok(WalSyncer {
+            files: self.files.clone(),
+            durability: self.durability.clone(),
+        })
+    }
+
+    /// Deletes the segments that only hold entries written before the specified position, returning
+    /// how many were deleted. The wal is synced first, so that the entries superseding the deleted
+    /// ones are not lost on a crash. A wal in a single file is never truncated.
+    pub fn truncate_before(&mut self, position: WalPosition) -> io::Result<usize> {
+        let Some(dir) = &self.files.dir else {
+            return Ok(0);
+        };
+        let first_retained = self.files.segment(position.start).min(self.segment);
+        let deleted: Vec<_> = self
+            .files
+            .segments
+            .read()
+            .range(..first_retained)
+            .map(|(segment, _)| *segment)
+            .collect();
+        if deleted.is_empty() {
+            return Ok(0);
+        }
+        self.sync()?;
+        // Oldest first, a crash leaves the wal without a gap
+        for segment in &deleted {
+            self.files.segments.write().remove(segment);
+            fs::remove_file(segment_path(dir, *segment))?;
+        }
+        Ok(deleted.len())
+    }
+
+    /// Whether WalWriter::truncate_before would delete any segment for the specified position.
+    pub fn can_truncate_before(&self, position: WalPosition) -> bool {
+        if self.files.dir.is_none() {
+            return false;
+        }
+        let first_retained = self.files.segment(position.start).min(self.segment);
+        self.files
+            .segments
+            .read()
+            .range(..first_retained)
+            .next()
+            .is_some()
+    }
+
+    /// The position of the first entry WalWriter::truncate_before keeps for the specified position,
+    /// the entries before it are deleted.
+    pub fn first_retained_position(&self, position: WalPosition) -> WalPosition {
+        if self.files.dir.is_none() {
+            return self.first_position();
+        }
+        let first_retained = self.files.segment(position.start).min(self.segment);
+        WalPosition {
+            start: self.files.segment_start(first_retained),
+        }
+    }
+
+    /// The position the next entry is written at.
+    pub fn position(&self) -> WalPosition {
+        WalPosition { start: self.pos }
+    }
+
+    /// The position of the first entry that is still in the wal.
+    pub fn first_position(&self) -> WalPosition {
+        WalPosition {
+            start: self.files.segment_start(self.files.first_segment()),
+        }
+    }
+
+    pub fn segments(&self) -> usize {
+        self.files.segments.read().len()
+    }
+
+    /// The end of the wal that was dropped when opening it, if it was not fully written before a crash.
+    pub fn dropped_tail(&self) -> Option<DroppedTail> {
+        self.dropped_tail
+    }
+
+    fn roll_segment(&mut self, segment: u64) -> io::Result<()> {
+        let dir = self
+            .files
+            .dir
+            .as_ref()
+            .expect("Wal in a single file can not roll to a new segment");
+        // The syncer only syncs the last segment, the end of this one is synced here
+        self.file.sync_data()?;
+        let file = Arc::new(open_segment(&segment_path(dir, segment))?);
+        File::open(dir)?.sync_all()?;
+        self.files.segments.write().insert(segment, file.clone());
+        tracing::debug!("Wal rolled to segment {segment}");
+        self.file = file;
+        self.segment = segment;
+        self.pos = self.files.segment_start(segment);
+        Ok(())
     }
 }
 
 impl WalSyncer {
-    pub fn sync(&self) -> io::Result<()> {
-        self.file.sync_data()
+    /// Syncs the wal and notifies the waiters of the entries that became durable. Returns the
+    /// number of sync requests served.
+    pub fn sync(&self) -> io::Result<u64> {
+        let served = self.durability.pending_requests.swap(0, Ordering::AcqRel);
+        let requested = self.durability.requested.load(Ordering::Acquire);
+        // Earlier segments are synced by the writer when it rolls to a new one, before the new
+        // segment is visible here
+        self.files.last_segment().1.sync_data()?;
+        self.files.record_synced(requested)?;
+        self.durability.publish(requested);
+        Ok(served)
+    }
+
+    /// Completes once a sync was requested since the last call.
+    pub async fn sync_requested(&self) {
+        self.durability.requests.notified().await
     }
 }
 
+impl WalDurability {
+    fn request(&self, end: u64) {
+        self.requested.fetch_max(end, Ordering::AcqRel);
+        self.pending_requests.fetch_add(1, Ordering::AcqRel);
+        self.requests.notify_one();
+    }
+
+    fn publish(&self, end: u64) {
+        self.durable.send_if_modified(|durable| {
+            if end > *durable {
+                *durable = end;
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    /// Whether the entry at the specified position survives a crash.
+    pub fn is_durable(&self, position: WalPosition) -> bool {
+        // Durable ends are always the end of an entry
+        position.start < *self.durable.borrow()
+    }
+
+    /// The end of the entries that survive a crash.
+    pub fn durable_position(&self) -> WalPosition {
+        WalPosition {
+            start: *self.durable.borrow(),
+        }
+    }
+
+    /// Receives the end of the durable entries whenever it moves forward.
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.durable.subscribe()
+    }
+
+    /// Completes once the entry at the specified position survives a crash.
+    pub async fn wait_durable(&self, position: WalPosition) {
+        let mut durable = self.durable.subscribe();
+        while *durable.borrow_and_update() <= position.start {
+            durable
+                .changed()
+                .await
+                .expect("Durability sender is held by self");
+        }
+    }
+}
+
+impl WalFiles {
+    fn single(file: File) -> Self {
+        Self {
+            dir: None,
+            segment_size: u64::MAX,
+            segments: RwLock::new(BTreeMap::from([(0, Arc::new(file))])),
+            synced: None,
+        }
+    }
+
+    fn open_dir(dir: &Path, segment_size: u64, writable: bool) -> io::Result<Self> {
+        if writable {
+            fs::create_dir_all(dir)?;
+        }
+        let mut segments = BTreeMap::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let Some(segment) = parse_segment_name(&entry.file_name()) else {
+                continue;
+            };
+            let file = if writable {
+                open_segment(&entry.path())?
+            } else {
+                File::open(entry.path())?
+            };
+            segments.insert(segment, Arc::new(file));
+        }
+        if segments.is_empty() {
+            if !writable {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("No wal segment in {}", dir.display()),
+                ));
+            }
+            segments.insert(0, Arc::new(open_segment(&segment_path(dir, 0))?));
+        }
+        let synced_path = dir.join(SYNCED_FILENAME);
+        let synced = if writable {
+            // Not in append mode, the end is written in place
+            Some(
+                OpenOptions::new()
+                    .create(true)
+                    .truncate(false)
+                    .read(true)
+                    .write(true)
+                    .open(synced_path)?,
+            )
+        } else {
+            File::open(synced_path).ok()
+        };
+        Ok(Self {
+            dir: Some(dir.to_path_buf()),
+            segment_size,
+            segments: RwLock::new(segments),
+            synced,
+        })
+    }
+
+    /// The end of the entries known to be synced, zero when it is not recorded.
+    fn synced(&self) -> io::Result<u64> {
+        let Some(file) = &self.synced else {
+            return Ok(0);
+        };
+        let mut record = [0u8; SYNCED_RECORD_SIZE];
+        match file.read_exact_at(&mut record, 0) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(0),
+            Err(err) => return Err(err),
+        }
+        let (end, crc) = record.split_at(8);
+        if crc32fast::hash(end).to_le_bytes() != crc {
+            tracing::warn!("Ignoring the corrupted record of the synced wal entries");
+            return Ok(0);
+        }
+        Ok(u64::from_le_bytes(end.try_into().unwrap()))
+    }
+
+    // Called once the entries up to the end are synced. The record itself is not synced: it may
+    // lag behind after a crash, but never runs ahead of the synced entries.
+    fn record_synced(&self, end: u64) -> io::Result<()> {
+        let Some(file) = &self.synced else {
+            return Ok(());
+        };
+        let mut record = [0u8; SYNCED_RECORD_SIZE];
+        record[..8].copy_from_slice(&end.to_le_bytes());
+        let crc = crc32fast::hash(&record[..8]);
+        record[8..].copy_from_slice(&crc.to_le_bytes());
+        file.write_all_at(&record, 0)
+    }
+
+    fn segment(&self, position: u64) -> u64 {
+        position / self.segment_size
+    }
+
+    fn segment_start(&self, segment: u64) -> u64 {
+        segment * self.segment_size
+    }
+
+    fn first_segment(&self) -> u64 {
+        *self
+            .segments
+            .read()
+            .keys()
+            .next()
+            .expect("Wal has at least one segment")
+    }
+
+    fn last_segment(&self) -> (u64, Arc<File>) {
+        let segments = self.segments.read();
+        let (segment, file) = segments
+            .iter()
+            .next_back()
+            .expect("Wal has at least one segment");
+        (*segment, file.clone())
+    }
+
+    /// The segment file holding the position, along with the offset of the position in that file.
+    fn locate(&self, position: u64) -> io::Result<(Arc<File>, u64)> {
+        let segment = self.segment(position);
+        let Some(file) = self.segments.read().get(&segment).cloned() else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Wal segment {segment} holding position {position} was deleted"),
+            ));
+        };
+        Ok((file, position - self.segment_start(segment)))
+    }
+
+    /// The range of positions written in each segment, up to the specified position.
+    fn written_ranges(&self, end_position: u64) -> io::Result<VecDeque<(u64, u64)>> {
+        let mut ranges = VecDeque::new();
+        for (segment, file) in self.segments.read().iter() {
+            let start = self.segment_start(*segment);
+            let end = (start + file.metadata()?.len()).min(end_position);
+            if start < end {
+                ranges.push_back((start, end));
+            }
+        }
+        Ok(ranges)
+    }
+}
+
+// The end of the synced entries, followed by its crc
+const SYNCED_FILENAME: &str = "synced";
+const SYNCED_RECORD_SIZE: usize = 8 + 4;
+
+fn open_segment(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .read(true)
+        .open(path)
+}
+
+fn segment_path(dir: &Path, segment: u64) -> PathBuf {
+    dir.join(format!("{segment:010}.wal"))
+}
+
+fn parse_segment_name(name: &OsStr) -> Option<u64> {
+    name.to_str()?.strip_suffix(".wal")?.parse().ok()
+}
+
 fn combine_header(crc: u64, len: u64, tag: Tag) -> u128 {
     // wal entries are currently limited by (much smaller) MAP_SIZE
     assert!(len <= u32::MAX as u64);
@@ -231,38 +725,156 @@ fn split_header(combined: u128) -> (u64, u64, Tag) {
     (crc, len, tag)
 }
 
+fn combine_crc(content_crc: u32, header_crc: u32) -> u64 {
+    content_crc as u64 + ((header_crc as u64) << 32)
+}
+
+fn split_crc(crc: u64) -> (u32, u32 /*(content_crc, header_crc)*/) {
+    (crc as u32, (crc >> 32) as u32)
+}
+
+// Covers the length and the tag of an entry, so that a corrupted header is not taken for
+// another entry. Entries written before have zero instead, their header is not checked.
+fn header_crc(len: u64, tag: Tag) -> u32 {
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(&(len as u32).to_le_bytes());
+    crc.update(&tag.to_le_bytes());
+    crc.finalize()
+}
+
 impl WalReader {
-    pub fn read(&self, position: WalPosition) -> io::Result<(Tag, Bytes)> {
-        match self.try_read(position)? {
-            Some(entry) => Ok(entry),
-            None => panic!("No entry found at position {}", position.start),
+    pub fn read(&self, position: WalPosition) -> Result<(Tag, Bytes), WalError> {
+        match self.try_read(position, u64::MAX)? {
+            Some((_len, tag, data)) => Ok((tag, data)),
+            None => Err(WalError::InvalidHeader {
+                position,
+                crc: 0,
+                len: 0,
+            }),
         }
     }
 
-    fn try_read(&self, position: WalPosition) -> io::Result<Option<(Tag, Bytes)>> {
+    // Reads the entry at the position, the data written to its segment ends at end_position.
+    // Returns None when there is no entry there, either because the rest of the mapping is padding
+    // or because nothing was written. Otherwise returns the length of the entry in the wal along
+    // with its tag and content.
+    fn try_read(
+        &self,
+        position: WalPosition,
+        end_position: u64,
+    ) -> Result<Option<(u64, Tag, Bytes)>, WalError> {
         let offset = offset(position.start);
-        let bytes = self.map_offset(offset)?;
         let buf_offset = (position.start - offset) as usize;
+        if buf_offset + HEADER_LEN_BYTES_USIZE > MAP_SIZE as usize {
+            // No room for an entry before the end of the mapping
+            return Ok(None);
+        }
+        if position.start + HEADER_LEN_BYTES > end_position {
+            return Err(WalError::Truncated {
+                position,
+                end: end_position,
+            });
+        }
+        let bytes = self.map_offset(offset)?;
         let (crc, len, tag) = Self::read_header(&bytes[buf_offset..]);
         if len == 0 {
             if crc == 0 {
                 return Ok(None);
             }
-            panic!(
-                "Non-zero crc at len 0, crc: {crc}, position:{}",
-                position.start
-            );
+            return Err(WalError::InvalidHeader { position, crc, len });
+        }
+        let (content_crc, entry_header_crc) = split_crc(crc);
+        if len < HEADER_LEN_BYTES
+            || buf_offset as u64 + len > MAP_SIZE
+            || (entry_header_crc != 0 && entry_header_crc != header_crc(len, tag))
+        {
+            return Err(WalError::InvalidHeader { position, crc, len });
+        }
+        if position.start + len > end_position {
+            return Err(WalError::Truncated {
+                position,
+                end: end_position,
+            });
         }
         let bytes = bytes.slice(buf_offset + HEADER_LEN_BYTES_USIZE..buf_offset + (len as usize));
-        let actual_crc = crc32fast::hash(bytes.as_ref()) as u64;
-        if actual_crc != crc {
-            // todo - return error
-            panic!(
-                "Crc mismatch, expected {}, found {} at position {}:{}",
-                crc, actual_crc, position.start, len
-            );
+        let actual_crc = crc32fast::hash(bytes.as_ref());
+        if actual_crc != content_crc {
+            return Err(WalError::CrcMismatch {
+                position,
+                expected: content_crc,
+                found: actual_crc,
+            });
+        }
+        if tag & COMPRESSED_TAG_FLAG == 0 {
+            return Ok(Some((len, tag, bytes)));
         }
-        Ok(Some((tag, bytes)))
+        let content = lz4_flex::decompress_size_prepended(&bytes).map_err(|err| {
+            WalError::InvalidCompressed {
+                position,
+                reason: err.to_string(),
+            }
+        })?;
+        Ok(Some((len, tag & !COMPRESSED_TAG_FLAG, content.into())))
+    }
+
+    // Finds the end of the valid entries of the last segment, starting at start. The entries
+    // after it were not fully written before a crash, unless they were synced and a valid entry
+    // follows the damage: the wal is then corrupted rather than torn.
+    fn recover_tail(&self, start: u64, end: u64, synced: u64) -> Result<u64, WalError> {
+        let mut iter = WalIterator {
+            wal_reader: self,
+            position: WalPosition { start },
+            segments: VecDeque::from([(start, end)]),
+        };
+        let damage = iter.find_map(Result::err);
+        let tail = iter.position;
+        if let Some(err) = damage {
+            if let WalError::Io(_) = err {
+                return Err(err);
+            }
+            if tail.start < synced {
+                if let Some(next) = self.find_entry_after(tail, end)? {
+                    tracing::error!(
+                        "Wal is corrupted at position {tail}, synced entries follow from {next}"
+                    );
+                    return Err(err);
+                }
+            }
+            tracing::warn!("Wal tail is corrupted: {err}");
+        }
+        // Mappings of the tail are not used anymore
+        self.cleanup();
+        Ok(tail.start)
+    }
+
+    // Looks for a valid entry after the position and before end, at any offset since the length
+    // of the damaged entry is not known.
+    fn find_entry_after(
+        &self,
+        position: WalPosition,
+        end: u64,
+    ) -> Result<Option<WalPosition>, WalError> {
+        let mut start = position.start + 1;
+        while start + HEADER_LEN_BYTES <= end {
+            let map = offset(start);
+            let bytes = self.map_offset(map)?;
+            let map_end = (map + MAP_SIZE).min(end);
+            while start + HEADER_LEN_BYTES <= map_end {
+                let (crc, len, tag) = Self::read_header(&bytes[(start - map) as usize..]);
+                // Only entries with a checked header, so that the content of the damaged entry
+                // is not taken for an entry
+                let candidate = WalPosition { start };
+                if len >= HEADER_LEN_BYTES
+                    && split_crc(crc).1 == header_crc(len, tag)
+                    && matches!(self.try_read(candidate, end), Ok(Some(_)))
+                {
+                    return Ok(Some(candidate));
+                }
+                start += 1;
+            }
+            start = map + MAP_SIZE;
+        }
+        Ok(None)
     }
 
     // Attempts cleaning internal mem maps, returning number of retained maps
@@ -275,10 +887,28 @@ impl WalReader {
 
     // Iter all entries up to writer position at the time iter_until(...) is called
     pub fn iter_until(&self, w: &WalWriter) -> WalIterator {
+        self.iter_from(WalPosition::default(), w)
+    }
+
+    // Iter the entries from the position, which must be the end of an entry, up to the writer
+    // position at the time iter_from(...) is called
+    pub fn iter_from(&self, position: WalPosition, w: &WalWriter) -> WalIterator {
+        self.iter_between(position, w.position())
+    }
+
+    // Iter the entries from the position up to the end, both must be the end of an entry
+    pub fn iter_between(&self, position: WalPosition, end: WalPosition) -> WalIterator {
+        let mut segments = self
+            .files
+            .written_ranges(end.start)
+            .expect("Failed to read the length of the wal segments");
+        while matches!(segments.front(), Some((_, end)) if *end <= position.start) {
+            segments.pop_front();
+        }
         WalIterator {
             wal_reader: self,
-            position: Some(WalPosition { start: 0 }),
-            end_position: w.pos,
+            position,
+            segments,
         }
     }
 
@@ -286,11 +916,12 @@ impl WalReader {
         let mut maps = self.maps.lock();
         let bytes = match maps.entry(offset) {
             Entry::Vacant(va) => {
+                let (file, file_offset) = self.files.locate(offset)?;
                 let mmap = unsafe {
                     MmapOptions::new()
-                        .offset(offset)
+                        .offset(file_offset)
                         .len(MAP_SIZE as usize)
-                        .map(self.fd)?
+                        .map(file.as_raw_fd())?
                 };
                 va.insert(mmap.into())
             }
@@ -310,41 +941,71 @@ impl WalReader {
 
 pub struct WalIterator<'a> {
     wal_reader: &'a WalReader,
-    position: Option<WalPosition>,
-    end_position: u64,
+    position: WalPosition,
+    // Range of positions written in each remaining segment
+    segments: VecDeque<(u64, u64)>,
 }
 
+/// An entry of the wal with its position.
+pub type WalEntry = (WalPosition, (Tag, Bytes));
+
 impl<'a> Iterator for WalIterator<'a> {
-    type Item = (WalPosition, (Tag, Bytes));
+    type Item = Result<WalEntry, WalError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let position = self.position.take()?;
+        loop {
+            let (start, end) = *self.segments.front()?;
+            self.position = self.position.max(WalPosition { start });
+            match self.next_in_segment(end) {
+                Ok(Some(item)) => return Some(Ok(item)),
+                Ok(None) if self.position.start >= end => {
+                    self.segments.pop_front();
+                }
+                // Segments end right after their last entry, anything else is corrupted
+                Ok(None) => {
+                    self.segments.clear();
+                    return Some(Err(WalError::Truncated {
+                        position: self.position,
+                        end,
+                    }));
+                }
+                Err(err) => {
+                    self.segments.clear();
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+impl<'a> WalIterator<'a> {
+    fn next_in_segment(&mut self, end_position: u64) -> Result<Option<WalEntry>, WalError> {
+        let position = self.position;
         tracing::trace!("Iter read {}", position.start);
         // Either read from current position, or try next mapping, but only once
-        if let Some(item) = self.try_position(position) {
-            return Some(item);
+        if let Some(item) = self.try_position(position, end_position)? {
+            return Ok(Some(item));
         }
         if position.first_in_map() {
-            return None;
+            return Ok(None);
         }
         tracing::trace!("Iter fallback read {}", position.next_start_offset().start);
-        // todo - need to consider crash recovery here
-        // Either need to reset writer position, or read all offsets until writer position
-        self.try_position(position.next_start_offset())
+        self.try_position(position.next_start_offset(), end_position)
     }
-}
 
-impl<'a> WalIterator<'a> {
-    fn try_position(&mut self, position: WalPosition) -> Option<(WalPosition, (Tag, Bytes))> {
-        if position.start >= self.end_position {
-            return None;
+    fn try_position(
+        &mut self,
+        position: WalPosition,
+        end_position: u64,
+    ) -> Result<Option<WalEntry>, WalError> {
+        if position.start >= end_position {
+            return Ok(None);
         }
-        let (tag, data) = self
-            .wal_reader
-            .try_read(position)
-            .expect("Failed to read wal")?;
-        self.position = Some(position.add(data.len() as u64 + HEADER_LEN_BYTES));
-        Some((position, (tag, data)))
+        let Some((len, tag, data)) = self.wal_reader.try_read(position, end_position)? else {
+            return Ok(None);
+        };
+        self.position = position.add(len);
+        Ok(Some((position, (tag, data))))
     }
 }
 
@@ -357,6 +1018,11 @@ impl WalPosition {
         }
     }
 
+    /// Number of bytes of the wal from an earlier position to this one, padding included.
+    pub fn bytes_since(&self, earlier: WalPosition) -> u64 {
+        self.start.saturating_sub(earlier.start)
+    }
+
     fn next_start_offset(&self) -> Self {
         let offset = offset(self.start);
         Self {
@@ -369,20 +1035,57 @@ impl WalPosition {
     }
 }
 
-impl Drop for WalReader {
-    fn drop(&mut self) {
-        unsafe {
-            if libc::close(self.fd) != 0 {
-                #[allow(clippy::unnecessary_literal_unwrap)]
-                Err::<(), _>(io::Error::last_os_error()).expect("Failed to close wal fd");
+impl fmt::Display for WalPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.start)
+    }
+}
+
+impl fmt::Display for WalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "failed to read wal: {err}"),
+            Self::InvalidHeader { position, crc, len } => write!(
+                f,
+                "invalid entry header at position {position}, crc: {crc}, len: {len}"
+            ),
+            Self::CrcMismatch {
+                position,
+                expected,
+                found,
+            } => write!(
+                f,
+                "crc mismatch at position {position}, expected {expected}, found {found}"
+            ),
+            Self::Truncated { position, end } => write!(
+                f,
+                "entry at position {position} extends past the end of its segment at {end}"
+            ),
+            Self::InvalidCompressed { position, reason } => write!(
+                f,
+                "invalid compressed entry at position {position}: {reason}"
+            ),
+            Self::InvalidEntry { position, reason } => {
+                write!(f, "invalid entry at position {position}: {reason}")
             }
         }
     }
 }
 
-impl fmt::Display for WalPosition {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.start)
+impl std::error::Error for WalError {}
+
+impl From<io::Error> for WalError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<WalError> for io::Error {
+    fn from(err: WalError) -> Self {
+        match err {
+            WalError::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        }
     }
 }
 
@@ -467,7 +1170,7 @@ mod tests {
     #[track_caller]
     // Read from iterator, assert tag and position
     fn rd_it(iter: &mut WalIterator, tag: Tag, pos: WalPosition) -> Bytes {
-        let (read_pos, (read_tag, data)) = iter.next().unwrap();
+        let (read_pos, (read_tag, data)) = iter.next().unwrap().unwrap();
         assert_eq!(read_tag, tag);
         assert_eq!(read_pos, pos);
         data
@@ -494,6 +1197,249 @@ mod tests {
         assert_eq!(1, reader.cleanup()); // assert only one mapping was created (therefore one and two share same mapping)
     }
 
+    #[test]
+    fn test_wal_compression() {
+        let temp = tempdir::TempDir::new("test_wal_compression").unwrap();
+        let (mut writer, reader) = segmented_wal(temp.path()).unwrap();
+        let zeroes = [0u8; 4096];
+        let small = [1u8, 2, 3];
+        let plain_pos = writer.write(1, &zeroes).unwrap();
+        writer.compress_tag(1);
+        let compressed_pos = writer
+            .writev(1, &[IoSlice::new(&zeroes), IoSlice::new(&zeroes)])
+            .unwrap();
+        // Not smaller once compressed, written as is
+        let small_pos = writer.write(1, &small).unwrap();
+        let other_pos = writer.write(2, &zeroes).unwrap();
+        assert!(small_pos.start - compressed_pos.start < zeroes.len() as u64);
+
+        let both = [zeroes, zeroes].concat();
+        let (tag, data) = reader.read(compressed_pos).unwrap();
+        assert_eq!(tag, 1);
+        assert_eq!(&both, data.as_ref());
+        let (tag, data) = reader.read(small_pos).unwrap();
+        assert_eq!(tag, 1);
+        assert_eq!(&small, data.as_ref());
+        drop(writer);
+        drop(reader);
+
+        // A wal mixing compressed and uncompressed entries is read without compression on
+        let (writer, reader) = segmented_wal(temp.path()).unwrap();
+        let mut iter = reader.iter_until(&writer);
+        assert_eq!(&zeroes, rd_it(&mut iter, 1, plain_pos).as_ref());
+        assert_eq!(&both, rd_it(&mut iter, 1, compressed_pos).as_ref());
+        assert_eq!(&small, rd_it(&mut iter, 1, small_pos).as_ref());
+        assert_eq!(&zeroes, rd_it(&mut iter, 2, other_pos).as_ref());
+        assert!(iter.next().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_wal_durability() {
+        let temp = tempdir::TempDir::new("test_wal_durability").unwrap();
+        let (mut writer, _reader) = segmented_wal(temp.path()).unwrap();
+        let syncer = writer.syncer().unwrap();
+        let durability = writer.durability();
+        let one = writer.write(1, &[1, 2]).unwrap();
+        writer.request_sync();
+        let two = writer.write(2, &[3]).unwrap();
+        writer.request_sync();
+        assert!(!durability.is_durable(one));
+        // Both requests are served by a single sync
+        assert_eq!(syncer.sync().unwrap(), 2);
+        assert!(durability.is_durable(one));
+        assert!(durability.is_durable(two));
+
+        let three = writer.write(3, &[4]).unwrap();
+        let waiter = tokio::spawn({
+            let durability = durability.clone();
+            async move { durability.wait_durable(three).await }
+        });
+        // Entries written after the last request are not reported durable
+        assert_eq!(syncer.sync().unwrap(), 0);
+        assert!(!durability.is_durable(three));
+        writer.request_sync();
+        syncer.sync_requested().await;
+        assert_eq!(syncer.sync().unwrap(), 1);
+        waiter.await.unwrap();
+        assert!(durability.is_durable(three));
+
+        let four = writer.write(4, &[5]).unwrap();
+        writer.assume_durable();
+        assert!(durability.is_durable(four));
+    }
+
+    #[test]
+    fn test_segmented_wal() {
+        let temp = tempdir::TempDir::new("test_segmented_wal").unwrap();
+        let (mut writer, reader) = segmented_wal(temp.path()).unwrap();
+        // Each entry takes a mapping of its own, so a segment holds 4 of them
+        let entries: Vec<_> = (0..10u8)
+            .map(|i| vec![i; (MAP_SIZE / 2) as usize])
+            .collect();
+        let positions: Vec<_> = entries
+            .iter()
+            .enumerate()
+            .map(|(tag, entry)| writer.write(tag as Tag, entry).unwrap())
+            .collect();
+        assert_eq!(writer.segments(), 3);
+        for (tag, (entry, position)) in entries.iter().zip(&positions).enumerate() {
+            assert_eq!(entry, rd(&reader, *position, tag as Tag).as_ref());
+        }
+        drop(reader);
+        drop(writer);
+
+        let (mut writer, reader) = segmented_wal(temp.path()).unwrap();
+        let mut iter = reader.iter_until(&writer);
+        for (tag, (entry, position)) in entries.iter().zip(&positions).enumerate() {
+            assert_eq!(entry, rd_it(&mut iter, tag as Tag, *position).as_ref());
+        }
+        assert!(iter.next().is_none());
+
+        // Segments before the one holding the position are deleted, positions stay valid
+        assert!(writer.can_truncate_before(positions[5]));
+        assert_eq!(writer.truncate_before(positions[5]).unwrap(), 1);
+        assert!(!writer.can_truncate_before(positions[5]));
+        assert_eq!(writer.first_position(), positions[4]);
+        assert_eq!(writer.segments(), 2);
+        reader.cleanup();
+        assert!(reader.read(positions[0]).is_err());
+        assert_eq!(&entries[4], rd(&reader, positions[4], 4).as_ref());
+        let eleven = [11u8; 18];
+        let eleven_pos = writer.write(11, &eleven).unwrap();
+        drop(reader);
+        drop(writer);
+
+        let (writer, reader) = segmented_wal(temp.path()).unwrap();
+        let mut iter = reader.iter_until(&writer);
+        for (tag, (entry, position)) in entries.iter().zip(&positions).enumerate().skip(4) {
+            assert_eq!(entry, rd_it(&mut iter, tag as Tag, *position).as_ref());
+        }
+        assert_eq!(&eleven, rd_it(&mut iter, 11, eleven_pos).as_ref());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_wal_torn_tail() {
+        let temp = tempdir::TempDir::new("test_wal_torn_tail").unwrap();
+        let (mut writer, reader) = segmented_wal(temp.path()).unwrap();
+        let one = [1u8; 1024];
+        let two = [2u8; 2048];
+        let one_pos = writer.write(5, &one).unwrap();
+        let two_pos = writer.write(6, &two).unwrap();
+        drop(reader);
+        drop(writer);
+
+        // Only part of the second entry made it to disk
+        let segment = open_segment(&segment_path(temp.path(), 0)).unwrap();
+        let len = segment.metadata().unwrap().len();
+        segment.set_len(len - 100).unwrap();
+        drop(segment);
+
+        let (mut writer, reader) = segmented_wal(temp.path()).unwrap();
+        let dropped_tail = writer.dropped_tail().unwrap();
+        assert_eq!(dropped_tail.position, two_pos);
+        assert_eq!(
+            dropped_tail.bytes,
+            two.len() as u64 + HEADER_LEN_BYTES - 100
+        );
+        let mut iter = reader.iter_until(&writer);
+        assert_eq!(&one, rd_it(&mut iter, 5, one_pos).as_ref());
+        assert!(iter.next().is_none());
+
+        // Writing resumes after the last complete entry
+        let three = [3u8; 15];
+        let three_pos = writer.write(7, &three).unwrap();
+        assert_eq!(three_pos, two_pos);
+        drop(reader);
+        drop(writer);
+
+        let (writer, reader) = segmented_wal(temp.path()).unwrap();
+        assert!(writer.dropped_tail().is_none());
+        let mut iter = reader.iter_until(&writer);
+        assert_eq!(&one, rd_it(&mut iter, 5, one_pos).as_ref());
+        assert_eq!(&three, rd_it(&mut iter, 7, three_pos).as_ref());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_wal_corrupted_segment() {
+        let temp = tempdir::TempDir::new("test_wal_corrupted_segment").unwrap();
+        let (mut writer, reader) = segmented_wal(temp.path()).unwrap();
+        let entries: Vec<_> = (0..6u8).map(|i| vec![i; (MAP_SIZE / 2) as usize]).collect();
+        let positions: Vec<_> = entries
+            .iter()
+            .map(|entry| writer.write(1, entry).unwrap())
+            .collect();
+        assert_eq!(writer.segments(), 2);
+        drop(reader);
+        drop(writer);
+
+        // Flip a byte of the content of the second entry, in the first segment
+        flip_byte(
+            &segment_path(temp.path(), 0),
+            MAP_SIZE + HEADER_LEN_BYTES + 10,
+        );
+
+        // The corruption is not in the tail, reading the wal fails rather than dropping entries
+        let (writer, reader) = segmented_wal(temp.path()).unwrap();
+        assert!(writer.dropped_tail().is_none());
+        let mut iter = reader.iter_until(&writer);
+        assert_eq!(&entries[0], rd_it(&mut iter, 1, positions[0]).as_ref());
+        match iter.next() {
+            Some(Err(WalError::CrcMismatch { position, .. })) => assert_eq!(position, positions[1]),
+            Some(Err(err)) => panic!("Expected crc mismatch, got {err}"),
+            _ => panic!("Expected crc mismatch"),
+        }
+        assert!(iter.next().is_none());
+        assert!(matches!(
+            reader.read(positions[1]),
+            Err(WalError::CrcMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_wal_corrupted_tail() {
+        for synced in [false, true] {
+            let temp = tempdir::TempDir::new("test_wal_corrupted_tail").unwrap();
+            let (mut writer, reader) = segmented_wal(temp.path()).unwrap();
+            writer.write(1, &[1u8; 1024]).unwrap();
+            let two_pos = writer.write(2, &[2u8; 1024]).unwrap();
+            writer.write(3, &[3u8; 1024]).unwrap();
+            if synced {
+                writer.sync().unwrap();
+            }
+            drop(reader);
+            drop(writer);
+
+            flip_byte(
+                &segment_path(temp.path(), 0),
+                two_pos.start + HEADER_LEN_BYTES + 10,
+            );
+            let result = segmented_wal(temp.path());
+            if synced {
+                // A synced entry follows the damage, the wal was not torn by a crash
+                let err = result.err().expect("Corrupted synced entries were dropped");
+                assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            } else {
+                // Entries that were not synced may reach the disk in any order before a crash
+                let (writer, _reader) = result.unwrap();
+                assert_eq!(writer.dropped_tail().unwrap().position, two_pos);
+            }
+        }
+    }
+
+    fn flip_byte(path: &Path, offset: u64) {
+        // Not in append mode, positioned writes would append
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .unwrap();
+        let mut byte = [0u8];
+        file.read_exact_at(&mut byte, offset).unwrap();
+        file.write_all_at(&[byte[0] ^ 0xff], offset).unwrap();
+    }
+
     #[test]
     fn test_header_combine_split() {
         for crc in [0, 1, 12, u64::MAX] {
@@ -2,21 +2,42 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::{
+    env,
     fs,
-    net::{IpAddr, Ipv4Addr},
-    path::PathBuf,
+    io::Write,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    os::unix::{
+        fs::{FileTypeExt, OpenOptionsExt, PermissionsExt},
+        net::UnixListener,
+    },
+    path::{Path, PathBuf},
     sync::Arc,
 };
 
 use clap::{command, Parser};
-use eyre::{eyre, Context, Result};
+use eyre::{bail, Context, Result};
 use mysticeti_core::{
+    block_signer,
     committee::Committee,
     config::{ClientParameters, ImportExport, NodeParameters, NodePrivateConfig, NodePublicConfig},
-    types::AuthorityIndex,
+    consensus::{
+        decision_trace,
+        replay::{self, CommitterConfig},
+    },
+    crypto::{HashFunction, Signer},
+    genesis::{self, GenesisSubmission},
+    keystore::Keystore,
+    reconfiguration::{CommitteeChange, CommitteeChangeKind},
+    sign_guard::SignGuard,
+    types::{AuthorityIndex, Epoch, RoundNumber, SignatureScheme, Stake},
     validator::Validator,
 };
+use rand::rngs::OsRng;
 use tracing_subscriber::{filter::LevelFilter, fmt, EnvFilter};
+use zeroize::Zeroizing;
+
+/// Environment variable holding the keystore passphrase, used when no passphrase file is given.
+const PASSPHRASE_ENV: &str = "MYSTICETI_KEYSTORE_PASSPHRASE";
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -39,12 +60,15 @@ enum Operation {
         /// Path to the file holding the node parameters. If not provided, default parameters are used.
         #[clap(long, value_name = "FILE")]
         node_parameters_path: Option<PathBuf>,
+        /// The signature scheme of the committee (ed25519 or ml-dsa-65).
+        #[clap(long, value_name = "SCHEME", default_value_t = SignatureScheme::Ed25519)]
+        signature_scheme: SignatureScheme,
+        /// The hash function of block digests (blake2b, sha256 or blake3).
+        #[clap(long, value_name = "HASH", default_value_t = HashFunction::Blake2b)]
+        hash_function: HashFunction,
     },
-    /// Run a validator node.
+    /// Run a validator node. Its authority index is found in the committee from its private key.
     Run {
-        /// The authority index of this node.
-        #[clap(long, value_name = "INT")]
-        authority: AuthorityIndex,
         /// Path to the file holding the public committee information.
         #[clap(long, value_name = "FILE")]
         committee_path: String,
@@ -57,6 +81,8 @@ enum Operation {
         /// Path to the file holding the client parameters (for benchmarks).
         #[clap(long, value_name = "FILE")]
         client_parameters_path: String,
+        #[clap(flatten)]
+        passphrase: PassphraseSource,
     },
     /// Deploy a local validator for test. Dryrun mode uses default keys and committee configurations.
     DryRun {
@@ -66,7 +92,231 @@ enum Operation {
         /// The number of authorities in the committee.
         #[clap(long, value_name = "INT")]
         committee_size: usize,
+        /// The signature scheme of the committee (ed25519 or ml-dsa-65).
+        #[clap(long, value_name = "SCHEME", default_value_t = SignatureScheme::Ed25519)]
+        signature_scheme: SignatureScheme,
+    },
+    /// Manage the private key of this validator.
+    Keys {
+        #[clap(subcommand)]
+        operation: KeysOperation,
+    },
+    /// Run a signer process holding the private key of a validator. The validator connects to it
+    /// over a Unix socket when its private config has a `remote_signer_path`.
+    Signer {
+        /// Path to the keystore holding the private key.
+        #[clap(long, value_name = "FILE", default_value = Keystore::DEFAULT_FILENAME)]
+        keystore_path: PathBuf,
+        /// Path of the Unix socket to listen on.
+        #[clap(long, value_name = "FILE")]
+        socket_path: PathBuf,
+        /// Path of the file recording the last signed block, it must survive restarts.
+        #[clap(long, value_name = "FILE", default_value = "sign-guard")]
+        sign_guard_path: PathBuf,
+        #[clap(flatten)]
+        passphrase: PassphraseSource,
+    },
+    /// Genesis ceremony where every operator keeps its own private key.
+    Genesis {
+        #[clap(subcommand)]
+        operation: GenesisOperation,
+    },
+    /// Sign a change of the committee of the next epoch. Joining needs the approval of a quorum
+    /// of the current committee, see `approve-committee-change`. Post the file to the metrics
+    /// server of a validator that approved it, on the `/committee-changes` route, to propose it.
+    CommitteeChange {
+        /// Path to the keystore holding the private key of the joining or leaving validator.
+        #[clap(long, value_name = "FILE", default_value = Keystore::DEFAULT_FILENAME)]
+        keystore_path: PathBuf,
+        /// The current epoch, the change takes effect in the next one.
+        #[clap(long, value_name = "INT")]
+        epoch: Epoch,
+        /// Leave the committee instead of joining it.
+        #[clap(long, conflicts_with_all = ["network_address", "metrics_address"])]
+        leave: bool,
+        /// The address other validators connect to.
+        #[clap(long, value_name = "ADDR", required_unless_present = "leave")]
+        network_address: Option<SocketAddr>,
+        /// The address of the prometheus server of the validator.
+        #[clap(long, value_name = "ADDR", required_unless_present = "leave")]
+        metrics_address: Option<SocketAddr>,
+        /// The stake of the validator in the next epoch.
+        #[clap(long, value_name = "INT", default_value_t = 1)]
+        stake: Stake,
+        /// Path of the committee change file to create.
+        #[clap(long, value_name = "FILE", default_value = CommitteeChange::DEFAULT_FILENAME)]
+        change_path: PathBuf,
+        #[clap(flatten)]
+        passphrase: PassphraseSource,
+    },
+    /// Approve a committee change with the key of a validator of the current epoch, updating the
+    /// committee change file in place.
+    ApproveCommitteeChange {
+        /// Path to the keystore holding the private key of the approving validator.
+        #[clap(long, value_name = "FILE", default_value = Keystore::DEFAULT_FILENAME)]
+        keystore_path: PathBuf,
+        /// Path to the file holding the committee of the current epoch.
+        #[clap(long, value_name = "FILE")]
+        committee_path: PathBuf,
+        /// Path of the committee change file to approve.
+        #[clap(long, value_name = "FILE", default_value = CommitteeChange::DEFAULT_FILENAME)]
+        change_path: PathBuf,
+        #[clap(flatten)]
+        passphrase: PassphraseSource,
+    },
+    /// Explain why the leaders of a dag were committed, skipped or left undecided.
+    Explain {
+        /// Path to the file holding the public committee information.
+        #[clap(long, value_name = "FILE")]
+        committee_path: PathBuf,
+        /// Path to the file holding the node parameters. If not provided, default parameters are used.
+        #[clap(long, value_name = "FILE")]
+        node_parameters_path: Option<PathBuf>,
+        /// Path to the wal of a validator, the directory of its segments.
+        #[clap(long, value_name = "PATH", required_unless_present = "dag_path")]
+        wal_path: Option<PathBuf>,
+        /// Path to a dag dump.
+        #[clap(long, value_name = "FILE", conflicts_with = "wal_path")]
+        dag_path: Option<PathBuf>,
+        /// The round of the first leader to explain.
+        #[clap(long, value_name = "INT", default_value_t = 1)]
+        from_round: RoundNumber,
+        /// The round of the last leader to explain. All rounds of the dag are explained by default.
+        #[clap(long, value_name = "INT")]
+        to_round: Option<RoundNumber>,
+    },
+    /// Replay a dag through several committer configurations and compare their commits.
+    Replay {
+        /// Path to the file holding the public committee information.
+        #[clap(long, value_name = "FILE")]
+        committee_path: PathBuf,
+        /// Path to the wal of a validator, the directory of its segments.
+        #[clap(long, value_name = "PATH", required_unless_present = "dag_path")]
+        wal_path: Option<PathBuf>,
+        /// Path to a dag dump.
+        #[clap(long, value_name = "FILE", conflicts_with = "wal_path")]
+        dag_path: Option<PathBuf>,
+        /// The committer configurations to compare, such as `wave=3,leaders=2,pipeline=true`.
+        #[clap(long = "config", value_name = "CONFIG", num_args(1..), required = true)]
+        configs: Vec<CommitterConfig>,
+        /// Also print the commit sequence of each configuration.
+        #[clap(long)]
+        print_sequence: bool,
+    },
+}
+
+#[derive(Parser)]
+enum GenesisOperation {
+    /// Sign the public key, addresses and stake of this validator for the coordinator.
+    Submit {
+        /// Path to the keystore holding the private key of this validator.
+        #[clap(long, value_name = "FILE", default_value = Keystore::DEFAULT_FILENAME)]
+        keystore_path: PathBuf,
+        /// The address other validators connect to.
+        #[clap(long, value_name = "ADDR")]
+        network_address: SocketAddr,
+        /// The address of the prometheus server of this validator.
+        #[clap(long, value_name = "ADDR")]
+        metrics_address: SocketAddr,
+        /// The stake of this validator.
+        #[clap(long, value_name = "INT", default_value_t = 1)]
+        stake: Stake,
+        /// Path of the submission file to create.
+        #[clap(long, value_name = "FILE", default_value = GenesisSubmission::DEFAULT_FILENAME)]
+        submission_path: PathBuf,
+        #[clap(flatten)]
+        passphrase: PassphraseSource,
+    },
+    /// Check the submissions of all validators and generate the committee and public config files.
+    Assemble {
+        /// The submission files of all validators.
+        #[clap(long, value_name = "FILE", num_args(1..), required = true)]
+        submissions: Vec<PathBuf>,
+        /// The working directory where the files will be generated.
+        #[clap(long, value_name = "FILE", default_value = "genesis")]
+        working_directory: PathBuf,
+        /// Path to the file holding the node parameters. If not provided, default parameters are used.
+        #[clap(long, value_name = "FILE")]
+        node_parameters_path: Option<PathBuf>,
+        /// The hash function of block digests (blake2b, sha256 or blake3).
+        #[clap(long, value_name = "HASH", default_value_t = HashFunction::Blake2b)]
+        hash_function: HashFunction,
+    },
+    /// Check the generated files against the private key of this validator.
+    Verify {
+        /// Path to the file holding the public committee information.
+        #[clap(long, value_name = "FILE")]
+        committee_path: PathBuf,
+        /// Path to the file holding the public validator configurations (such as network addresses).
+        #[clap(long, value_name = "FILE")]
+        public_config_path: PathBuf,
+        /// Path to the file holding the private validator configurations (including keys).
+        #[clap(long, value_name = "FILE")]
+        private_config_path: PathBuf,
+        #[clap(flatten)]
+        passphrase: PassphraseSource,
+    },
+}
+
+#[derive(Parser)]
+enum KeysOperation {
+    /// Generate a new private key and store it in a passphrase-encrypted keystore.
+    Generate {
+        /// Path of the keystore file to create.
+        #[clap(long, value_name = "FILE", default_value = Keystore::DEFAULT_FILENAME)]
+        keystore_path: PathBuf,
+        /// The signature scheme of the key (ed25519 or ml-dsa-65).
+        #[clap(long, value_name = "SCHEME", default_value_t = SignatureScheme::Ed25519)]
+        signature_scheme: SignatureScheme,
+        #[clap(flatten)]
+        passphrase: PassphraseSource,
     },
+    /// Encrypt an existing plaintext private key into a keystore.
+    Import {
+        /// Path to the file holding the plaintext private key.
+        #[clap(long, value_name = "FILE")]
+        key_path: PathBuf,
+        /// Path of the keystore file to create.
+        #[clap(long, value_name = "FILE", default_value = Keystore::DEFAULT_FILENAME)]
+        keystore_path: PathBuf,
+        #[clap(flatten)]
+        passphrase: PassphraseSource,
+    },
+    /// Decrypt a keystore and write the private key in plaintext.
+    Export {
+        /// Path to the keystore file.
+        #[clap(long, value_name = "FILE", default_value = Keystore::DEFAULT_FILENAME)]
+        keystore_path: PathBuf,
+        /// Path of the plaintext private key file to create.
+        #[clap(long, value_name = "FILE")]
+        key_path: PathBuf,
+        #[clap(flatten)]
+        passphrase: PassphraseSource,
+    },
+    /// Write a private config that unlocks the keystore when the validator starts, instead of
+    /// holding the private key in plaintext.
+    PrivateConfig {
+        /// Path to the keystore holding the private key.
+        #[clap(long, value_name = "FILE", default_value = Keystore::DEFAULT_FILENAME)]
+        keystore_path: PathBuf,
+        /// The directory where the validator stores its data.
+        #[clap(long, value_name = "PATH", default_value = "storage")]
+        storage_path: PathBuf,
+        /// Path of the private config file to create.
+        #[clap(long, value_name = "FILE", default_value = "private-config.yaml")]
+        private_config_path: PathBuf,
+        #[clap(flatten)]
+        passphrase: PassphraseSource,
+    },
+}
+
+/// Where to read the keystore passphrase from. If no file is given, the passphrase is taken
+/// from the MYSTICETI_KEYSTORE_PASSPHRASE environment variable, or prompted for interactively.
+#[derive(clap::Args)]
+struct PassphraseSource {
+    /// Path to a file holding the keystore passphrase.
+    #[clap(long, value_name = "FILE")]
+    passphrase_file: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -84,27 +334,94 @@ async fn main() -> Result<()> {
             ips,
             working_directory,
             node_parameters_path,
-        } => benchmark_genesis(ips, working_directory, node_parameters_path)?,
+            signature_scheme,
+            hash_function,
+        } => benchmark_genesis(
+            ips,
+            working_directory,
+            node_parameters_path,
+            signature_scheme,
+            hash_function,
+        )?,
         Operation::Run {
-            authority,
             committee_path,
             public_config_path,
             private_config_path,
             client_parameters_path,
+            passphrase,
         } => {
             run(
-                authority,
                 committee_path,
                 public_config_path,
                 private_config_path,
                 client_parameters_path,
+                passphrase,
             )
             .await?
         }
         Operation::DryRun {
             authority,
             committee_size,
-        } => dryrun(authority, committee_size).await?,
+            signature_scheme,
+        } => dryrun(authority, committee_size, signature_scheme).await?,
+        Operation::Keys { operation } => keys(operation)?,
+        Operation::Genesis { operation } => genesis(operation)?,
+        Operation::Signer {
+            keystore_path,
+            socket_path,
+            sign_guard_path,
+            passphrase,
+        } => signer(keystore_path, socket_path, sign_guard_path, passphrase)?,
+        Operation::CommitteeChange {
+            keystore_path,
+            epoch,
+            leave,
+            network_address,
+            metrics_address,
+            stake,
+            change_path,
+            passphrase,
+        } => {
+            let kind = match (network_address, metrics_address) {
+                (Some(network_address), Some(metrics_address)) if !leave => {
+                    CommitteeChangeKind::Join {
+                        network_address,
+                        metrics_address,
+                        stake,
+                    }
+                }
+                _ => CommitteeChangeKind::Leave,
+            };
+            committee_change(keystore_path, epoch, kind, change_path, passphrase)?
+        }
+        Operation::ApproveCommitteeChange {
+            keystore_path,
+            committee_path,
+            change_path,
+            passphrase,
+        } => approve_committee_change(keystore_path, committee_path, change_path, passphrase)?,
+        Operation::Explain {
+            committee_path,
+            node_parameters_path,
+            wal_path,
+            dag_path,
+            from_round,
+            to_round,
+        } => explain(
+            committee_path,
+            node_parameters_path,
+            wal_path,
+            dag_path,
+            from_round,
+            to_round,
+        )?,
+        Operation::Replay {
+            committee_path,
+            wal_path,
+            dag_path,
+            configs,
+            print_sequence,
+        } => replay(committee_path, wal_path, dag_path, configs, print_sequence)?,
     }
 
     Ok(())
@@ -114,6 +431,8 @@ fn benchmark_genesis(
     ips: Vec<IpAddr>,
     working_directory: PathBuf,
     node_parameters_path: Option<PathBuf>,
+    signature_scheme: SignatureScheme,
+    hash_function: HashFunction,
 ) -> Result<()> {
     tracing::info!("Generating benchmark genesis files");
     fs::create_dir_all(&working_directory).wrap_err(format!(
@@ -125,7 +444,7 @@ fn benchmark_genesis(
     let committee_size = ips.len();
     let mut committee_path = working_directory.clone();
     committee_path.push(Committee::DEFAULT_FILENAME);
-    Committee::new_for_benchmarks(committee_size)
+    Committee::new_for_benchmarks_with_scheme(committee_size, signature_scheme, hash_function)
         .print(&committee_path)
         .wrap_err("Failed to print committee file")?;
     tracing::info!("Generated committee file: {}", committee_path.display());
@@ -139,7 +458,11 @@ fn benchmark_genesis(
         None => NodeParameters::default(),
     };
 
-    let node_public_config = NodePublicConfig::new_for_benchmarks(ips, Some(node_parameters));
+    let node_public_config = NodePublicConfig::new_for_benchmarks_with_scheme(
+        ips,
+        Some(node_parameters),
+        signature_scheme,
+    );
     let mut node_public_config_path = working_directory.clone();
     node_public_config_path.push(NodePublicConfig::DEFAULT_FILENAME);
     node_public_config
@@ -151,8 +474,11 @@ fn benchmark_genesis(
     );
 
     // Generate the private node config files.
-    let node_private_configs =
-        NodePrivateConfig::new_for_benchmarks(&working_directory, committee_size);
+    let node_private_configs = NodePrivateConfig::new_for_benchmarks_with_scheme(
+        &working_directory,
+        committee_size,
+        signature_scheme,
+    );
     for (i, private_config) in node_private_configs.into_iter().enumerate() {
         fs::create_dir_all(&private_config.storage_path)
             .expect("Failed to create storage directory");
@@ -166,71 +492,185 @@ fn benchmark_genesis(
     Ok(())
 }
 
+fn genesis(operation: GenesisOperation) -> Result<()> {
+    match operation {
+        GenesisOperation::Submit {
+            keystore_path,
+            network_address,
+            metrics_address,
+            stake,
+            submission_path,
+            passphrase,
+        } => {
+            ensure_absent(&submission_path)?;
+            let keystore = Keystore::load(&keystore_path).wrap_err(format!(
+                "Failed to load keystore '{}'",
+                keystore_path.display()
+            ))?;
+            let passphrase = passphrase.read(false)?;
+            let signer = keystore
+                .decrypt(&passphrase)
+                .wrap_err("Failed to unlock the keystore")?;
+            GenesisSubmission::new(&signer, network_address, metrics_address, stake)
+                .print(&submission_path)
+                .wrap_err("Failed to print submission file")?;
+            tracing::info!("Generated submission file: {}", submission_path.display());
+        }
+        GenesisOperation::Assemble {
+            submissions,
+            working_directory,
+            node_parameters_path,
+            hash_function,
+        } => {
+            let submissions = submissions
+                .iter()
+                .map(|path| {
+                    GenesisSubmission::load(path).wrap_err(format!(
+                        "Failed to load submission file '{}'",
+                        path.display()
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let node_parameters = match node_parameters_path {
+                Some(path) => NodeParameters::load(&path).wrap_err(format!(
+                    "Failed to load parameters file '{}'",
+                    path.display()
+                ))?,
+                None => NodeParameters::default(),
+            };
+            let (committee, public_config) =
+                genesis::assemble(submissions, node_parameters, hash_function)?;
+
+            fs::create_dir_all(&working_directory).wrap_err(format!(
+                "Failed to create directory '{}'",
+                working_directory.display()
+            ))?;
+            let committee_path = working_directory.join(Committee::DEFAULT_FILENAME);
+            committee
+                .print(&committee_path)
+                .wrap_err("Failed to print committee file")?;
+            tracing::info!("Generated committee file: {}", committee_path.display());
+            let public_config_path = working_directory.join(NodePublicConfig::DEFAULT_FILENAME);
+            public_config
+                .print(&public_config_path)
+                .wrap_err("Failed to print public config file")?;
+            tracing::info!(
+                "Generated public node config file: {}",
+                public_config_path.display()
+            );
+            // Authority indices follow the order of the public keys.
+            for (authority, identifier) in public_config.identifiers.iter().enumerate() {
+                tracing::info!("Authority {authority}: {}", identifier.network_address);
+            }
+        }
+        GenesisOperation::Verify {
+            committee_path,
+            public_config_path,
+            private_config_path,
+            passphrase,
+        } => {
+            let committee = Committee::load(&committee_path).wrap_err(format!(
+                "Failed to load committee file '{}'",
+                committee_path.display()
+            ))?;
+            let public_config = NodePublicConfig::load(&public_config_path).wrap_err(format!(
+                "Failed to load parameters file '{}'",
+                public_config_path.display()
+            ))?;
+            let mut private_config =
+                NodePrivateConfig::load(&private_config_path).wrap_err(format!(
+                    "Failed to load private configuration file '{}'",
+                    private_config_path.display()
+                ))?;
+            if private_config.is_locked() {
+                let passphrase = passphrase.read(false)?;
+                private_config
+                    .unlock(&passphrase)
+                    .wrap_err("Failed to unlock the keystore")?;
+            }
+            let signer = private_config
+                .block_signer()
+                .wrap_err("Failed to load the private key")?;
+            let authority =
+                genesis::find_own_authority(&committee, &public_config, &signer.public_key())?;
+            tracing::info!("Genesis files match the private key of authority {authority}");
+        }
+    }
+    Ok(())
+}
+
 /// Boot a single validator node.
 async fn run(
-    authority: AuthorityIndex,
     committee_path: String,
     public_config_path: String,
     private_config_path: String,
     client_parameters_path: String,
+    passphrase: PassphraseSource,
 ) -> Result<()> {
-    tracing::info!("Starting validator {authority}");
+    tracing::info!("Starting validator");
 
     let committee = Committee::load(&committee_path)
         .wrap_err(format!("Failed to load committee file '{committee_path}'"))?;
     let public_config = NodePublicConfig::load(&public_config_path).wrap_err(format!(
         "Failed to load parameters file '{public_config_path}'"
     ))?;
-    let private_config = NodePrivateConfig::load(&private_config_path).wrap_err(format!(
+    let mut private_config = NodePrivateConfig::load(&private_config_path).wrap_err(format!(
         "Failed to load private configuration file '{private_config_path}'"
     ))?;
+    if private_config.is_locked() {
+        let passphrase = passphrase.read(false)?;
+        private_config
+            .unlock(&passphrase)
+            .wrap_err("Failed to unlock the keystore")?;
+    }
     let client_parameters = ClientParameters::load(&client_parameters_path).wrap_err(format!(
         "Failed to load client parameters file '{client_parameters_path}'"
     ))?;
 
     let committee = Arc::new(committee);
 
-    let network_address = public_config
-        .network_address(authority)
-        .ok_or(eyre!("No network address for authority {authority}"))
-        .wrap_err("Unknown authority")?;
-    let mut binding_network_address = network_address;
-    binding_network_address.set_ip(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
-
-    let metrics_address = public_config
-        .metrics_address(authority)
-        .ok_or(eyre!("No metrics address for authority {authority}"))
-        .wrap_err("Unknown authority")?;
-    let mut binding_metrics_address = metrics_address;
-    binding_metrics_address.set_ip(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
-
     // Boot the validator node.
     let validator = Validator::start(
-        authority,
         committee,
         public_config.clone(),
         private_config,
         client_parameters,
     )
     .await?;
-    let (network_result, _metrics_result) = validator.await_completion().await;
-    network_result.expect("Validator crashed");
-    Ok(())
+    validator
+        .await_completion()
+        .await
+        .expect("Validator crashed")
 }
 
-async fn dryrun(authority: AuthorityIndex, committee_size: usize) -> Result<()> {
+async fn dryrun(
+    authority: AuthorityIndex,
+    committee_size: usize,
+    signature_scheme: SignatureScheme,
+) -> Result<()> {
     tracing::warn!(
-        "Starting validator {authority} in dryrun mode (committee size: {committee_size})"
+        "Starting validator {authority} in dryrun mode (committee size: {committee_size}, signature scheme: {signature_scheme})"
     );
     let ips = vec![IpAddr::V4(Ipv4Addr::LOCALHOST); committee_size];
-    let committee = Committee::new_for_benchmarks(committee_size);
+    let committee = Committee::new_for_benchmarks_with_scheme(
+        committee_size,
+        signature_scheme,
+        HashFunction::default(),
+    );
     let client_parameters = ClientParameters::default();
     let node_parameters = NodeParameters::default();
-    let public_config = NodePublicConfig::new_for_benchmarks(ips, Some(node_parameters));
+    let public_config = NodePublicConfig::new_for_benchmarks_with_scheme(
+        ips,
+        Some(node_parameters),
+        signature_scheme,
+    );
 
     let working_dir = PathBuf::from(format!("dryrun-validator-{authority}"));
-    let mut all_private_config =
-        NodePrivateConfig::new_for_benchmarks(&working_dir, committee_size);
+    let mut all_private_config = NodePrivateConfig::new_for_benchmarks_with_scheme(
+        &working_dir,
+        committee_size,
+        signature_scheme,
+    );
     let private_config = all_private_config.remove(authority as usize);
     match fs::remove_dir_all(&working_dir) {
         Ok(_) => {}
@@ -252,16 +692,328 @@ async fn dryrun(authority: AuthorityIndex, committee_size: usize) -> Result<()>
         }
     }
 
-    let validator = Validator::start(
-        authority,
-        committee,
-        public_config,
-        private_config,
-        client_parameters,
-    )
-    .await?;
-    let (network_result, _metrics_result) = validator.await_completion().await;
-    network_result.expect("Validator crashed");
+    let validator =
+        Validator::start(committee, public_config, private_config, client_parameters).await?;
+    validator
+        .await_completion()
+        .await
+        .expect("Validator crashed")
+}
+
+fn keys(operation: KeysOperation) -> Result<()> {
+    match operation {
+        KeysOperation::Generate {
+            keystore_path,
+            signature_scheme,
+            passphrase,
+        } => {
+            ensure_absent(&keystore_path)?;
+            let signer = Signer::generate(signature_scheme, &mut OsRng);
+            let passphrase = passphrase.read(true)?;
+            write_keystore(&signer, &passphrase, &keystore_path)?;
+        }
+        KeysOperation::Import {
+            key_path,
+            keystore_path,
+            passphrase,
+        } => {
+            ensure_absent(&keystore_path)?;
+            let signer = Signer::load(&key_path).wrap_err(format!(
+                "Failed to load private key file '{}'",
+                key_path.display()
+            ))?;
+            let passphrase = passphrase.read(true)?;
+            write_keystore(&signer, &passphrase, &keystore_path)?;
+        }
+        KeysOperation::Export {
+            keystore_path,
+            key_path,
+            passphrase,
+        } => {
+            ensure_absent(&key_path)?;
+            let keystore = Keystore::load(&keystore_path).wrap_err(format!(
+                "Failed to load keystore '{}'",
+                keystore_path.display()
+            ))?;
+            let passphrase = passphrase.read(false)?;
+            let signer = keystore
+                .decrypt(&passphrase)
+                .wrap_err("Failed to unlock the keystore")?;
+            let content = Zeroizing::new(serde_yaml_string(&signer)?);
+            write_private_file(&key_path, content.as_bytes())?;
+            tracing::warn!(
+                "Exported the private key in plaintext to {}",
+                key_path.display()
+            );
+        }
+        KeysOperation::PrivateConfig {
+            keystore_path,
+            storage_path,
+            private_config_path,
+            passphrase,
+        } => {
+            ensure_absent(&private_config_path)?;
+            // The validator may be started from another directory.
+            let keystore_path = fs::canonicalize(&keystore_path).wrap_err(format!(
+                "Failed to find keystore '{}'",
+                keystore_path.display()
+            ))?;
+            fs::create_dir_all(&storage_path).wrap_err(format!(
+                "Failed to create directory '{}'",
+                storage_path.display()
+            ))?;
+            let storage_path = fs::canonicalize(&storage_path)
+                .wrap_err(format!("Failed to find '{}'", storage_path.display()))?;
+
+            // Catch a wrong passphrase now rather than when the validator starts.
+            let keystore = Keystore::load(&keystore_path).wrap_err(format!(
+                "Failed to load keystore '{}'",
+                keystore_path.display()
+            ))?;
+            let passphrase = passphrase.read(false)?;
+            keystore
+                .decrypt(&passphrase)
+                .wrap_err("Failed to unlock the keystore")?;
+
+            NodePrivateConfig::new_with_keystore(keystore_path, storage_path)
+                .print(&private_config_path)
+                .wrap_err("Failed to print private config file")?;
+            tracing::info!(
+                "Generated private config file: {}",
+                private_config_path.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn signer(
+    keystore_path: PathBuf,
+    socket_path: PathBuf,
+    sign_guard_path: PathBuf,
+    passphrase: PassphraseSource,
+) -> Result<()> {
+    let keystore = Keystore::load(&keystore_path).wrap_err(format!(
+        "Failed to load keystore '{}'",
+        keystore_path.display()
+    ))?;
+    let passphrase = passphrase.read(false)?;
+    let signer = keystore
+        .decrypt(&passphrase)
+        .wrap_err("Failed to unlock the keystore")?;
+    let sign_guard = SignGuard::open(&sign_guard_path).wrap_err(format!(
+        "Failed to open sign guard '{}'",
+        sign_guard_path.display()
+    ))?;
+
+    // Remove the socket left behind by a previous run, but nothing else
+    if let Ok(metadata) = fs::symlink_metadata(&socket_path) {
+        if !metadata.file_type().is_socket() {
+            bail!(
+                "Refusing to overwrite existing file '{}'",
+                socket_path.display()
+            );
+        }
+        fs::remove_file(&socket_path).wrap_err("Failed to remove stale socket")?;
+    }
+    let listener = UnixListener::bind(&socket_path)
+        .wrap_err(format!("Failed to listen on '{}'", socket_path.display()))?;
+    fs::set_permissions(&socket_path, fs::Permissions::from_mode(0o600))
+        .wrap_err("Failed to restrict socket permissions")?;
+    tracing::info!(
+        "Signing blocks for {:?} on {}",
+        keystore.public_key(),
+        socket_path.display()
+    );
+    block_signer::serve_signer(listener, signer, sign_guard).wrap_err("Signer failed")
+}
+
+fn explain(
+    committee_path: PathBuf,
+    node_parameters_path: Option<PathBuf>,
+    wal_path: Option<PathBuf>,
+    dag_path: Option<PathBuf>,
+    from_round: RoundNumber,
+    to_round: Option<RoundNumber>,
+) -> Result<()> {
+    let committee = Committee::load(&committee_path).wrap_err(format!(
+        "Failed to load committee file '{}'",
+        committee_path.display()
+    ))?;
+    let node_parameters = match node_parameters_path {
+        Some(path) => NodeParameters::load(&path).wrap_err(format!(
+            "Failed to load parameters file '{}'",
+            path.display()
+        ))?,
+        None => NodeParameters::default(),
+    };
+
+    let committee = Arc::new(committee);
+    let traces = match (wal_path, dag_path) {
+        (Some(path), _) => {
+            decision_trace::explain_wal(&path, committee, &node_parameters, from_round)
+                .wrap_err(format!("Failed to read wal '{}'", path.display()))?
+        }
+        (None, Some(path)) => {
+            decision_trace::explain_dag_dump(&path, committee, &node_parameters, from_round)
+                .wrap_err(format!("Failed to read dag dump '{}'", path.display()))?
+        }
+        (None, None) => bail!("Either a wal or a dag dump is required"),
+    };
+    let to_round = to_round.unwrap_or(RoundNumber::MAX);
+    for trace in traces.iter().take_while(|trace| trace.round <= to_round) {
+        print!("{trace}");
+    }
+    Ok(())
+}
+
+fn replay(
+    committee_path: PathBuf,
+    wal_path: Option<PathBuf>,
+    dag_path: Option<PathBuf>,
+    configs: Vec<CommitterConfig>,
+    print_sequence: bool,
+) -> Result<()> {
+    let committee = Committee::load(&committee_path).wrap_err(format!(
+        "Failed to load committee file '{}'",
+        committee_path.display()
+    ))?;
+
+    let committee = Arc::new(committee);
+    let reports = match (wal_path, dag_path) {
+        (Some(path), _) => replay::replay_wal(&path, committee, &configs)
+            .wrap_err(format!("Failed to read wal '{}'", path.display()))?,
+        (None, Some(path)) => replay::replay_dag_dump(&path, committee, &configs)
+            .wrap_err(format!("Failed to read dag dump '{}'", path.display()))?,
+        (None, None) => bail!("Either a wal or a dag dump is required"),
+    };
+    if print_sequence {
+        for report in &reports {
+            let sequence: Vec<_> = report.sequence.iter().map(ToString::to_string).collect();
+            println!("{}: {}", report.config, sequence.join(" "));
+        }
+    }
+    println!("{}", replay::format_reports(&reports));
+    Ok(())
+}
+
+fn write_keystore(signer: &Signer, passphrase: &str, keystore_path: &Path) -> Result<()> {
+    let keystore = Keystore::encrypt(signer, passphrase);
+    keystore.print(keystore_path).wrap_err(format!(
+        "Failed to write keystore '{}'",
+        keystore_path.display()
+    ))?;
+    tracing::info!("Generated keystore: {}", keystore_path.display());
+    // Operators share the public key with the rest of the committee.
+    print!("{}", serde_yaml_string(keystore.public_key())?);
+    Ok(())
+}
 
+fn serde_yaml_string<T: serde::Serialize>(value: &T) -> Result<String> {
+    serde_yaml::to_string(value).wrap_err("Failed to serialize to YAML")
+}
+
+fn committee_change(
+    keystore_path: PathBuf,
+    epoch: Epoch,
+    kind: CommitteeChangeKind,
+    change_path: PathBuf,
+    passphrase: PassphraseSource,
+) -> Result<()> {
+    ensure_absent(&change_path)?;
+    let keystore = Keystore::load(&keystore_path).wrap_err(format!(
+        "Failed to load keystore '{}'",
+        keystore_path.display()
+    ))?;
+    let passphrase = passphrase.read(false)?;
+    let signer = keystore
+        .decrypt(&passphrase)
+        .wrap_err("Failed to unlock the keystore")?;
+    CommitteeChange::new(&signer, epoch, kind)
+        .print(&change_path)
+        .wrap_err("Failed to print committee change file")?;
+    tracing::info!("Generated committee change file: {}", change_path.display());
+    Ok(())
+}
+
+fn approve_committee_change(
+    keystore_path: PathBuf,
+    committee_path: PathBuf,
+    change_path: PathBuf,
+    passphrase: PassphraseSource,
+) -> Result<()> {
+    let committee = Committee::load(&committee_path).wrap_err(format!(
+        "Failed to load committee file '{}'",
+        committee_path.display()
+    ))?;
+    let mut change = CommitteeChange::load(&change_path).wrap_err(format!(
+        "Failed to load committee change file '{}'",
+        change_path.display()
+    ))?;
+    let keystore = Keystore::load(&keystore_path).wrap_err(format!(
+        "Failed to load keystore '{}'",
+        keystore_path.display()
+    ))?;
+    let passphrase = passphrase.read(false)?;
+    let signer = keystore
+        .decrypt(&passphrase)
+        .wrap_err("Failed to unlock the keystore")?;
+    change
+        .approve(&signer, &committee)
+        .wrap_err("Failed to approve the committee change")?;
+    change
+        .print(&change_path)
+        .wrap_err("Failed to print committee change file")?;
+    tracing::info!("Approved committee change file: {}", change_path.display());
     Ok(())
 }
+
+fn ensure_absent(path: &Path) -> Result<()> {
+    if path.exists() {
+        bail!("Refusing to overwrite existing file '{}'", path.display());
+    }
+    Ok(())
+}
+
+/// Write a file readable only by the current user.
+fn write_private_file(path: &Path, content: &[u8]) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .wrap_err(format!("Failed to create file '{}'", path.display()))?;
+    file.write_all(content)
+        .wrap_err(format!("Failed to write file '{}'", path.display()))
+}
+
+impl PassphraseSource {
+    fn read(&self, confirm: bool) -> Result<Zeroizing<String>> {
+        if let Some(path) = &self.passphrase_file {
+            let content = Zeroizing::new(fs::read_to_string(path).wrap_err(format!(
+                "Failed to read passphrase file '{}'",
+                path.display()
+            ))?);
+            return Ok(Zeroizing::new(
+                content.trim_end_matches(['\n', '\r']).to_string(),
+            ));
+        }
+        if let Ok(passphrase) = env::var(PASSPHRASE_ENV) {
+            return Ok(Zeroizing::new(passphrase));
+        }
+        let passphrase = Zeroizing::new(
+            rpassword::prompt_password("Keystore passphrase: ")
+                .wrap_err("Failed to read passphrase")?,
+        );
+        if confirm {
+            let confirmation = Zeroizing::new(
+                rpassword::prompt_password("Repeat passphrase: ")
+                    .wrap_err("Failed to read passphrase")?,
+            );
+            if passphrase != confirmation {
+                bail!("Passphrases do not match");
+            }
+        }
+        Ok(passphrase)
+    }
+}
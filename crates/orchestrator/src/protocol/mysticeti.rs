@@ -159,7 +159,6 @@ impl ProtocolCommands for MysticetiProtocol {
                 let run = [
                     &format!("./{BINARY_PATH}/mysticeti"),
                     "run",
-                    &format!("--authority {authority}"),
                     &format!("--committee-path {}", committee_path.display()),
                     &format!("--public-config-path {}", public_config_path.display()),
                     &format!("--private-config-path {}", private_config_path.display()),